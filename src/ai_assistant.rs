@@ -6,17 +6,73 @@ use tokio::sync::mpsc;
 use std::env;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
+use crate::ai_cache::{self, AiCache};
+use crate::history::HistoryFilter;
 
 // OpenRouter API endpoint
 const OPENROUTER_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
 
+/// Model commands are generated with unless the config names another
+pub const MODEL: &str = "meta-llama/llama-3.2-3b-instruct:free"; // A good model for command generation
+pub const PROVIDER: &str = "OpenRouter";
+
+/// How long to wait for the model unless the config says otherwise
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Get the OpenRouter API key from environment variable
-fn get_openrouter_api_key() -> Result<String, String> {
-    env::var("OPENROUTER_API_KEY")
-        .map_err(|_| "OPENROUTER_API_KEY environment variable not set. Please set it with: export OPENROUTER_API_KEY='your_api_key_here'".to_string())
+fn get_openrouter_api_key() -> Result<String, AiError> {
+    env::var("OPENROUTER_API_KEY").ok().filter(|key| !key.trim().is_empty()).ok_or(AiError::NoApiKey)
+}
+
+/// What to tell someone who hasn't set up a key, one line each
+pub const API_KEY_HINT: &[&str] = &[
+    "💡 Unknown commands can be turned into real ones by the AI, but no OpenRouter API key is set.",
+    "   Get one at https://openrouter.ai/keys and start Linara with OPENROUTER_API_KEY=<key> in the environment,",
+    "   or run `ai off` (or set `ai = off` in the config) to stop using the AI.",
+];
+
+/// Why the AI couldn't give a command
+#[derive(Debug)]
+pub enum AiError {
+    /// Gibberish, or a request the model couldn't make a command of
+    NotUnderstood,
+    /// No answer within the request timeout
+    Timeout,
+    Network(reqwest::Error),
+    Api { status: reqwest::StatusCode, body: String },
+    NoApiKey,
+    /// The model answered with something that can't run here
+    InvalidCommand,
+}
+
+impl std::fmt::Display for AiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AiError::NotUnderstood => write!(f, "I don't understand that request. Please try rephrasing your command."),
+            AiError::Timeout => write!(f, "the AI didn't answer in time"),
+            AiError::Network(e) => write!(f, "network error: {}", e),
+            AiError::Api { status, body } => write!(f, "API error: {} - {}", status, body),
+            AiError::NoApiKey => write!(f, "OPENROUTER_API_KEY is not set"),
+            AiError::InvalidCommand => write!(f, "the answer isn't a command that can run here"),
+        }
+    }
+}
+
+impl std::error::Error for AiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AiError::Network(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() { AiError::Timeout } else { AiError::Network(e) }
+    }
 }
 
 #[derive(Serialize)]
@@ -36,6 +92,30 @@ struct Message {
 #[derive(Deserialize)]
 struct OpenRouterResponse {
     choices: Vec<Choice>,
+    #[serde(default)] // Not every provider reports it
+    usage: Option<Usage>,
+}
+
+/// Tokens a request used, as reported by the API
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+impl std::fmt::Display for Usage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in / {} out", self.prompt_tokens, self.completion_tokens)
+    }
 }
 
 #[derive(Deserialize)]
@@ -43,27 +123,154 @@ struct Choice {
     message: Message,
 }
 
+/// Result of a background command request, delivered through `AIAssistant::receiver`
+pub struct AIResponse {
+    pub input: String,
+    pub result: Result<String, AiError>,
+    pub usage: Option<Usage>, // None when the answer didn't come from the API
+    pub cache_error: Option<String>, // Saving the answer to the cache failed
+}
+
+/// Word lists behind the gibberish check; both can be replaced from the config file
 #[derive(Clone)]
-struct CacheEntry {
-    command: String,
-    timestamp: SystemTime,
+pub struct GibberishRules {
+    pub meaningful_words: Vec<String>,
+    pub incoherent_patterns: Vec<String>,
+}
+
+// Verbs that make a multi-word input a real request even when it also contains question words
+const CLEAR_VERBS: &[&str] = &[
+    "create", "make", "delete", "remove", "list", "show", "find", "search",
+    "copy", "move", "download", "install", "update", "open", "close", "start", "stop",
+    "compress", "extract", "rename", "count", "check", "kill", "print", "run", "edit",
+];
+
+impl Default for GibberishRules {
+    fn default() -> Self {
+        let meaningful_words = ["open", "cursor", "vscode", "editor", "ide", "folder", "directory", "file", "this", "here", "current"];
+        let incoherent_patterns = [
+            "how hello", "hello how", "what hello", "hello what",
+            "why hello", "hello why", "when hello", "hello when",
+            "where hello", "hello where", "who hello", "hello who",
+            "how what", "what how", "why what", "what why",
+            "how are", "what are", "why are", "when are", "where are", "who are",
+            "hello world", "world hello", "test hello", "hello test"
+        ];
+        Self {
+            meaningful_words: meaningful_words.iter().map(|word| word.to_string()).collect(),
+            incoherent_patterns: incoherent_patterns.iter().map(|pattern| pattern.to_string()).collect(),
+        }
+    }
+}
+
+impl GibberishRules {
+    /// Check if input appears to be gibberish or nonsensical
+    pub fn is_gibberish(&self, input: &str) -> bool {
+        let input = input.trim().to_lowercase();
+
+        // Too short - likely not meaningful
+        if input.len() < 2 {
+            return true;
+        }
+
+        // Allow inputs that contain meaningful IDE/editor related words
+        if self.meaningful_words.iter().any(|word| input.contains(word.as_str())) {
+            return false;
+        }
+
+        // Several words with a clear verb ("how do I list files") are a real request
+        let words: Vec<&str> = input.split_whitespace().collect();
+        if words.len() >= 2 && words.iter().any(|word| CLEAR_VERBS.contains(word)) {
+            return false;
+        }
+
+        // Check for repeated characters (like "aaaaa", "sdasdasdasdas")
+        let chars: Vec<char> = input.chars().collect();
+        if chars.len() >= 4 {
+            let mut repeated_count = 1;
+            for i in 1..chars.len() {
+                if chars[i] == chars[i-1] {
+                    repeated_count += 1;
+                    if repeated_count >= 4 {
+                        return true;
+                    }
+                } else {
+                    repeated_count = 1;
+                }
+            }
+        }
+
+        // Check if input has no alphanumeric characters
+        if !input.chars().any(|c| c.is_alphanumeric()) {
+            return true;
+        }
+
+        // Check for patterns that suggest gibberish (like alternating same characters)
+        if input.len() >= 6 {
+            let mut alternating_pattern = true;
+            for i in 2..input.len() {
+                if input.chars().nth(i) != input.chars().nth(i-2) {
+                    alternating_pattern = false;
+                    break;
+                }
+            }
+            if alternating_pattern {
+                return true;
+            }
+        }
+
+        // Keyboard mashing ("asdkjasd"): a single word with a long run of consonants
+        if words.len() == 1 && input.len() >= 6 && input.chars().all(|c| c.is_ascii_alphabetic()) {
+            let longest_consonant_run = input
+                .split(|c: char| "aeiouy".contains(c))
+                .map(str::len)
+                .max()
+                .unwrap_or(0);
+            if longest_consonant_run >= 4 {
+                return true;
+            }
+        }
+
+        // Check for incoherent phrases (like "how hello", "what is", etc.)
+        if self.incoherent_patterns.iter().any(|pattern| input.contains(pattern.as_str())) {
+            return true;
+        }
+
+        // Check for inputs that are just question words without context
+        let question_words = ["how", "what", "why", "when", "where", "who", "which"];
+
+        if words.len() <= 3 {
+            // If it's just 1-3 words and contains question words without meaningful context
+            let has_question = words.iter().any(|&word| question_words.contains(&word));
+            if has_question {
+                return true;
+            }
+        }
+
+        false
+    }
 }
 
 pub struct AIAssistant {
     client: reqwest::Client,
-    pub sender: mpsc::UnboundedSender<String>,
-    pub receiver: mpsc::UnboundedReceiver<String>,
-    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+    pub sender: mpsc::UnboundedSender<AIResponse>,
+    pub receiver: mpsc::UnboundedReceiver<AIResponse>,
+    cache: Arc<Mutex<AiCache>>, // Shared with the other tabs and requests in flight, which add their answers
     local_commands: HashMap<String, String>,
+    pub request_timeout: Duration,
+    pub gibberish_rules: GibberishRules,
+    pub history_filter: HistoryFilter, // Inputs it matches are kept out of the cache
+    pub has_api_key: bool, // Checked once at startup; without a key only local and cached answers are possible
+    pub model: String, // OpenRouter model id
 }
 
 impl AIAssistant {
-    pub fn new() -> Self {
+    pub fn new(cache: Arc<Mutex<AiCache>>) -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
 
         // Configure HTTP client for maximum speed
         let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(5)) // Even faster timeout
+            // No client-wide timeout: each request is bounded by `request_timeout` so retries can wait longer
             .pool_max_idle_per_host(20) // More connection pooling
             .pool_idle_timeout(Duration::from_secs(60)) // Longer keep-alive
             .tcp_keepalive(Duration::from_secs(60)) // TCP keepalive
@@ -122,42 +329,31 @@ impl AIAssistant {
             client,
             sender,
             receiver,
-            cache: Arc::new(Mutex::new(HashMap::new())),
+            cache,
             local_commands,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            gibberish_rules: GibberishRules::default(),
+            history_filter: HistoryFilter::default(),
+            has_api_key: get_openrouter_api_key().is_ok(),
+            model: MODEL.to_string(),
         }
     }
 
-    /// Check cache for existing response (5-minute TTL)
+    /// A command the AI gave for `input` before, in this run or an earlier one
     fn get_cached_response(&self, input: &str) -> Option<String> {
-        if let Ok(cache) = self.cache.lock() {
-            if let Some(entry) = cache.get(input) {
-                if entry.timestamp.elapsed().unwrap_or(Duration::from_secs(0)) < Duration::from_secs(300) {
-                    return Some(entry.command.clone());
-                }
-            }
-        }
-        None
+        let cache = self.cache.lock().ok()?;
+        cache.get(input, ai_cache::now()).map(str::to_string)
     }
 
-    /// Store response in cache
-    fn cache_response(&self, input: &str, command: &str) {
-        if let Ok(mut cache) = self.cache.lock() {
-            cache.insert(input.to_string(), CacheEntry {
-                command: command.to_string(),
-                timestamp: SystemTime::now(),
-            });
-            // Keep cache size manageable (max 100 entries)
-            if cache.len() > 100 {
-                // Remove oldest entries (simple FIFO)
-                let keys_to_remove: Vec<String> = cache.keys()
-                    .take(cache.len() - 100)
-                    .cloned()
-                    .collect();
-                for key in keys_to_remove {
-                    cache.remove(&key);
-                }
-            }
+    /// Store response in cache, returning why it couldn't be saved. The file is written
+    /// after the lock is released, so lookups from other tabs don't wait on the disk.
+    fn cache_response(cache: &Mutex<AiCache>, input: &str, command: &str) -> Option<String> {
+        let snapshot = cache.lock().ok()?.insert(input, command, ai_cache::now())?;
+        let error = snapshot.write().err()?;
+        if let Ok(mut cache) = cache.lock() {
+            cache.stop_saving();
         }
+        Some(error.to_string())
     }
 
     pub fn is_natural_language(input: &str) -> bool {
@@ -190,88 +386,9 @@ impl AIAssistant {
         natural_indicators.iter().any(|&indicator| input.contains(indicator))
     }
 
-    /// Check if input appears to be gibberish or nonsensical
+    /// Check if input appears to be gibberish or nonsensical, using the built-in word lists
     pub fn is_gibberish(input: &str) -> bool {
-        let input = input.trim().to_lowercase();
-
-        // Too short - likely not meaningful
-        if input.len() < 2 {
-            return true;
-        }
-
-        // Allow inputs that contain meaningful IDE/editor related words
-        let meaningful_words = ["open", "cursor", "vscode", "editor", "ide", "folder", "directory", "file", "this", "here", "current"];
-        if meaningful_words.iter().any(|&word| input.contains(word)) {
-            return false;
-        }
-
-        // Check for repeated characters (like "aaaaa", "sdasdasdasdas")
-        let chars: Vec<char> = input.chars().collect();
-        if chars.len() >= 4 {
-            let mut repeated_count = 1;
-            for i in 1..chars.len() {
-                if chars[i] == chars[i-1] {
-                    repeated_count += 1;
-                    if repeated_count >= 4 {
-                        return true;
-                    }
-                } else {
-                    repeated_count = 1;
-                }
-            }
-        }
-
-        // Check if input has no alphanumeric characters
-        if !input.chars().any(|c| c.is_alphanumeric()) {
-            return true;
-        }
-
-        // Check for patterns that suggest gibberish (like alternating same characters)
-        if input.len() >= 6 {
-            let mut alternating_pattern = true;
-            for i in 2..input.len() {
-                if input.chars().nth(i) != input.chars().nth(i-2) {
-                    alternating_pattern = false;
-                    break;
-                }
-            }
-            if alternating_pattern {
-                return true;
-            }
-        }
-
-        // Check for incoherent phrases (like "how hello", "what is", etc.)
-        let incoherent_patterns = [
-            "how hello", "hello how", "what hello", "hello what",
-            "why hello", "hello why", "when hello", "hello when",
-            "where hello", "hello where", "who hello", "hello who",
-            "how what", "what how", "why what", "what why",
-            "how are", "what are", "why are", "when are", "where are", "who are",
-            "hello world", "world hello", "test hello", "hello test"
-        ];
-
-        if incoherent_patterns.iter().any(|&pattern| input.contains(pattern)) {
-            return true;
-        }
-
-        // Check for inputs that are just question words without context
-        let question_words = ["how", "what", "why", "when", "where", "who", "which"];
-        let words: Vec<&str> = input.split_whitespace().collect();
-
-        if words.len() <= 3 {
-            // If it's just 1-3 words and contains question words without meaningful context
-            let has_question = words.iter().any(|&word| question_words.contains(&word));
-            let has_meaningful = words.iter().any(|&word|
-                ["create", "make", "delete", "remove", "list", "show", "find", "search",
-                 "copy", "move", "download", "install", "update", "open", "close", "start", "stop"].contains(&word)
-            );
-
-            if has_question && !has_meaningful {
-                return true;
-            }
-        }
-
-        false
+        GibberishRules::default().is_gibberish(input)
     }
 
     /// Quick validation that a suggested command looks executable on this system.
@@ -406,10 +523,10 @@ impl AIAssistant {
         }
     }
 
-    pub async fn generate_command(&self, natural_input: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    pub async fn generate_command(&self, natural_input: &str) -> Result<String, AiError> {
         // First check if input is gibberish
-        if Self::is_gibberish(natural_input) {
-            return Err("I don't understand that input. Please provide a clear command or natural language request.".into());
+        if self.gibberish_rules.is_gibberish(natural_input) {
+            return Err(AiError::NotUnderstood);
         }
 
         // Check local commands first (INSTANT responses)
@@ -422,6 +539,26 @@ impl AIAssistant {
             return Ok(cached_command);
         }
 
+        let result = Self::fetch_command(&self.client, &self.model, natural_input, self.request_timeout).await.0;
+        if let (false, Ok(command)) = (self.history_filter.is_private(natural_input), &result) {
+            // Nobody to tell here; the answer is still good, it just won't be remembered
+            let _ = Self::cache_response(&self.cache, natural_input, command);
+        }
+        result
+    }
+
+    /// Ask the model for a command. Also returns the tokens used, which count even when
+    /// the answer isn't usable.
+    async fn fetch_command(client: &reqwest::Client, model: &str, natural_input: &str, timeout_after: Duration) -> (Result<String, AiError>, Option<Usage>) {
+        let (answer, usage) = match Self::ask_model(client, model, natural_input, timeout_after).await {
+            Ok(reply) => reply,
+            Err(e) => return (Err(e), None),
+        };
+        (Self::check_answer(&answer, natural_input), usage)
+    }
+
+    /// The model's raw answer to `natural_input`, with the tokens it took
+    async fn ask_model(client: &reqwest::Client, model: &str, natural_input: &str, timeout_after: Duration) -> Result<(String, Option<Usage>), AiError> {
         // Ultra-clear prompt with direct pattern matching for accurate command generation
         let prompt = format!(
             "Convert natural language to Linux command. Return ONLY the command.
@@ -448,7 +585,7 @@ Output:",
         );
 
         let request = OpenRouterRequest {
-            model: "meta-llama/llama-3.2-3b-instruct:free".to_string(), // Using a good model for command generation
+            model: model.to_string(),
             messages: vec![Message {
                 role: "user".to_string(),
                 content: prompt,
@@ -457,81 +594,97 @@ Output:",
             temperature: Some(0.1), // Low temperature for consistent command generation
         };
 
-        let api_key = get_openrouter_api_key().map_err(|e| e)?;
+        let api_key = get_openrouter_api_key()?;
         let url = OPENROUTER_URL.to_string();
 
-        // Increased timeout for better accuracy
-        let response = timeout(Duration::from_secs(10),
-            self.client
+        let response = timeout(timeout_after,
+            client
                 .post(&url)
                 .header("Authorization", format!("Bearer {}", api_key))
                 .header("Content-Type", "application/json")
                 .json(&request)
                 .send()
-        ).await??;
+        ).await.map_err(|_| AiError::Timeout)??;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or("failed to get response body".to_string());
-            return Err(format!("API error: {} - {}", status, body).into());
+            return Err(AiError::Api { status, body });
         }
 
         let openrouter_response: OpenRouterResponse = response.json().await?;
 
         // Extract first choice text
-        let command = if let Some(choice) = openrouter_response.choices.first() {
-            choice.message.content.trim().to_string()
-        } else { String::new() };
+        let answer = openrouter_response.choices.first().map_or("", |choice| choice.message.content.as_str());
+        Ok((answer.to_string(), openrouter_response.usage))
+    }
 
+    /// The command in the model's answer, or why there isn't a usable one
+    fn check_answer(answer: &str, natural_input: &str) -> Result<String, AiError> {
         // Clean up the response - remove markdown formatting if present
-        let command = command.trim_start_matches("```bash").trim_start_matches("```").trim_end_matches("```").trim().to_string();
+        let command = answer.trim().trim_start_matches("```bash").trim_start_matches("```").trim_end_matches("```").trim();
 
-        // Check if AI responded that it doesn't understand
-        if command == "I_DONT_UNDERSTAND" {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
-        }
-
-        // Validate the response - make sure it's not the same as input
-        if command == natural_input.trim() {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
+        // Check if AI responded that it doesn't understand, or just repeated the input
+        if command == "I_DONT_UNDERSTAND" || command == natural_input.trim() {
+            return Err(AiError::NotUnderstood);
         }
 
         // Basic validation - check if response looks like a command
         if command.is_empty() || command.len() > 200 || !command.chars().any(|c| c.is_alphanumeric()) {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
+            return Err(AiError::InvalidCommand);
         }
 
         // Stronger validation: ensure first token is a known/builtin or executable in PATH
-        if !Self::looks_like_valid_command(&command) {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
+        if !Self::looks_like_valid_command(command) {
+            return Err(AiError::InvalidCommand);
         }
 
-        // Cache successful response
-        self.cache_response(natural_input, &command);
-
-        return Ok(command.to_string());
+        Ok(command.to_string())
     }
 
-    pub fn request_command_async(&self, input: String) {
+    /// Generate a command on `runtime` without blocking the caller, giving up after `timeout_after`.
+    /// The outcome arrives as an `AIResponse` on `receiver`.
+    pub fn request_command_async(&self, runtime: &tokio::runtime::Handle, input: String, timeout_after: Duration) {
         let sender = self.sender.clone();
+
+        // Gibberish, local and cached answers need no network round trip
+        let instant = if self.gibberish_rules.is_gibberish(&input) {
+            Some(Err(AiError::NotUnderstood))
+        } else {
+            self.get_local_command(&input)
+                .or_else(|| self.get_cached_response(&input))
+                .map(Ok)
+        };
+        if let Some(result) = instant {
+            let _ = sender.send(AIResponse { input, result, usage: None, cache_error: None });
+            return;
+        }
+
         let client = self.client.clone();
-        
-        tokio::spawn(async move {
-            match Self::generate_command_static(&client, &input).await {
-                Ok(command) => {
-                    let _ = sender.send(command);
-                }
-                Err(_) => {
-                    // Silently fail - don't spam with errors
+        let model = self.model.clone();
+        // Requests that look like they hold a secret are never remembered
+        let cache = (!self.history_filter.is_private(&input)).then(|| Arc::clone(&self.cache));
+        runtime.spawn(async move {
+            let (result, usage) = Self::fetch_command(&client, &model, &input, timeout_after).await;
+            let cache_error = match (cache, &result) {
+                (Some(cache), Ok(command)) => {
+                    // Writing the file blocks, so it happens off the runtime's workers
+                    let (input, command) = (input.clone(), command.clone());
+                    tokio::task::spawn_blocking(move || Self::cache_response(&cache, &input, &command))
+                        .await
+                        .ok()
+                        .flatten()
                 }
-            }
+                _ => None,
+            };
+            let _ = sender.send(AIResponse { input, result, usage, cache_error });
         });
     }
 
-    pub async fn generate_command_static(client: &reqwest::Client, natural_input: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    pub async fn generate_command_static(client: &reqwest::Client, natural_input: &str, timeout_after: Duration) -> Result<String, AiError> {
         // First check if input is gibberish
         if Self::is_gibberish(natural_input) {
-            return Err("I don't understand that input. Please provide a clear command or natural language request.".into());
+            return Err(AiError::NotUnderstood);
         }
 
         let prompt = format!(
@@ -590,7 +743,7 @@ Command:",
         );
 
         let request = OpenRouterRequest {
-            model: "meta-llama/llama-3.2-3b-instruct:free".to_string(), // Using a good model for command generation
+            model: MODEL.to_string(),
             messages: vec![Message {
                 role: "user".to_string(),
                 content: prompt,
@@ -599,55 +752,93 @@ Command:",
             temperature: Some(0.1), // Low temperature for consistent command generation
         };
 
-        let api_key = get_openrouter_api_key().map_err(|e| e)?;
+        let api_key = get_openrouter_api_key()?;
         let url = OPENROUTER_URL.to_string();
 
-        // Increased timeout for better accuracy
-        let response = timeout(Duration::from_secs(10),
+        let response = timeout(timeout_after,
             client
                 .post(&url)
                 .header("Authorization", format!("Bearer {}", api_key))
                 .header("Content-Type", "application/json")
                 .json(&request)
                 .send()
-        ).await??;
+        ).await.map_err(|_| AiError::Timeout)??;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or("failed to get response body".to_string());
-            return Err(format!("API error: {} - {}", status, body).into());
+            return Err(AiError::Api { status, body });
         }
 
         let openrouter_response: OpenRouterResponse = response.json().await?;
 
         // Extract first choice text
-        let mut command = String::new();
-        if let Some(choice) = openrouter_response.choices.first() {
-            command = choice.message.content.trim().to_string();
-        }
-        // Clean up the response - remove markdown formatting if present
-        let command = command.trim_start_matches("```bash").trim_start_matches("```").trim_end_matches("```").trim();
+        let answer = openrouter_response.choices.first().map_or("", |choice| choice.message.content.as_str());
+        Self::check_answer(answer, natural_input)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn questions_with_a_clear_verb_are_not_gibberish() {
+        let rules = GibberishRules::default();
+        assert!(!rules.is_gibberish("how do I list files"));
+        assert!(!rules.is_gibberish("how to compress a folder"));
+        assert!(!rules.is_gibberish("what process should I kill"));
+    }
 
-        // Check if AI responded that it doesn't understand
-        if command == "I_DONT_UNDERSTAND" {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
-        }
+    #[test]
+    fn keyboard_mashing_is_gibberish() {
+        let rules = GibberishRules::default();
+        assert!(rules.is_gibberish("asdkjasd"));
+        assert!(rules.is_gibberish("aaaaaa"));
+        assert!(rules.is_gibberish("how hello"));
+        assert!(rules.is_gibberish("what"));
+    }
 
-        // Validate the response - make sure it's not the same as input
-        if command == natural_input.trim() {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
-        }
+    #[test]
+    fn custom_word_lists_replace_the_defaults() {
+        let rules = GibberishRules {
+            meaningful_words: vec!["kubernetes".to_string()],
+            incoherent_patterns: vec!["foo bar".to_string()],
+        };
+        assert!(!rules.is_gibberish("what kubernetes"));
+        assert!(rules.is_gibberish("foo bar baz qux"));
+        // "hello world" is only incoherent in the default list
+        assert!(!rules.is_gibberish("hello world again today"));
+    }
 
-        // Basic validation - check if response looks like a command
-        if command.is_empty() || command.len() > 200 || !command.chars().any(|c| c.is_alphanumeric()) {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
-        }
+    #[test]
+    fn answers_are_unwrapped_and_checked() {
+        assert_eq!(AIAssistant::check_answer("```bash\ncd /tmp\n```", "go to tmp").unwrap(), "cd /tmp");
+        assert!(matches!(AIAssistant::check_answer("I_DONT_UNDERSTAND", "qwe"), Err(AiError::NotUnderstood)));
+        assert!(matches!(AIAssistant::check_answer("say hi", "say hi"), Err(AiError::NotUnderstood)));
+        assert!(matches!(AIAssistant::check_answer("", "list files"), Err(AiError::InvalidCommand)));
+        assert!(matches!(AIAssistant::check_answer("no-such-program-here --x", "list files"), Err(AiError::InvalidCommand)));
+    }
 
-        // Stronger validation: ensure first token is a known/builtin or executable in PATH
-        if !Self::looks_like_valid_command(&command) {
-            return Err("I don't understand that request. Please try rephrasing your command.".into());
-        }
+    #[test]
+    fn usage_is_read_from_the_response() {
+        let json = r#"{"choices": [{"message": {"role": "assistant", "content": "ls"}}], "usage": {"prompt_tokens": 312, "completion_tokens": 2, "total_tokens": 314}}"#;
+        let response: OpenRouterResponse = serde_json::from_str(json).unwrap();
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.to_string(), "312 in / 2 out");
+
+        let mut total = Usage::default();
+        total.add(usage);
+        total.add(usage);
+        assert_eq!(total, Usage { prompt_tokens: 624, completion_tokens: 4 });
 
-        return Ok(command.to_string());
+        let without: OpenRouterResponse = serde_json::from_str(r#"{"choices": []}"#).unwrap();
+        assert!(without.usage.is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn errors_describe_themselves() {
+        assert_eq!(AiError::Timeout.to_string(), "the AI didn't answer in time");
+        let api = AiError::Api { status: reqwest::StatusCode::UNAUTHORIZED, body: "bad key".to_string() };
+        assert!(api.to_string().contains("401"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+// Commands the AI gave for natural-language requests, kept on disk so a request
+// like "list files" is answered without a network round trip in later runs too.
+// The app loads it once and every tab shares it, so tabs don't overwrite each
+// other's answers.
+// Entries are only trusted for a while, since the answer to "update the system"
+// may change along with the system. Inputs the history filter treats as private
+// never get here.
+// The file is written after the shared cache is unlocked again, so a lookup from
+// the UI never waits on the disk.
+
+/// How long an answer is reused, counted from when the AI gave it
+pub const TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+// Oldest entries are dropped once the cache grows past this, which keeps the file small
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub command: String,
+    pub saved: u64, // Unix time the AI answered
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AiCache {
+    pub entries: HashMap<String, CacheEntry>,
+    #[serde(skip)]
+    path: Option<PathBuf>, // Where inserts are written; None keeps the cache in memory
+    #[serde(skip)]
+    generation: u64, // Inserts so far, to tell snapshots apart
+    #[serde(skip)]
+    written: Arc<Mutex<u64>>, // Generation of the snapshot last written, held while writing
+}
+
+/// The cache as it was after an insert, to write out once the cache is unlocked
+pub struct Snapshot {
+    path: PathBuf,
+    json: String,
+    generation: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl AiCache {
+    pub fn path() -> PathBuf {
+        crate::state::cache_dir().join("ai_cache.json")
+    }
+
+    /// The cache saved at `path`, without entries that have expired since
+    pub fn load(path: &Path, now: u64) -> Self {
+        let mut cache: Self = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        cache.entries.retain(|_, entry| !entry.expired(now));
+        cache.path = Some(path.to_path_buf());
+        cache
+    }
+
+    pub fn get(&self, input: &str, now: u64) -> Option<&str> {
+        self.entries.get(input)
+            .filter(|entry| !entry.expired(now))
+            .map(|entry| entry.command.as_str())
+    }
+
+    /// Remember `command` as the answer to `input`. Returns what to write to the file,
+    /// unless the cache is only kept in memory.
+    pub fn insert(&mut self, input: &str, command: &str, now: u64) -> Option<Snapshot> {
+        self.entries.insert(input.to_string(), CacheEntry { command: command.to_string(), saved: now });
+        while self.entries.len() > MAX_ENTRIES {
+            match self.entries.iter().min_by_key(|(_, entry)| entry.saved).map(|(input, _)| input.clone()) {
+                Some(oldest) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+        self.generation += 1;
+        Some(Snapshot {
+            path: self.path.clone()?,
+            json: serde_json::to_string(self).ok()?,
+            generation: self.generation,
+            written: Arc::clone(&self.written),
+        })
+    }
+
+    /// Keep the cache in memory only from now on, after writing it failed
+    pub fn stop_saving(&mut self) {
+        self.path = None;
+    }
+}
+
+impl Snapshot {
+    /// Write the cache file, unless a later snapshot has already been written
+    pub fn write(&self) -> std::io::Result<()> {
+        let mut written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+        if *written >= self.generation {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, &self.json)?;
+        *written = self.generation;
+        Ok(())
+    }
+}
+
+impl CacheEntry {
+    fn expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.saved) >= TTL_SECS
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_expire() {
+        let mut cache = AiCache::default();
+        assert!(cache.insert("list files", "ls", 1000).is_none());
+        assert_eq!(cache.get("list files", 1000 + TTL_SECS - 1), Some("ls"));
+        assert_eq!(cache.get("list files", 1000 + TTL_SECS), None);
+        assert_eq!(cache.get("show disk usage", 1000), None);
+    }
+
+    #[test]
+    fn oldest_answers_are_dropped_first() {
+        let mut cache = AiCache::default();
+        for i in 0..=MAX_ENTRIES as u64 {
+            cache.insert(&format!("request {}", i), "true", i);
+        }
+        assert_eq!(cache.entries.len(), MAX_ENTRIES);
+        assert_eq!(cache.get("request 0", MAX_ENTRIES as u64), None);
+        assert_eq!(cache.get("request 1", MAX_ENTRIES as u64), Some("true"));
+    }
+
+    #[test]
+    fn answers_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("linara-ai-cache-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut cache = AiCache::load(&path, 0);
+        cache.insert("list files", "ls", 100).unwrap().write().unwrap();
+        cache.insert("old request", "true", 0).unwrap().write().unwrap();
+
+        let reloaded = AiCache::load(&path, TTL_SECS + 50);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reloaded.get("list files", TTL_SECS + 50), Some("ls"));
+        assert!(!reloaded.entries.contains_key("old request"));
+    }
+
+    #[test]
+    fn a_later_snapshot_is_not_overwritten() {
+        let path = std::env::temp_dir().join(format!("linara-ai-cache-order-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut cache = AiCache::load(&path, 0);
+        let first = cache.insert("list files", "ls", 100).unwrap();
+        let second = cache.insert("show disk usage", "df -h", 100).unwrap();
+        second.write().unwrap();
+        first.write().unwrap();
+
+        let reloaded = AiCache::load(&path, 100);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(reloaded.get("show disk usage", 100), Some("df -h"));
+    }
+
+    #[test]
+    fn a_failed_save_is_reported_once() {
+        let mut cache = AiCache::load(Path::new("/dev/null/ai_cache.json"), 0);
+        assert!(cache.insert("list files", "ls", 100).unwrap().write().is_err());
+        cache.stop_saving();
+        assert!(cache.insert("show disk usage", "df -h", 100).is_none());
+        assert_eq!(cache.get("list files", 100), Some("ls"));
+    }
+}
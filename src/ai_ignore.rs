@@ -0,0 +1,48 @@
+use regex::Regex;
+
+// Command names that are never handed to the AI when they aren't found, from
+// `ai_ignore = <name>` lines in the config. A typo of a real command (`grpe`) then
+// just reports "command not found" instead of the AI guessing what was meant, while
+// everything else still falls back to it. A value is a command name, or a regex the
+// whole name must match (`ai_ignore = g(it|ti|tt)`).
+
+#[derive(Debug, Clone, Default)]
+pub struct AiIgnore {
+    patterns: Vec<Regex>,
+}
+
+impl AiIgnore {
+    /// Also keep names matching `pattern` from the AI
+    pub fn add(&mut self, pattern: &str) -> Result<(), String> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern.trim())).map_err(|e| format!("ai_ignore: {}", e))?;
+        self.patterns.push(regex);
+        Ok(())
+    }
+
+    /// Whether a missing `name` should be reported rather than sent to the AI
+    pub fn ignores(&self, name: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_patterns_match_the_whole_name() {
+        let mut ignore = AiIgnore::default();
+        ignore.add("grpe").unwrap();
+        ignore.add(" g(it|ti|tt) ").unwrap();
+        assert!(ignore.ignores("grpe"));
+        assert!(ignore.ignores("gti"));
+        assert!(!ignore.ignores("grep"));
+        assert!(!ignore.ignores("gitk"));
+        assert!(ignore.add("(").is_err());
+    }
+
+    #[test]
+    fn nothing_is_ignored_by_default() {
+        assert!(!AiIgnore::default().ignores("grpe"));
+    }
+}
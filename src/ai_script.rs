@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+// `ai-run <file>`: a setup script written in plain English, one request per line.
+// Each request goes to the AI like a typed one; the command it comes back with is
+// shown and runs once confirmed. The script stops at the first request that fails,
+// can't be resolved or is declined, unless it was started with `-k`.
+
+/// One request from the script, with its line number for messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub line: usize,
+    pub request: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub keep_going: bool, // -k: carry on after a step fails
+    pub yes: bool, // -y: run resolved commands without asking, unless the guard flags them
+}
+
+#[derive(Debug)]
+pub struct AiScript {
+    pub file: String, // As given, for messages
+    pub steps: VecDeque<Step>, // Still to be resolved
+    pub current: Option<Step>, // Being resolved, or waiting for y/n
+    pub options: Options,
+    pub ran: usize,
+    pub resolved: usize, // Answered but not run, in dry-run mode
+    pub failed: usize,
+}
+
+impl AiScript {
+    pub fn new(file: &str, content: &str, options: Options) -> Self {
+        Self { file: file.to_string(), steps: parse(content), current: None, options, ran: 0, resolved: 0, failed: 0 }
+    }
+
+    /// `file:line` of the current step
+    pub fn location(&self) -> String {
+        match &self.current {
+            Some(step) => format!("{}:{}", self.file, step.line),
+            None => self.file.clone(),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let total = self.ran + self.resolved + self.failed + self.steps.len() + usize::from(self.current.is_some());
+        let mut summary = format!("ai-run: {}: {} of {} requests ran", self.file, self.ran, total);
+        if self.resolved > 0 {
+            summary.push_str(&format!(", {} resolved without running (dry run)", self.resolved));
+        }
+        if self.failed > 0 {
+            summary.push_str(&format!(", {} failed", self.failed));
+        }
+        summary
+    }
+}
+
+/// The requests in a script: every line that isn't blank or a `#` comment
+pub fn parse(content: &str) -> VecDeque<Step> {
+    content.lines()
+        .enumerate()
+        .map(|(index, line)| Step { line: index + 1, request: line.trim().to_string() })
+        .filter(|step| !step.request.is_empty() && !step.request.starts_with('#'))
+        .collect()
+}
+
+/// The options and file from `ai-run`'s arguments
+pub fn parse_args(args: &[String]) -> Result<(Options, &str), String> {
+    let mut options = Options::default();
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "-k" | "--keep-going" => options.keep_going = true,
+            "-y" | "--yes" => options.yes = true,
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("{}: invalid option", flag)),
+            _ if file.is_some() => return Err("too many arguments".to_string()),
+            path => file = Some(path),
+        }
+    }
+    file.map(|file| (options, file)).ok_or_else(|| "filename argument required".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let steps = parse("# set up the project\n\ncreate a folder called build\n  install the dependencies  \n");
+        assert_eq!(steps, [
+            Step { line: 3, request: "create a folder called build".to_string() },
+            Step { line: 4, request: "install the dependencies".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn options_come_before_or_after_the_file() {
+        assert_eq!(parse_args(&args("setup.txt")).unwrap(), (Options::default(), "setup.txt"));
+        assert_eq!(parse_args(&args("-k setup.txt -y")).unwrap(), (Options { keep_going: true, yes: true }, "setup.txt"));
+        assert!(parse_args(&args("-x setup.txt")).is_err());
+        assert!(parse_args(&args("a.txt b.txt")).is_err());
+        assert!(parse_args(&[]).is_err());
+    }
+
+    #[test]
+    fn summary_counts_every_request() {
+        let mut script = AiScript::new("setup.txt", "one\ntwo\nthree\n", Options::default());
+        script.steps.pop_front();
+        script.ran = 1;
+        script.current = script.steps.pop_front();
+        assert_eq!(script.location(), "setup.txt:2");
+        assert_eq!(script.summary(), "ai-run: setup.txt: 1 of 3 requests ran");
+        script.current = None;
+        script.failed = 1;
+        assert_eq!(script.summary(), "ai-run: setup.txt: 1 of 3 requests ran, 1 failed");
+
+        let mut dry_run = AiScript::new("setup.txt", "one\ntwo\nthree\n", Options::default());
+        dry_run.steps.clear();
+        dry_run.resolved = 2;
+        dry_run.failed = 1;
+        assert_eq!(dry_run.summary(), "ai-run: setup.txt: 0 of 3 requests ran, 2 resolved without running (dry run), 1 failed");
+    }
+}
@@ -0,0 +1,69 @@
+use std::process::{Command, Stdio};
+
+// Programs write BEL (`\x07`) to get the user's attention: a build that finished,
+// a mention in an IRC client. It is taken out of the output line it came in and
+// rings the bell instead: a short flash of the pane, or the desktop's bell sound
+// played with `canberra-gtk-play` when that is installed. Either way a window in
+// the background asks for attention too.
+
+pub const BEL: char = '\x07';
+
+/// What a BEL does, from `bell = visual | audible | off`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bell {
+    Visual,
+    Audible,
+    Off,
+}
+
+impl Bell {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "visual" | "flash" => Some(Bell::Visual),
+            "audible" | "sound" => Some(Bell::Audible),
+            "off" | "none" => Some(Bell::Off),
+            _ => None,
+        }
+    }
+}
+
+/// `text` without its BEL characters, and whether it had any
+pub fn strip(text: &str) -> (String, bool) {
+    match text.contains(BEL) {
+        true => (text.replace(BEL, ""), true),
+        false => (text.to_string(), false),
+    }
+}
+
+/// Play the desktop's bell sound; does nothing without `canberra-gtk-play`
+pub fn play() {
+    let child = Command::new("canberra-gtk-play")
+        .args(["--id=bell", "--description=Linara bell"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Ok(mut child) = child {
+        // Reap it off the UI thread
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bells_are_taken_out_of_the_line() {
+        assert_eq!(strip("build finished\x07"), ("build finished".to_string(), true));
+        assert_eq!(strip("\x07\x07"), (String::new(), true));
+        assert_eq!(strip("plain output"), ("plain output".to_string(), false));
+    }
+
+    #[test]
+    fn bell_modes_parse() {
+        assert_eq!(Bell::parse("Audible"), Some(Bell::Audible));
+        assert_eq!(Bell::parse("off"), Some(Bell::Off));
+        assert_eq!(Bell::parse("loud"), None);
+    }
+}
@@ -0,0 +1,358 @@
+// Commands Linara handles itself instead of running a program.
+//
+// execute_command looks builtins up here and `help` is generated from this table,
+// so adding an entry (and its handler on Session) is all a new builtin needs.
+// The caller records the command on the prompt line before the handler runs,
+// and shows the next prompt and clears the input afterwards. At the head of a
+// pipeline, everything a handler prints is captured instead of shown.
+
+use crate::Session;
+
+/// A builtin as it was typed
+pub struct Call<'a> {
+    /// The name used, which may be an alias
+    pub name: &'a str,
+    pub args: &'a [String],
+    /// The whole command line
+    pub command: &'a str,
+}
+
+/// What the caller should do once a handler returns
+pub enum Flow {
+    /// Show a fresh prompt
+    Prompt,
+    /// The handler already showed one, or there shouldn't be one (`exit`)
+    Done,
+}
+
+pub(crate) type Handler = fn(&mut Session, &Call) -> Flow;
+
+pub struct Builtin {
+    /// The first name is the one `help` lists; the rest are aliases
+    pub names: &'static [&'static str],
+    pub usage: &'static str,
+    pub summary: &'static str,
+    /// Longer text for `help <builtin>`, one entry per line
+    pub details: &'static [&'static str],
+    /// Whether it can start a pipeline (`history | grep git`): it only prints, and its
+    /// output is captured and fed to the rest of the pipeline
+    pub pipes: bool,
+    pub(crate) run: Handler,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        names: &["help"],
+        usage: "help [builtin]",
+        summary: "List builtins, or describe one",
+        details: &["Without an argument, lists every builtin with a one-line summary."],
+        pipes: true,
+        run: Session::builtin_help,
+    },
+    Builtin {
+        names: &["cd"],
+        usage: "cd [dir | -]",
+        summary: "Change directory",
+        details: &[
+            "With no argument, goes to your home directory; `cd -` returns to the previous one.",
+            "A plain name that doesn't exist here is looked up like `j <name>`.",
+        ],
+        pipes: false,
+        run: Session::builtin_cd,
+    },
+    Builtin {
+        names: &["j"],
+        usage: "j <query>...",
+        summary: "Jump to a frequently used directory",
+        details: &[
+            "Picks the most frequently and recently visited directory whose path",
+            "contains the query words in order, e.g. `j proj api`.",
+        ],
+        pipes: false,
+        run: Session::builtin_j,
+    },
+    Builtin {
+        names: &["pwd"],
+        usage: "pwd [-L | -P]",
+        summary: "Print the current directory",
+        details: &[
+            "Shows the path as you got there, through any symlinks, like the prompt does;",
+            "`-P` shows it with symlinks resolved instead.",
+        ],
+        pipes: true,
+        run: Session::builtin_pwd,
+    },
+    Builtin {
+        names: &["pushd"],
+        usage: "pushd [dir]",
+        summary: "Change directory, saving the current one on the stack",
+        details: &["With no argument, swaps the current directory with the top of the stack."],
+        pipes: false,
+        run: Session::builtin_pushd,
+    },
+    Builtin {
+        names: &["popd"],
+        usage: "popd",
+        summary: "Return to the directory on top of the stack",
+        details: &[],
+        pipes: false,
+        run: Session::builtin_popd,
+    },
+    Builtin {
+        names: &["dirs"],
+        usage: "dirs",
+        summary: "Show the directory stack",
+        details: &[],
+        pipes: true,
+        run: Session::builtin_dirs,
+    },
+    Builtin {
+        names: &["history"],
+        usage: "history [-c | -d offset]",
+        summary: "List previous commands, or remove them",
+        details: &[
+            "Use the Up and Down keys to bring one back.",
+            "`-c` clears the history and `-d N` deletes entry N as listed (`-d -1` is the newest);",
+            "the saved session is updated straight away.",
+        ],
+        pipes: true,
+        run: Session::builtin_history,
+    },
+    Builtin {
+        names: &["jobs"],
+        usage: "jobs",
+        summary: "List background jobs",
+        details: &["Add `&` to the end of a command to run it in the background."],
+        pipes: true,
+        run: Session::builtin_jobs,
+    },
+    Builtin {
+        names: &["fg"],
+        usage: "fg [%job]",
+        summary: "Bring a background job to the foreground",
+        details: &["When a job in the foreground asks for a password, what you type is masked and sent to it."],
+        pipes: false,
+        run: Session::builtin_fg,
+    },
+    Builtin {
+        names: &["bg"],
+        usage: "bg [%job]",
+        summary: "Resume a stopped job in the background",
+        details: &[],
+        pipes: false,
+        run: Session::builtin_bg,
+    },
+    Builtin {
+        names: &["kill"],
+        usage: "kill [-signal | -s signal | -l] <pid | %job>...",
+        summary: "Send a signal to processes or jobs",
+        details: &[
+            "Signals can be given by name or number (`-TERM`, `-HUP`, `-9`); the default is TERM.",
+            "`kill -l` lists the signal names.",
+        ],
+        pipes: true,
+        run: Session::builtin_kill,
+    },
+    Builtin {
+        names: &["touch"],
+        usage: "touch <file>...",
+        summary: "Create empty files, or update their modification time",
+        details: &["Paths are relative to the current directory; each file that can't be touched is reported."],
+        pipes: false,
+        run: Session::builtin_touch,
+    },
+    Builtin {
+        names: &["watch"],
+        usage: "watch [-n seconds] <command>",
+        summary: "Rerun a command every few seconds, showing only its latest output",
+        details: &[
+            "The default interval is 2 seconds. Press q, Esc or Ctrl+C to stop.",
+            "The command runs with sh, so pipes work: `watch -n 1 ps aux | grep cargo`.",
+        ],
+        pipes: false,
+        run: Session::builtin_watch,
+    },
+    Builtin {
+        names: &["copy"],
+        usage: "copy",
+        summary: "Copy the previous command's output to the clipboard",
+        details: &["Ctrl+Shift+O does the same from the input line."],
+        pipes: false,
+        run: Session::builtin_copy,
+    },
+    Builtin {
+        names: &["time"],
+        usage: "time <command>",
+        summary: "Run a command and report how long it took",
+        details: &["Prints real, user and sys time like bash's `time`."],
+        pipes: false,
+        run: Session::builtin_time,
+    },
+    Builtin {
+        names: &["json-log"],
+        usage: "json-log [path | off]",
+        summary: "Append each command's result to a file as JSON",
+        details: &[
+            "Every program run adds one line: {\"command\", \"stdout\", \"stderr\", \"exit_code\", \"duration_ms\"}.",
+            "Builtins and commands kept out of history are not logged. With no argument, shows where logging goes.",
+        ],
+        pipes: false,
+        run: Session::builtin_json_log,
+    },
+    Builtin {
+        names: &["source", "."],
+        usage: "source <file>",
+        summary: "Run each line of a file as a command",
+        details: &[
+            "Blank lines and `#` comments are skipped.",
+            "~/.linararc is sourced at startup.",
+        ],
+        pipes: false,
+        run: Session::builtin_source,
+    },
+    Builtin {
+        names: &["explain", "whatis", "what"],
+        usage: "explain <command>",
+        summary: "Explain what a command does in plain words",
+        details: &[
+            "`what is <command>` and `what does <command> do` work too.",
+            "Add or change entries in ~/.config/linara/explain.json: {\"mytool\": \"what it does\"}.",
+            "Commands with no entry are looked up with tldr or whatis when installed.",
+        ],
+        pipes: true,
+        run: Session::builtin_explain,
+    },
+    Builtin {
+        names: &["examples"],
+        usage: "examples <command>",
+        summary: "Show common ways to use a command",
+        details: &[
+            "Press the example's number straight afterwards to put it in the input line.",
+            "Commands without built-in examples are looked up with tldr when it is installed.",
+        ],
+        pipes: true,
+        run: Session::builtin_examples,
+    },
+    Builtin {
+        names: &["type"],
+        usage: "type <name>...",
+        summary: "Say whether a name is a builtin, a macro or a program, and where the program is",
+        details: &["Names are looked up the way a command would be: builtins first, then macros, then PATH."],
+        pipes: true,
+        run: Session::builtin_type,
+    },
+    Builtin {
+        names: &["ai"],
+        usage: "ai [on | off]",
+        summary: "Turn the AI fallback for unknown commands on or off",
+        details: &[
+            "With AI off nothing is sent over the network; an unknown command",
+            "reports \"command not found\" and suggests the closest known command.",
+            "Set `ai = off` in the config file to start with it off.",
+            "To keep only some names from the AI, such as typos of real commands, add `ai_ignore = <name>` lines.",
+        ],
+        pipes: false,
+        run: Session::builtin_ai,
+    },
+    Builtin {
+        names: &["macro"],
+        usage: "macro [<name> = <request> | -d <name>]",
+        summary: "Name a natural-language request so typing the name runs it",
+        details: &[
+            "e.g. `macro deploy = build and push the docker image`, then type `deploy`.",
+            "The AI resolves the request the first time; the command it gives is saved and reused.",
+            "Without arguments, lists the macros. `macro <name> = <request>` lines in the config work too.",
+        ],
+        pipes: true,
+        run: Session::builtin_macro,
+    },
+    Builtin {
+        names: &["ai-run"],
+        usage: "ai-run [-k] [-y] <file>",
+        summary: "Resolve and run a file of plain-English requests, one per line",
+        details: &[
+            "Each request is sent to the AI and the command it gives runs once you press y;",
+            "n stops the script. `-y` runs commands without asking, except ones the AI guard flags.",
+            "The script stops at the first failing step unless `-k` is given. With `ai-dryrun on`",
+            "the commands are only shown. Blank lines and `#` comments are skipped.",
+        ],
+        pipes: false,
+        run: Session::builtin_ai_run,
+    },
+    Builtin {
+        names: &["ai-dryrun"],
+        usage: "ai-dryrun [on | off]",
+        summary: "Review AI-suggested commands before they run",
+        details: &["With no argument, shows whether dry run is on."],
+        pipes: false,
+        run: Session::builtin_ai_dryrun,
+    },
+    Builtin {
+        names: &["neofetch", "sysinfo"],
+        usage: "neofetch",
+        summary: "Show system information",
+        details: &[],
+        pipes: true,
+        run: Session::builtin_neofetch,
+    },
+    Builtin {
+        names: &["clear", "cls"],
+        usage: "clear",
+        summary: "Clear the screen",
+        details: &["Ctrl+L does the same."],
+        pipes: false,
+        run: Session::builtin_clear,
+    },
+    Builtin {
+        names: &["reset"],
+        usage: "reset",
+        summary: "Clear the screen and put the terminal back to its defaults",
+        details: &[
+            "Besides clearing, goes back to the configured font size, stops paging, `watch` and the",
+            "output filter, closes pickers, drops pending questions and the selection, and scrolls to the bottom.",
+        ],
+        pipes: false,
+        run: Session::builtin_reset,
+    },
+    Builtin {
+        names: &["exit"],
+        usage: "exit",
+        summary: "Close this tab",
+        details: &["The window closes with the last tab."],
+        pipes: false,
+        run: Session::builtin_exit,
+    },
+];
+
+/// The builtin called `name`, by its main name or an alias
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.names.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_are_found_by_alias() {
+        assert_eq!(find(".").map(|b| b.names[0]), Some("source"));
+        assert_eq!(find("whatis").map(|b| b.names[0]), Some("explain"));
+        assert!(find("ls").is_none());
+    }
+
+    #[test]
+    fn builtins_that_change_state_do_not_pipe() {
+        assert!(find("history").is_some_and(|b| b.pipes));
+        assert!(!find("cd").is_some_and(|b| b.pipes));
+        assert!(!find("watch").is_some_and(|b| b.pipes));
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let mut names: Vec<&str> = BUILTINS.iter().flat_map(|b| b.names.iter().copied()).collect();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), count);
+    }
+}
@@ -0,0 +1,225 @@
+// Arithmetic for `=` lines (`=2+2*10`).
+//
+// A small recursive-descent parser over f64:
+//   expr   = term (('+' | '-') term)*
+//   term   = unary (('*' | '/' | '%') unary)*
+//   unary  = '-' unary | '+' unary | power
+//   power  = atom ('^' unary)?            -- right associative, so -2^2 is -4
+//   atom   = number | constant | function '(' expr ')' | '(' expr ')'
+
+/// Evaluate an arithmetic expression
+pub fn evaluate(input: &str) -> Result<f64, String> {
+    let mut parser = Parser { chars: input.chars().filter(|c| !c.is_whitespace()).collect(), pos: 0 };
+    if parser.chars.is_empty() {
+        return Err("empty expression".to_string());
+    }
+    let value = parser.expr()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{}'", c)),
+    }
+}
+
+/// Show a result without float noise: `4`, `0.5`, `0.3` rather than `0.30000000000000004`
+pub fn format_number(value: f64) -> String {
+    if value.is_finite() && value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    let rounded = format!("{:.10}", value);
+    let trimmed = rounded.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" { "0".to_string() } else { trimmed.to_string() }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            return Ok(-self.unary()?);
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            let exponent = self.unary()?;
+            return Ok(base.powf(exponent));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.name(),
+            Some(c) => Err(format!("unexpected '{}'", c)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        // Scientific notation: 1e3, 2.5E-4
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let mark = self.pos;
+            self.pos += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos = mark;
+            }
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map_err(|_| format!("invalid number '{}'", text))
+    }
+
+    fn name(&mut self) -> Result<f64, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.as_str() {
+            "pi" => return Ok(std::f64::consts::PI),
+            "e" => return Ok(std::f64::consts::E),
+            _ => {}
+        }
+
+        if !self.eat('(') {
+            return Err(format!("unknown name '{}'", name));
+        }
+        let arg = self.expr()?;
+        if !self.eat(')') {
+            return Err("missing ')'".to_string());
+        }
+        let value = match name.as_str() {
+            "sqrt" if arg < 0.0 => return Err("square root of a negative number".to_string()),
+            "sqrt" => arg.sqrt(),
+            "abs" => arg.abs(),
+            "sin" => arg.sin(),
+            "cos" => arg.cos(),
+            "tan" => arg.tan(),
+            "ln" | "log" | "log10" | "log2" if arg <= 0.0 => return Err(format!("{} of a non-positive number", name)),
+            "ln" => arg.ln(),
+            "log" | "log10" => arg.log10(),
+            "log2" => arg.log2(),
+            "exp" => arg.exp(),
+            "floor" => arg.floor(),
+            "ceil" => arg.ceil(),
+            "round" => arg.round(),
+            _ => return Err(format!("unknown function '{}'", name)),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(input: &str) -> String {
+        evaluate(input).map(format_number).unwrap_or_else(|e| format!("error: {}", e))
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        assert_eq!(calc("2+2*10"), "22");
+        assert_eq!(calc("(2+2)*10"), "40");
+        assert_eq!(calc("2^3^2"), "512");
+        assert_eq!(calc("-2^2"), "-4");
+        assert_eq!(calc("2^-1"), "0.5");
+        assert_eq!(calc("10 % 4 - 1"), "1");
+        assert_eq!(calc("7/2"), "3.5");
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert_eq!(calc("sqrt(16) + abs(-2)"), "6");
+        assert_eq!(calc("round(pi*100)"), "314");
+        assert_eq!(calc("log(1000)"), "3");
+        assert_eq!(calc("1.5e3"), "1500");
+        assert_eq!(calc("0.1+0.2"), "0.3");
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        assert_eq!(calc("1/0"), "error: division by zero");
+        assert_eq!(calc("5 % (2-2)"), "error: division by zero");
+        assert_eq!(calc("(1+2"), "error: missing ')'");
+        assert_eq!(calc("2+"), "error: unexpected end of expression");
+        assert_eq!(calc("(2))"), "error: unexpected ')'");
+        assert_eq!(calc("foo(1)"), "error: unknown function 'foo'");
+        assert_eq!(calc(""), "error: empty expression");
+    }
+}
@@ -0,0 +1,70 @@
+// Command-line options, so launchers and scripts can open Linara on a task:
+// `linara -C ~/project -e cargo build`. Like xterm's -e, everything after
+// `-e` is the command, so it doesn't need quoting.
+
+pub const USAGE: &str = "usage: linara [-C <dir>] [-e <command>...]";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    /// Run this in the first tab once it is shown
+    pub execute: Option<String>,
+    /// Start in this directory instead of the current one
+    pub directory: Option<String>,
+    pub help: bool,
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--execute" => {
+                let command: Vec<String> = args.by_ref().collect();
+                if command.is_empty() {
+                    return Err(format!("{} needs a command", arg));
+                }
+                parsed.execute = Some(command.join(" "));
+            }
+            "-C" | "--directory" => {
+                parsed.directory = Some(args.next().ok_or_else(|| format!("{} needs a directory", arg))?);
+            }
+            "-h" | "--help" => parsed.help = true,
+            _ => {
+                if let Some(command) = arg.strip_prefix("--execute=") {
+                    parsed.execute = Some(command.to_string());
+                } else if let Some(dir) = arg.strip_prefix("--directory=") {
+                    parsed.directory = Some(dir.to_string());
+                } else {
+                    return Err(format!("unknown option '{}'", arg));
+                }
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Args, String> {
+        parse(line.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn command_and_directory_are_parsed() {
+        let args = parse_line("-C /tmp -e cargo build --release").unwrap();
+        assert_eq!(args.directory.as_deref(), Some("/tmp"));
+        assert_eq!(args.execute.as_deref(), Some("cargo build --release"));
+        assert_eq!(parse(["--execute=ls -la".to_string()]).unwrap().execute.as_deref(), Some("ls -la"));
+        assert_eq!(parse_line("").unwrap(), Args::default());
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        assert!(parse_line("-e").is_err());
+        assert!(parse_line("-C").is_err());
+        assert!(parse_line("--frobnicate").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+// Shell-style comments in command input: `ls -la # list everything` runs `ls -la`.
+//
+// A `#` starts a comment only outside quotes and at the start of a word, so
+// `echo "a # b"`, `echo 'x#y'` and `http://x#y` are left alone.
+
+/// The command with any trailing comment removed
+pub fn strip_comment(command: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut word_start = true;
+    for (i, c) in command.char_indices() {
+        if escaped {
+            escaped = false;
+            word_start = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => escaped = true,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') if word_start => return command[..i].trim_end(),
+            (None, _) => {}
+        }
+        word_start = quote.is_none() && c.is_whitespace();
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_comments_are_removed() {
+        assert_eq!(strip_comment("echo hi # bye"), "echo hi");
+        assert_eq!(strip_comment("ls -la #list everything"), "ls -la");
+        assert_eq!(strip_comment("# just a note"), "");
+    }
+
+    #[test]
+    fn quoted_and_mid_word_hashes_are_kept() {
+        assert_eq!(strip_comment("echo \"a # b\""), "echo \"a # b\"");
+        assert_eq!(strip_comment("echo 'a # b' # c"), "echo 'a # b'");
+        assert_eq!(strip_comment("curl http://x#y"), "curl http://x#y");
+        assert_eq!(strip_comment("echo \\# not a comment"), "echo \\# not a comment");
+    }
+}
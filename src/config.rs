@@ -0,0 +1,428 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::ai_assistant::GibberishRules;
+use crate::ai_ignore::AiIgnore;
+use crate::bell::Bell;
+use crate::guard::CommandGuard;
+use crate::history::HistoryFilter;
+use crate::keymap::Keymap;
+
+/// User settings loaded from `~/.config/linara/config`.
+///
+/// The file is a list of `key = value` lines; `#` starts a comment, unless it
+/// is quoted or in the middle of a word. Unknown
+/// keys and unparsable values are ignored so a bad line never blocks startup.
+/// List settings (`meaningful_words`, `incoherent_patterns`) are comma-separated
+/// and replace the built-in list. `bind = <keys> <action>` changes a shortcut
+/// and may be repeated, as may `history_ignore = <regex>`, `ai_deny = <regex>`,
+/// `ai_ignore = <name>` and `macro <name> = <phrase>`.
+pub struct Config {
+    pub scrollback_limit: usize,
+    pub font_size: f32, // `font_size = 18` scales the whole window; 16 is the default
+    pub theme: Theme, // `theme = black`
+    pub cursor_style: CursorStyle, // `cursor_style = bar`
+    pub cursor_blink: Option<Duration>, // `cursor_blink = off`, or `cursor_blink = 800` (ms the cursor stays shown, and hidden)
+    pub fuzzy: bool, // `fuzzy = off` matches suggestions by prefix only
+    pub model: String, // `model = openai/gpt-4o-mini`: the OpenRouter model commands are generated with
+    pub ai_timeout: Duration, // `ai_timeout = <seconds>`
+    pub gibberish: GibberishRules,
+    pub prompt: String, // Template expanded by `prompt::expand`
+    pub classic_prompt: bool, // `classic_prompt = true` echoes each command on a plain `user@host:~$ ls` line with output below
+    pub show_banner: bool, // `show_banner = false` skips the startup system info banner
+    pub status_clock: bool, // `status_clock = off` drops the clock and CPU/memory use from the status bar
+    pub keymap: Keymap, // `bind = ctrl+shift+a select-all`, or `none` to free a key
+    pub line_wrap: LineWrap, // `line_wrap = wrap` or `line_wrap = truncate`
+    pub pager: bool, // `pager = on` shows long output a screenful at a time
+    pub stderr_prefix: bool, // `stderr_prefix = off` shows programs' stderr in the error color without `ERROR:`
+    pub bell: Bell, // `bell = audible` plays a sound for BEL in output, `visual` (the default) flashes the pane, `off` ignores it
+    pub notify_after: Option<Duration>, // `notify_after = 30` (seconds, or `off`): notify when a longer command finishes in the background
+    pub background_alpha: f32, // `background_alpha = 0.85` lets the desktop show through; needs a compositor
+    pub window_size: [f32; 2], // `window_size = 1200x800`; rewritten on exit, so the next launch opens at the last size
+    pub window_position: Option<[f32; 2]>, // `window_position = 40,30`, the top-left corner; rewritten on exit like the size
+    pub ai_enabled: bool, // `ai = off` keeps everything local: unknown commands are never sent to the AI
+    pub history_filter: HistoryFilter, // `history_ignore = ^vault ` keeps matching commands out of history
+    pub macros: Vec<(String, String)>, // `macro deploy = "build and push the docker image"`
+    pub ai_guard: CommandGuard, // `ai_deny = <regex>`: AI suggestions matching it need a y/n before they run
+    pub ai_ignore: AiIgnore, // `ai_ignore = grpe`: that name is reported as not found instead of sent to the AI
+}
+
+/// Font sizes the window can be scaled to
+pub const FONT_SIZES: std::ops::RangeInclusive<f32> = 8.0..=40.0;
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// Background color scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Midnight,
+    Black,
+    Solarized,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Midnight, Theme::Black, Theme::Solarized];
+
+    /// The name used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Midnight => "midnight",
+            Theme::Black => "black",
+            Theme::Solarized => "solarized",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|theme| theme.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Shape of the input line's cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    Block,
+    Bar,
+    Underline,
+}
+
+impl CursorStyle {
+    pub const ALL: [CursorStyle; 3] = [CursorStyle::Block, CursorStyle::Bar, CursorStyle::Underline];
+
+    /// The name used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            CursorStyle::Block => "block",
+            CursorStyle::Bar => "bar",
+            CursorStyle::Underline => "underline",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Blink intervals the config accepts, in milliseconds
+pub const CURSOR_BLINK_MS: std::ops::RangeInclusive<u64> = 100..=5000;
+pub const DEFAULT_CURSOR_BLINK: Duration = Duration::from_millis(500);
+
+/// How output lines wider than the window are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineWrap {
+    Wrap,
+    /// Keep each line on one row and scroll sideways, for wide output like `ps aux`
+    Truncate,
+}
+
+/// The window's size and top-left corner in points. The position is unknown on
+/// platforms that don't report it (Wayland), where the window manager places the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowGeometry {
+    pub size: [f32; 2],
+    pub position: Option<[f32; 2]>,
+}
+
+impl WindowGeometry {
+    /// Shrink and move the window so it lies within a monitor of `monitor` size, so a
+    /// position saved on a since-disconnected screen doesn't leave it out of sight.
+    /// egui only knows the size of the monitor the window opened on, not where the
+    /// monitors are, so the position is kept within that size from the origin.
+    pub fn clamp_to(&self, monitor: [f32; 2]) -> Self {
+        let size = [self.size[0].min(monitor[0]), self.size[1].min(monitor[1])];
+        let position = self.position.map(|[x, y]| [
+            x.clamp(0.0, monitor[0] - size[0]),
+            y.clamp(0.0, monitor[1] - size[1]),
+        ]);
+        Self { size, position }
+    }
+
+    /// Write the geometry into the config file, for the next launch to open with
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut settings = vec![("window_size", format!("{}x{}", self.size[0].round(), self.size[1].round()))];
+        if let Some([x, y]) = self.position {
+            settings.push(("window_position", format!("{},{}", x.round(), y.round())));
+        }
+        Config::save_settings(&settings)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scrollback_limit: 10_000,
+            font_size: DEFAULT_FONT_SIZE,
+            theme: Theme::Midnight,
+            cursor_style: CursorStyle::Block,
+            cursor_blink: Some(DEFAULT_CURSOR_BLINK),
+            fuzzy: true,
+            model: crate::ai_assistant::MODEL.to_string(),
+            ai_timeout: crate::ai_assistant::DEFAULT_REQUEST_TIMEOUT,
+            gibberish: GibberishRules::default(),
+            prompt: crate::prompt::DEFAULT_TEMPLATE.to_string(),
+            classic_prompt: false,
+            show_banner: true,
+            status_clock: true,
+            keymap: Keymap::default(),
+            line_wrap: LineWrap::Wrap,
+            pager: false,
+            stderr_prefix: true,
+            bell: Bell::Visual,
+            notify_after: Some(Duration::from_secs(10)),
+            background_alpha: 1.0,
+            window_size: [1000.0, 700.0],
+            window_position: None,
+            ai_enabled: true,
+            history_filter: HistoryFilter::default(),
+            macros: Vec::new(),
+            ai_guard: CommandGuard::default(),
+            ai_ignore: AiIgnore::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Location of the config file, honouring `XDG_CONFIG_HOME`
+    pub fn path() -> PathBuf {
+        let base = env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                let home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+                PathBuf::from(home).join(".config")
+            });
+        base.join("linara").join("config")
+    }
+
+    /// Load the config file, falling back to defaults if it is missing
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        if let Ok(content) = std::fs::read_to_string(Self::path()) {
+            config.apply(&content);
+        }
+        config
+    }
+
+    /// Apply every `key = value` line of a config file. A `#` starts a comment the way
+    /// it does on the command line: outside quotes, at the start of a word.
+    fn apply(&mut self, content: &str) {
+        for line in content.lines() {
+            if let Some((key, value)) = crate::comment::strip_comment(line).split_once('=') {
+                self.set(key.trim(), value.trim());
+            }
+        }
+    }
+
+    /// Write `settings` into the config file, replacing the lines they were on and
+    /// leaving the rest of the file alone; the file is created if needed
+    pub fn save_settings(settings: &[(&str, String)]) -> std::io::Result<()> {
+        let path = Self::path();
+        let content = std::fs::read_to_string(&path).unwrap_or_default();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, crate::preferences::with_settings(&content, settings))
+    }
+
+    /// Apply a single `key = value` setting, ignoring invalid values
+    pub fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "scrollback_limit" => {
+                if let Ok(limit) = value.parse::<usize>() {
+                    if limit > 0 {
+                        self.scrollback_limit = limit;
+                    }
+                }
+            }
+            "font_size" => {
+                if let Ok(size) = value.parse::<f32>() {
+                    if FONT_SIZES.contains(&size) {
+                        self.font_size = size;
+                    }
+                }
+            }
+            "theme" => {
+                if let Some(theme) = Theme::parse(value) {
+                    self.theme = theme;
+                }
+            }
+            "cursor_style" => {
+                if let Some(style) = CursorStyle::parse(value) {
+                    self.cursor_style = style;
+                }
+            }
+            "cursor_blink" => match parse_bool(value) {
+                Some(false) => self.cursor_blink = None,
+                Some(true) => self.cursor_blink = Some(DEFAULT_CURSOR_BLINK),
+                None => {
+                    if let Some(ms) = value.parse::<u64>().ok().filter(|ms| CURSOR_BLINK_MS.contains(ms)) {
+                        self.cursor_blink = Some(Duration::from_millis(ms));
+                    }
+                }
+            },
+            "fuzzy" => {
+                if let Some(fuzzy) = parse_bool(value) {
+                    self.fuzzy = fuzzy;
+                }
+            }
+            "model" if !value.is_empty() => self.model = value.to_string(),
+            "ai_timeout" => {
+                if let Ok(secs) = value.parse::<u64>() {
+                    if secs > 0 {
+                        self.ai_timeout = Duration::from_secs(secs);
+                    }
+                }
+            }
+            "prompt" => {
+                // Quotes keep leading/trailing spaces: prompt = "{user}$ "
+                let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+                if !value.is_empty() {
+                    self.prompt = value.to_string();
+                }
+            }
+            "ai" => {
+                if let Some(enabled) = parse_bool(value) {
+                    self.ai_enabled = enabled;
+                }
+            }
+            "classic_prompt" => {
+                if let Some(classic) = parse_bool(value) {
+                    self.classic_prompt = classic;
+                }
+            }
+            "show_banner" => {
+                if let Some(show) = parse_bool(value) {
+                    self.show_banner = show;
+                }
+            }
+            "status_clock" => {
+                if let Some(show) = parse_bool(value) {
+                    self.status_clock = show;
+                }
+            }
+            "pager" => {
+                if let Some(pager) = parse_bool(value) {
+                    self.pager = pager;
+                }
+            }
+            "stderr_prefix" => {
+                if let Some(prefix) = parse_bool(value) {
+                    self.stderr_prefix = prefix;
+                }
+            }
+            "bell" => {
+                if let Some(bell) = Bell::parse(value) {
+                    self.bell = bell;
+                }
+            }
+            "notify_after" => {
+                if parse_bool(value) == Some(false) {
+                    self.notify_after = None;
+                } else if let Ok(secs) = value.parse::<u64>() {
+                    self.notify_after = Some(Duration::from_secs(secs));
+                }
+            }
+            "background_alpha" => {
+                if let Ok(alpha) = value.parse::<f32>() {
+                    if (0.0..=1.0).contains(&alpha) {
+                        self.background_alpha = alpha;
+                    }
+                }
+            }
+            "window_size" => {
+                let size = value.split_once('x')
+                    .and_then(|(width, height)| Some([width.trim().parse::<f32>().ok()?, height.trim().parse::<f32>().ok()?]));
+                if let Some(size) = size.filter(|size| size[0] >= 200.0 && size[1] >= 100.0) {
+                    self.window_size = size;
+                }
+            }
+            "window_position" => {
+                let position = value.split_once(',')
+                    .and_then(|(x, y)| Some([x.trim().parse::<f32>().ok()?, y.trim().parse::<f32>().ok()?]));
+                if let Some(position) = position.filter(|position| position.iter().all(|coord| coord.is_finite())) {
+                    self.window_position = Some(position);
+                }
+            }
+            "line_wrap" => match value {
+                "wrap" => self.line_wrap = LineWrap::Wrap,
+                "truncate" => self.line_wrap = LineWrap::Truncate,
+                _ => {}
+            },
+            "bind" => {
+                if let Some((chord, action)) = value.split_once(char::is_whitespace) {
+                    let _ = self.keymap.bind(chord, action.trim());
+                }
+            }
+            "history_ignore" => {
+                let _ = self.history_filter.add(value);
+            }
+            "ai_deny" => {
+                let _ = self.ai_guard.add(value);
+            }
+            "ai_ignore" => {
+                let _ = self.ai_ignore.add(value);
+            }
+            _ if key.starts_with("macro ") => {
+                if let Some(definition) = crate::macros::parse_definition(&format!("{}={}", &key["macro ".len()..], value)) {
+                    self.macros.push(definition);
+                }
+            }
+            "meaningful_words" => self.gibberish.meaningful_words = parse_list(value),
+            "incoherent_patterns" => self.gibberish.incoherent_patterns = parse_list(value),
+            _ => {}
+        }
+    }
+}
+
+// true/false, yes/no, on/off or 1/0
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+// Split a comma-separated list, dropping empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_in_values_are_not_comments() {
+        let mut config = Config::default();
+        config.apply("# my config\nprompt = \"{user} # \" # with a hash\nmacro tally = \"count lines with # in them\"\nmodel = org/model#2\ntheme = black# dark\n");
+        assert_eq!(config.prompt, "{user} # ");
+        assert_eq!(config.macros, vec![("tally".to_string(), "count lines with # in them".to_string())]);
+        assert_eq!(config.model, "org/model#2");
+        assert_eq!(config.theme, Theme::Midnight);
+
+        config.apply("theme = black # dark enough\n   # theme = solarized\n");
+        assert_eq!(config.theme, Theme::Black);
+    }
+
+    #[test]
+    fn window_geometry_loads_back() {
+        let mut config = Config::default();
+        config.set("window_size", "1200x800");
+        config.set("window_position", "-40, 30");
+        assert_eq!(config.window_size, [1200.0, 800.0]);
+        assert_eq!(config.window_position, Some([-40.0, 30.0]));
+        config.set("window_position", "left");
+        assert_eq!(config.window_position, Some([-40.0, 30.0]));
+    }
+
+    #[test]
+    fn off_screen_windows_are_pulled_back() {
+        let window = WindowGeometry { size: [1200.0, 800.0], position: Some([2500.0, -50.0]) };
+        assert_eq!(window.clamp_to([1920.0, 1080.0]), WindowGeometry { size: [1200.0, 800.0], position: Some([720.0, 0.0]) });
+        let visible = WindowGeometry { size: [1200.0, 800.0], position: Some([100.0, 50.0]) };
+        assert_eq!(visible.clamp_to([1920.0, 1080.0]), visible);
+        let too_big = WindowGeometry { size: [3000.0, 1200.0], position: None };
+        assert_eq!(too_big.clamp_to([1920.0, 1080.0]), WindowGeometry { size: [1920.0, 1080.0], position: None });
+    }
+}
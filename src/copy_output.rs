@@ -0,0 +1,96 @@
+use crate::prompt::PromptLine;
+
+// What `copy` and Ctrl+Shift+O put on the clipboard: a command's output as it sits in
+// the scrollback. Each scrollback line is a whole line as the program printed it, and
+// wrapping to the window only happens when it is drawn, so the copy is those lines
+// joined with `\n`, with no break where the screen wrapped one.
+
+/// A scrollback line, as far as copying is concerned
+pub struct Row<'a> {
+    pub text: &'a str,
+    pub prompt: Option<&'a PromptLine>,
+    pub live: bool, // The prompt still waiting for input
+}
+
+/// Output of a previous command, counting back from the newest (0), as it appears
+/// between that command's prompt line and the next prompt
+pub fn command_output<'a>(rows: impl DoubleEndedIterator<Item = Row<'a>>, skip: usize) -> Option<String> {
+    let mut skip = skip;
+    let mut output: Vec<&str> = Vec::new();
+    for row in rows.rev() {
+        let Some(prompt) = row.prompt else {
+            output.push(row.text);
+            continue;
+        };
+        // The live prompt, or Enter on an empty line
+        if row.live || prompt.command.is_none() {
+            continue;
+        }
+        if skip > 0 {
+            skip -= 1;
+            output.clear();
+            continue;
+        }
+        // Short output was shown inline on the prompt line
+        if let Some(inline) = &prompt.output {
+            return Some(inline.clone());
+        }
+        output.reverse();
+        return (!output.is_empty()).then(|| output.join("\n"));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ran(command: &str) -> PromptLine {
+        PromptLine { segments: Vec::new(), command: Some(command.to_string()), output: None }
+    }
+
+    // Lay `text` out the way the scrollback draws it with `line_wrap = wrap`, in a window `width` points wide
+    fn wrapped(text: &str, width: f32) -> std::sync::Arc<egui::Galley> {
+        let ctx = egui::Context::default();
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(width, 600.0))),
+            ..Default::default()
+        };
+        let mut galley = None;
+        let _ = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                let label = egui::Label::new(egui::RichText::new(text).font(egui::FontId::monospace(18.0)));
+                galley = Some(label.layout_in_ui(ui).1);
+            });
+        });
+        galley.unwrap()
+    }
+
+    #[test]
+    fn wrapped_lines_are_copied_whole() {
+        let long = "warning: ".to_string() + &"this line is far wider than any window ".repeat(8);
+        let galley = wrapped(&long, 300.0);
+        assert!(galley.rows.len() > 1, "the line was not wrapped");
+
+        // Dragging over the whole wrapped line copies it as one line, and so does `copy`
+        let selected = egui::text_selection::CursorRange::select_all(&galley);
+        assert_eq!(selected.slice_str(galley.text()), long);
+
+        let (first, second, live) = (ran("cargo build"), ran("cat notes"), PromptLine { segments: Vec::new(), command: None, output: None });
+        let rows = [
+            ("", Some(&first), false),
+            (galley.text(), None, false),
+            ("done", None, false),
+            ("", Some(&second), false),
+            ("notes", None, false),
+            ("", Some(&live), true),
+        ];
+        let rows = || rows.iter().map(|&(text, prompt, live)| Row { text, prompt, live });
+
+        let copied = command_output(rows(), 1).unwrap();
+        assert_eq!(copied, format!("{}\ndone", long));
+        assert_eq!(copied.lines().count(), 2);
+        assert_eq!(command_output(rows(), 0).as_deref(), Some("notes"));
+        assert_eq!(command_output(rows(), 2), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::process::Command;
+use regex::Regex;
+use crate::flags::run_for_text;
+
+// Runnable examples for `examples <command>`: the ones shipped in examples.json,
+// or for other commands the examples from the `tldr` page when it is installed.
+
+const BUILT_IN: &str = include_str!("examples.json");
+
+/// Most examples shown for one command; also the highest number key that picks one
+pub const MAX_EXAMPLES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example {
+    pub description: String,
+    pub command: String,
+}
+
+/// Examples for `command`, built-in first, then from tldr
+pub fn lookup(command: &str) -> Vec<Example> {
+    let built_in = built_in(command);
+    if !built_in.is_empty() || command.starts_with('-') || command.contains('/') {
+        return built_in;
+    }
+    run_for_text(Command::new("tldr").arg(command).env("NO_COLOR", "1"))
+        .map(|page| parse_tldr(&page))
+        .unwrap_or_default()
+}
+
+fn built_in(command: &str) -> Vec<Example> {
+    let table: HashMap<String, Vec<(String, String)>> =
+        serde_json::from_str(BUILT_IN).expect("built-in examples are valid JSON");
+    table.get(command)
+        .into_iter()
+        .flatten()
+        .take(MAX_EXAMPLES)
+        .map(|(description, command)| Example { description: description.clone(), command: command.clone() })
+        .collect()
+}
+
+// tldr pages describe each example on a line ending in ':' followed by the command,
+// with placeholders in {{braces}}
+fn parse_tldr(page: &str) -> Vec<Example> {
+    let ansi = Regex::new(r"\x1b\[[0-9;]*m").expect("valid regex");
+    let page = ansi.replace_all(page, "");
+    let mut examples = Vec::new();
+    let mut description: Option<String> = None;
+    for line in page.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match description.take() {
+            Some(description) => examples.push(Example {
+                description,
+                command: line.replace("{{", "").replace("}}", ""),
+            }),
+            None => {
+                if let Some(text) = line.strip_suffix(':') {
+                    description = Some(text.trim_start_matches("- ").to_string());
+                }
+            }
+        }
+        if examples.len() == MAX_EXAMPLES {
+            break;
+        }
+    }
+    examples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_examples_are_found() {
+        let examples = lookup("tar");
+        assert!(!examples.is_empty() && examples.len() <= MAX_EXAMPLES);
+        assert!(examples.iter().all(|example| example.command.starts_with("tar ")));
+        assert!(built_in("no-such-command").is_empty());
+    }
+
+    #[test]
+    fn tldr_pages_are_parsed() {
+        let page = "\n  \x1b[1mtar\x1b[0m\n\n  Archiving utility.\n  More information: https://www.gnu.org/software/tar.\n\n  - Create an archive from files:\n\n    tar cf {{target.tar}} {{file1}}\n\n  - Extract an archive:\n\n    tar xf {{source.tar}}\n";
+        assert_eq!(parse_tldr(page), vec![
+            Example { description: "Create an archive from files".to_string(), command: "tar cf target.tar file1".to_string() },
+            Example { description: "Extract an archive".to_string(), command: "tar xf source.tar".to_string() },
+        ]);
+        assert!(parse_tldr("Page not found.").is_empty());
+    }
+}
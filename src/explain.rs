@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::process::Command;
+use regex::Regex;
+use crate::config::Config;
+use crate::flags::run_for_text;
+
+// What `explain` knows about commands. The entries shipped with Linara live in
+// explanations.json; ~/.config/linara/explain.json has the same shape (command
+// name to text) and adds or replaces entries. It is read on each lookup, so
+// edits show up straight away. Commands in neither are looked up with `tldr`,
+// then `whatis`, when those are installed.
+
+const BUILT_IN: &str = include_str!("explanations.json");
+
+// Lines of a tldr page worth showing; the rest is examples
+const TLDR_LINES: usize = 12;
+
+pub struct Explanations {
+    entries: HashMap<String, String>,
+    /// Why the user's file couldn't be used, if it exists but is broken
+    pub error: Option<String>,
+}
+
+impl Explanations {
+    /// The built-in entries with the user's file on top
+    pub fn load() -> Self {
+        let path = Config::path().with_file_name("explain.json");
+        let user = std::fs::read_to_string(&path).ok();
+        let mut explanations = Self::from_json(user.as_deref());
+        if let Some(error) = &mut explanations.error {
+            *error = format!("{}: {}", path.display(), error);
+        }
+        explanations
+    }
+
+    fn from_json(user: Option<&str>) -> Self {
+        let mut entries: HashMap<String, String> =
+            serde_json::from_str(BUILT_IN).expect("built-in explanations are valid JSON");
+        let mut error = None;
+        if let Some(user) = user {
+            match serde_json::from_str::<HashMap<String, String>>(user) {
+                Ok(user_entries) => entries.extend(user_entries),
+                Err(e) => error = Some(e.to_string()),
+            }
+        }
+        Self { entries, error }
+    }
+
+    pub fn get(&self, command: &str) -> Option<&str> {
+        self.entries.get(command).map(String::as_str)
+    }
+}
+
+/// The command `explain` was asked about. Called as `what`, the question around it is
+/// skipped, so `what is ls?` and `what does tar do` ask about `ls` and `tar`;
+/// None if no command was named
+pub fn subject<'a>(name: &str, args: &'a [String]) -> Option<&'a str> {
+    let mut words = args.iter()
+        .map(|arg| arg.trim_end_matches('?'))
+        .filter(|word| !word.is_empty())
+        .peekable();
+    if name == "what" {
+        let question = |word: &&str| matches!(word.to_lowercase().as_str(), "is" | "are" | "does" | "do" | "a" | "an" | "the");
+        while words.next_if(question).is_some() {}
+    }
+    words.next()
+}
+
+/// An explanation from the system's own documentation, for commands without an entry
+pub fn from_system(command: &str) -> Option<String> {
+    if command.starts_with('-') || command.contains('/') {
+        return None;
+    }
+    run_for_text(Command::new("tldr").arg(command).env("NO_COLOR", "1"))
+        .and_then(|page| parse_tldr(&page))
+        .or_else(|| run_for_text(Command::new("whatis").arg(command)).and_then(|text| parse_whatis(&text, command)))
+}
+
+// "ls (1)               - list directory contents" from whatis
+fn parse_whatis(text: &str, command: &str) -> Option<String> {
+    text.lines()
+        .filter(|line| line.split_whitespace().next() == Some(command))
+        .find_map(|line| line.split_once(" - "))
+        .map(|(_, description)| format!("📖 {} - {}", command, description.trim()))
+}
+
+// A tldr page without colors, blank lines or the trailing examples past TLDR_LINES
+fn parse_tldr(page: &str) -> Option<String> {
+    let ansi = Regex::new(r"\x1b\[[0-9;]*m").expect("valid regex");
+    let page = ansi.replace_all(page, "");
+    let lines: Vec<&str> = page.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .take(TLDR_LINES)
+        .collect();
+    // Clients print an error rather than failing when there is no page
+    if lines.is_empty() || lines.iter().any(|line| line.to_lowercase().contains("page not found")) {
+        return None;
+    }
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_entries_add_to_and_replace_built_in_ones() {
+        let explanations = Explanations::from_json(Some(r#"{"ls": "my ls", "mytool": "does things"}"#));
+        assert_eq!(explanations.get("ls"), Some("my ls"));
+        assert_eq!(explanations.get("mytool"), Some("does things"));
+        assert!(explanations.get("grep").is_some_and(|text| text.contains("grep")));
+        assert!(explanations.error.is_none());
+
+        let broken = Explanations::from_json(Some("{ nope"));
+        assert!(broken.error.is_some());
+        assert!(broken.get("ls").is_some_and(|text| text.starts_with("📁 ls")));
+    }
+
+    #[test]
+    fn aliases_share_an_entry() {
+        let explanations = Explanations::from_json(None);
+        assert_eq!(explanations.get("top"), explanations.get("htop"));
+    }
+
+    #[test]
+    fn questions_name_their_command() {
+        let args = |line: &str| line.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(subject("what", &args("is ls")), Some("ls"));
+        assert_eq!(subject("what", &args("ls")), Some("ls"));
+        assert_eq!(subject("what", &args("does tar do?")), Some("tar"));
+        assert_eq!(subject("what", &args("is the grep command")), Some("grep"));
+        assert_eq!(subject("explain", &args("ls -la")), Some("ls"));
+        // Only `what` reads its arguments as a question
+        assert_eq!(subject("explain", &args("is")), Some("is"));
+        assert_eq!(subject("what", &args("is")), None);
+        assert_eq!(subject("what", &args("is ?")), None);
+        assert_eq!(subject("what", &[]), None);
+    }
+
+    #[test]
+    fn system_documentation_is_parsed() {
+        let whatis = "ls (1)               - list directory contents\nls (1p)              - list directory contents\n";
+        assert_eq!(parse_whatis(whatis, "ls"), Some("📖 ls - list directory contents".to_string()));
+        assert_eq!(parse_whatis("qqq: nothing appropriate.", "qqq"), None);
+
+        let tldr = "\n  \x1b[1mtar\x1b[0m\n\n  Archiving utility.\n\n  - Create an archive:\n\n    tar cf target.tar file1\n";
+        assert_eq!(parse_tldr(tldr), Some("  tar\n  Archiving utility.\n  - Create an archive:\n    tar cf target.tar file1".to_string()));
+        assert_eq!(parse_tldr("Page not found."), None);
+    }
+}
@@ -0,0 +1,61 @@
+use regex::Regex;
+
+// Live filter for the scrollback (Ctrl+Shift+F): hides lines that don't match,
+// like piping what is already on screen through grep.
+
+#[derive(Clone)]
+pub enum LineFilter {
+    Text(String),
+    Regex(Regex),
+}
+
+impl LineFilter {
+    /// Build a filter from the query; an empty query means no filter
+    pub fn new(query: &str, regex: bool) -> Result<Option<Self>, String> {
+        if query.is_empty() {
+            return Ok(None);
+        }
+        if regex {
+            Regex::new(query)
+                .map(|re| Some(LineFilter::Regex(re)))
+                .map_err(|e| format!("invalid regex: {}", e))
+        } else {
+            Ok(Some(LineFilter::Text(query.to_string())))
+        }
+    }
+
+    pub fn matches(&self, line: &str) -> bool {
+        match self {
+            LineFilter::Text(text) => line.contains(text.as_str()),
+            LineFilter::Regex(re) => re.is_match(line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_query_does_not_filter() {
+        assert!(LineFilter::new("", false).unwrap().is_none());
+        assert!(LineFilter::new("", true).unwrap().is_none());
+    }
+
+    #[test]
+    fn text_filter_matches_substrings() {
+        let filter = LineFilter::new("error", false).unwrap().unwrap();
+        assert!(filter.matches("build error: missing file"));
+        assert!(!filter.matches("Error with a capital"));
+        // Regex syntax is taken literally in text mode
+        assert!(!LineFilter::new("err.r", false).unwrap().unwrap().matches("error"));
+    }
+
+    #[test]
+    fn regex_filter_matches_patterns() {
+        let filter = LineFilter::new(r"^\d+ passed", true).unwrap().unwrap();
+        assert!(filter.matches("12 passed; 0 failed"));
+        assert!(!filter.matches("passed: 12"));
+        assert!(LineFilter::new("(unclosed", true).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use regex::Regex;
+
+// Flag completion for commands that aren't in the built-in table, read from their
+// man page. Only `man` is run, never the command itself, and on a worker thread so
+// a slow man page doesn't hold up typing. The result is cached in `command_flags`
+// by the caller, so each command is only looked up once.
+
+// A man page that takes longer than this to render is given up on
+const HELP_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Values offered after `--flag=`, by command and flag
+const FLAG_VALUES: &[(&str, &str, &[&str])] = &[
+    ("ls", "--color", &["auto", "always", "never"]),
+    ("ls", "--sort", &["none", "size", "time", "version", "extension", "width"]),
+    ("ls", "--time-style", &["full-iso", "long-iso", "iso", "locale"]),
+    ("grep", "--color", &["auto", "always", "never"]),
+    ("diff", "--color", &["auto", "always", "never"]),
+    ("git", "--format", &["oneline", "short", "medium", "full", "fuller", "reference", "email", "raw"]),
+    ("git", "--pretty", &["oneline", "short", "medium", "full", "fuller", "reference", "email", "raw"]),
+];
+
+/// Completions for a word like `--color=au`: the known values of the flag that start with what is typed
+pub fn complete_value(command: &str, word: &str) -> Vec<String> {
+    let Some((flag, typed)) = word.split_once('=') else {
+        return Vec::new();
+    };
+    FLAG_VALUES.iter()
+        .filter(|(name, known_flag, _)| *name == command && *known_flag == flag)
+        .flat_map(|(_, _, values)| values.iter())
+        .filter(|value| value.starts_with(typed))
+        .map(|value| format!("{}={}", flag, value))
+        .collect()
+}
+
+/// Options mentioned in help text: `-x` and `--long-name`, in order of first appearance
+pub fn parse_flags(help: &str) -> Vec<String> {
+    let pattern = Regex::new(r"(?:^|[\s,\[(|])(--?[A-Za-z0-9][A-Za-z0-9-]*)").expect("valid flag pattern");
+    let mut flags: Vec<String> = Vec::new();
+    for captures in pattern.captures_iter(help) {
+        let flag = captures[1].trim_end_matches('-');
+        // `-abc` is usually an example of combined short flags, not an option of its own
+        let is_short_cluster = !flag.starts_with("--") && flag.len() > 2;
+        if !is_short_cluster && !flags.iter().any(|known| known == flag) {
+            flags.push(flag.to_string());
+        }
+    }
+    flags
+}
+
+/// Look up the flags of `command` on a worker thread, sending them with its name when done
+pub fn read_flags_in_background(command: &str, results: Sender<(String, Vec<String>)>) {
+    let command = command.to_string();
+    std::thread::spawn(move || {
+        let flags = read_flags(Command::new("man"), &command);
+        let _ = results.send((command, flags));
+    });
+}
+
+/// The flags in `command`'s page, as shown by `man`; empty if it has none or isn't a plain program name
+pub fn read_flags(mut man: Command, command: &str) -> Vec<String> {
+    let plain_name = !command.is_empty()
+        && command.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'));
+    if !plain_name {
+        return Vec::new();
+    }
+    run_for_text(man.arg(command).env("MANPAGER", "cat").env("MANWIDTH", "120"))
+        .map(|page| parse_flags(&page))
+        .unwrap_or_default()
+}
+
+/// Stdout and stderr together (help goes to either), or None if it didn't finish in time
+pub fn run_for_text(command: &mut Command) -> Option<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let pipes: [Option<Box<dyn Read + Send>>; 2] = [
+        child.stdout.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+        child.stderr.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+    ];
+    for mut pipe in pipes.into_iter().flatten() {
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            let mut text = String::new();
+            let _ = pipe.read_to_string(&mut text);
+            let _ = sender.send(text);
+        });
+    }
+    drop(sender);
+
+    let mut output = String::new();
+    for _ in 0..2 {
+        match receiver.recv_timeout(HELP_TIMEOUT) {
+            Ok(text) => output.push_str(&text),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+    let _ = child.wait();
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_and_long_options_are_extracted() {
+        let help = "\
+Usage: tar [OPTION...] [FILE]...
+  -c, --create               create a new archive
+  -x, --extract, --get       extract files from an archive
+      --exclude=PATTERN      exclude files, given as a PATTERN
+  -f, --file=ARCHIVE         use archive file
+Examples: tar -cf archive.tar foo bar";
+        assert_eq!(parse_flags(help), ["-c", "--create", "-x", "--extract", "--get", "--exclude", "-f", "--file"]);
+    }
+
+    #[test]
+    fn hyphenated_words_are_not_flags() {
+        assert_eq!(parse_flags("a well-known non-flag, e.g. x-y [-v]"), ["-v"]);
+    }
+
+    #[test]
+    fn values_complete_after_the_equals_sign() {
+        assert_eq!(complete_value("ls", "--color=a"), ["--color=auto", "--color=always"]);
+        assert_eq!(complete_value("git", "--format=o"), ["--format=oneline"]);
+        assert!(complete_value("ls", "--color").is_empty());
+        assert!(complete_value("cat", "--color=").is_empty());
+    }
+
+    #[test]
+    fn flags_are_read_from_the_man_page() {
+        // A stand-in for `man` that prints the page it's given from the fixtures
+        let man = || {
+            let mut man = Command::new("sh");
+            man.arg("-c").arg("cat \"$0.1\"").current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/src/fixtures"));
+            man
+        };
+        assert_eq!(read_flags(man(), "ls"), ["-a", "--all", "-l", "--color", "-h", "--human-readable"]);
+        assert!(read_flags(man(), "missing").is_empty());
+        assert!(read_flags(man(), "ls; rm -rf /").is_empty());
+    }
+}
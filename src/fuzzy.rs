@@ -0,0 +1,154 @@
+// Fuzzy scoring for autocomplete and the command palette.
+//
+// Prefix matches rank highest, then substring matches, then subsequence matches.
+// Like fzf, a subsequence earns a bonus for each character that lands on a word
+// boundary (start, after `-`, `_`, `/`, `.` or a space, or a camelCase hump) and
+// for runs of consecutive characters, so `gco` prefers `git-checkout` over names
+// that merely contain those letters somewhere.
+
+const BOUNDARY_BONUS: i32 = 6;
+const CONSECUTIVE_BONUS: i32 = 3;
+// Keeps even the best subsequence match below a substring match of the same length
+const MAX_BONUS: i32 = 24;
+
+/// Score `candidate` against `query`; 0 or less means no match
+pub fn score(query: &str, candidate: &str) -> i32 {
+    if query.is_empty() {
+        return 0;
+    }
+
+    let query_lower = query.to_lowercase();
+    let candidate_lower = candidate.to_lowercase();
+
+    // Exact prefix match gets highest score
+    if candidate_lower.starts_with(&query_lower) {
+        return 100 - candidate.len() as i32;
+    }
+
+    // Contains match gets medium score
+    if candidate_lower.contains(&query_lower) {
+        return 50 - candidate.len() as i32;
+    }
+
+    // All characters in order, but not consecutive
+    match subsequence_bonus(&query_lower, candidate) {
+        Some(bonus) => 25 + bonus.min(MAX_BONUS) - candidate.len() as i32,
+        None => 0,
+    }
+}
+
+// Best bonus over every way of matching `query` as a subsequence of `candidate`,
+// or None if it isn't one. `query` is already lowercase.
+fn subsequence_bonus(query: &str, candidate: &str) -> Option<i32> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let mut query = query.chars();
+
+    // best[j]: highest bonus so far with the latest query character matched at position j
+    let first = query.next()?;
+    let mut best: Vec<Option<i32>> = (0..chars.len())
+        .map(|j| (lower[j] == first).then(|| boundary_bonus(&chars, j)))
+        .collect();
+
+    for qc in query {
+        let mut next = vec![None; chars.len()];
+        let mut best_with_gap: Option<i32> = None; // Best match ending at least two characters back
+        for j in 0..chars.len() {
+            if j >= 2 {
+                best_with_gap = best_with_gap.max(best[j - 2]);
+            }
+            if lower[j] != qc {
+                continue;
+            }
+            let bonus = boundary_bonus(&chars, j);
+            let after_gap = best_with_gap.map(|b| b + bonus);
+            let consecutive = j.checked_sub(1)
+                .and_then(|k| best[k])
+                .map(|b| b + bonus + CONSECUTIVE_BONUS);
+            next[j] = after_gap.max(consecutive);
+        }
+        best = next;
+    }
+
+    best.into_iter().flatten().max()
+}
+
+/// Typing distance between two words: insertions, deletions, substitutions and
+/// swaps of neighbouring characters each count as one edit (`gti` is 1 from `git`)
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j]: distance between the first i characters of a and the first j of b
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+fn boundary_bonus(chars: &[char], j: usize) -> i32 {
+    let Some(&prev) = j.checked_sub(1).and_then(|k| chars.get(k)) else {
+        return BOUNDARY_BONUS;
+    };
+    let after_separator = matches!(prev, '-' | '_' | '/' | '.' | ' ');
+    let camel_hump = prev.is_lowercase() && chars[j].is_uppercase();
+    if after_separator || camel_hump {
+        BOUNDARY_BONUS
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_beats_substring_beats_subsequence() {
+        assert!(score("git", "gitk") > score("git", "legit"));
+        assert!(score("git", "legit") > score("git", "gsitx"));
+        assert_eq!(score("xyz", "git"), 0);
+        assert_eq!(score("", "git"), 0);
+    }
+
+    #[test]
+    fn boundary_matches_rank_above_incidental_ones() {
+        let alias = score("gco", "git checkout");
+        assert!(alias > score("gco", "glances-top"));
+        assert!(alias > score("gco", "pkg-config-tool"));
+        assert!(alias > score("gco", "gnome-connection-manager"));
+        assert!(score("gco", "git-checkout") > score("gco", "egrep-cloud"));
+    }
+
+    #[test]
+    fn camel_case_humps_count_as_boundaries() {
+        assert!(score("gc", "getConfig") > score("gc", "getconfig"));
+    }
+
+    #[test]
+    fn best_alignment_is_used_not_the_first() {
+        // The first `c` is mid-word; the later one starts a segment
+        assert_eq!(subsequence_bonus("gc", "gxcx-c"), Some(2 * BOUNDARY_BONUS));
+    }
+
+    #[test]
+    fn edit_distance_counts_typos() {
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("sl", "ls"), 1);
+        assert_eq!(edit_distance("grpe", "grep"), 1);
+        assert_eq!(edit_distance("pyhton3", "python3"), 1);
+        assert_eq!(edit_distance("cat", "cat"), 0);
+        assert_eq!(edit_distance("", "ls"), 2);
+    }
+}
@@ -0,0 +1,79 @@
+use regex::Regex;
+
+// Which commands are kept out of history (and so out of the saved session) and
+// out of the AI cache. Like bash's HISTCONTROL=ignorespace, a command typed with
+// a leading space is never recorded; so is anything that looks like it holds a
+// secret. More patterns can be added with `history_ignore = <regex>` lines.
+
+// Credentials in assignments and flags, and well-known API token formats
+const DEFAULT_PATTERNS: &[&str] = &[
+    r"(?i)\b\w*(key|token|secret|passw(or)?d|pwd|credentials?)\w*=\S",
+    r"(?i)--(password|passwd|token|secret|api-key)[= ]\S",
+    r"\bmysql(dump)?\b.*\s-p\S",
+    r"(?i)\bauthorization:\s*(bearer|basic)\s",
+    r"\bsk-[A-Za-z0-9_-]{20,}",
+    r"\bgh[pousr]_[A-Za-z0-9]{20,}",
+    r"\bAKIA[0-9A-Z]{16}\b",
+    r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+];
+
+#[derive(Debug, Clone)]
+pub struct HistoryFilter {
+    patterns: Vec<Regex>,
+}
+
+impl Default for HistoryFilter {
+    fn default() -> Self {
+        let patterns = DEFAULT_PATTERNS.iter()
+            .map(|pattern| Regex::new(pattern).expect("default history patterns are valid"))
+            .collect();
+        Self { patterns }
+    }
+}
+
+impl HistoryFilter {
+    /// Also skip commands matching `pattern`
+    pub fn add(&mut self, pattern: &str) -> Result<(), String> {
+        let regex = Regex::new(pattern).map_err(|e| format!("history_ignore: {}", e))?;
+        self.patterns.push(regex);
+        Ok(())
+    }
+
+    /// Whether `command` must not be remembered
+    pub fn is_private(&self, command: &str) -> bool {
+        command.starts_with(' ') || self.patterns.iter().any(|pattern| pattern.is_match(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_not_recorded() {
+        let filter = HistoryFilter::default();
+        assert!(filter.is_private("export API_KEY=abc123"));
+        assert!(filter.is_private("export OPENROUTER_API_KEY=sk-or-v1-0123456789abcdefghij"));
+        assert!(filter.is_private("mysql -u root -phunter2 shop"));
+        assert!(filter.is_private("curl -H 'Authorization: Bearer abc' https://example.com"));
+        assert!(filter.is_private("git clone https://ghp_0123456789abcdefghijABCD@github.com/x/y"));
+        assert!(filter.is_private(" ls secret-dir"));
+    }
+
+    #[test]
+    fn ordinary_commands_are_recorded() {
+        let filter = HistoryFilter::default();
+        assert!(!filter.is_private("ls -la"));
+        assert!(!filter.is_private("mysql -u root -p shop"));
+        assert!(!filter.is_private("ssh-keygen -t ed25519"));
+        assert!(!filter.is_private("grep token src/main.rs"));
+    }
+
+    #[test]
+    fn extra_patterns_can_be_configured() {
+        let mut filter = HistoryFilter::default();
+        filter.add("^vault ").unwrap();
+        assert!(filter.is_private("vault login"));
+        assert!(filter.add("(unclosed").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use crate::fuzzy;
+
+// The Ctrl+R history picker: every distinct command from this tab's history in a
+// full-window list, narrowed with the fuzzy scorer as a query is typed. Unlike the
+// command palette it only looks at history, and the chosen command replaces the
+// input line instead of being inserted into it.
+
+#[derive(Debug, Default)]
+pub struct HistoryPicker {
+    pub query: String,
+    pub index: usize, // Selected row in `matches`
+    pub matches: Vec<String>, // `results` for the current query, ranked when it changes
+    pub distinct: usize, // Distinct commands in the history
+    pub scroll: bool, // The selection moved; scroll it into view on the next frame
+}
+
+impl HistoryPicker {
+    /// Start with the input line as the query, as fzf's Ctrl+R does
+    pub fn new(input: &str, history: &[String]) -> Self {
+        let mut picker = Self { query: input.trim().to_string(), ..Self::default() };
+        picker.distinct = history.iter().collect::<std::collections::HashSet<_>>().len();
+        picker.update(history);
+        picker
+    }
+
+    /// Rank the matches again after the query changed, selecting the best
+    pub fn update(&mut self, history: &[String]) {
+        self.matches = self.results(history).into_iter().map(str::to_string).collect();
+        self.select(0);
+    }
+
+    /// Select a row, kept within the matches
+    pub fn select(&mut self, index: usize) {
+        self.index = index.min(self.matches.len().saturating_sub(1));
+        self.scroll = true;
+    }
+
+    /// Distinct commands matching the query, best first; newest first on ties and with no query
+    pub fn results<'a>(&self, history: &'a [String]) -> Vec<&'a str> {
+        let query = self.query.trim();
+        let mut seen = std::collections::HashSet::new();
+        let mut scored: Vec<(i32, &str)> = history.iter()
+            .rev()
+            .filter(|cmd| seen.insert(cmd.as_str()))
+            .filter_map(|cmd| match query.is_empty() {
+                true => Some((0, cmd.as_str())),
+                false => Some((fuzzy::score(query, cmd), cmd.as_str())).filter(|(score, _)| *score > 0),
+            })
+            .collect();
+        // A stable sort keeps the newest first among equal scores
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, cmd)| cmd).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|cmd| cmd.to_string()).collect()
+    }
+
+    #[test]
+    fn newest_distinct_commands_come_first() {
+        let history = history(&["ls", "git status", "cargo build", "ls"]);
+        let picker = HistoryPicker::new("", &history);
+        assert_eq!(picker.matches, ["ls", "cargo build", "git status"]);
+        assert_eq!(picker.distinct, 3);
+    }
+
+    #[test]
+    fn the_query_narrows_and_ranks() {
+        let history = history(&["git status", "cargo test", "git stash", "grep -r todo src"]);
+        let mut picker = HistoryPicker::new("gsta", &history);
+        assert_eq!(picker.matches, ["git stash", "git status"]);
+        picker.query = "cargo".to_string();
+        picker.update(&history);
+        assert_eq!(picker.matches, ["cargo test"]);
+        assert!(HistoryPicker::new("docker", &history).matches.is_empty());
+    }
+
+    #[test]
+    fn the_selection_stays_within_the_matches() {
+        let history = history(&["ls", "pwd", "make"]);
+        let mut picker = HistoryPicker::new("", &history);
+        picker.scroll = false;
+        picker.select(10);
+        assert_eq!(picker.index, 2);
+        assert!(picker.scroll);
+        picker.query = "pwd".to_string();
+        picker.update(&history);
+        assert_eq!(picker.index, 0);
+    }
+}
@@ -0,0 +1,131 @@
+// Programs that need a real terminal. Run with captured output they wait for
+// keyboard input that never arrives and the UI hangs, so they are opened in the
+// user's terminal emulator instead. So are programs that ask for a password on
+// the terminal itself rather than on stdin.
+
+// Editors, pagers, monitors and other full-screen programs
+const FULL_SCREEN: &[&str] = &[
+    "vi", "vim", "nvim", "nano", "pico", "micro", "emacs", "joe",
+    "less", "more", "most",
+    "top", "htop", "btop", "atop", "iotop", "nmtui",
+    "tmux", "screen", "mc", "ranger", "nnn", "vifm",
+    "mutt", "neomutt", "irssi", "weechat", "tig", "lazygit",
+];
+
+// Shells and REPLs are only interactive when started without a script or command
+const REPLS: &[&str] = &[
+    "bash", "zsh", "fish", "sh", "python", "python3", "ipython", "node", "irb", "ghci",
+];
+
+// Programs that read a password from the terminal itself (/dev/tty), never from
+// stdin, so there is no way to pass one on from here
+const TTY_PASSWORD: &[&str] = &["su", "doas", "ssh", "scp", "sftp"];
+
+// ssh options that take a value, so the value isn't mistaken for the host or command
+const SSH_OPTIONS_WITH_VALUE: &[&str] = &["-b", "-c", "-D", "-E", "-e", "-F", "-i", "-J", "-L", "-l", "-m", "-O", "-o", "-p", "-R", "-S", "-W", "-w"];
+
+/// Whether `program args` needs a terminal to interact with
+pub fn needs_tty(program: &str, args: &[String]) -> bool {
+    match program {
+        "sudo" => match args.split_first() {
+            Some((inner, rest)) if !inner.starts_with('-') => needs_tty(inner, rest),
+            _ => false,
+        },
+        _ if TTY_PASSWORD.contains(&program) => true,
+        _ if FULL_SCREEN.contains(&program) => true,
+        _ if REPLS.contains(&program) => args.iter().all(|arg| arg.starts_with('-')),
+        _ => false,
+    }
+}
+
+/// Whether the terminal window should stay open after `program args` exits: a
+/// command run after asking for a password, whose output would otherwise vanish
+/// with the window, as opposed to a session or full-screen program
+pub fn hold_open(program: &str, args: &[String]) -> bool {
+    match program {
+        "scp" => true,
+        // `ssh host` opens a session; `ssh host uptime` just runs a command
+        "ssh" => {
+            let mut words = 0;
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                if SSH_OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+                    args.next();
+                } else if !arg.starts_with('-') {
+                    words += 1;
+                }
+            }
+            words > 1
+        }
+        "su" => args.iter().any(|arg| arg == "-c" || arg.starts_with("--command")),
+        "doas" => match args.iter().position(|arg| !arg.starts_with('-')) {
+            Some(inner) => !needs_tty(&args[inner], &args[inner + 1..]),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// The terminal emulator to open interactive programs in: `$TERMINAL`, else the Debian default
+pub fn terminal_emulator(env_terminal: Option<String>) -> String {
+    env_terminal
+        .filter(|terminal| !terminal.trim().is_empty())
+        .unwrap_or_else(|| "x-terminal-emulator".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn full_screen_programs_need_a_tty() {
+        assert!(needs_tty("vim", &args("notes.txt")));
+        assert!(needs_tty("top", &[]));
+        assert!(needs_tty("sudo", &args("nano /etc/hosts")));
+        assert!(!needs_tty("ls", &args("-la")));
+        assert!(!needs_tty("sudo", &args("apt update")));
+    }
+
+    #[test]
+    fn repls_only_without_a_command() {
+        assert!(needs_tty("python3", &[]));
+        assert!(needs_tty("bash", &args("-l")));
+        assert!(!needs_tty("python3", &args("script.py")));
+        assert!(!needs_tty("bash", &args("-c ls")));
+    }
+
+    #[test]
+    fn programs_reading_the_password_from_the_terminal_get_one() {
+        assert!(needs_tty("su", &[]));
+        assert!(needs_tty("su", &args("-c whoami")));
+        assert!(needs_tty("doas", &args("apt update")));
+        assert!(needs_tty("ssh", &args("server uptime")));
+        assert!(needs_tty("scp", &args("notes.txt server:")));
+        assert!(needs_tty("sftp", &args("server")));
+        assert!(!needs_tty("passwd", &[]));
+    }
+
+    #[test]
+    fn commands_keep_their_window_open_and_sessions_do_not() {
+        assert!(!hold_open("ssh", &args("server")));
+        assert!(!hold_open("ssh", &args("-p 2222 -i key user@server")));
+        assert!(hold_open("ssh", &args("server uptime")));
+        assert!(hold_open("scp", &args("notes.txt server:")));
+        assert!(!hold_open("su", &[]));
+        assert!(hold_open("su", &args("-c whoami")));
+        assert!(hold_open("doas", &args("apt update")));
+        assert!(!hold_open("doas", &args("nano /etc/hosts")));
+        assert!(!hold_open("sftp", &args("server")));
+    }
+
+    #[test]
+    fn terminal_falls_back_to_the_system_default() {
+        assert_eq!(terminal_emulator(Some("kitty".to_string())), "kitty");
+        assert_eq!(terminal_emulator(Some(String::new())), "x-terminal-emulator");
+        assert_eq!(terminal_emulator(None), "x-terminal-emulator");
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, ExitStatus};
+use std::sync::mpsc;
+
+// A child's stdout and stderr read at the same time, each line tagged with the pipe
+// it came from and kept in the order it arrived, so compiler errors show up next to
+// the output they belong to instead of all together at the end. Programs writing to
+// a pipe may buffer their stdout, so the order is as good as their flushing allows.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// What `Command::output` gives, plus the lines of both pipes in the order they came
+pub struct Output {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub lines: Vec<(Stream, String)>,
+}
+
+/// Read both of `child`'s pipes, which must have been set to `Stdio::piped()`, and wait for it
+pub fn wait(mut child: Child) -> std::io::Result<Output> {
+    let (sender, receiver) = mpsc::channel();
+    let readers = [
+        child.stdout.take().map(|pipe| read_lines(pipe, Stream::Stdout, sender.clone())),
+        child.stderr.take().map(|pipe| read_lines(pipe, Stream::Stderr, sender.clone())),
+    ];
+    drop(sender);
+
+    let mut output = Output { status: ExitStatus::default(), stdout: Vec::new(), stderr: Vec::new(), lines: Vec::new() };
+    for (stream, bytes) in receiver {
+        match stream {
+            Stream::Stdout => output.stdout.extend_from_slice(&bytes),
+            Stream::Stderr => output.stderr.extend_from_slice(&bytes),
+        }
+        // Lines as `str::lines` splits them: without the `\n`, or a `\r\n`
+        let text = String::from_utf8_lossy(&bytes);
+        let line = text.strip_suffix('\n').map_or(&*text, |line| line.strip_suffix('\r').unwrap_or(line));
+        output.lines.push((stream, line.to_string()));
+    }
+    for reader in readers.into_iter().flatten() {
+        let _ = reader.join();
+    }
+    output.status = child.wait()?;
+    Ok(output)
+}
+
+fn read_lines(pipe: impl Read + Send + 'static, stream: Stream, lines: mpsc::Sender<(Stream, Vec<u8>)>) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut pipe = BufReader::new(pipe);
+        let mut line = Vec::new();
+        while pipe.read_until(b'\n', &mut line).is_ok_and(|read| read > 0) {
+            if lines.send((stream, std::mem::take(&mut line))).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn lines_keep_their_stream_and_order() {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg("echo one; sleep 0.05; echo two >&2; sleep 0.05; printf 'three\\r\\nfour'")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let output = wait(child).unwrap();
+        let lines: Vec<(Stream, &str)> = output.lines.iter().map(|(stream, line)| (*stream, line.as_str())).collect();
+        assert_eq!(lines, [(Stream::Stdout, "one"), (Stream::Stderr, "two"), (Stream::Stdout, "three"), (Stream::Stdout, "four")]);
+        assert_eq!(output.stdout, b"one\nthree\r\nfour");
+        assert_eq!(output.stderr, b"two\n");
+        assert!(output.status.success());
+    }
+}
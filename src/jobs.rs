@@ -0,0 +1,284 @@
+use std::io::{ErrorKind, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Instant;
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use crate::interleave::Stream;
+
+// Background jobs started with a trailing `&`, and programs that ask for a
+// password, which run as a job in the foreground.
+//
+// Each job is spawned with piped output; worker threads forward its output and,
+// once the child has been reaped, its exit code back to the UI over a channel.
+// Jobs run in a session of their own, without a controlling terminal, so a
+// password prompt shows up in their output instead of on the terminal Linara was
+// started from. Only the password programs get a stdin to send the password to;
+// every other job reads /dev/null, so `sort &` sees the end of its input instead
+// of waiting for more.
+
+// Programs run in the foreground with a stdin for the password they may ask for.
+// Only programs that read it from stdin (through PAM) belong here; su, ssh and
+// the like read /dev/tty and are opened in a terminal instead (see interactive.rs)
+const ASKS_FOR_PASSWORD: &[&str] = &["passwd", "chsh", "chfn"];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobStatus {
+    Running,
+    Stopped,
+    Done(i32),
+}
+
+impl JobStatus {
+    pub fn label(&self) -> String {
+        match self {
+            JobStatus::Running => "Running".to_string(),
+            JobStatus::Stopped => "Stopped".to_string(),
+            JobStatus::Done(0) => "Done".to_string(),
+            JobStatus::Done(code) => format!("Exit {}", code),
+        }
+    }
+}
+
+pub struct Job {
+    pub id: usize,
+    pub pid: u32,
+    pub command: String,
+    pub status: JobStatus,
+    pub foreground: bool, // Output goes straight to the terminal instead of being buffered
+    pub output: Vec<(Stream, String)>, // Output collected while the job runs in the background
+    pub started: Instant,
+    pub dir: String, // Where it was started, for links in its output
+    pub stdin: Option<ChildStdin>,
+}
+
+pub enum JobEvent {
+    Output { id: usize, line: String, stream: Stream },
+    Exited { id: usize, code: i32 },
+}
+
+/// Whether `program` may stop to ask for a password
+pub fn asks_for_password(program: &str) -> bool {
+    ASKS_FOR_PASSWORD.contains(&program)
+}
+
+/// Spawn `command` (program, arguments, directory and environment already set) and start
+/// the threads that report back on `events`. Returns the pid, and the job's stdin when
+/// `password_input` asks for one.
+pub fn spawn_job(id: usize, mut command: Command, events: Sender<JobEvent>, password_input: bool) -> std::io::Result<(u32, Option<ChildStdin>)> {
+    // SAFETY: setsid is async-signal-safe, and nothing else runs between fork and exec
+    unsafe {
+        command.pre_exec(|| nix::unistd::setsid().map(drop).map_err(std::io::Error::from));
+    }
+    let mut child = command
+        .stdin(if password_input { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    let stdin = child.stdin.take();
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let stderr_events = events.clone();
+    let stderr_thread = thread::spawn(move || {
+        if let Some(stderr) = stderr {
+            forward_lines(id, stderr, Stream::Stderr, &stderr_events);
+        }
+    });
+
+    thread::spawn(move || {
+        if let Some(stdout) = stdout {
+            forward_lines(id, stdout, Stream::Stdout, &events);
+        }
+        let _ = stderr_thread.join();
+        let code = child.wait().ok().and_then(|status| status.code()).unwrap_or(1);
+        let _ = events.send(JobEvent::Exited { id, code });
+    });
+
+    Ok((pid, stdin))
+}
+
+// Send each line as it completes. A password prompt has no newline after it, as the
+// program waits on the same line, so one is sent as soon as it has been written.
+fn forward_lines(id: usize, mut source: impl Read, stream: Stream, events: &Sender<JobEvent>) {
+    let send = |bytes: &[u8]| {
+        let text = String::from_utf8_lossy(bytes);
+        let line = text.trim_end_matches('\r');
+        line.is_empty() || events.send(JobEvent::Output { id, line: line.to_string(), stream }).is_ok()
+    };
+    let mut pending = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        pending.extend_from_slice(&buffer[..read]);
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            if !send(&line[..end]) {
+                return;
+            }
+        }
+        if is_password_prompt(&String::from_utf8_lossy(&pending)) {
+            if !send(&pending) {
+                return;
+            }
+            pending.clear();
+        }
+    }
+    send(&pending);
+}
+
+/// Whether a line of output is asking for a password or passphrase
+pub fn is_password_prompt(line: &str) -> bool {
+    let line = line.trim_end().to_lowercase();
+    line.ends_with(':') && (line.contains("password") || line.contains("passphrase"))
+}
+
+/// Parse a job reference (`%1`, `1`), defaulting to the most recent job
+pub fn parse_job_spec(spec: Option<&str>, jobs: &[Job]) -> Result<usize, String> {
+    match spec {
+        Some(spec) => spec.trim_start_matches('%').parse::<usize>()
+            .ok()
+            .filter(|id| jobs.iter().any(|job| job.id == *id))
+            .ok_or_else(|| format!("{}: no such job", spec)),
+        None => jobs.last().map(|job| job.id).ok_or_else(|| "current: no such job".to_string()),
+    }
+}
+
+/// Whether the process is currently stopped (state `T` in `/proc/<pid>/stat`)
+pub fn is_stopped(pid: u32) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|stat| stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().next().map(|state| state == "T")))
+        .unwrap_or(false)
+}
+
+/// Send SIGCONT so a stopped job carries on running
+pub fn resume(pid: u32) {
+    let _ = send_signal(pid, Signal::SIGCONT);
+}
+
+/// Parse a signal given as a number (`9`) or a name with or without `SIG` (`TERM`, `sighup`)
+pub fn parse_signal(spec: &str) -> Result<Signal, String> {
+    let invalid = || format!("{}: invalid signal specification", spec);
+    if let Ok(number) = spec.parse::<i32>() {
+        return Signal::try_from(number).map_err(|_| invalid());
+    }
+    let name = spec.to_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    name.parse::<Signal>().map_err(|_| invalid())
+}
+
+/// Signal names without the `SIG` prefix, for `kill -l`
+pub fn signal_names() -> Vec<&'static str> {
+    Signal::iterator().map(|signal| &signal.as_str()[3..]).collect()
+}
+
+/// Send `signal` to process `pid`
+pub fn send_signal(pid: u32, signal: Signal) -> Result<(), String> {
+    let pid = i32::try_from(pid).ok().filter(|pid| *pid > 0).ok_or_else(|| format!("{}: invalid process id", pid))?;
+    signal::kill(Pid::from_raw(pid), signal).map_err(|errno| match errno {
+        Errno::ESRCH => format!("({}) - No such process", pid),
+        Errno::EPERM => format!("({}) - Operation not permitted", pid),
+        other => format!("({}) - {}", pid, other.desc()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals_parse_by_name_or_number() {
+        assert_eq!(parse_signal("9"), Ok(Signal::SIGKILL));
+        assert_eq!(parse_signal("TERM"), Ok(Signal::SIGTERM));
+        assert_eq!(parse_signal("sighup"), Ok(Signal::SIGHUP));
+        assert!(parse_signal("BOGUS").is_err());
+        assert!(parse_signal("99").is_err());
+        assert!(signal_names().contains(&"INT"));
+    }
+
+    #[test]
+    fn password_prompts_are_recognised() {
+        assert!(is_password_prompt("[sudo] password for user: "));
+        assert!(is_password_prompt("Enter passphrase for key '/home/user/.ssh/id_ed25519':"));
+        assert!(!is_password_prompt("Password changed successfully"));
+        assert!(!is_password_prompt("Downloading:"));
+    }
+
+    #[test]
+    fn prompts_are_sent_without_waiting_for_a_newline() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        forward_lines(1, &b"checking\r\n\nPassword: "[..], Stream::Stdout, &sender);
+        let lines: Vec<String> = receiver.try_iter().map(|event| match event {
+            JobEvent::Output { line, .. } => line,
+            JobEvent::Exited { .. } => String::new(),
+        }).collect();
+        assert_eq!(lines, ["checking", "Password: "]);
+    }
+
+    #[test]
+    fn only_password_jobs_get_a_stdin() {
+        let exit_code = |receiver: &std::sync::mpsc::Receiver<JobEvent>| loop {
+            match receiver.recv_timeout(std::time::Duration::from_secs(5)) {
+                Ok(JobEvent::Exited { code, .. }) => break Some(code),
+                Ok(JobEvent::Output { .. }) => {}
+                Err(_) => break None,
+            }
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (_, stdin) = spawn_job(1, Command::new("cat"), sender, false).unwrap();
+        assert!(stdin.is_none());
+        assert_eq!(exit_code(&receiver), Some(0));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut reader = Command::new("sh");
+        reader.arg("-c").arg("read secret; echo \"got $secret\"");
+        let (_, stdin) = spawn_job(2, reader, sender, true).unwrap();
+        std::io::Write::write_all(&mut stdin.unwrap(), b"hunter2\n").unwrap();
+        match receiver.recv_timeout(std::time::Duration::from_secs(5)) {
+            Ok(JobEvent::Output { line, .. }) => assert_eq!(line, "got hunter2"),
+            _ => panic!("no output from the job"),
+        }
+        assert_eq!(exit_code(&receiver), Some(0));
+    }
+
+    #[test]
+    fn password_programs_run_without_a_terminal() {
+        // passwd goes through the same path as a real password change, but a user
+        // that doesn't exist makes it fail before anything is changed
+        if !std::path::Path::new("/usr/bin/passwd").exists() {
+            return;
+        }
+        assert!(asks_for_password("passwd"));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut passwd = Command::new("passwd");
+        passwd.arg("linara-no-such-user");
+        let (_, stdin) = spawn_job(1, passwd, sender, true).unwrap();
+        assert!(stdin.is_some());
+        let mut output = Vec::new();
+        let code = loop {
+            match receiver.recv_timeout(std::time::Duration::from_secs(5)) {
+                Ok(JobEvent::Output { line, .. }) => output.push(line),
+                Ok(JobEvent::Exited { code, .. }) => break code,
+                Err(_) => panic!("passwd hung waiting for a terminal"),
+            }
+        };
+        assert_ne!(code, 0);
+        assert!(output.iter().any(|line| line.contains("linara-no-such-user")), "{:?}", output);
+    }
+
+    #[test]
+    fn missing_processes_are_reported() {
+        assert!(send_signal(0, Signal::SIGTERM).is_err());
+        assert_eq!(send_signal(i32::MAX as u32, Signal::SIGTERM), Err(format!("({}) - No such process", i32::MAX)));
+    }
+}
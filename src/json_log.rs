@@ -0,0 +1,89 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Serialize;
+
+// `json-log <path>` appends one JSON object per line for every program run, so
+// a session can be analysed by other tools. serde_json escapes newlines and
+// control characters, so multi-line output stays on its line.
+
+/// One command's result as written to the log
+#[derive(Debug, Serialize)]
+pub struct Entry<'a> {
+    pub command: &'a str,
+    pub stdout: &'a str,
+    pub stderr: &'a str,
+    pub exit_code: i32,
+    pub duration_ms: u128,
+}
+
+impl<'a> Entry<'a> {
+    pub fn new(command: &'a str, stdout: &'a str, stderr: &'a str, exit_code: i32, duration: Duration) -> Self {
+        Self { command, stdout, stderr, exit_code, duration_ms: duration.as_millis() }
+    }
+
+    /// The entry as a single line of JSON, without the newline
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("log entries always serialize")
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonLog {
+    path: PathBuf,
+}
+
+impl JsonLog {
+    /// Start logging to `path`, creating it if needed; existing entries are kept
+    pub fn open(path: &Path) -> Result<Self, String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+        Ok(Self { path: path.to_path_buf() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &Entry) -> Result<(), String> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| e.to_string())?;
+        writeln!(file, "{}", entry.to_line()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_line_output_stays_on_one_line() {
+        let entry = Entry::new("printf 'a\\nb'", "a\nb\n", "warn: \"x\"\t\n", 0, Duration::from_millis(1500));
+        let line = entry.to_line();
+        assert!(!line.contains('\n'));
+        let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed["stdout"], "a\nb\n");
+        assert_eq!(parsed["stderr"], "warn: \"x\"\t\n");
+        assert_eq!(parsed["exit_code"], 0);
+        assert_eq!(parsed["duration_ms"], 1500);
+    }
+
+    #[test]
+    fn entries_are_appended() {
+        let path = std::env::temp_dir().join(format!("linara-json-log-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = JsonLog::open(&path).unwrap();
+        log.append(&Entry::new("true", "", "", 0, Duration::ZERO)).unwrap();
+        log.append(&Entry::new("false", "", "", 1, Duration::ZERO)).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().nth(1).unwrap().contains("\"exit_code\":1"));
+    }
+}
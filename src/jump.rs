@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+// Frecency-ranked directory jumping (`j proj`), like z/autojump.
+//
+// Every directory cd enters is recorded with a visit count and the time of the
+// last visit. A query matches directories whose path contains its words in order;
+// the best match is the most frequently and recently visited one.
+
+// Oldest entries are dropped once the database grows past this
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Visits {
+    pub count: u32,
+    pub last: u64, // Unix time of the latest visit
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JumpDatabase {
+    pub dirs: HashMap<String, Visits>,
+}
+
+impl JumpDatabase {
+    pub fn path() -> PathBuf {
+        crate::state::state_dir().join("dirs.json")
+    }
+
+    /// Load the database; tabs share it, so it is re-read for every lookup and update
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn record(&mut self, dir: &str, now: u64) {
+        let visits = self.dirs.entry(dir.to_string()).or_insert(Visits { count: 0, last: now });
+        visits.count += 1;
+        visits.last = now;
+
+        if self.dirs.len() > MAX_ENTRIES {
+            if let Some(oldest) = self.dirs.iter().min_by_key(|(_, visits)| visits.last).map(|(dir, _)| dir.clone()) {
+                self.dirs.remove(&oldest);
+            }
+        }
+    }
+
+    /// Best directory for `query`, skipping `exclude` (usually the current directory)
+    /// and directories that no longer exist
+    pub fn best_match(&self, query: &[String], exclude: &str, now: u64) -> Option<String> {
+        self.ranked(query, now)
+            .into_iter()
+            .find(|dir| dir != exclude && Path::new(dir).is_dir())
+    }
+
+    // Matching directories, best first
+    fn ranked(&self, query: &[String], now: u64) -> Vec<String> {
+        let mut matches: Vec<(f64, &String)> = self.dirs.iter()
+            .filter_map(|(dir, visits)| {
+                let weight = match_weight(dir, query)?;
+                Some((frecency(visits, now) * weight, dir))
+            })
+            .collect();
+        matches.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        matches.into_iter().map(|(_, dir)| dir.clone()).collect()
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Visit count weighted by how recent the last visit was, as z does
+fn frecency(visits: &Visits, now: u64) -> f64 {
+    let age = now.saturating_sub(visits.last);
+    let recency = match age {
+        0..=3_599 => 4.0,
+        3_600..=86_399 => 2.0,
+        86_400..=604_799 => 0.5,
+        _ => 0.25,
+    };
+    visits.count as f64 * recency
+}
+
+// Words must appear in order, case-insensitively. Matches whose last word lands
+// in the final path component count double, so `j src` prefers `.../src`.
+fn match_weight(dir: &str, query: &[String]) -> Option<f64> {
+    let lower = dir.to_lowercase();
+    let mut from = 0;
+    let mut last_at = 0;
+    for word in query {
+        let word = word.to_lowercase();
+        let at = from + lower[from..].find(&word)?;
+        last_at = at;
+        from = at + word.len();
+    }
+    let last_component_start = lower.rfind('/').map_or(0, |slash| slash + 1);
+    Some(if last_at >= last_component_start { 2.0 } else { 1.0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn words(query: &str) -> Vec<String> {
+        query.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn database(entries: &[(&str, u32, u64)]) -> JumpDatabase {
+        let dirs = entries.iter()
+            .map(|(dir, count, age)| (dir.to_string(), Visits { count: *count, last: NOW - age }))
+            .collect();
+        JumpDatabase { dirs }
+    }
+
+    #[test]
+    fn frequent_and_recent_directories_win() {
+        let db = database(&[
+            ("/home/ada/old-project", 10, 30 * 86_400),
+            ("/home/ada/project", 3, 60),
+        ]);
+        assert_eq!(db.ranked(&words("proj"), NOW), ["/home/ada/project", "/home/ada/old-project"]);
+    }
+
+    #[test]
+    fn words_match_in_order_and_prefer_the_last_component() {
+        let db = database(&[
+            ("/home/ada/src/linara", 5, 60),
+            ("/home/ada/linara/src", 5, 60),
+        ]);
+        assert_eq!(db.ranked(&words("linara src"), NOW), ["/home/ada/linara/src"]);
+        assert_eq!(db.ranked(&words("SRC"), NOW).first().map(String::as_str), Some("/home/ada/linara/src"));
+        assert!(db.ranked(&words("nothing"), NOW).is_empty());
+    }
+
+    #[test]
+    fn recording_counts_visits() {
+        let mut db = JumpDatabase::default();
+        db.record("/tmp", NOW - 10);
+        db.record("/tmp", NOW);
+        assert_eq!(db.dirs["/tmp"], Visits { count: 2, last: NOW });
+    }
+
+    #[test]
+    fn current_directory_is_skipped() {
+        let tmp = std::env::temp_dir().to_string_lossy().to_string();
+        let db = database(&[(&tmp, 5, 60)]);
+        assert_eq!(db.best_match(&words("tmp"), &tmp, NOW), None);
+        assert_eq!(db.best_match(&words("tmp"), "/", NOW), Some(tmp));
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use eframe::egui::{Key, Modifiers};
+
+// Keyboard shortcuts, as named actions looked up by key chord.
+//
+// The defaults below can be changed from the config file with lines like
+//   bind = ctrl+shift+a select-all
+//   bind = ctrl+a none
+// Enter, Backspace, the arrows and other plain editing keys are not remappable.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    ToggleAutocomplete,
+    CommandPalette,
+    HistoryPicker,
+    Filter,
+    ToggleFuzzy,
+    Copy,
+    CopyLastOutput,
+    CopyOrInterrupt, // Copy the selection or line, or interrupt when there is nothing to copy
+    Cut,
+    Paste,
+    SelectAll,
+    LineStart,
+    LineEnd,
+    DeleteToStart,
+    DeleteToEnd,
+    Eof,
+    Clear,
+    ScrollPageUp,
+    ScrollPageDown,
+    ScrollUp, // A few lines at a time
+    ScrollDown,
+    ScrollTop,
+    ScrollBottom,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+    ToggleSplit,
+    Preferences,
+}
+
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("toggle-autocomplete", Action::ToggleAutocomplete),
+    ("command-palette", Action::CommandPalette),
+    ("history-picker", Action::HistoryPicker),
+    ("filter", Action::Filter),
+    ("toggle-fuzzy", Action::ToggleFuzzy),
+    ("copy", Action::Copy),
+    ("copy-last-output", Action::CopyLastOutput),
+    ("copy-or-interrupt", Action::CopyOrInterrupt),
+    ("cut", Action::Cut),
+    ("paste", Action::Paste),
+    ("select-all", Action::SelectAll),
+    ("line-start", Action::LineStart),
+    ("line-end", Action::LineEnd),
+    ("delete-to-start", Action::DeleteToStart),
+    ("delete-to-end", Action::DeleteToEnd),
+    ("eof", Action::Eof),
+    ("clear", Action::Clear),
+    ("scroll-page-up", Action::ScrollPageUp),
+    ("scroll-page-down", Action::ScrollPageDown),
+    ("scroll-up", Action::ScrollUp),
+    ("scroll-down", Action::ScrollDown),
+    ("scroll-top", Action::ScrollTop),
+    ("scroll-bottom", Action::ScrollBottom),
+    ("new-tab", Action::NewTab),
+    ("close-tab", Action::CloseTab),
+    ("next-tab", Action::NextTab),
+    ("prev-tab", Action::PrevTab),
+    ("toggle-split", Action::ToggleSplit),
+    ("preferences", Action::Preferences),
+];
+
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl+space", "toggle-autocomplete"),
+    ("ctrl+p", "command-palette"),
+    ("ctrl+r", "history-picker"),
+    ("ctrl+shift+f", "filter"),
+    ("ctrl+f", "toggle-fuzzy"),
+    ("ctrl+shift+c", "copy"),
+    ("ctrl+shift+o", "copy-last-output"),
+    ("ctrl+c", "copy-or-interrupt"),
+    ("ctrl+x", "cut"),
+    ("ctrl+v", "paste"),
+    ("ctrl+shift+a", "select-all"),
+    ("ctrl+a", "line-start"),
+    ("ctrl+e", "line-end"),
+    ("ctrl+u", "delete-to-start"),
+    ("ctrl+k", "delete-to-end"),
+    ("ctrl+d", "eof"),
+    ("ctrl+l", "clear"),
+    ("pageup", "scroll-page-up"),
+    ("pagedown", "scroll-page-down"),
+    ("shift+pageup", "scroll-up"),
+    ("shift+pagedown", "scroll-down"),
+    ("ctrl+home", "scroll-top"),
+    ("ctrl+end", "scroll-bottom"),
+    ("ctrl+t", "new-tab"),
+    ("ctrl+w", "close-tab"),
+    ("ctrl+tab", "next-tab"),
+    ("ctrl+shift+tab", "prev-tab"),
+    ("ctrl+shift+d", "toggle-split"),
+    ("ctrl+comma", "preferences"),
+];
+
+impl Action {
+    /// Actions handled by the window rather than the focused session
+    pub fn is_window_action(self) -> bool {
+        matches!(self, Action::NewTab | Action::CloseTab | Action::NextTab | Action::PrevTab | Action::ToggleSplit | Action::Preferences)
+    }
+
+    /// Actions that move the scrollback rather than edit the input line
+    pub fn is_scroll(self) -> bool {
+        matches!(self, Action::ScrollPageUp | Action::ScrollPageDown | Action::ScrollUp | Action::ScrollDown | Action::ScrollTop | Action::ScrollBottom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub key: Key,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Chord {
+    /// Parse `ctrl+shift+f`; modifier and key names are case-insensitive
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut chord = Chord { key: Key::Escape, ctrl: false, shift: false, alt: false };
+        let mut key = None;
+        for part in spec.split('+').map(str::trim) {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                _ if key.is_some() => return None,
+                _ => key = Some(parse_key(part)?),
+            }
+        }
+        chord.key = key?;
+        Some(chord)
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        Modifiers { ctrl: self.ctrl, shift: self.shift, alt: self.alt, ..Modifiers::NONE }
+    }
+}
+
+// Key names as egui spells them (`Space`, `Tab`, `PageUp`, `F1`, `A`), in any case,
+// or a symbol such as `-`
+fn parse_key(name: &str) -> Option<Key> {
+    Key::ALL.iter()
+        .find(|key| key.name().eq_ignore_ascii_case(name))
+        .copied()
+        .or_else(|| Key::from_name(name))
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Chord, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap { bindings: HashMap::new() };
+        for (chord, action) in DEFAULT_BINDINGS {
+            keymap.bind(chord, action).expect("default bindings are valid");
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Bind `chord` to the action named `action`; `none` removes the binding
+    pub fn bind(&mut self, chord: &str, action: &str) -> Result<(), String> {
+        let parsed = Chord::parse(chord).ok_or_else(|| format!("unknown key '{}'", chord))?;
+        if action == "none" {
+            self.bindings.remove(&parsed);
+            return Ok(());
+        }
+        let action = ACTION_NAMES.iter()
+            .find(|(name, _)| *name == action)
+            .map(|(_, action)| *action)
+            .ok_or_else(|| format!("unknown action '{}'", action))?;
+        self.bindings.insert(parsed, action);
+        Ok(())
+    }
+
+    /// The action bound to `key` with `modifiers` held, if any. A shifted chord
+    /// with no binding of its own falls back to the unshifted one, so Ctrl+Shift+V pastes.
+    pub fn action(&self, key: Key, modifiers: Modifiers) -> Option<Action> {
+        let chord = Chord { key, ctrl: modifiers.ctrl, shift: modifiers.shift, alt: modifiers.alt };
+        self.bindings.get(&chord)
+            .or_else(|| chord.shift.then(|| self.bindings.get(&Chord { shift: false, ..chord })).flatten())
+            .copied()
+    }
+
+    /// Window-level bindings, shifted chords first so `ctrl+tab` doesn't swallow `ctrl+shift+tab`
+    pub fn window_bindings(&self) -> Vec<(Chord, Action)> {
+        let mut bindings: Vec<(Chord, Action)> = self.bindings.iter()
+            .filter(|(_, action)| action.is_window_action())
+            .map(|(chord, action)| (*chord, *action))
+            .collect();
+        bindings.sort_by_key(|(chord, _)| !chord.shift);
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL_SHIFT: Modifiers = Modifiers { shift: true, ..Modifiers::CTRL };
+
+    #[test]
+    fn default_bindings_are_looked_up_exactly() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(Key::A, Modifiers::CTRL), Some(Action::LineStart));
+        assert_eq!(keymap.action(Key::A, CTRL_SHIFT), Some(Action::SelectAll));
+        assert_eq!(keymap.action(Key::A, Modifiers::NONE), None);
+        assert_eq!(keymap.action(Key::V, CTRL_SHIFT), Some(Action::Paste));
+        assert_eq!(keymap.action(Key::PageUp, Modifiers::NONE), Some(Action::ScrollPageUp));
+        assert_eq!(keymap.action(Key::PageUp, Modifiers::SHIFT), Some(Action::ScrollUp));
+    }
+
+    #[test]
+    fn config_can_rebind_and_unbind() {
+        let mut keymap = Keymap::default();
+        keymap.bind("Ctrl+A", "select-all").unwrap();
+        keymap.bind("ctrl+l", "none").unwrap();
+        assert_eq!(keymap.action(Key::A, Modifiers::CTRL), Some(Action::SelectAll));
+        assert_eq!(keymap.action(Key::L, Modifiers::CTRL), None);
+        assert!(keymap.bind("ctrl+nothing", "copy").is_err());
+        assert!(keymap.bind("ctrl+b", "launch-rockets").is_err());
+    }
+
+    #[test]
+    fn chords_parse_named_keys() {
+        assert_eq!(Chord::parse("ctrl+space").map(|c| c.key), Some(Key::Space));
+        assert_eq!(Chord::parse("alt+F2").map(|c| (c.key, c.alt)), Some((Key::F2, true)));
+        assert_eq!(Chord::parse("ctrl+shift+tab").map(|c| (c.key, c.shift)), Some((Key::Tab, true)));
+        assert_eq!(Chord::parse("ctrl+a+b"), None);
+    }
+
+    #[test]
+    fn shifted_window_bindings_come_first() {
+        let bindings = Keymap::default().window_bindings();
+        let prev = bindings.iter().position(|(_, action)| *action == Action::PrevTab).unwrap();
+        let next = bindings.iter().position(|(_, action)| *action == Action::NextTab).unwrap();
+        assert!(prev < next);
+    }
+}
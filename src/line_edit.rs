@@ -0,0 +1,251 @@
+// Editing helpers for the input line.
+//
+// `cursor_pos` and the selection bounds are byte offsets into `input_buffer`,
+// so every step has to land on a UTF-8 character boundary or slicing panics.
+
+use std::ops::Range;
+
+/// Clamp `pos` to the text and move it back onto a character boundary
+pub fn floor_char_boundary(text: &str, pos: usize) -> usize {
+    let mut pos = pos.min(text.len());
+    while !text.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    pos
+}
+
+/// Byte offset of the character before `pos` (0 at the start of the text)
+pub fn prev_char_boundary(text: &str, pos: usize) -> usize {
+    let pos = floor_char_boundary(text, pos);
+    text[..pos].char_indices().next_back().map_or(0, |(i, _)| i)
+}
+
+/// Byte offset just past the character at `pos` (the text length at the end)
+pub fn next_char_boundary(text: &str, pos: usize) -> usize {
+    let pos = floor_char_boundary(text, pos);
+    text[pos..].chars().next().map_or(text.len(), |c| pos + c.len_utf8())
+}
+
+/// Order a selection's anchor and end and snap both onto character boundaries
+pub fn selection_range(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    (floor_char_boundary(text, start), floor_char_boundary(text, end))
+}
+
+/// Insert `ch` at the cursor and move the cursor past it
+pub fn insert_char(text: &mut String, cursor: &mut usize, ch: char) {
+    *cursor = floor_char_boundary(text, *cursor);
+    text.insert(*cursor, ch);
+    *cursor += ch.len_utf8();
+}
+
+/// Backspace: remove the character before the cursor. Returns false at the start of the text.
+pub fn delete_before(text: &mut String, cursor: &mut usize) -> bool {
+    let end = floor_char_boundary(text, *cursor);
+    if end == 0 {
+        return false;
+    }
+    let start = prev_char_boundary(text, end);
+    text.replace_range(start..end, "");
+    *cursor = start;
+    true
+}
+
+/// Delete: remove the character at the cursor. Returns false at the end of the text.
+pub fn delete_after(text: &mut String, cursor: &mut usize) -> bool {
+    let start = floor_char_boundary(text, *cursor);
+    *cursor = start;
+    if start >= text.len() {
+        return false;
+    }
+    let end = next_char_boundary(text, start);
+    text.replace_range(start..end, "");
+    true
+}
+
+/// Drop the `$ ` or `# ` prompt marker that commands copied from a tutorial start with.
+/// Every non-blank line has to carry the same marker, so a pasted script whose
+/// `# ` lines are comments is left alone.
+pub fn strip_prompt_markers(text: &str) -> String {
+    let marker = ["$ ", "# "].into_iter().find(|marker| {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .all(|line| line.trim_start().starts_with(marker))
+    });
+    let Some(marker) = marker.filter(|_| !text.trim().is_empty()) else {
+        return text.to_string();
+    };
+    text.split('\n')
+        .map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            line[indent..].strip_prefix(marker).unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Fish-style autosuggestion: what the newest history entry starting with `input` adds to it
+pub fn history_hint<'a>(history: &'a [String], input: &str) -> Option<&'a str> {
+    if input.trim().is_empty() {
+        return None;
+    }
+    history.iter().rev()
+        .find_map(|entry| entry.strip_prefix(input))
+        .filter(|rest| !rest.is_empty())
+}
+
+/// The word being completed: the one the cursor is in or just after. Returns its byte range
+/// (which may run past the cursor) and how many words come before it.
+pub fn word_at(text: &str, cursor: usize) -> (Range<usize>, usize) {
+    let cursor = floor_char_boundary(text, cursor);
+    let start = text[..cursor].rfind(char::is_whitespace).map_or(0, |i| next_char_boundary(text, i));
+    let end = text[cursor..].find(char::is_whitespace).map_or(text.len(), |i| cursor + i);
+    (start..end, text[..start].split_whitespace().count())
+}
+
+/// Put `replacement` in place of `range`, returning the range it now covers
+pub fn replace_word(text: &mut String, range: Range<usize>, replacement: &str) -> Range<usize> {
+    text.replace_range(range.clone(), replacement);
+    range.start..range.start + replacement.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_str(text: &mut String, cursor: &mut usize, input: &str) {
+        for ch in input.chars() {
+            insert_char(text, cursor, ch);
+        }
+    }
+
+    #[test]
+    fn inserts_multibyte_characters_at_cursor() {
+        let mut text = String::new();
+        let mut cursor = 0;
+        type_str(&mut text, &mut cursor, "café");
+        assert_eq!(text, "café");
+        assert_eq!(cursor, text.len());
+
+        // Insert an emoji in the middle, right after "ca"
+        cursor = 2;
+        insert_char(&mut text, &mut cursor, '🚀');
+        assert_eq!(text, "ca🚀fé");
+        assert_eq!(cursor, 2 + '🚀'.len_utf8());
+    }
+
+    #[test]
+    fn backspace_removes_whole_characters() {
+        let mut text = "añ🚀é".to_string();
+        let mut cursor = text.len();
+        assert!(delete_before(&mut text, &mut cursor));
+        assert_eq!(text, "añ🚀");
+        assert!(delete_before(&mut text, &mut cursor));
+        assert_eq!(text, "añ");
+        assert!(delete_before(&mut text, &mut cursor));
+        assert!(delete_before(&mut text, &mut cursor));
+        assert_eq!(text, "");
+        assert_eq!(cursor, 0);
+        assert!(!delete_before(&mut text, &mut cursor));
+    }
+
+    #[test]
+    fn delete_removes_character_under_cursor() {
+        let mut text = "é🚀x".to_string();
+        let mut cursor = 0;
+        assert!(delete_after(&mut text, &mut cursor));
+        assert_eq!(text, "🚀x");
+        assert!(delete_after(&mut text, &mut cursor));
+        assert_eq!(text, "x");
+        cursor = 1;
+        assert!(!delete_after(&mut text, &mut cursor));
+    }
+
+    #[test]
+    fn cursor_inside_a_character_is_snapped_back() {
+        let mut text = "é".to_string();
+        // Byte 1 is in the middle of 'é'
+        let mut cursor = 1;
+        insert_char(&mut text, &mut cursor, 'x');
+        assert_eq!(text, "xé");
+
+        let mut cursor = 2;
+        assert!(delete_before(&mut text, &mut cursor));
+        assert_eq!(text, "é");
+    }
+
+    #[test]
+    fn select_and_copy_after_typing_accented_character() {
+        // Regression test: arrow keys used to step by one byte and slicing panicked
+        let mut text = String::new();
+        let mut cursor = 0;
+        type_str(&mut text, &mut cursor, "é");
+
+        // Left arrow, then Shift+Right to select the character again
+        cursor = prev_char_boundary(&text, cursor);
+        assert_eq!(cursor, 0);
+        let anchor = cursor;
+        cursor = next_char_boundary(&text, cursor);
+        let (start, end) = selection_range(&text, anchor, cursor);
+        assert_eq!(&text[start..end], "é");
+
+        // A stale selection ending mid-character is snapped instead of panicking
+        let (start, end) = selection_range(&text, 1, 0);
+        assert_eq!(&text[start..end], "");
+    }
+
+    #[test]
+    fn boundaries_step_over_multibyte_characters() {
+        let text = "a🚀b";
+        assert_eq!(next_char_boundary(text, 1), 5);
+        assert_eq!(prev_char_boundary(text, 5), 1);
+        assert_eq!(prev_char_boundary(text, 0), 0);
+        assert_eq!(next_char_boundary(text, text.len()), text.len());
+    }
+
+    #[test]
+    fn tutorial_prompt_markers_are_stripped() {
+        assert_eq!(strip_prompt_markers("$ sudo apt update"), "sudo apt update");
+        assert_eq!(strip_prompt_markers("# apt install git\n"), "apt install git\n");
+        assert_eq!(strip_prompt_markers("  $ cd src\n$ make\n"), "cd src\nmake\n");
+        // Mixed lines are probably a script or copied output, so they stay as they are
+        assert_eq!(strip_prompt_markers("# build it\nmake\n"), "# build it\nmake\n");
+        assert_eq!(strip_prompt_markers("echo $ HOME"), "echo $ HOME");
+        assert_eq!(strip_prompt_markers("$HOME"), "$HOME");
+    }
+
+    #[test]
+    fn history_hint_completes_from_the_newest_match() {
+        let history = vec!["git status".to_string(), "git stash pop".to_string(), "ls".to_string()];
+        assert_eq!(history_hint(&history, "git st"), Some("ash pop"));
+        assert_eq!(history_hint(&history, "git status"), None);
+        assert_eq!(history_hint(&history, "cargo"), None);
+        assert_eq!(history_hint(&history, ""), None);
+    }
+
+    #[test]
+    fn completion_uses_the_word_at_the_cursor() {
+        let text = "git chec --verbose";
+        assert_eq!(word_at(text, 8), (4..8, 1));
+        assert_eq!(&text[4..8], "chec");
+        // Inside a word, the part before the cursor is the prefix and the whole word is replaced
+        assert_eq!(word_at("git checkout", 6), (4..12, 1));
+        assert_eq!(word_at("gi status", 2), (0..2, 0));
+        // After a space a new, empty word starts
+        assert_eq!(word_at("ls  -l", 3), (3..3, 1));
+        assert_eq!(word_at("", 0), (0..0, 0));
+    }
+
+    #[test]
+    fn completing_mid_line_keeps_the_rest() {
+        let mut text = "git chec --verbose".to_string();
+        let (range, _) = word_at(&text, 8);
+        assert_eq!(replace_word(&mut text, range, "checkout"), 4..12);
+        assert_eq!(text, "git checkout --verbose");
+
+        let mut text = "cat é/fi | wc -l".to_string();
+        let (range, _) = word_at(&text, "cat é/fi".len());
+        replace_word(&mut text, range, "é/file.txt");
+        assert_eq!(text, "cat é/file.txt | wc -l");
+    }
+}
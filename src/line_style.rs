@@ -0,0 +1,96 @@
+use eframe::egui::Color32;
+use crate::linkify::{self, Segment};
+
+// How a scrollback line is drawn. Working it out means a run of prefix checks and a
+// scan for links, so it's done once when the line is added (or its text changes)
+// and stored on the line, instead of for every visible line on every frame.
+
+/// Normal output
+pub const TEXT: Color32 = Color32::from_rgb(220, 220, 220);
+const ERROR: Color32 = Color32::from_rgb(255, 100, 100);
+const INPUT: Color32 = Color32::from_rgb(255, 255, 100);
+const PROMPT: Color32 = Color32::from_rgb(100, 255, 100);
+
+// Banner fields get a colored label and a white value
+const FIELDS: &[(&str, Color32)] = &[
+    ("OS:", Color32::from_rgb(100, 150, 255)),
+    ("Kernel:", Color32::from_rgb(150, 100, 255)),
+    ("Uptime:", Color32::from_rgb(255, 200, 100)),
+    ("Memory:", Color32::from_rgb(255, 150, 100)),
+    ("CPU:", Color32::from_rgb(255, 100, 255)),
+    ("Terminal:", Color32::from_rgb(100, 255, 255)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineStyle {
+    /// One color; `links` says whether it has paths or URLs to make clickable
+    Text { color: Color32, links: bool },
+    /// The legacy `┌─ 💻 user ◦ 📁 dir` prompt, each part in its own color
+    PromptParts,
+    /// The banner logo, its █ blocks in rainbow colors
+    Art,
+    /// A banner `Label: value` line; `label` is the label's length, colon included
+    Field { label: usize, color: Color32 },
+    /// The banner box's top and bottom edges
+    Border,
+    /// A `$ command` line from the banner
+    Command,
+}
+
+impl LineStyle {
+    pub fn classify(text: &str, is_input: bool, is_prompt: bool) -> Self {
+        if is_prompt && text.starts_with("┌─") {
+            Self::PromptParts
+        } else if text.contains("██") {
+            Self::Art
+        } else if let Some((label, color)) = FIELDS.iter().find(|(label, _)| text.starts_with(label)) {
+            Self::Field { label: label.len(), color: *color }
+        } else if text.starts_with("┌─") && text.contains("System Information") || text.starts_with("└─") {
+            Self::Border
+        } else if text.starts_with("$ ") {
+            Self::Command
+        } else {
+            let color = if text.starts_with("ERROR:") {
+                ERROR
+            } else if is_prompt {
+                PROMPT
+            } else if is_input {
+                INPUT
+            } else {
+                TEXT
+            };
+            Self::Text { color, links: has_links(text) }
+        }
+    }
+
+    /// A line a program wrote to stderr, shown without an `ERROR:` prefix
+    pub fn error(text: &str) -> Self {
+        Self::Text { color: ERROR, links: has_links(text) }
+    }
+}
+
+fn has_links(text: &str) -> bool {
+    linkify::linkify(text).iter().any(|segment| matches!(segment, Segment::Link(..)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banner_lines_get_their_own_styles() {
+        assert_eq!(LineStyle::classify("  ██████  ", false, false), LineStyle::Art);
+        assert_eq!(LineStyle::classify("Kernel: 6.1.0", false, false), LineStyle::Field { label: 7, color: FIELDS[1].1 });
+        assert_eq!(LineStyle::classify("┌─ System Information ─┐", false, false), LineStyle::Border);
+        assert_eq!(LineStyle::classify("$ neofetch", false, false), LineStyle::Command);
+        assert_eq!(LineStyle::classify("┌─ 💻 user ◦ 📁 ~", false, true), LineStyle::PromptParts);
+    }
+
+    #[test]
+    fn other_lines_are_colored_text() {
+        assert_eq!(LineStyle::classify("ERROR: no such file", true, false), LineStyle::Text { color: ERROR, links: false });
+        assert_eq!(LineStyle::classify("hello", true, false), LineStyle::Text { color: INPUT, links: false });
+        assert_eq!(LineStyle::classify("see ./src/main.rs", false, false), LineStyle::Text { color: TEXT, links: true });
+        assert_eq!(LineStyle::error("warning: unused import in ./src/main.rs"), LineStyle::Text { color: ERROR, links: true });
+    }
+}
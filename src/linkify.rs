@@ -0,0 +1,83 @@
+// Detection of URLs and file paths in command output.
+//
+// Matching is deliberately conservative: only whitespace-separated words that
+// start with `http://`/`https://` or an explicit path prefix (`/`, `~/`, `./`,
+// `../`) are considered, so ordinary prose like "and/or" stays plain text.
+
+#[derive(Clone, PartialEq)]
+pub enum Link {
+    Url(String),
+    Path(String), // Path as written, without any `:line:col` suffix
+}
+
+pub enum Segment<'a> {
+    Text(&'a str),
+    Link(&'a str, Link),
+}
+
+const LEADING_PUNCTUATION: &[char] = &['(', '[', '<', '"', '\'', '`'];
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\'', '`'];
+
+/// Split a line into plain text and link segments
+pub fn linkify(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut plain_start = 0;
+
+    for (word_start, word) in words(text) {
+        let trimmed = word.trim_start_matches(LEADING_PUNCTUATION);
+        let start = word_start + (word.len() - trimmed.len());
+        let trimmed = trimmed.trim_end_matches(TRAILING_PUNCTUATION);
+        let Some(link) = detect(trimmed) else { continue };
+
+        if plain_start < start {
+            segments.push(Segment::Text(&text[plain_start..start]));
+        }
+        segments.push(Segment::Link(trimmed, link));
+        plain_start = start + trimmed.len();
+    }
+
+    if plain_start < text.len() {
+        segments.push(Segment::Text(&text[plain_start..]));
+    }
+    segments
+}
+
+// Whitespace-separated words with their byte offsets
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_whitespace().map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+fn detect(word: &str) -> Option<Link> {
+    if let Some(rest) = word.strip_prefix("https://").or_else(|| word.strip_prefix("http://")) {
+        let host = rest.split('/').next().unwrap_or("");
+        if host.contains('.') || host.starts_with("localhost") {
+            return Some(Link::Url(word.to_string()));
+        }
+        return None;
+    }
+
+    let has_prefix = ["/", "~/", "./", "../"].iter().any(|prefix| word.starts_with(prefix));
+    if !has_prefix || word.len() < 2 || word.contains("//") {
+        return None;
+    }
+
+    let path = strip_line_suffix(word);
+    let is_path_char = |c: char| c.is_alphanumeric() || "/._-~+@%".contains(c);
+    if path.chars().all(is_path_char) && path.chars().any(|c| c.is_alphanumeric()) {
+        Some(Link::Path(path.to_string()))
+    } else {
+        None
+    }
+}
+
+// Drop a compiler-style `:line` or `:line:col` location from the end of a path
+fn strip_line_suffix(word: &str) -> &str {
+    let mut path = word;
+    for _ in 0..2 {
+        match path.rsplit_once(':') {
+            Some((head, tail)) if !tail.is_empty() && tail.chars().all(|c| c.is_ascii_digit()) => path = head,
+            _ => break,
+        }
+    }
+    path
+}
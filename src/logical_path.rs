@@ -0,0 +1,57 @@
+use std::path::{Component, Path, PathBuf};
+
+// The current directory as the user got there, symlinks and all, like bash's $PWD.
+// `cd` joins its target onto it and drops `.` and `..` by name, so `cd link/..`
+// comes back to where it started rather than to the link target's parent. The
+// physical path, with symlinks resolved, is what `pwd -P` shows.
+
+/// `target` taken from the logical directory `base`, with `.` and `..` resolved by name
+pub fn join(base: &str, target: &str) -> PathBuf {
+    let mut path = PathBuf::from("/");
+    for component in Path::new(base).join(target).components() {
+        match component {
+            Component::ParentDir => {
+                path.pop();
+            }
+            Component::Normal(name) => path.push(name),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    path
+}
+
+/// `$PWD` when it names the directory the process started in, so a terminal opened
+/// through a symlink keeps showing it
+pub fn inherited(physical: &Path) -> Option<String> {
+    let pwd = std::env::var("PWD").ok().filter(|pwd| pwd.starts_with('/'))?;
+    let same = Path::new(&pwd).canonicalize().ok()? == physical.canonicalize().ok()?;
+    same.then(|| join(&pwd, ".").to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_are_resolved_by_name() {
+        assert_eq!(join("/home/user/link", ".."), PathBuf::from("/home/user"));
+        assert_eq!(join("/home/user", "./src/../docs/"), PathBuf::from("/home/user/docs"));
+        assert_eq!(join("/home/user", "/tmp/./x"), PathBuf::from("/tmp/x"));
+        assert_eq!(join("/", "../.."), PathBuf::from("/"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_kept() {
+        let dir = std::env::temp_dir().join(format!("linara-logical-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("real/inner")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+        let base = dir.to_string_lossy().to_string();
+        let logical = join(&base, "link/inner");
+        assert_eq!(logical, dir.join("link/inner"));
+        assert_eq!(logical.canonicalize().unwrap(), dir.join("real/inner").canonicalize().unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
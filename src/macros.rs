@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+// Named natural-language requests: after `macro deploy = build and push the docker
+// image`, typing `deploy` sends the phrase to the AI once and then reruns the
+// command it answered with. Macros come from `macro <name> = <phrase>` lines in
+// the config and from the `macro` builtin; the builtin's definitions and every
+// resolved command are kept in macros.json in the state directory.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub phrase: String,
+    /// What the AI turned the phrase into, once it has been asked
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Macros {
+    pub macros: BTreeMap<String, Macro>,
+}
+
+impl Macros {
+    pub fn path() -> PathBuf {
+        crate::state::state_dir().join("macros.json")
+    }
+
+    /// The saved macros with the config's definitions on top. Read once at startup, and
+    /// again before each change, since other windows write the same file.
+    pub fn load(configured: &[(String, String)]) -> Self {
+        let mut macros: Self = std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        for (name, phrase) in configured {
+            macros.define(name, phrase);
+        }
+        macros
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)
+    }
+
+    /// Add or change a macro; a new phrase has to be resolved again
+    pub fn define(&mut self, name: &str, phrase: &str) {
+        if self.macros.get(name).is_some_and(|existing| existing.phrase == phrase) {
+            return;
+        }
+        self.macros.insert(name.to_string(), Macro { phrase: phrase.to_string(), command: None });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    /// Remember the command the AI gave for `name`'s phrase
+    pub fn resolve(&mut self, name: &str, command: &str) {
+        if let Some(entry) = self.macros.get_mut(name) {
+            entry.command = Some(command.to_string());
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.macros.remove(name).is_some()
+    }
+}
+
+/// `name = phrase` from a `macro` line; the phrase may be quoted
+pub fn parse_definition(text: &str) -> Option<(String, String)> {
+    let (name, phrase) = text.split_once('=')?;
+    let name = name.trim();
+    let phrase = phrase.trim();
+    let phrase = phrase.strip_prefix('"').and_then(|p| p.strip_suffix('"')).unwrap_or(phrase).trim();
+    let valid_name = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    (valid_name && !phrase.is_empty()).then(|| (name.to_string(), phrase.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_are_parsed() {
+        let expected = Some(("deploy".to_string(), "build and push the docker image".to_string()));
+        assert_eq!(parse_definition("deploy = \"build and push the docker image\""), expected);
+        assert_eq!(parse_definition("deploy=build and push the docker image"), expected);
+        assert_eq!(parse_definition("two words = x"), None);
+        assert_eq!(parse_definition("deploy ="), None);
+        assert_eq!(parse_definition("deploy"), None);
+    }
+
+    #[test]
+    fn a_new_phrase_forgets_the_resolved_command() {
+        let mut macros = Macros::default();
+        macros.define("logs", "show the last nginx errors");
+        macros.resolve("logs", "tail -n 50 /var/log/nginx/error.log");
+        macros.define("logs", "show the last nginx errors");
+        assert!(macros.get("logs").is_some_and(|entry| entry.command.is_some()));
+        macros.define("logs", "show nginx errors from today");
+        assert_eq!(macros.get("logs").and_then(|entry| entry.command.clone()), None);
+        assert!(macros.remove("logs"));
+        assert!(macros.get("logs").is_none());
+    }
+}
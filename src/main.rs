@@ -1,36 +1,156 @@
 use eframe::egui;
 use std::collections::{VecDeque, HashMap};
+use std::io::Write;
 use std::process::Command;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::env;
 use std::os::unix::fs::PermissionsExt;
-use crate::ai_assistant::AIAssistant;
+use crate::ai_assistant::{AIAssistant, AIResponse, AiError, Usage};
+use crate::ai_cache::AiCache;
+use crate::ai_ignore::AiIgnore;
+use crate::ai_script::AiScript;
+use crate::bell::Bell;
+use crate::builtins::{Call, Flow};
+use crate::config::{Config, CursorStyle, LineWrap, Theme, WindowGeometry};
+use crate::copy_output::Row;
+use crate::explain::Explanations;
+use crate::filter::LineFilter;
+use crate::guard::CommandGuard;
+use crate::history::HistoryFilter;
+use crate::history_picker::HistoryPicker;
+use crate::interleave::Stream;
+use crate::jobs::{Job, JobEvent, JobStatus};
+use crate::json_log::{Entry, JsonLog};
+use crate::jump::JumpDatabase;
+use crate::keymap::{Action, Keymap};
+use crate::line_style::LineStyle;
+use crate::linkify::{Link, Segment};
+use crate::macros::{Macro, Macros};
+use crate::notify::Finished;
+use crate::packages::PackageManager;
+use crate::preferences::Preferences;
+use crate::prompt::{PromptInfo, PromptLine, PromptSegment, SegmentKind};
+use crate::redirect::{Input, StdinSource};
+use crate::state::{SavedSession, SavedTab};
+use crate::suggestion::{Suggestion, SuggestionKind};
+use crate::system_info::{Resources, SystemInfo};
+use crate::tabs::Layout;
 
 pub mod ai_assistant;
+pub mod ai_cache;
+pub mod ai_ignore;
+pub mod ai_script;
+pub mod bell;
+pub mod builtins;
+pub mod calc;
+pub mod cli;
+pub mod comment;
+pub mod config;
+pub mod copy_output;
+pub mod examples;
+pub mod explain;
+pub mod filter;
+pub mod flags;
+pub mod fuzzy;
+pub mod guard;
+pub mod history;
+pub mod history_picker;
+pub mod interactive;
+pub mod interleave;
+pub mod jobs;
+pub mod json_log;
+pub mod jump;
+pub mod keymap;
+pub mod line_edit;
+pub mod line_style;
+pub mod linkify;
+pub mod logical_path;
+pub mod macros;
+pub mod notify;
+pub mod packages;
+pub mod preferences;
+pub mod prompt;
+pub mod redirect;
+pub mod shell;
+pub mod substitute;
+pub mod suggestion;
+pub mod sudo;
+pub mod system_info;
+pub mod state;
+pub mod tabs;
+
+// Pastes above either limit need a y/n confirmation before they are inserted
+const LARGE_PASTE_CHARS: usize = 1000;
+const LARGE_PASTE_LINES: usize = 10;
+// Maximum number of entries listed in the Ctrl+P command palette
+const PALETTE_MAX_RESULTS: usize = 50;
+// Points from the bottom of the scrollback that still count as being at the bottom
+const SCROLL_LOCK_SLACK: f32 = 4.0;
+// How long the pane stays lit after a visual bell
+const BELL_FLASH: Duration = Duration::from_millis(150);
+// Lines moved by Shift+PageUp and Shift+PageDown
+const SCROLL_STEP_LINES: f32 = 3.0;
+// Terminal background; `background_alpha` makes it see-through
+const BACKGROUND: egui::Color32 = egui::Color32::from_rgb(12, 12, 20);
+// Prompt symbol and header border while running as root
+const ROOT_ACCENT: egui::Color32 = egui::Color32::from_rgb(255, 80, 80);
+// Background of selected text, in the input line and (half transparent) in the scrollback
+const SELECTION_COLOR: egui::Color32 = egui::Color32::from_rgb(0, 120, 255);
 
 fn main() -> Result<(), eframe::Error> {
     // Load .env if present
     let _ = dotenvy::dotenv();
+    let mut args = match cli::parse(env::args().skip(1)) {
+        Ok(args) if args.help => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("linara: {}\n{}", msg, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Some(dir) = &args.directory {
+        match std::fs::canonicalize(dir) {
+            Ok(path) if path.is_dir() => args.directory = Some(path.to_string_lossy().to_string()),
+            Ok(_) => {
+                eprintln!("linara: {}: not a directory", dir);
+                std::process::exit(2);
+            }
+            Err(e) => {
+                eprintln!("linara: {}: {}", dir, e);
+                std::process::exit(2);
+            }
+        }
+    }
+    // Reopen the window where it was last closed; the config file holds its size and position
+    let config = Config::load();
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(config.window_size)
+        .with_title("Linara Terminal")
+        .with_resizable(true)
+        .with_transparent(config.background_alpha < 1.0);
+    if let Some(position) = config.window_position {
+        viewport = viewport.with_position(position);
+    }
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1000.0, 700.0])
-            .with_title("Linara Terminal")
-            .with_resizable(true),
+        viewport,
         ..Default::default()
     };
 
     eframe::run_native(
         "Terminal",
         options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             // Set up authentic terminal theme
-            let mut visuals = egui::Visuals::dark();
-            visuals.window_fill = egui::Color32::from_rgb(12, 12, 20);
-            visuals.panel_fill = egui::Color32::from_rgb(12, 12, 20);
-            visuals.extreme_bg_color = egui::Color32::from_rgb(12, 12, 20);
-            cc.egui_ctx.set_visuals(visuals);
-            
-            Ok(Box::new(TerminalApp::new()))
+            cc.egui_ctx.set_visuals(terminal_visuals(config.theme));
+            cc.egui_ctx.set_zoom_factor(config.font_size / config::DEFAULT_FONT_SIZE);
+
+            Ok(Box::new(TerminalApp::new(args, config)))
         }),
     )
 }
@@ -40,27 +160,112 @@ struct TerminalLine {
     text: String,
     is_input: bool,
     is_prompt: bool,
+    prompt: Option<PromptLine>, // Structured prompt, so the renderer never has to parse `text`
+    style: LineStyle, // Follows `text`; change that through set_text
+    dir: Rc<str>, // Directory the line was printed in; relative paths in it are links from there
+    stderr: bool, // A program wrote it to stderr, shown without an `ERROR:` prefix
+}
+
+impl TerminalLine {
+    fn new(text: String, is_input: bool, is_prompt: bool, dir: Rc<str>) -> Self {
+        let style = LineStyle::classify(&text, is_input, is_prompt);
+        Self { text, is_input, is_prompt, prompt: None, style, dir, stderr: false }
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.style = match self.stderr {
+            true => LineStyle::error(&text),
+            false => LineStyle::classify(&text, self.is_input, self.is_prompt),
+        };
+        self.text = text;
+    }
 }
 
+// All open tabs; keyboard input goes to the active session
 struct TerminalApp {
+    sessions: Vec<Session>,
+    layout: Layout,
+    next_session_id: usize,
+    keymap: Keymap, // For the tab shortcuts; each session has its own copy for the rest
+    window: Option<WindowGeometry>, // Current size and position, written to the config file on exit
+    window_clamped: bool, // The restored geometry has been checked against the monitor
+    background: egui::Color32, // The theme's background with the configured alpha
+    startup_command: Option<String>, // From `-e`, run once the first frame is on screen
+    preferences: Option<Preferences>, // Settings being edited in the preferences window, while it is open
+    config: Config, // Loaded once at startup; new tabs start from it, and the preferences window keeps it current
+    runtime: tokio::runtime::Runtime, // Every tab's AI requests run on this one
+    macros: Rc<RefCell<Macros>>, // Shared by every tab, so a macro defined in one works in all
+    ai_cache: Arc<Mutex<AiCache>>, // Loaded once; every tab's AI answers go into it
+}
+
+// One terminal: its output, input line, directory, history, jobs and AI state
+struct Session {
+    id: usize, // Stable across tab closes, keeps each session's widget state apart
+    closed: bool, // Set by `exit`/Ctrl+D; the app removes the tab
     lines: VecDeque<TerminalLine>,
+    lines_added: usize, // Every line ever added to `lines`, including those the scrollback limit has dropped
+    scrollback_limit: usize, // Maximum number of lines kept in `lines`
     input_buffer: String,
     cursor_pos: usize,
     show_cursor: bool,
     last_cursor_blink: Instant,
+    cursor_style: CursorStyle,
+    cursor_blink: Option<Duration>, // How long the cursor stays shown, and hidden; None holds it still
     // Clipboard and selection support
     selection_start: Option<usize>,
     selection_end: Option<usize>,
     pending_copy: Option<String>,
     pending_paste: bool,
     clipboard_content: String,
+    pending_large_paste: Option<String>, // Large paste waiting for y/n confirmation
+    pager: bool, // Hold back output longer than the window until space is pressed, like `less`
+    paged_output: VecDeque<(Stream, String)>, // Output lines still to be shown; the input waits until it is empty
+    page_lines: usize, // Output lines that fit in the window, measured every frame
+    term_size: (usize, usize), // Columns and rows of output that fit, passed to children as COLUMNS and LINES
     command_history: Vec<String>,
+    history_edited: bool, // Entries were removed; the session file is rewritten now rather than on exit
     history_index: isize,
-    current_dir: String,
+    current_dir: String, // Logical path, through any symlinks cd'd into; shown by `pwd` and the prompt
+    physical_dir: String, // current_dir with symlinks resolved, for `pwd -P`
+    prev_dir: Option<String>, // Directory before the last successful cd, for `cd -`
+    dir_stack: Vec<String>, // pushd/popd stack, top of stack is the last element
+    sourcing: Vec<std::path::PathBuf>, // Scripts currently being sourced, to stop recursion
+    running_ai_command: bool, // An AI suggestion is running; if it isn't found, don't ask the AI again
+    last_exit_code: i32, // Exit status of the last command (0 = success)
+    command_started: Option<Instant>, // When the running command started; the next prompt shows how long it took
+    running_command: String, // The command `command_started` belongs to
+    notify_after: Option<Duration>, // Commands that take longer are reported if the window isn't focused
+    finished: Option<Finished>, // Long command that just finished; the app notifies if it's in the background
+    bell: Bell, // What a BEL in the output does
+    bell_rung: Option<Instant>, // Last BEL, for the flash and so a burst of them only sounds once
+    bell_attention: bool, // A BEL arrived; the app asks for attention if it's in the background
+    json_log: Option<JsonLog>, // `json-log <path>`: each program's result is appended there as a JSON line
+    keymap: Keymap, // Ctrl shortcuts, from the defaults and `bind` lines in the config
+    line_wrap: LineWrap, // Wrap long output lines, or keep them whole and scroll sideways
+    scroll_locked: bool, // The user scrolled up, so new output doesn't pull the view down
+    scroll_to_bottom: bool, // Scroll back down to the input line on the next frame
+    scroll_target: Option<f32>, // Offset a scroll key asked for, applied on the next frame
+    scroll_offset: f32, // Where the scrollback was last frame, and how far it could go
+    scroll_max: f32,
+    scroll_page: f32, // One screenful less a line, so a page step keeps a line of context
+    scroll_line: f32, // Height of an output line
+    prompt_template: String, // Prompt layout with {user}, {host}, {cwd}, {git} and {exit} placeholders
+    classic_prompt: bool, // Commands are echoed on a plain `$` line and all output goes below it
+    is_root: bool, // Running with effective uid 0: the prompt shows a red `#`
+    // Background jobs started with `&`
+    jobs: Vec<Job>,
+    job_sender: std::sync::mpsc::Sender<JobEvent>,
+    job_receiver: std::sync::mpsc::Receiver<JobEvent>,
+    // Flags of PATH commands, looked up from their man pages on worker threads
+    flag_sender: std::sync::mpsc::Sender<(String, Vec<String>)>,
+    flag_receiver: std::sync::mpsc::Receiver<(String, Vec<String>)>,
+    flag_lookups: usize, // Lookups not yet answered
     username: String,
     hostname: String,
     // Autocomplete fields
-    autocomplete_suggestions: Vec<String>,
+    autocomplete_suggestions: Vec<Suggestion>,
+    completion_range: std::ops::Range<usize>, // Part of the input a suggestion replaces: the word at the cursor, then the last one applied
+    completion_word: usize, // Index of that word in the line; 0 is the command
     autocomplete_index: isize,
     show_autocomplete: bool,
     common_commands: Vec<String>,
@@ -70,46 +275,434 @@ struct TerminalApp {
     command_cache: HashMap<String, Vec<String>>, // Cache for different contexts
     last_path_scan: Instant,
     fuzzy_enabled: bool,
+    // Ctrl+P command palette
+    palette_open: bool,
+    palette_query: String,
+    palette_index: usize,
+    palette_matches: Vec<(String, &'static str)>, // Ranked when the query changes, not every frame
+    palette_scroll: bool, // The selection moved; scroll it into view on the next frame
+    history_picker: Option<HistoryPicker>, // Ctrl+R, while open
+    // Ctrl+Shift+F scrollback filter
+    filter_query: String, // Empty when no filter is applied
+    filter_regex: bool,
+    line_filter: Result<Option<LineFilter>, String>, // Built from the query when it changes, not every frame
+    filter_editing: bool, // Typing goes to the filter query instead of the input line
     // AI
     ai: AIAssistant,
-    rt: tokio::runtime::Runtime,
+    rt: tokio::runtime::Handle, // The app's runtime, shared by every tab
+    ai_pending: Option<PendingAI>, // Request the AI is still working on; the prompt returns once it answers
+    pending_ai_retry: Option<(String, Duration)>, // Timed-out input and the longer timeout offered for a retry
+    ai_guard: CommandGuard, // Suggestions it flags wait for a y/n instead of running
+    ai_ignore: AiIgnore, // Names that are only reported when missing, never sent to the AI
+    pending_ai_confirm: Option<String>, // Flagged AI suggestion waiting for y/n
+    configured_macros: Vec<(String, String)>, // `macro` lines from the config, merged into the saved macros when they are read
+    macros: Rc<RefCell<Macros>>, // The app's macros, shared by every tab
+    resolving_macro: Option<String>, // Macro whose phrase the AI is working on; its answer is saved for next time
+    ai_script: Option<AiScript>, // `ai-run` script in progress; AI answers go to its current step
+    pending_ai_step: Option<String>, // Command resolved for the script's current step, waiting for y/n
+    ai_enabled: bool, // Off means unknown commands are never sent to the AI
+    api_key_hint_shown: bool, // How to set up an API key has been explained; it isn't repeated
+    last_ai_usage: Option<Usage>, // Tokens the latest AI request used, for the status bar
+    ai_usage_total: Usage, // Tokens used by every AI request in this tab
+    history_filter: HistoryFilter, // Commands that look like they hold secrets aren't recorded
+    ai_dry_run: bool, // Put AI suggestions in the input line for review instead of running them
+    pending_heredoc: Option<HereDoc>, // Here-doc whose body is being typed in
+    watch: Option<Watch>, // Takes over the tab until q, Esc or Ctrl+C
+    pending_sudo: Option<PendingSudo>, // sudo command waiting for its password
+    sudo_password: String, // Typed in masked; wiped once it has been handed to sudo or cancelled
+    secure_input: Option<usize>, // Foreground job that asked for a password; keys go to its stdin, masked
+    secure_text: String, // What has been typed for it so far; wiped once sent or cancelled
+    package_manager: Option<PackageManager>, // Used to offer installing commands that aren't found
+    pending_install: Option<String>, // Install command offered for a missing program, waiting for y/n
+    package_lookup: Option<std::sync::mpsc::Receiver<Option<String>>>, // Install command for the last missing program, being looked up
+    offered_examples: Vec<String>, // Shown by `examples`; a number key puts one in the input line
+    banner_receiver: Option<std::sync::mpsc::Receiver<SystemInfo>>, // Startup banner being gathered in the background
+    resources: Option<Resources>, // CPU and memory use for the status bar clock; None when `status_clock = off`
+    preferences_requested: bool, // The status bar gear was clicked; the app opens or closes the preferences window
+    reset_requested: bool, // `reset` ran; the app puts the font size back to the configured one
+    captured_output: Option<Vec<String>>, // Lines printed by a builtin at the head of a pipeline, instead of shown
+    stderr_prefix: bool, // Programs' stderr lines start with `ERROR:`; off, they are only colored
+    output_dir: Option<String>, // Directory of the job whose output is being added, when it isn't current_dir
+}
+
+// A `cmd << DELIM` waiting for its body; each Enter adds a line until DELIM is typed
+struct HereDoc {
+    command: String,
+    cmd_name: String,
+    args: Vec<String>,
+    delimiter: String,
+    body: Vec<String>,
+}
+
+// `watch`: a command rerun on an interval, its output replacing the previous run's
+struct Watch {
+    command: String,
+    interval: Duration,
+    next_run: Instant,
+    runs: usize,
+    shown: usize, // Lines of the output region, counted from the back of `lines`
+    run: Option<WatchRun>, // The run in progress
+}
+
+// A run of a watched command, waited for on a worker thread
+struct WatchRun {
+    pid: u32,
+    output: std::sync::mpsc::Receiver<std::io::Result<interleave::Output>>,
+}
+
+// A `sudo` command held back while its password is typed in
+struct PendingSudo {
+    command: String,
+    args: Vec<String>,
+}
+
+// An AI request in flight
+struct PendingAI {
+    started: Instant,
+    timeout: Duration,
 }
 
 impl TerminalApp {
-    fn new() -> Self {
-        let current_dir = env::current_dir()
-            .unwrap_or_else(|_| std::path::PathBuf::from("/"))
-            .to_string_lossy()
-            .to_string();
-        
+    fn new(args: cli::Args, config: Config) -> Self {
+        let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+        let macros = Rc::new(RefCell::new(Macros::load(&config.macros)));
+        let ai_cache = Arc::new(Mutex::new(AiCache::load(&AiCache::path(), ai_cache::now())));
+        let current_dir = args.directory.clone().unwrap_or_else(|| {
+            let physical = env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("/"));
+            logical_path::inherited(&physical).unwrap_or_else(|| physical.to_string_lossy().to_string())
+        });
+
+        // Reopen the tabs from last time, each in its old directory
+        if let Some(saved) = SavedSession::load() {
+            let home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+            let sessions: Vec<Session> = saved.tabs.into_iter().enumerate().map(|(id, tab)| {
+                let mut session = Session::new(id, state::restore_dir(&tab.current_dir, &home), &config, runtime.handle().clone(), Rc::clone(&macros), Arc::clone(&ai_cache));
+                session.command_history = tab.history;
+                session
+            }).collect();
+            let layout = Layout { active: saved.active.min(sessions.len() - 1), split: None };
+            let mut app = Self {
+                next_session_id: sessions.len(),
+                sessions,
+                layout,
+                keymap: config.keymap.clone(),
+                window: None,
+                window_clamped: false,
+                background: background_color(&config),
+                startup_command: None,
+                preferences: None,
+                config,
+                runtime,
+                macros,
+                ai_cache,
+            };
+            // `-C`/`-e` get a tab of their own next to the restored ones
+            if args.directory.is_some() || args.execute.is_some() {
+                app.sessions.push(Session::new(app.next_session_id, current_dir, &app.config, app.runtime.handle().clone(), Rc::clone(&app.macros), Arc::clone(&app.ai_cache)));
+                app.next_session_id += 1;
+                app.layout.focus(app.sessions.len() - 1);
+                app.startup_command = args.execute;
+            }
+            return app;
+        }
+
+        Self {
+            sessions: vec![Session::new(0, current_dir, &config, runtime.handle().clone(), Rc::clone(&macros), Arc::clone(&ai_cache))],
+            layout: Layout::default(),
+            next_session_id: 1,
+            keymap: config.keymap.clone(),
+            window: None,
+            window_clamped: false,
+            background: background_color(&config),
+            startup_command: args.execute,
+            preferences: None,
+            config,
+            runtime,
+            macros,
+            ai_cache,
+        }
+    }
+
+    // Remember each tab's directory and recent history for the next launch
+    fn save_session(&self) {
+        let saved = SavedSession {
+            tabs: self.sessions.iter()
+                .map(|session| SavedTab::new(&session.current_dir, &session.command_history))
+                .collect(),
+            active: self.layout.active,
+        };
+        // The window is already closing, so there is nowhere to report a failure
+        let _ = saved.save();
+    }
+
+    // Track the window geometry, and once the monitor is known pull a restored window back on screen
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let viewport = ctx.input(|i| i.viewport().clone());
+        let Some(inner) = viewport.inner_rect else { return };
+        let window = WindowGeometry {
+            size: [inner.width(), inner.height()],
+            position: viewport.outer_rect.map(|outer| [outer.min.x, outer.min.y]),
+        };
+        self.window = Some(window);
+
+        if self.window_clamped {
+            return;
+        }
+        let Some(monitor) = viewport.monitor_size else { return };
+        self.window_clamped = true;
+        let clamped = window.clamp_to([monitor.x, monitor.y]);
+        if clamped.size != window.size {
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(clamped.size.into()));
+        }
+        if let (Some(position), true) = (clamped.position, clamped.position != window.position) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(position.into()));
+        }
+    }
+
+    // New tabs start in the active tab's directory
+    fn open_session(&mut self) -> usize {
+        let current_dir = self.sessions[self.layout.active].current_dir.clone();
+        self.sessions.push(Session::new(self.next_session_id, current_dir, &self.config, self.runtime.handle().clone(), Rc::clone(&self.macros), Arc::clone(&self.ai_cache)));
+        self.next_session_id += 1;
+        self.sessions.len() - 1
+    }
+
+    fn new_tab(&mut self) {
+        let index = self.open_session();
+        self.layout.focus(index);
+    }
+
+    // Ctrl+Shift+D: open a session beside the active one, or go back to a single pane
+    fn toggle_split(&mut self) {
+        if self.layout.split.is_some() {
+            self.layout.split = None;
+        } else {
+            let index = self.open_session();
+            self.layout.split_with(index);
+        }
+    }
+
+    // Drop tabs whose shell exited; closing the last one closes the window
+    fn close_finished_sessions(&mut self, ctx: &egui::Context) {
+        while let Some(index) = self.sessions.iter().position(|session| session.closed) {
+            if self.sessions.len() == 1 {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                return;
+            }
+            self.sessions.remove(index);
+            self.layout.remove(index, self.sessions.len());
+        }
+    }
+
+    // Open the preferences window with the current settings, or close it
+    fn toggle_preferences(&mut self) {
+        match self.preferences.take() {
+            Some(preferences) => self.save_preferences(&preferences),
+            None => self.preferences = Some(Preferences::from_config(&self.config)),
+        }
+    }
+
+    fn show_preferences(&mut self, ctx: &egui::Context) {
+        let Some(mut preferences) = self.preferences.take() else { return };
+        let mut open = true;
+        let mut changed = false;
+        egui::Window::new("Preferences")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| changed = preferences.show(ui));
+        if changed {
+            self.apply_preferences(ctx, &preferences);
+        }
+        if open {
+            self.preferences = Some(preferences);
+        } else {
+            self.save_preferences(&preferences);
+        }
+    }
+
+    // Settings from the preferences window take effect in every tab straight away
+    fn apply_preferences(&mut self, ctx: &egui::Context, preferences: &Preferences) {
+        ctx.set_zoom_factor(preferences.font_size / config::DEFAULT_FONT_SIZE);
+        ctx.set_visuals(terminal_visuals(preferences.theme));
+        self.background = with_alpha(theme_background(preferences.theme), self.background.a() as f32 / 255.0);
+        for (key, value) in preferences.settings() {
+            self.config.set(key, &value);
+        }
+        let model = preferences.model.trim();
+        for session in &mut self.sessions {
+            session.fuzzy_enabled = preferences.fuzzy;
+            session.ai_enabled = preferences.ai_enabled;
+            session.scrollback_limit = preferences.scrollback_limit;
+            session.cursor_style = preferences.cursor_style;
+            session.cursor_blink = preferences.cursor_blink.then(|| Duration::from_millis(preferences.cursor_blink_ms));
+            if !model.is_empty() {
+                session.ai.model = model.to_string();
+            }
+        }
+    }
+
+    fn save_preferences(&mut self, preferences: &Preferences) {
+        if !preferences.edited {
+            return;
+        }
+        if let Err(e) = preferences.save() {
+            let msg = format!("ERROR: could not save preferences to {}: {}", Config::path().display(), e);
+            self.sessions[self.layout.active].add_line_above_prompt(&msg);
+        }
+    }
+
+    // Tab shortcuts are taken out of the input before the session sees them
+    fn handle_tab_shortcuts(&mut self, ctx: &egui::Context) {
+        let actions: Vec<Action> = ctx.input_mut(|i| {
+            self.keymap.window_bindings().into_iter()
+                .filter(|(chord, _)| i.consume_key(chord.modifiers(), chord.key))
+                .map(|(_, action)| action)
+                .collect()
+        });
+
+        for action in actions {
+            match action {
+                Action::NewTab => self.new_tab(),
+                Action::ToggleSplit => self.toggle_split(),
+                Action::NextTab => self.layout.cycle(self.sessions.len(), true),
+                Action::PrevTab => self.layout.cycle(self.sessions.len(), false),
+                Action::CloseTab => self.sessions[self.layout.active].closed = true,
+                Action::Preferences => self.toggle_preferences(),
+                _ => {}
+            }
+        }
+    }
+
+    fn show_tab_bar(&mut self, ctx: &egui::Context) {
+        let mut clicked_tab = None;
+        let mut closed_tab = None;
+        let mut new_tab = false;
+
+        egui::TopBottomPanel::top("tab_bar")
+            .frame(egui::Frame::none().fill(egui::Color32::from_rgb(20, 20, 30)).inner_margin(egui::Margin::symmetric(8.0, 4.0)))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for (i, session) in self.sessions.iter().enumerate() {
+                        let selected = i == self.layout.active;
+                        let color = if selected {
+                            egui::Color32::from_rgb(255, 255, 255)
+                        } else {
+                            egui::Color32::from_rgb(150, 150, 150)
+                        };
+                        let title = egui::RichText::new(format!("{} {}", i + 1, session.title()))
+                            .font(egui::FontId::monospace(14.0))
+                            .color(color);
+                        if ui.selectable_label(selected, title).clicked() {
+                            clicked_tab = Some(i);
+                        }
+                        if ui.small_button("×").clicked() {
+                            closed_tab = Some(i);
+                        }
+                        ui.add_space(6.0);
+                    }
+                    if ui.small_button("+").clicked() {
+                        new_tab = true;
+                    }
+                });
+            });
+
+        if let Some(index) = clicked_tab {
+            self.layout.focus(index);
+        }
+        if let Some(index) = closed_tab {
+            self.sessions[index].closed = true;
+        }
+        if new_tab {
+            self.new_tab();
+        }
+    }
+}
+
+impl Session {
+    fn new(id: usize, current_dir: String, config: &Config, rt: tokio::runtime::Handle, macros: Rc<RefCell<Macros>>, ai_cache: Arc<Mutex<AiCache>>) -> Self {
         let username = env::var("USER").unwrap_or_else(|_| "user".to_string());
         let hostname = env::var("HOSTNAME").unwrap_or_else(|_| {
-            // Try to get hostname from system
-            Command::new("hostname")
-                .output()
-                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            // Ask the kernel rather than spawning `hostname`
+            nix::sys::utsname::uname()
+                .map(|uts| uts.nodename().to_string_lossy().to_string())
                 .unwrap_or_else(|_| "localhost".to_string())
         });
 
+        let (job_sender, job_receiver) = std::sync::mpsc::channel();
+        let (flag_sender, flag_receiver) = std::sync::mpsc::channel();
+
     let mut app = Self {
+            id,
+            closed: false,
             lines: VecDeque::new(),
+            lines_added: 0,
+            scrollback_limit: config.scrollback_limit,
             input_buffer: String::new(),
             cursor_pos: 0,
             show_cursor: true,
             last_cursor_blink: Instant::now(),
+            cursor_style: config.cursor_style,
+            cursor_blink: config.cursor_blink,
             // Initialize clipboard and selection
             selection_start: None,
             selection_end: None,
             pending_copy: None,
             pending_paste: false,
             clipboard_content: String::new(),
+            pending_large_paste: None,
+            pager: config.pager,
+            stderr_prefix: config.stderr_prefix,
+            paged_output: VecDeque::new(),
+            page_lines: 24,
+            term_size: (80, 24),
             command_history: Vec::new(),
+            history_edited: false,
             history_index: -1,
+            physical_dir: std::fs::canonicalize(&current_dir)
+                .map_or_else(|_| current_dir.clone(), |path| path.to_string_lossy().to_string()),
             current_dir,
+            prev_dir: None,
+            dir_stack: Vec::new(),
+            sourcing: Vec::new(),
+            running_ai_command: false,
+            last_exit_code: 0,
+            command_started: None,
+            running_command: String::new(),
+            notify_after: config.notify_after,
+            finished: None,
+            bell: config.bell,
+            bell_rung: None,
+            bell_attention: false,
+            json_log: None,
+            keymap: config.keymap.clone(),
+            line_wrap: config.line_wrap,
+            scroll_locked: false,
+            scroll_to_bottom: false,
+            scroll_target: None,
+            scroll_offset: 0.0,
+            scroll_max: 0.0,
+            scroll_page: 0.0,
+            scroll_line: 0.0,
+            prompt_template: if config.classic_prompt && config.prompt == prompt::DEFAULT_TEMPLATE {
+                prompt::CLASSIC_TEMPLATE.to_string()
+            } else {
+                config.prompt.clone()
+            },
+            classic_prompt: config.classic_prompt,
+            is_root: nix::unistd::geteuid().is_root(),
+            jobs: Vec::new(),
+            job_sender,
+            job_receiver,
+            flag_sender,
+            flag_receiver,
+            flag_lookups: 0,
             username,
             hostname,
             // Initialize autocomplete
             autocomplete_suggestions: Vec::new(),
+            completion_range: 0..0,
+            completion_word: 0,
             autocomplete_index: -1,
             show_autocomplete: false,
             common_commands: vec![
@@ -194,9 +787,58 @@ impl TerminalApp {
             // Enhanced suggestion system
             command_cache: HashMap::new(),
             last_path_scan: Instant::now(),
-            fuzzy_enabled: true,
-            ai: AIAssistant::new(),
-            rt: tokio::runtime::Runtime::new().expect("tokio runtime"),
+            fuzzy_enabled: config.fuzzy,
+            palette_open: false,
+            palette_query: String::new(),
+            palette_index: 0,
+            palette_matches: Vec::new(),
+            palette_scroll: false,
+            history_picker: None,
+            filter_query: String::new(),
+            filter_regex: false,
+            line_filter: Ok(None),
+            filter_editing: false,
+            ai: {
+                let mut ai = AIAssistant::new(ai_cache);
+                ai.request_timeout = config.ai_timeout;
+                ai.gibberish_rules = config.gibberish.clone();
+                ai.history_filter = config.history_filter.clone();
+                ai.model = config.model.clone();
+                ai
+            },
+            rt,
+            ai_pending: None,
+            pending_ai_retry: None,
+            ai_guard: config.ai_guard.clone(),
+            ai_ignore: config.ai_ignore.clone(),
+            pending_ai_confirm: None,
+            configured_macros: config.macros.clone(),
+            macros,
+            resolving_macro: None,
+            ai_script: None,
+            pending_ai_step: None,
+            ai_enabled: config.ai_enabled,
+            api_key_hint_shown: false,
+            last_ai_usage: None,
+            ai_usage_total: Usage::default(),
+            history_filter: config.history_filter.clone(),
+            ai_dry_run: false,
+            pending_heredoc: None,
+            watch: None,
+            pending_sudo: None,
+            sudo_password: String::new(),
+            secure_input: None,
+            secure_text: sudo::password_buffer(), // Wiping keeps the room, so it is reused for every password
+            package_manager: PackageManager::detect(),
+            pending_install: None,
+            package_lookup: None,
+            offered_examples: Vec::new(),
+            banner_receiver: None,
+            resources: config.status_clock.then(Resources::default),
+            preferences_requested: false,
+            reset_requested: false,
+            captured_output: None,
+            output_dir: None,
         };
 
         // Initialize command flags (reduced to most common ones for speed)
@@ -233,156 +875,203 @@ impl TerminalApp {
         // Scan PATH for available commands
         app.scan_path_commands();
 
-        // Add beautiful system information display
-        app.add_system_info();
+        // Add beautiful system information display, gathered off the UI thread so the first paint isn't held up
+        if config.show_banner {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(SystemInfo::gather());
+            });
+            app.banner_receiver = Some(receiver);
+        }
         
         // Show initial prompt
         app.show_prompt();
+
+        // Apply the user's startup file (aliases, exports, cd, ...)
+        app.source_startup_file();
         
         app
     }
 
     fn add_line(&mut self, text: &str, is_input: bool, is_prompt: bool) {
-        self.lines.push_back(TerminalLine {
-            text: text.to_string(),
-            is_input,
-            is_prompt,
-        });
+        self.push_line(text, is_input, is_prompt, false);
+    }
+
+    // A line a program printed. Stderr is marked with `ERROR:`, or with `stderr_prefix = off`
+    // only shown in the error color
+    fn add_output_line(&mut self, stream: Stream, text: &str) {
+        match stream {
+            Stream::Stdout => self.add_line(text, false, false),
+            Stream::Stderr if self.stderr_prefix => self.add_line(&format!("ERROR: {}", text), false, false),
+            Stream::Stderr => self.push_line(text, false, false, true),
+        }
+    }
+
+    fn push_line(&mut self, text: &str, is_input: bool, is_prompt: bool, stderr: bool) {
+        if let Some(captured) = &mut self.captured_output {
+            captured.push(text.to_string());
+            return;
+        }
+        let (text, rang) = bell::strip(text);
+        if rang {
+            self.ring_bell();
+            if text.is_empty() {
+                return;
+            }
+        }
+        let dir = self.line_dir();
+        let mut line = TerminalLine::new(text, is_input, is_prompt, dir);
+        if stderr {
+            line.stderr = true;
+            line.style = LineStyle::error(&line.text);
+        }
+        self.lines.push_back(line);
+        self.lines_added += 1;
         
-        // Keep buffer smaller for better performance
-        while self.lines.len() > 500 {
+        // Drop the oldest lines once the scrollback limit is reached
+        while self.lines.len() > self.scrollback_limit {
             self.lines.pop_front();
         }
     }
 
+    // A BEL in the output: flash the pane or play the bell sound, once for a burst of them
+    fn ring_bell(&mut self) {
+        if self.bell == Bell::Off {
+            return;
+        }
+        let burst = self.bell_rung.is_some_and(|rung| rung.elapsed() < BELL_FLASH);
+        self.bell_rung = Some(Instant::now());
+        self.bell_attention = true;
+        if self.bell == Bell::Audible && !burst {
+            bell::play();
+        }
+    }
+
+    // The directory for a new line, shared with the line before it when that was printed in the same one
+    fn line_dir(&self) -> Rc<str> {
+        let dir = self.output_dir.as_deref().unwrap_or(&self.current_dir);
+        match self.lines.back() {
+            Some(line) if *line.dir == *dir => line.dir.clone(),
+            _ => Rc::from(dir),
+        }
+    }
+
+    // Add a line just above the live prompt, so output arriving later doesn't hide the input line
+    fn add_line_above_prompt(&mut self, text: &str) {
+        self.above_prompt(|session| session.add_line(text, false, false));
+    }
+
+    fn add_output_line_above_prompt(&mut self, stream: Stream, text: &str) {
+        self.above_prompt(|session| session.add_output_line(stream, text));
+    }
+
+    fn above_prompt(&mut self, add: impl FnOnce(&mut Self)) {
+        let prompt = if self.lines.back().is_some_and(|line| line.is_prompt) { self.lines.pop_back() } else { None };
+        add(self);
+        if let Some(prompt) = prompt {
+            self.lines.push_back(prompt);
+        }
+    }
+
     fn show_prompt(&mut self) {
         let home = env::var("HOME").unwrap_or_else(|_| "/home/user".to_string());
-        let display_dir = if self.current_dir.starts_with(&home) {
-            self.current_dir.replace(&home, "~")
-        } else {
-            self.current_dir.clone()
-        };
-        
-        // Shorten path to only show last 2 parent directories
-        let short_path = if display_dir == "~" {
-            "~".to_string()
-        } else {
-            let path_parts: Vec<&str> = display_dir.split('/').collect();
-            if path_parts.len() <= 2 {
-                display_dir.clone()
-            } else {
-                format!(".../{}/{}", path_parts[path_parts.len() - 2], path_parts[path_parts.len() - 1])
-            }
-        };
+        let short_path = prompt::short_display_path(&self.current_dir, &home);
         
         // Check if we're in a Git repository and get the current branch
         let git_info = self.get_git_branch();
         
-        // Create PowerShell-like header bar (without timestamp, dynamic git info)
-        let header_bar = if git_info.is_empty() {
-            format!("🏠 {} 📂 {}", 
-                self.username, 
-                short_path
-            )
-        } else {
-            format!("🏠 {} 📂 {} {}", 
-                self.username, 
-                short_path,
-                git_info
-            )
-        };
+        let duration = self.command_started.take().map(|started| started.elapsed());
+        if let Some(duration) = duration {
+            let command = std::mem::take(&mut self.running_command);
+            self.note_finished(&command, self.last_exit_code, duration);
+        }
+
+        // Expand the configured prompt template (PowerShell-like header bar by default)
+        let segments = prompt::expand(&self.prompt_template, &PromptInfo {
+            user: &self.username,
+            host: &self.hostname,
+            cwd: &short_path,
+            git: &git_info,
+            exit_code: self.last_exit_code,
+            duration,
+        });
         
         // Add the header bar and simple prompt on the same line
-        self.add_line(&header_bar, false, true);
+        self.add_line(&prompt::segments_text(&segments), false, true);
+        if let Some(line) = self.lines.back_mut() {
+            line.prompt = Some(PromptLine { segments, command: None, output: None });
+        }
     }
-    
-    fn add_system_info(&mut self) {
-        // Add beautiful ASCII art and system information like neofetch
-        self.add_line("", false, false);
-        
-        // Colorful ASCII Art for LINARA - Clean and readable design (left-aligned)
-        self.add_line("██╗     ██╗███╗   ██╗ █████╗ ██████╗  █████╗ ", false, false);
-        self.add_line("██║     ██║████╗  ██║██╔══██╗██╔══██╗██╔══██╗", false, false);
-        self.add_line("██║     ██║██╔██╗ ██║███████║██████╔╝███████║", false, false);
-        self.add_line("██║     ██║██║╚██╗██║██╔══██║██╔══██╗██╔══██║", false, false);
-        self.add_line("███████╗██║██║ ╚████║██║  ██║██║  ██║██║  ██║", false, false);
-        self.add_line("╚══════╝╚═╝╚═╝  ╚═══╝╚═╝  ╚═╝╚═╝  ╚═╝╚═╝  ╚═╝", false, false);
-        self.add_line("", false, false);
-        
-        // Get system information
-        let username = self.username.clone();
-        let hostname = self.hostname.clone();
-        
-        // OS Information
-        let os_info = std::process::Command::new("uname")
-            .args(&["-sr"])
-            .output()
-            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
-            .unwrap_or_else(|_| "Linux".to_string());
-            
-        // Kernel version
-        let kernel = std::process::Command::new("uname")
-            .args(&["-r"])
-            .output()
-            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
-            .unwrap_or_else(|_| "Unknown".to_string());
-            
-        // Uptime
-        let uptime = std::process::Command::new("uptime")
-            .args(&["-p"])
-            .output()
-            .map(|output| String::from_utf8_lossy(&output.stdout).trim().replace("up ", ""))
-            .unwrap_or_else(|_| "Unknown".to_string());
-            
-        // Memory info
-        let memory = std::fs::read_to_string("/proc/meminfo")
-            .and_then(|content| {
-                let lines: Vec<&str> = content.lines().collect();
-                let total_kb = lines.iter()
-                    .find(|line| line.starts_with("MemTotal:"))
-                    .and_then(|line| line.split_whitespace().nth(1))
-                    .and_then(|s| s.parse::<u64>().ok())
-                    .unwrap_or(0);
-                    
-                let available_kb = lines.iter()
-                    .find(|line| line.starts_with("MemAvailable:"))
-                    .and_then(|line| line.split_whitespace().nth(1))
-                    .and_then(|s| s.parse::<u64>().ok())
-                    .unwrap_or(0);
-                    
-                let used_kb = total_kb - available_kb;
-                let total_gb = total_kb as f64 / 1024.0 / 1024.0;
-                let used_gb = used_kb as f64 / 1024.0 / 1024.0;
-                
-                Ok(format!("{:.1}GB / {:.1}GB", used_gb, total_gb))
-            })
-            .unwrap_or_else(|_| "Unknown".to_string());
-            
-        // CPU info
-        let cpu = std::fs::read_to_string("/proc/cpuinfo")
-            .and_then(|content| {
-                content.lines()
-                    .find(|line| line.starts_with("model name"))
-                    .and_then(|line| line.split(':').nth(1))
-                    .map(|s| s.trim().to_string())
-                    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "CPU not found"))
-            })
-            .unwrap_or_else(|_| "Unknown CPU".to_string());
-
-        // Display system information in a beautiful box (left-aligned)
-        self.add_line("╭─────────────────────────────────────────────────────────────╮", false, false);
-        self.add_line(&format!("{}@{}", username, hostname), false, false);
-        self.add_line("├─────────────────────────────────────────────────────────────┤", false, false);
-        self.add_line(&format!("OS: {}", os_info), false, false);
-        self.add_line(&format!("Host: {}", hostname), false, false);
-        self.add_line(&format!("Kernel: {}", kernel), false, false);
-        self.add_line(&format!("Uptime: {}", uptime), false, false);
-        self.add_line(&format!("Terminal: Linara Terminal"), false, false);
-        self.add_line(&format!("CPU: {}", cpu), false, false);
-        self.add_line(&format!("Memory: {}", memory), false, false);
-        self.add_line("╰─────────────────────────────────────────────────────────────╯", false, false);
+
+    // Finish the live prompt line with the command that was run and any short output shown inline.
+    // The classic prompt keeps the command alone on its line and puts the output below it.
+    fn complete_prompt(&mut self, command: &str, output: Option<&str>) {
+        if self.classic_prompt {
+            let has_live_prompt = self.lines.back().is_some_and(|line| line.is_prompt);
+            self.complete_prompt_line(command, None);
+            if let Some(output) = output.filter(|_| has_live_prompt) {
+                self.add_line(output, false, false);
+            }
+        } else {
+            self.complete_prompt_line(command, output);
+        }
+    }
+
+    // `#` as root, like traditional shells; otherwise `$` for the classic prompt and `>` for the header bar
+    fn prompt_symbol(&self) -> &'static str {
+        match (self.is_root, self.classic_prompt) {
+            (true, _) => "#",
+            (false, true) => "$",
+            (false, false) => ">",
+        }
+    }
+
+    // The prompt symbol's color: red as root, so a privileged session is hard to miss
+    fn prompt_symbol_color(&self, normal: egui::Color32) -> egui::Color32 {
+        if self.is_root { ROOT_ACCENT } else { normal }
+    }
+
+    fn complete_prompt_line(&mut self, command: &str, output: Option<&str>) {
+        let output = output.map(bell::strip).map(|(output, rang)| {
+            if rang {
+                self.ring_bell();
+            }
+            output
+        });
+        let output = output.as_deref();
+        let arrow = if self.classic_prompt { self.prompt_symbol().to_string() } else { format!(" {}", self.prompt_symbol()) };
+        if let Some(last_line) = self.lines.back_mut() {
+            if last_line.is_prompt {
+                last_line.is_prompt = false; // Mark as completed command
+                let text = match output {
+                    Some(output) => format!("{}{} {} {}", last_line.text, arrow, command, output),
+                    None => format!("{}{} {}", last_line.text, arrow, command),
+                };
+                last_line.set_text(text);
+                if let Some(prompt) = &mut last_line.prompt {
+                    prompt.command = Some(command.to_string());
+                    prompt.output = output.map(str::to_string);
+                }
+            }
+        }
     }
     
+    fn add_system_info(&mut self, info: &SystemInfo) {
+        for line in info.banner_lines(&self.username, &self.hostname) {
+            self.add_line(&line, false, false);
+        }
+    }
+
+    // The startup banner arrives after the first prompt; keep it at the top of the scrollback
+    fn insert_banner(&mut self, info: &SystemInfo) {
+        for line in info.banner_lines(&self.username, &self.hostname).into_iter().rev() {
+            self.lines.push_front(TerminalLine::new(line, false, false, Rc::from(self.current_dir.as_str())));
+        }
+        while self.lines.len() > self.scrollback_limit {
+            self.lines.pop_back();
+        }
+    }
+
     fn get_git_branch(&self) -> String {
         // Try to get the current git branch
         let result = Command::new("git")
@@ -411,12 +1100,100 @@ impl TerminalApp {
             return;
         }
 
-        // Add to history
-        if !command.trim().is_empty() && (self.command_history.is_empty() || self.command_history.last() != Some(&command.to_string())) {
+        // Add to history, unless it starts with a space or looks like it holds a secret
+        if !self.history_filter.is_private(command) && (self.command_history.is_empty() || self.command_history.last() != Some(&command.to_string())) {
             self.command_history.push(command.to_string());
         }
         self.history_index = -1;
 
+        // `$?` is the previous command's exit status (history keeps the unexpanded text)
+        let expanded = substitute::expand_exit_status(command, self.last_exit_code);
+        let command = expanded.as_str();
+        self.last_exit_code = 0;
+        self.command_started = Some(Instant::now());
+        self.running_command = command.to_string();
+
+        // A macro's name runs its saved request (an AI suggestion that names a macro doesn't)
+        let name = command.trim();
+        if !self.running_ai_command && builtins::find(name).is_none() {
+            let entry = self.macros.borrow().get(name).cloned();
+            if let Some(entry) = entry {
+                self.run_macro(name, entry);
+                return;
+            }
+        }
+
+        // Unquoted `# ...` is a comment; a line that is only a comment does nothing
+        let uncommented = comment::strip_comment(command);
+        if uncommented.is_empty() {
+            self.complete_prompt(command, None);
+            self.show_prompt();
+
+            // Clear the input buffer after command execution so new prompt is clean
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
+        let command = uncommented;
+
+        // A trailing `&` (but not `&&`) runs the command as a background job
+        if let Some(job_command) = command.trim().strip_suffix('&').filter(|rest| !rest.ends_with('&')) {
+            self.complete_prompt(command, None);
+            self.start_job(job_command.trim(), false);
+            self.show_prompt();
+
+            // Clear the input buffer after command execution so new prompt is clean
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
+
+        // `=2+2*10` is quick math, printed inline instead of being run
+        if let Some(expression) = command.trim().strip_prefix('=') {
+            match calc::evaluate(expression) {
+                Ok(value) => self.complete_prompt(command, Some(&calc::format_number(value))),
+                Err(msg) => {
+                    self.complete_prompt(command, None);
+                    self.add_line(&format!("ERROR: {}", msg), false, false);
+                    self.last_exit_code = 1;
+                }
+            }
+            self.show_prompt();
+
+            // Clear the input buffer after command execution so new prompt is clean
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
+
+        // `$(...)` and backticks are replaced by the inner command's output. When the rest of the
+        // line needs sh anyway, sh substitutes too; otherwise the output is never re-parsed by a shell.
+        let substituted;
+        let literal = substitute::has_substitution(command)
+            && substitute::substitute(command, |_| Ok(String::new())).is_ok_and(|rest| !shell::needs_shell(&rest));
+        let command = if literal {
+            // The prompt shows the command as typed; the inner commands' errors go below it
+            self.complete_prompt(command, None);
+            match substitute::substitute(command, |inner| self.capture_output(inner)) {
+                Ok(text) => {
+                    substituted = text;
+                    substituted.as_str()
+                }
+                Err(msg) => {
+                    self.add_line(&format!("ERROR: {}", msg), false, false);
+                    self.last_exit_code = 1;
+                    self.show_prompt();
+
+                    // Clear the input buffer after command execution so new prompt is clean
+                    self.input_buffer.clear();
+                    self.cursor_pos = 0;
+                    return;
+                }
+            }
+        } else {
+            command
+        };
+
         // Command will be displayed inline with output for short commands
 
         let parts: Vec<String> = command.trim().split_whitespace().map(|s| s.to_string()).collect();
@@ -432,6 +1209,20 @@ impl TerminalApp {
         let cmd_name = parts[0].clone();
         let args: Vec<String> = parts[1..].to_vec();
 
+        // A builtin at the head of a pipeline prints into the rest of it: `history | grep git`
+        if let Some((head, rest)) = shell::split_pipe(command).filter(|_| !literal) {
+            if let Some(builtin) = builtins::find(&cmd_name).filter(|builtin| builtin.pipes) {
+                self.run_builtin_pipeline(command, builtin, head, rest);
+                return;
+            }
+        }
+
+        // A builtin leading `&&`, `||` or `;` runs here, so `cd build && make` moves this tab
+        if !literal && builtins::find(&cmd_name).is_some() && shell::needs_shell(command) {
+            self.run_chain(command);
+            return;
+        }
+
         // Check if user is asking for help
         if args.contains(&"--help".to_string()) || args.contains(&"-h".to_string()) {
             self.format_help_output(&cmd_name);
@@ -444,377 +1235,1913 @@ impl TerminalApp {
         }
 
         // Handle built-in commands
-        match cmd_name.as_str() {
-            "help" => {
-                // Update the last prompt line to include the help command
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
-                }
-                
-                self.add_line("🚀 Terminal Help", false, false);
-                self.add_line("ls, cd, pwd, mkdir, rm, cp, mv", false, false);
-                self.add_line("grep, find, cat, git, ps, kill", false, false);
-                self.add_line("Type 'explain <command>' for simple explanations", false, false);
-                self.add_line("Type 'what is <command>' for simple explanations", false, false);
-                self.add_line("Type 'command --help' for details", false, false);
+        if let Some(builtin) = builtins::find(&cmd_name) {
+            self.complete_prompt(command, None);
+            let call = Call { name: &cmd_name, args: &args, command };
+            if let Flow::Prompt = (builtin.run)(self, &call) {
                 self.show_prompt();
-                
-                // Clear the input buffer after command execution so new prompt is clean
-                self.input_buffer.clear();
-                self.cursor_pos = 0;
-                return;
             }
-            "explain" | "whatis" => {
-                // Update the last prompt line to include the explain command
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
-                }
 
-                if args.is_empty() {
-                    self.add_line("Usage: explain <command>", false, false);
-                    self.add_line("Example: explain ls", false, false);
-                } else {
-                    let cmd_to_explain = &args[0];
-                    self.explain_command(cmd_to_explain);
-                }
-                self.show_prompt();
+            // Clear the input buffer after command execution so new prompt is clean
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
 
-                // Clear the input buffer after command execution so new prompt is clean
-                self.input_buffer.clear();
-                self.cursor_pos = 0;
-                return;
-            }
-            "what" => {
-                // Handle "what is <command>" syntax
-                if args.len() >= 2 && args[0] == "is" {
-                    // Update the last prompt line to include the what is command
-                    if let Some(last_line) = self.lines.back_mut() {
-                        if last_line.is_prompt {
-                            last_line.text = format!("{} > {}", last_line.text, command);
-                            last_line.is_prompt = false; // Mark as completed command
+        // Pipes and chaining go to sh, which handles any redirection as well
+        if !literal && shell::needs_shell(command) {
+            self.run_external(command, true, &cmd_name, &args, StdinSource::Null);
+            return;
+        }
+
+        // Full-screen and interactive programs would hang waiting for input; open them in a real terminal
+        if interactive::needs_tty(&cmd_name, &args) {
+            self.complete_prompt(command, None);
+            self.open_in_terminal(&cmd_name, &args);
+            self.show_prompt();
+
+            // Clear the input buffer after command execution so new prompt is clean
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
+
+        // sudo has no terminal to ask for a password on, so ask for it here (unless stdin is redirected)
+        let stdin_redirected = redirect::split_input_redirect(&args).map_or(true, |(_, input)| input.is_some());
+        if cmd_name == "sudo" && !stdin_redirected && sudo::may_prompt(&args) && !sudo::credentials_cached() {
+            self.complete_prompt(command, None);
+            self.pending_sudo = Some(PendingSudo { command: command.to_string(), args });
+            self.sudo_password = sudo::password_buffer();
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
+
+        // Programs that ask for a password run as a foreground job: output shows as it comes, above
+        // the prompt, and a password prompt switches to masked input sent to the program's stdin
+        if jobs::asks_for_password(&cmd_name) && !stdin_redirected {
+            self.complete_prompt(command, None);
+            self.start_job(command, true);
+            self.show_prompt();
+
+            // Clear the input buffer after command execution so new prompt is clean
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
+        }
+
+        // `< file`, `<<< word` and `<< DELIM` feed the command's stdin
+        let redirected = redirect::split_input_redirect(&args).and_then(|(remaining, input)| {
+            let stdin = match input {
+                None => StdinSource::Null,
+                Some(Input::File(file)) => StdinSource::File(redirect::open_input(&self.resolve_path(&file), &file)?),
+                Some(Input::HereString(text)) => StdinSource::Text(format!("{}\n", text)),
+                Some(Input::HereDoc(delimiter)) => {
+                    // Collect the body from the following input lines before running anything
+                    self.complete_prompt(command, None);
+                    self.pending_heredoc = Some(HereDoc {
+                        command: command.to_string(),
+                        cmd_name: cmd_name.clone(),
+                        args: remaining,
+                        delimiter,
+                        body: Vec::new(),
+                    });
+                    self.show_continuation_prompt();
+                    self.input_buffer.clear();
+                    self.cursor_pos = 0;
+                    return Ok(None);
+                }
+            };
+            Ok(Some((remaining, stdin)))
+        });
+        match redirected {
+            Ok(Some((args, stdin))) => self.run_external(command, false, &cmd_name, &args, stdin),
+            Ok(None) => {}
+            Err(msg) => {
+                self.complete_prompt(command, None);
+                self.add_line(&format!("ERROR: {}", msg), false, false);
+                self.last_exit_code = 1;
+                self.show_prompt();
+
+                // Clear the input buffer after command execution so new prompt is clean
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+            }
+        }
+    }
+
+    fn builtin_help(&mut self, call: &Call) -> Flow {
+        match call.args.first() {
+            None => self.list_builtins(),
+            Some(name) => match builtins::find(name) {
+                Some(builtin) => {
+                    self.add_line(&format!("{} - {}", builtin.usage, builtin.summary), false, false);
+                    if builtin.names.len() > 1 {
+                        self.add_line(&format!("Also: {}", builtin.names[1..].join(", ")), false, false);
+                    }
+                    for line in builtin.details {
+                        self.add_line(line, false, false);
+                    }
+                }
+                None => {
+                    self.add_line(&format!("help: no builtin named '{}'; try '{} --help'", name, name), false, false);
+                    self.last_exit_code = 1;
+                }
+            },
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_examples(&mut self, call: &Call) -> Flow {
+        let Some(command) = call.args.first() else {
+            self.add_line("Usage: examples <command>", false, false);
+            self.last_exit_code = 2;
+            return Flow::Prompt;
+        };
+        let examples = examples::lookup(command);
+        if examples.is_empty() {
+            self.add_line(&format!("No examples for '{}'. Try: explain {}", command, command), false, false);
+            self.last_exit_code = 1;
+            return Flow::Prompt;
+        }
+        for (number, example) in examples.iter().enumerate() {
+            self.add_line(&format!("{}. {}", number + 1, example.description), false, false);
+            self.add_line(&format!("     {}", example.command), false, false);
+        }
+        self.offered_examples = examples.into_iter().map(|example| example.command).collect();
+        Flow::Prompt
+    }
+
+    fn builtin_explain(&mut self, call: &Call) -> Flow {
+        // `what is ls` reads the same as `explain ls`
+        match explain::subject(call.name, call.args) {
+            Some(cmd_to_explain) => self.explain_command(cmd_to_explain),
+            None if call.name == "what" => {
+                self.add_line("Usage: what is <command>", false, false);
+                self.add_line("Example: what is ls", false, false);
+                self.last_exit_code = 2;
+            }
+            None => {
+                self.add_line(&format!("Usage: {} <command>", call.name), false, false);
+                self.add_line(&format!("Example: {} ls", call.name), false, false);
+                self.last_exit_code = 2;
+            }
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_neofetch(&mut self, _call: &Call) -> Flow {
+        self.add_system_info(&SystemInfo::gather());
+        Flow::Prompt
+    }
+
+    fn builtin_clear(&mut self, _call: &Call) -> Flow {
+        self.lines.clear();
+        Flow::Prompt
+    }
+
+    // Like `clear`, and also leaves any mode that might be stuck: paging, watch, the
+    // filter, pickers, pending y/n questions and masked input, a selection or a scroll
+    fn builtin_reset(&mut self, _call: &Call) -> Flow {
+        // Typed passwords are wiped, not just dropped
+        if self.pending_sudo.is_some() {
+            self.cancel_sudo();
+        }
+        self.secure_input = None;
+        sudo::wipe(&mut self.secure_text);
+        self.lines.clear();
+        self.paged_output.clear();
+        self.watch = None;
+        self.filter_query.clear();
+        self.filter_regex = false;
+        self.update_filter();
+        self.filter_editing = false;
+        self.palette_open = false;
+        self.history_picker = None;
+        self.show_autocomplete = false;
+        self.selection_start = None;
+        self.selection_end = None;
+        self.pending_large_paste = None;
+        self.pending_ai_retry = None;
+        self.pending_ai_confirm = None;
+        self.pending_install = None;
+        self.package_lookup = None;
+        self.offered_examples.clear();
+        self.ai_script = None;
+        self.pending_ai_step = None;
+        self.pending_heredoc = None;
+        self.scroll_locked = false;
+        self.scroll_target = None;
+        self.scroll_to_bottom = true;
+        self.show_cursor = true;
+        self.last_cursor_blink = Instant::now();
+        self.reset_requested = true;
+        Flow::Prompt
+    }
+
+    fn builtin_exit(&mut self, _call: &Call) -> Flow {
+        // Closes this tab; the window closes with the last one
+        self.closed = true;
+        Flow::Done
+    }
+
+    fn builtin_cd(&mut self, call: &Call) -> Flow {
+        let target = call.args.first().map(|s| s.as_str());
+        match self.resolve_cd_target(target).and_then(|dir| self.change_directory(&dir)) {
+            Ok(new_dir) => {
+                // Like bash, `cd -` prints the directory it switched to
+                if target == Some("-") {
+                    self.add_line(&new_dir, false, false);
+                }
+            }
+            Err(msg) => {
+                // A plain name that isn't here is tried as a `j` query before giving up
+                let jumped = target
+                    .filter(|name| !name.contains('/') && *name != "-")
+                    .is_some_and(|name| self.jump(&[name.to_string()]).is_ok());
+                if !jumped {
+                    for line in msg.lines() {
+                        self.add_line(line, false, false);
+                    }
+                    self.last_exit_code = 1;
+                }
+            }
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_j(&mut self, call: &Call) -> Flow {
+        if call.args.is_empty() {
+            self.add_line("Usage: j <query>", false, false);
+            self.add_line("Jumps to the most visited directory matching the query", false, false);
+            self.last_exit_code = 2;
+        } else if let Err(msg) = self.jump(call.args) {
+            self.add_line(&msg, false, false);
+            self.last_exit_code = 1;
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_type(&mut self, call: &Call) -> Flow {
+        if call.args.is_empty() {
+            self.add_line("Usage: type <name>...", false, false);
+            self.last_exit_code = 2;
+            return Flow::Prompt;
+        }
+        // Looked up in the order execute_command tries them
+        let macros = Rc::clone(&self.macros);
+        let macros = macros.borrow();
+        for name in call.args {
+            let description = if builtins::find(name).is_some() {
+                format!("{} is a shell builtin", name)
+            } else if let Some(entry) = macros.get(name) {
+                format!("{} is a macro for `{}`", name, entry.phrase)
+            } else if let Some(path) = self.find_in_path(name) {
+                format!("{} is {}", name, path.display())
+            } else {
+                self.last_exit_code = 1;
+                format!("type: {}: not found", name)
+            };
+            self.add_line(&description, false, false);
+        }
+        Flow::Prompt
+    }
+
+    // Where the program `name` would be run from: the path itself if it has a `/`, otherwise the first match in PATH
+    fn find_in_path(&self, name: &str) -> Option<std::path::PathBuf> {
+        if name.contains('/') {
+            let path = self.resolve_path(name);
+            return (path.is_file() && self.is_executable(&path)).then_some(path);
+        }
+        let path_var = env::var("PATH").ok()?;
+        env::split_paths(&path_var)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file() && self.is_executable(path))
+    }
+
+    fn builtin_pwd(&mut self, call: &Call) -> Flow {
+        // The last of -L and -P wins, as in bash
+        let mut physical = false;
+        for arg in call.args {
+            match arg.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                _ => {
+                    self.add_line(&format!("pwd: {}: invalid option", arg), false, false);
+                    self.add_line("Usage: pwd [-L | -P]", false, false);
+                    self.last_exit_code = 2;
+                    return Flow::Prompt;
+                }
+            }
+        }
+        let pwd = if physical { self.physical_dir.clone() } else { self.current_dir.clone() };
+        self.add_line(&pwd, false, false);
+        Flow::Prompt
+    }
+
+    fn builtin_pushd(&mut self, call: &Call) -> Flow {
+        let old_dir = self.current_dir.clone();
+        let result = match call.args.first() {
+            Some(dir) => self.resolve_cd_target(Some(dir)).and_then(|dir| self.change_directory(&dir)).map(|_| {
+                self.dir_stack.push(old_dir);
+            }),
+            // With no argument, swap the current directory with the top of the stack
+            None => match self.dir_stack.last().cloned() {
+                Some(dir) => self.change_directory(&dir).map(|_| {
+                    self.dir_stack.pop();
+                    self.dir_stack.push(old_dir);
+                }),
+                None => Err("pushd: no other directory".to_string()),
+            },
+        };
+        self.show_dir_stack(result)
+    }
+
+    fn builtin_popd(&mut self, _call: &Call) -> Flow {
+        let result = match self.dir_stack.last().cloned() {
+            Some(dir) => self.change_directory(&dir).map(|_| {
+                self.dir_stack.pop();
+            }),
+            None => Err("popd: directory stack empty".to_string()),
+        };
+        self.show_dir_stack(result)
+    }
+
+    fn builtin_dirs(&mut self, _call: &Call) -> Flow {
+        self.show_dir_stack(Ok(()))
+    }
+
+    // pushd, popd and dirs all finish by printing the stack, or why it didn't change
+    fn show_dir_stack(&mut self, result: Result<(), String>) -> Flow {
+        match result {
+            Ok(()) => {
+                let stack = self.format_dir_stack();
+                self.add_line(&stack, false, false);
+            }
+            Err(msg) => {
+                self.add_line(&msg, false, false);
+                self.last_exit_code = 1;
+            }
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_jobs(&mut self, _call: &Call) -> Flow {
+        self.list_jobs();
+        Flow::Prompt
+    }
+
+    fn builtin_fg(&mut self, call: &Call) -> Flow {
+        let result = self.foreground_job(call.args.first().map(String::as_str));
+        self.report_job_error(call, result)
+    }
+
+    fn builtin_bg(&mut self, call: &Call) -> Flow {
+        let result = self.background_job(call.args.first().map(String::as_str));
+        self.report_job_error(call, result)
+    }
+
+    fn builtin_kill(&mut self, call: &Call) -> Flow {
+        let mut args = call.args.iter().map(String::as_str).peekable();
+        let signal = match args.peek().copied() {
+            Some("-l" | "-L") => {
+                self.add_line(&jobs::signal_names().join(" "), false, false);
+                return Flow::Prompt;
+            }
+            Some("-s") => {
+                args.next();
+                args.next().map(jobs::parse_signal)
+            }
+            Some(spec) if spec.len() > 1 && spec.starts_with('-') => {
+                args.next();
+                Some(jobs::parse_signal(&spec[1..]))
+            }
+            _ => Some(Ok(nix::sys::signal::Signal::SIGTERM)),
+        };
+        let signal = match signal {
+            Some(Ok(signal)) => signal,
+            Some(Err(msg)) => return self.report_job_error(call, Err(msg)),
+            None => return self.report_job_error(call, Err("-s: option requires an argument".to_string())),
+        };
+        let targets: Vec<&str> = args.collect();
+        if targets.is_empty() {
+            self.add_line("Usage: kill [-signal | -s signal | -l] <pid | %job>...", false, false);
+            self.last_exit_code = 2;
+            return Flow::Prompt;
+        }
+
+        let mut failed = false;
+        for target in targets {
+            let pid = if target.starts_with('%') {
+                jobs::parse_job_spec(Some(target), &self.jobs)
+                    .map(|id| self.jobs.iter().find(|job| job.id == id).map_or(0, |job| job.pid))
+            } else {
+                target.parse::<u32>().map_err(|_| format!("{}: arguments must be process or job IDs", target))
+            };
+            if let Err(msg) = pid.and_then(|pid| jobs::send_signal(pid, signal)) {
+                self.add_line(&format!("{}: {}", call.name, msg), false, false);
+                failed = true;
+            }
+        }
+        self.last_exit_code = if failed { 1 } else { 0 };
+        Flow::Prompt
+    }
+
+    fn report_job_error(&mut self, call: &Call, result: Result<(), String>) -> Flow {
+        if let Err(msg) = result {
+            self.add_line(&format!("{}: {}", call.name, msg), false, false);
+            self.last_exit_code = 1;
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_time(&mut self, call: &Call) -> Flow {
+        // Run the rest of the line normally, then report how long it took
+        let timed_command = call.command.trim_start()[call.name.len()..].trim().to_string();
+        if timed_command.is_empty() {
+            return Flow::Prompt;
+        }
+        let history_len = self.command_history.len();
+        let cpu_before = children_cpu_times();
+        let start = Instant::now();
+
+        self.execute_command(&timed_command);
+        // Only the `time ...` line belongs in history, not the inner command
+        self.command_history.truncate(history_len);
+        self.history_index = -1;
+        let real = start.elapsed();
+
+        // The inner command finished with a fresh prompt; put the report above it
+        let prompt = if self.lines.back().is_some_and(|line| line.is_prompt) { self.lines.pop_back() } else { None };
+        self.add_line(&format!("real  {}", format_duration(real)), false, false);
+        if let (Some((user_before, sys_before)), Some((user_after, sys_after))) = (cpu_before, children_cpu_times()) {
+            self.add_line(&format!("user  {}", format_duration(user_after.saturating_sub(user_before))), false, false);
+            self.add_line(&format!("sys   {}", format_duration(sys_after.saturating_sub(sys_before))), false, false);
+        }
+        if let Some(prompt) = prompt {
+            self.lines.push_back(prompt);
+        }
+        Flow::Done
+    }
+
+    fn builtin_macro(&mut self, call: &Call) -> Flow {
+        // Read again, in case another window changed the file since this one started
+        let mut macros = Macros::load(&self.configured_macros);
+        let rest = call.command.trim_start().strip_prefix(call.name).unwrap_or("").trim();
+        match call.args {
+            [] => {
+                if macros.macros.is_empty() {
+                    self.add_line("No macros. Define one with: macro <name> = <request>", false, false);
+                }
+                let listing: Vec<String> = macros.macros.iter()
+                    .map(|(name, entry)| match &entry.command {
+                        Some(cmd) => format!("{} = {}  →  {}", name, entry.phrase, cmd),
+                        None => format!("{} = {}  (not resolved yet)", name, entry.phrase),
+                    })
+                    .collect();
+                for line in listing {
+                    self.add_line(&line, false, false);
+                }
+                return Flow::Prompt;
+            }
+            [flag, name] if flag == "-d" => {
+                if self.configured_macros.iter().any(|(configured, _)| configured == name) {
+                    self.add_line(&format!("macro: {}: defined in {}; remove it there", name, Config::path().display()), false, false);
+                    self.last_exit_code = 1;
+                    return Flow::Prompt;
+                }
+                if !macros.remove(name) {
+                    self.add_line(&format!("macro: {}: no such macro", name), false, false);
+                    self.last_exit_code = 1;
+                    return Flow::Prompt;
+                }
+            }
+            [name] if !name.contains('=') => {
+                match macros.get(name) {
+                    Some(entry) => self.add_line(&format!("{} = {}", name, entry.phrase), false, false),
+                    None => {
+                        self.add_line(&format!("macro: {}: no such macro", name), false, false);
+                        self.last_exit_code = 1;
+                    }
+                }
+                return Flow::Prompt;
+            }
+            _ => match macros::parse_definition(rest) {
+                Some((name, _)) if builtins::find(&name).is_some() => {
+                    self.add_line(&format!("macro: {}: is a builtin", name), false, false);
+                    self.last_exit_code = 2;
+                    return Flow::Prompt;
+                }
+                Some((name, phrase)) => macros.define(&name, &phrase),
+                None => {
+                    self.add_line("Usage: macro [<name> = <request> | -d <name>]", false, false);
+                    self.last_exit_code = 2;
+                    return Flow::Prompt;
+                }
+            },
+        }
+        if let Err(e) = macros.save() {
+            self.add_line(&format!("macro: {}", e), false, false);
+            self.last_exit_code = 1;
+        }
+        *self.macros.borrow_mut() = macros;
+        Flow::Prompt
+    }
+
+    fn builtin_ai(&mut self, call: &Call) -> Flow {
+        match call.args.first().map(String::as_str) {
+            Some("on") => self.ai_enabled = true,
+            Some("off") => self.ai_enabled = false,
+            None => {}
+            Some(other) => {
+                self.add_line(&format!("ai: {}: expected 'on' or 'off'", other), false, false);
+                self.last_exit_code = 2;
+            }
+        }
+        let state = if self.ai_enabled { "on" } else { "off (nothing is sent to the AI service)" };
+        self.add_line(&format!("AI is {}", state), false, false);
+        Flow::Prompt
+    }
+
+    fn builtin_ai_dryrun(&mut self, call: &Call) -> Flow {
+        match call.args.first().map(String::as_str) {
+            Some("on") => self.ai_dry_run = true,
+            Some("off") => self.ai_dry_run = false,
+            None => {}
+            Some(other) => {
+                self.add_line(&format!("ai-dryrun: {}: expected 'on' or 'off'", other), false, false);
+                self.last_exit_code = 2;
+            }
+        }
+        let state = if self.ai_dry_run { "on" } else { "off" };
+        self.add_line(&format!("AI dry run is {}", state), false, false);
+        Flow::Prompt
+    }
+
+    fn builtin_ai_run(&mut self, call: &Call) -> Flow {
+        let (options, file) = match ai_script::parse_args(call.args) {
+            Ok(parsed) => parsed,
+            Err(msg) => {
+                self.add_line(&format!("ai-run: {}", msg), false, false);
+                self.last_exit_code = 2;
+                return Flow::Prompt;
+            }
+        };
+        let error = if self.ai_script.is_some() {
+            Some("a script is already running".to_string())
+        } else if !self.ai_enabled {
+            Some("AI is off; turn it on with `ai on`".to_string())
+        } else if !self.ai.has_api_key {
+            Some("no OpenRouter API key is set (OPENROUTER_API_KEY)".to_string())
+        } else {
+            None
+        };
+        if let Some(msg) = error {
+            self.add_line(&format!("ai-run: {}", msg), false, false);
+            self.last_exit_code = 1;
+            return Flow::Prompt;
+        }
+        let script = match std::fs::read_to_string(self.resolve_path(file)) {
+            Ok(content) => AiScript::new(file, &content, options),
+            Err(e) => {
+                self.add_line(&format!("ai-run: {}: {}", file, e), false, false);
+                self.last_exit_code = 1;
+                return Flow::Prompt;
+            }
+        };
+        if script.steps.is_empty() {
+            self.add_line(&format!("ai-run: {}: no requests", file), false, false);
+            return Flow::Prompt;
+        }
+        self.ai_script = Some(script);
+        self.next_ai_step();
+        Flow::Done
+    }
+
+    fn builtin_json_log(&mut self, call: &Call) -> Flow {
+        match call.args.first().map(String::as_str) {
+            None => {}
+            Some("off") => self.json_log = None,
+            Some(file) => match JsonLog::open(&self.resolve_path(file)) {
+                Ok(log) => self.json_log = Some(log),
+                Err(msg) => {
+                    self.add_line(&format!("json-log: {}: {}", file, msg), false, false);
+                    self.last_exit_code = 1;
+                    return Flow::Prompt;
+                }
+            },
+        }
+        match &self.json_log {
+            Some(log) => self.add_line(&format!("Logging command results to {}", log.path().display()), false, false),
+            None => self.add_line("JSON logging is off", false, false),
+        }
+        Flow::Prompt
+    }
+
+    fn builtin_source(&mut self, call: &Call) -> Flow {
+        let Some(file) = call.args.first() else {
+            self.add_line(&format!("{}: filename argument required", call.name), false, false);
+            self.last_exit_code = 2;
+            return Flow::Prompt;
+        };
+        let path = self.resolve_path(file);
+        self.show_prompt();
+        if let Err(msg) = self.source_file(&path, false) {
+            // Report above the prompt the script (or the error) left behind
+            self.add_line_above_prompt(&msg);
+            self.last_exit_code = 1;
+        }
+        Flow::Done
+    }
+
+    fn builtin_touch(&mut self, call: &Call) -> Flow {
+        if call.args.is_empty() {
+            self.add_line("touch: missing file operand", false, false);
+            self.last_exit_code = 2;
+            return Flow::Prompt;
+        }
+        let mut failed = false;
+        for name in call.args {
+            let result = if name.starts_with('-') {
+                Err("unsupported option".to_string())
+            } else {
+                // Opening for append creates a missing file without truncating an existing one
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.resolve_path(name))
+                    .and_then(|file| file.set_modified(std::time::SystemTime::now()))
+                    .map_err(|e| e.to_string())
+            };
+            if let Err(msg) = result {
+                self.add_line(&format!("touch: {}: {}", name, msg), false, false);
+                failed = true;
+            }
+        }
+        self.last_exit_code = if failed { 1 } else { 0 };
+        Flow::Prompt
+    }
+
+    fn builtin_watch(&mut self, call: &Call) -> Flow {
+        let (interval, command) = match call.args.first().map(String::as_str) {
+            Some("-n") => (call.args.get(1).and_then(|secs| secs.parse::<f64>().ok()), call.args.get(2..)),
+            _ => (Some(2.0), Some(call.args)),
+        };
+        let command = command.map(|words| words.join(" ")).filter(|command| !command.is_empty());
+        let (Some(interval), Some(command)) = (interval.filter(|secs| secs.is_finite() && *secs >= 0.1), command) else {
+            self.add_line("Usage: watch [-n seconds] <command>", false, false);
+            self.add_line("The interval is at least 0.1 seconds; press q, Esc or Ctrl+C to stop", false, false);
+            self.last_exit_code = 2;
+            return Flow::Prompt;
+        };
+        self.watch = Some(Watch {
+            command,
+            interval: Duration::from_secs_f64(interval),
+            next_run: Instant::now(),
+            runs: 0,
+            shown: 0,
+            run: None,
+        });
+        self.run_watch();
+        Flow::Done
+    }
+
+    // Start the next run of the watched command; `poll_watch` shows its output when it finishes
+    fn run_watch(&mut self) {
+        let Some(watch) = &self.watch else { return };
+        let child = self.child_command("sh")
+            .arg("-c")
+            .arg(&watch.command)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn();
+        let (sender, output) = std::sync::mpsc::channel();
+        let pid = match child {
+            Ok(child) => {
+                let pid = child.id();
+                std::thread::spawn(move || {
+                    let _ = sender.send(interleave::wait(child));
+                });
+                pid
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                0
+            }
+        };
+        if let Some(watch) = &mut self.watch {
+            watch.run = Some(WatchRun { pid, output });
+        }
+    }
+
+    // Replace the previous run's output with the finished run's, and schedule the next one
+    fn poll_watch(&mut self) {
+        let Some(watch) = &mut self.watch else { return };
+        let Some(output) = watch.run.as_ref().and_then(|run| run.output.try_recv().ok()) else { return };
+        watch.run = None;
+        watch.runs += 1;
+        let header = format!("Every {:.1}s: {}  (run {})", watch.interval.as_secs_f64(), watch.command, watch.runs);
+        // Counted from the back, the region stays put when the scrollback limit drops lines from the front
+        let start = self.lines.len().saturating_sub(watch.shown);
+        self.lines.truncate(start);
+        let added = self.lines_added;
+
+        self.add_line(&header, false, false);
+        self.add_line("", false, false);
+        match output {
+            Ok(output) => {
+                for (stream, line) in output.lines {
+                    if stream == Stream::Stdout || !line.is_empty() {
+                        self.add_output_line(stream, &line);
+                    }
+                }
+                self.last_exit_code = output.status.code().unwrap_or(1);
+            }
+            Err(e) => {
+                self.add_line(&format!("ERROR: {}", e), false, false);
+                self.last_exit_code = 1;
+            }
+        }
+
+        if let Some(watch) = &mut self.watch {
+            watch.shown = (self.lines_added - added).min(self.lines.len());
+            watch.next_run = Instant::now() + watch.interval;
+        }
+    }
+
+    // Keys while the sudo password is typed: Enter runs the command, Esc or Ctrl+C cancels it
+    fn handle_sudo_event(&mut self, event: &egui::Event) {
+        match event {
+            egui::Event::Text(text) | egui::Event::Paste(text) => {
+                sudo::push_password(&mut self.sudo_password, text.trim_end_matches(['\r', '\n']));
+            }
+            egui::Event::Key { key: egui::Key::Backspace, pressed: true, .. } => {
+                self.sudo_password.pop();
+            }
+            egui::Event::Key { key: egui::Key::Enter, pressed: true, .. } => {
+                let Some(pending) = self.pending_sudo.take() else { return };
+                let mut stdin = std::mem::take(&mut self.sudo_password);
+                stdin.push('\n');
+                // The stdin writer wipes the password once sudo has read it
+                let args = sudo::with_stdin_password(&pending.args);
+                self.run_external(&pending.command, false, "sudo", &args, StdinSource::Text(stdin));
+            }
+            egui::Event::Key { key: egui::Key::Escape, pressed: true, .. } => self.cancel_sudo(),
+            egui::Event::Key { key: egui::Key::C, pressed: true, modifiers, .. } if modifiers.ctrl => self.cancel_sudo(),
+            _ => {}
+        }
+    }
+
+    // Keys while a foreground job waits for a password: typed masked, and sent to the job's
+    // stdin on Enter. Escape goes back to the input line without sending anything; Ctrl+C
+    // interrupts the job.
+    fn handle_secure_input_event(&mut self, event: &egui::Event) {
+        let Some(id) = self.secure_input else { return };
+        match event {
+            egui::Event::Text(text) | egui::Event::Paste(text) => {
+                sudo::push_password(&mut self.secure_text, text.trim_end_matches(['\r', '\n']));
+            }
+            egui::Event::Key { key: egui::Key::Backspace, pressed: true, .. } => {
+                self.secure_text.pop();
+            }
+            egui::Event::Key { key: egui::Key::Enter, pressed: true, .. } => {
+                self.secure_input = None;
+                self.secure_text.push('\n');
+                let sent = match self.jobs.iter_mut().find(|job| job.id == id).and_then(|job| job.stdin.as_mut()) {
+                    Some(stdin) => stdin.write_all(self.secure_text.as_bytes()).and_then(|_| stdin.flush()),
+                    None => Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe)),
+                };
+                sudo::wipe(&mut self.secure_text);
+                if let Err(e) = sent {
+                    self.add_line_above_prompt(&format!("ERROR: job {}: could not send the password: {}", id, e));
+                }
+            }
+            egui::Event::Key { key: egui::Key::Escape, pressed: true, .. } => {
+                self.secure_input = None;
+                sudo::wipe(&mut self.secure_text);
+            }
+            egui::Event::Key { key: egui::Key::C, pressed: true, modifiers, .. } if modifiers.ctrl => {
+                self.secure_input = None;
+                sudo::wipe(&mut self.secure_text);
+                if let Some(job) = self.jobs.iter().find(|job| job.id == id) {
+                    let _ = jobs::send_signal(job.pid, nix::sys::signal::Signal::SIGINT);
+                }
+                self.add_line_above_prompt("^C");
+            }
+            _ => {}
+        }
+    }
+
+    fn cancel_sudo(&mut self) {
+        self.pending_sudo = None;
+        sudo::wipe(&mut self.sudo_password);
+        self.add_line("^C", false, false);
+        self.last_exit_code = 1;
+        self.show_prompt();
+    }
+
+    fn stop_watch(&mut self) {
+        // A run still going is stopped along with the watch
+        if let Some(run) = self.watch.take().and_then(|watch| watch.run).filter(|run| run.pid != 0) {
+            let _ = jobs::send_signal(run.pid, nix::sys::signal::Signal::SIGTERM);
+        }
+        self.show_prompt();
+    }
+
+    fn builtin_copy(&mut self, _call: &Call) -> Flow {
+        // The newest command is this `copy`, so take the one before it
+        match self.command_output(1) {
+            Some(output) => {
+                let count = output.lines().count();
+                self.add_line(&format!("Copied {} line{}", count, if count == 1 { "" } else { "s" }), false, false);
+                self.pending_copy = Some(output);
+            }
+            None => {
+                self.add_line("copy: no command output to copy", false, false);
+                self.last_exit_code = 1;
+            }
+        }
+        Flow::Prompt
+    }
+
+    /// Output of a previous command, counting back from the newest (0)
+    fn command_output(&self, skip: usize) -> Option<String> {
+        let rows = self.lines.iter().map(|line| Row { text: &line.text, prompt: line.prompt.as_ref(), live: line.is_prompt });
+        copy_output::command_output(rows, skip)
+    }
+
+    fn builtin_history(&mut self, call: &Call) -> Flow {
+        match call.args.first().map(String::as_str) {
+            None => {
+                let history = self.command_history.clone();
+                for (i, cmd) in history.iter().enumerate() {
+                    let history_line = format!(" {}: {}", i + 1, cmd);
+                    self.add_line(&history_line, false, false);
+                }
+            }
+            Some("-c") => {
+                self.command_history.clear();
+                self.history_edited = true;
+            }
+            Some("-d") => {
+                // Numbered as listed, or counted back from the newest with a negative number
+                let len = self.command_history.len() as i64;
+                let index = call.args.get(1)
+                    .and_then(|n| n.parse::<i64>().ok())
+                    .map(|n| if n < 0 { len + n } else { n - 1 })
+                    .filter(|index| (0..len).contains(index));
+                match index {
+                    Some(index) => {
+                        // Later entries move up one, as in bash
+                        self.command_history.remove(index as usize);
+                        self.history_edited = true;
+                    }
+                    None => {
+                        let offset = call.args.get(1).map_or("", String::as_str);
+                        self.add_line(&format!("history: {}: history position out of range", offset), false, false);
+                        self.last_exit_code = 1;
+                    }
+                }
+            }
+            Some(option) => {
+                self.add_line(&format!("history: {}: invalid option", option), false, false);
+                self.add_line("Usage: history [-c | -d offset]", false, false);
+                self.last_exit_code = 2;
+            }
+        }
+        self.history_index = -1;
+        Flow::Prompt
+    }
+
+    // Launch a program that needs a TTY in a new terminal window, detached from this one
+    fn open_in_terminal(&mut self, cmd_name: &str, args: &[String]) {
+        let terminal = interactive::terminal_emulator(env::var("TERMINAL").ok());
+        self.add_line(&format!("`{}` needs an interactive terminal; opening it in {}", cmd_name, terminal), false, false);
+        let mut command = Command::new(&terminal);
+        command.arg("-e");
+        if interactive::hold_open(cmd_name, args) {
+            // Wait for Enter before the window closes, so the command's output can be read
+            command.args(["sh", "-c", "\"$0\" \"$@\"; printf '\\n[exit %s, press Enter to close]' $?; read _"]);
+        }
+        let spawned = command
+            .arg(cmd_name)
+            .args(args)
+            .current_dir(&self.current_dir)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        if let Err(e) = spawned {
+            self.add_line(&format!("ERROR: {}: {} (set $TERMINAL to your terminal emulator)", terminal, e), false, false);
+            self.last_exit_code = 127;
+        }
+    }
+
+    // Stdout of a command substitution; its stderr is shown like any other error output
+    fn capture_output(&mut self, command: &str) -> Result<String, String> {
+        let output = self.child_command("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .map_err(|e| format!("$({}): {}", command, e))?;
+        for line in String::from_utf8_lossy(&output.stderr).lines().filter(|line| !line.is_empty()) {
+            self.add_output_line(Stream::Stderr, line);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    // Add a command's output, holding back everything past the first screenful when the pager is on
+    fn add_output(&mut self, lines: Vec<(Stream, String)>) {
+        let mut lines = lines.into_iter();
+        let shown = if self.pager { self.page_lines } else { usize::MAX };
+        for (stream, line) in lines.by_ref().take(shown) {
+            self.add_output_line(stream, &line);
+        }
+        self.paged_output.extend(lines);
+    }
+
+    // Space shows the next screenful of held-back output, Enter the next line, q or Escape drops the rest
+    fn handle_pager_event(&mut self, event: &egui::Event) {
+        let count = match event {
+            egui::Event::Text(text) if text == " " => self.page_lines,
+            egui::Event::Text(text) if text.eq_ignore_ascii_case("q") => usize::MAX,
+            egui::Event::Key { key: egui::Key::Enter, pressed: true, .. } => 1,
+            egui::Event::Key { key: egui::Key::Escape, pressed: true, .. } => usize::MAX,
+            _ => return,
+        };
+        if count == usize::MAX {
+            self.paged_output.clear();
+            return;
+        }
+        for _ in 0..count {
+            let Some((stream, line)) = self.paged_output.pop_front() else { break };
+            self.add_output_line_above_prompt(stream, &line);
+        }
+    }
+
+    // The known command nearest to a mistyped name, within a couple of typos
+    fn closest_command(&self, name: &str) -> Option<String> {
+        let max_distance = if name.chars().count() <= 4 { 1 } else { 2 };
+        self.common_commands.iter()
+            .chain(self.path_commands.iter())
+            .map(|cmd| (fuzzy::edit_distance(name, cmd), cmd))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, cmd)| cmd.clone())
+    }
+
+    // A child process of this session: it starts in the current directory, with PWD naming
+    // it as `pwd` does, and, as there is no pty to ask, learns the window's size from COLUMNS and LINES
+    fn child_command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        command.current_dir(&self.current_dir)
+            .env("PWD", &self.current_dir)
+            .env("COLUMNS", self.term_size.0.to_string())
+            .env("LINES", self.term_size.1.to_string());
+        command
+    }
+
+    // Run a program that isn't a builtin, falling back to the AI when it doesn't exist.
+    // With `use_shell` the whole line goes to sh instead of running `cmd_name args` directly.
+    fn run_external(&mut self, command: &str, use_shell: bool, cmd_name: &str, args: &[String], stdin: StdinSource) {
+        let mut process = if use_shell {
+            let mut sh = self.child_command("sh");
+            sh.arg("-c").arg(command);
+            sh
+        } else {
+            let mut program = self.child_command(cmd_name);
+            program.args(args);
+            program
+        };
+
+        // Execute external command synchronously for now
+        let result = redirect::output_with_stdin(&mut process, stdin);
+
+        match result {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+
+                // Check if output is short enough to display inline
+                let stdout_lines: Vec<&str> = stdout.lines().collect();
+                let stderr_lines: Vec<&str> = stderr.lines().collect();
+
+                let is_short_output = stdout_lines.len() <= 1 &&
+                                    stderr_lines.is_empty() &&
+                                    stdout.trim().len() < 80 && // Less than 80 characters
+                                    !stdout.contains('\n'); // No newlines
+
+                // Output can only go inline when there is a live prompt to complete (not after a here-doc)
+                let has_live_prompt = self.lines.back().is_some_and(|line| line.is_prompt);
+                let inline = is_short_output && !stdout.trim().is_empty() && has_live_prompt;
+                if inline {
+                    // Update the last prompt line to include the command and output inline
+                    self.complete_prompt(command, Some(stdout.trim()));
+                } else {
+                    // Update the last prompt line to include the command
+                    self.complete_prompt(command, None);
+                }
+
+                // Stdout and stderr lines as they were written, stderr always on separate lines for visibility
+                let mut output_lines: Vec<(Stream, String)> = output.lines.into_iter()
+                    .filter(|(stream, line)| !line.is_empty() && (!inline || *stream == Stream::Stderr))
+                    .collect();
+
+                // Add exit status if non-zero
+                if !output.status.success() {
+                    self.last_exit_code = output.status.code().unwrap_or(1);
+                    if let Some(code) = output.status.code() {
+                        output_lines.push((Stream::Stdout, format!("Command '{}' exited with code {}", cmd_name, code)));
+                    }
+                }
+                self.log_json(command, &stdout, &stderr);
+                self.add_output(output_lines);
+            }
+            Err(e) => {
+                // Try AI interpretation only when command/binary not found
+                let err_msg = format!("{}", e);
+                let is_cmd_missing = err_msg.contains("No such file or directory") || err_msg.contains("command not found");
+
+                if is_cmd_missing && (!self.sourcing.is_empty() || self.running_ai_command || !self.ai_enabled || self.ai_ignore.ignores(cmd_name)) {
+                    // Scripts, AI suggestions, AI-off mode and `ai_ignore` names never fall back to the AI; a missing command is just an error
+                    self.complete_prompt(command, None);
+                    self.report_command_not_found(cmd_name);
+                } else if is_cmd_missing {
+                    // Check for instant commands first (ultra-fast, no AI call)
+                    if let Some(instant_cmd) = AIAssistant::get_instant_command(command) {
+                        // Update the last prompt line to include the command
+                        self.complete_prompt(command, None);
+                        self.add_line(&format!("⚡ {}", &instant_cmd), false, false);
+                        if self.ai_dry_run {
+                            self.suggest_ai_command(&instant_cmd);
+                            return;
+                        }
+                        self.run_ai_command(&instant_cmd);
+                        return;
+                    }
+
+                    // Close the current prompt line with the raw input
+                    self.complete_prompt(command, None);
+                    if !self.ai.has_api_key {
+                        // The request could only fail; say how to set a key up, once per tab
+                        self.report_command_not_found(cmd_name);
+                        if !self.api_key_hint_shown {
+                            self.api_key_hint_shown = true;
+                            for line in ai_assistant::API_KEY_HINT {
+                                self.add_line(line, false, false);
+                            }
+                        }
+                    } else {
+                        self.request_ai_command(command, self.ai.request_timeout);
+                        self.input_buffer.clear();
+                        self.cursor_pos = 0;
+                        return;
+                    }
+                } else {
+                    // Update the last prompt line to include the failed command
+                    self.last_exit_code = 126;
+                    self.complete_prompt(command, Some(&format!("(Failed: {})", e)));
+                }
+            }
+        }
+
+    self.show_prompt();
+        
+        // Clear the input buffer after command execution so new prompt is clean
+        self.input_buffer.clear();
+        self.cursor_pos = 0;
+    }
+
+    // A missing command that isn't sent to the AI, with the closest known command unless a script
+    // ran it. Typed commands are also looked up in the package manager's database, to offer an install.
+    fn report_command_not_found(&mut self, cmd_name: &str) {
+        self.add_line(&format!("ERROR: {}: command not found", cmd_name), false, false);
+        if self.sourcing.is_empty() {
+            if let Some(closest) = self.closest_command(cmd_name) {
+                self.add_line(&format!("Did you mean '{}'?", closest), false, false);
+            }
+            if !self.running_ai_command {
+                self.package_lookup = self.package_manager.map(|manager| manager.find_install(cmd_name));
+            }
+        }
+        self.last_exit_code = 127;
+    }
+
+    // Offer the install command once the package lookup answers, unless something else was started meanwhile
+    fn poll_package_lookup(&mut self) {
+        let Some(found) = self.package_lookup.as_ref().and_then(|receiver| receiver.try_recv().ok()) else { return };
+        self.package_lookup = None;
+        let idle = self.input_buffer.is_empty() && self.command_started.is_none() && self.ai_pending.is_none();
+        if let Some(install) = found.filter(|_| idle) {
+            self.pending_install = Some(install);
+        }
+    }
+
+    // Run a line of `&&`, `||` and `;` that starts with a builtin. The builtins at its front run
+    // here in turn; from the first command that isn't one, the rest of the line goes to sh.
+    fn run_chain(&mut self, command: &str) {
+        self.complete_prompt(command, None);
+        let mut rest = command.trim();
+        let mut runs = true;
+        loop {
+            let (head, next) = match shell::split_chain(rest) {
+                Some((head, chain, tail)) => (head, Some((chain, tail))),
+                None => (rest, None),
+            };
+            let parts: Vec<String> = head.split_whitespace().map(str::to_string).collect();
+            let name = parts.first().cloned().unwrap_or_default();
+            if runs {
+                match builtins::find(&name).filter(|_| !shell::needs_shell(head)) {
+                    Some(builtin) => {
+                        self.last_exit_code = 0;
+                        let call = Call { name: &name, args: &parts[1..], command: head };
+                        if let Flow::Done = (builtin.run)(self, &call) {
+                            self.input_buffer.clear();
+                            self.cursor_pos = 0;
+                            return;
                         }
                     }
+                    None => {
+                        self.run_external(rest, true, &name, &parts[1..], StdinSource::Null);
+                        return;
+                    }
+                }
+            }
+            match next {
+                // A skipped command leaves the exit code for the operator after it, as in sh
+                Some((chain, tail)) if !tail.is_empty() => {
+                    runs = chain.runs_next(self.last_exit_code);
+                    rest = tail;
+                }
+                _ => break,
+            }
+        }
+        self.show_prompt();
+
+        // Clear the input buffer after command execution so new prompt is clean
+        self.input_buffer.clear();
+        self.cursor_pos = 0;
+    }
+
+    // Run a builtin with its output captured, then the rest of the pipeline with sh, reading that output
+    fn run_builtin_pipeline(&mut self, command: &str, builtin: &builtins::Builtin, head: &str, rest: &str) {
+        let parts: Vec<String> = head.split_whitespace().map(str::to_string).collect();
+        let call = Call { name: &parts[0], args: &parts[1..], command: head };
+        self.captured_output = Some(Vec::new());
+        (builtin.run)(self, &call);
+        let output: String = self.captured_output.take().unwrap_or_default().into_iter()
+            .map(|line| line + "\n")
+            .collect();
+        // `examples` offers what it printed for a number key, but it went into the pipe
+        self.offered_examples.clear();
+
+        // Like sh without pipefail, the status is the last stage's
+        self.last_exit_code = 0;
+        let args = ["-c".to_string(), rest.to_string()];
+        self.run_external(command, false, "sh", &args, StdinSource::Text(output));
+    }
+
+    // Append a program's result to the JSON log, if one is open. Commands kept out of
+    // history are kept out of the log too; a log that can't be written is closed
+    fn log_json(&mut self, command: &str, stdout: &str, stderr: &str) {
+        let Some(log) = &self.json_log else { return };
+        if self.history_filter.is_private(command) {
+            return;
+        }
+        let duration = self.command_started.map(|started| started.elapsed()).unwrap_or_default();
+        if let Err(msg) = log.append(&Entry::new(command, stdout, stderr, self.last_exit_code, duration)) {
+            self.add_line(&format!("json-log: {}: {} (logging stopped)", log.path().display(), msg), false, false);
+            self.json_log = None;
+        }
+    }
+
+    // Remember a command that ran past `notify_after`, so the app can notify if the window is in the background
+    fn note_finished(&mut self, command: &str, exit_code: i32, duration: Duration) {
+        if self.notify_after.is_some_and(|after| duration >= after) {
+            let command = (!self.history_filter.is_private(command)).then(|| command.to_string());
+            self.finished = Some(Finished { command, exit_code, duration });
+        }
+    }
+
+    // Bare `> ` prompt used while typing a here-doc body
+    fn show_continuation_prompt(&mut self) {
+        self.add_line("", false, true);
+        if let Some(line) = self.lines.back_mut() {
+            line.prompt = Some(PromptLine { segments: Vec::new(), command: None, output: None });
+        }
+    }
+
+    // Enter while a here-doc is open: add the line to its body, or run the command at the delimiter
+    fn continue_heredoc(&mut self) {
+        let line = std::mem::take(&mut self.input_buffer);
+        self.cursor_pos = 0;
+        self.complete_prompt(&line, None);
+
+        let Some(heredoc) = &mut self.pending_heredoc else { return };
+        if line.trim() != heredoc.delimiter {
+            heredoc.body.push(line);
+            self.show_continuation_prompt();
+            return;
+        }
 
-                    let cmd_to_explain = &args[1];
-                    self.explain_command(cmd_to_explain);
-                    self.show_prompt();
+        if let Some(heredoc) = self.pending_heredoc.take() {
+            let mut body = heredoc.body.join("\n");
+            if !heredoc.body.is_empty() {
+                body.push('\n');
+            }
+            self.run_external(&heredoc.command, false, &heredoc.cmd_name, &heredoc.args, StdinSource::Text(body));
+        }
+    }
 
-                    // Clear the input buffer after command execution so new prompt is clean
+    // Escape abandons a here-doc without running its command
+    fn cancel_heredoc(&mut self) {
+        if self.pending_heredoc.take().is_some() {
+            if self.lines.back().is_some_and(|line| line.is_prompt) {
+                self.lines.pop_back();
+            }
+            self.last_exit_code = 130;
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            self.show_prompt();
+        }
+    }
+
+    // Start `command` as a job: in the background for a trailing `&`, or in the foreground for a
+    // program that asks for a password, with a stdin to send it to
+    fn start_job(&mut self, command: &str, foreground: bool) {
+        let parts: Vec<String> = command.split_whitespace().map(|s| s.to_string()).collect();
+        let Some((program, args)) = parts.split_first() else {
+            self.add_line("syntax error near unexpected token `&'", false, false);
+            self.last_exit_code = 2;
+            return;
+        };
+
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        let mut job_command = self.child_command(program);
+        job_command.args(args);
+        match jobs::spawn_job(id, job_command, self.job_sender.clone(), foreground) {
+            Ok((pid, stdin)) => {
+                if !foreground {
+                    self.add_line(&format!("[{}] {}", id, pid), false, false);
+                }
+                self.jobs.push(Job {
+                    id,
+                    pid,
+                    command: command.to_string(),
+                    status: JobStatus::Running,
+                    foreground,
+                    output: Vec::new(),
+                    started: Instant::now(),
+                    dir: self.current_dir.clone(),
+                    stdin,
+                });
+            }
+            Err(e) => {
+                self.add_line(&format!("ERROR: {}: {}", program, e), false, false);
+                self.last_exit_code = 127;
+            }
+        }
+    }
+
+    fn list_jobs(&mut self) {
+        let mut listing = Vec::new();
+        for job in &mut self.jobs {
+            if job.status != JobStatus::Stopped && jobs::is_stopped(job.pid) {
+                job.status = JobStatus::Stopped;
+            } else if job.status == JobStatus::Stopped && !jobs::is_stopped(job.pid) {
+                job.status = JobStatus::Running;
+            }
+            listing.push(format!("[{}]  {:<8} {:>7}  {}", job.id, job.status.label(), job.pid, job.command));
+        }
+        for line in listing {
+            self.add_line(&line, false, false);
+        }
+    }
+
+    // Resume the job if needed and stream its output into the terminal from now on
+    fn foreground_job(&mut self, spec: Option<&str>) -> Result<(), String> {
+        let id = jobs::parse_job_spec(spec, &self.jobs)?;
+        let job = self.jobs.iter_mut().find(|job| job.id == id).ok_or("no such job")?;
+        jobs::resume(job.pid);
+        job.status = JobStatus::Running;
+        job.foreground = true;
+        let command = job.command.clone();
+        let dir = job.dir.clone();
+        let output = std::mem::take(&mut job.output);
+        // It may have stopped at a password prompt while in the background
+        if output.last().is_some_and(|(_, line)| jobs::is_password_prompt(line)) && job.stdin.is_some() {
+            self.secure_input = Some(id);
+        }
+
+        self.add_line(&command, false, false);
+        self.output_dir = Some(dir);
+        for (stream, line) in output {
+            self.add_output_line(stream, &line);
+        }
+        self.output_dir = None;
+        Ok(())
+    }
+
+    // Resume a stopped job in the background
+    fn background_job(&mut self, spec: Option<&str>) -> Result<(), String> {
+        let id = jobs::parse_job_spec(spec, &self.jobs)?;
+        let job = self.jobs.iter_mut().find(|job| job.id == id).ok_or("no such job")?;
+        if job.status == JobStatus::Running && !job.foreground && !jobs::is_stopped(job.pid) {
+            return Err(format!("job {} already in background", id));
+        }
+        jobs::resume(job.pid);
+        job.status = JobStatus::Running;
+        job.foreground = false;
+        // Nothing can be typed to it from now on, so it reads the end of its input
+        job.stdin = None;
+        let line = format!("[{}]+ {} &", job.id, job.command);
+        self.add_line(&line, false, false);
+        Ok(())
+    }
+
+    // Generate on the runtime; poll_ai picks up the answer and brings the prompt back
+    fn request_ai_command(&mut self, input: &str, timeout: Duration) {
+        self.add_line("⚡ Processing...", false, false);
+        self.ai.request_command_async(&self.rt, input.to_string(), timeout);
+        self.ai_pending = Some(PendingAI {
+            started: Instant::now(),
+            timeout,
+        });
+    }
+
+    // Animate the "Processing" line with a spinner and the seconds waited so far
+    fn update_ai_progress(&mut self) {
+        let Some(pending) = &self.ai_pending else { return };
+        let elapsed = pending.started.elapsed();
+        let spinner = ['|', '/', '-', '\\'][(elapsed.as_millis() / 250) as usize % 4];
+        let text = format!("⚡ Processing {} {}s / {}s", spinner, elapsed.as_secs(), pending.timeout.as_secs());
+        if let Some(line) = self.lines.iter_mut().rev().find(|line| line.text.starts_with("⚡ Processing")) {
+            line.set_text(text);
+        }
+    }
+
+    // Run a macro's resolved command, or ask the AI to resolve its phrase the first time
+    fn run_macro(&mut self, name: &str, entry: Macro) {
+        self.complete_prompt(name, None);
+        match entry.command {
+            Some(cmd) => {
+                self.add_line(&format!("⚡ {}", cmd), false, false);
+                if self.hold_back_ai_command(&entry.phrase, &cmd) {
+                    self.show_prompt();
                     self.input_buffer.clear();
                     self.cursor_pos = 0;
                     return;
-                } else {
-                    // Fall through to external command execution
-                }
-            }
-            "clear" => {
-                // Update the last prompt line to include the clear command
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
                 }
-                
-                self.lines.clear();
-                self.show_prompt();
-                
-                // Clear the input buffer after command execution so new prompt is clean
-                self.input_buffer.clear();
-                self.cursor_pos = 0;
-                return;
-            }
-            "exit" => {
-                // Update the last prompt line to include the exit command first
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
+                if self.ai_dry_run {
+                    self.suggest_ai_command(&cmd);
+                    return;
                 }
-                
-                std::process::exit(0);
+                self.run_ai_command(&cmd);
             }
-            "cd" => {
-                let target_dir = if args.is_empty() {
-                    env::var("HOME").unwrap_or_else(|_| "/".to_string())
-                } else {
-                    args[0].clone()
-                };
-                
-                let new_path = if target_dir.starts_with('/') {
-                    std::path::PathBuf::from(&target_dir)
-                } else {
-                    std::path::PathBuf::from(&self.current_dir).join(&target_dir)
-                };
-                
-                // Update the last prompt line to include the cd command first
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
-                }
-                
-                match new_path.canonicalize() {
-                    Ok(canonical_path) => {
-                        if canonical_path.is_dir() {
-                            self.current_dir = canonical_path.to_string_lossy().to_string();
-                            let _ = env::set_current_dir(&canonical_path);
-                        } else {
-                            self.add_line(&format!("cd: {}: Not a directory", target_dir), false, false);
-                        }
-                    }
-                    Err(_) => {
-                        self.add_line(&format!("cd: {}: No such file or directory", target_dir), false, false);
-                    }
-                }
+            None if !self.ai_enabled || !self.ai.has_api_key => {
+                let reason = if self.ai_enabled { "no OpenRouter API key is set" } else { "AI is off" };
+                self.add_line(&format!("ERROR: macro '{}' hasn't been resolved yet and {}", name, reason), false, false);
+                self.last_exit_code = 1;
                 self.show_prompt();
-                
-                // Clear the input buffer after command execution so new prompt is clean
                 self.input_buffer.clear();
                 self.cursor_pos = 0;
-                return;
             }
-            "pwd" => {
-                // Update the last prompt line to include the pwd command
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
-                }
-                
-                let pwd = self.current_dir.clone();
-                self.add_line(&pwd, false, false);
-                self.show_prompt();
-                
-                // Clear the input buffer after command execution so new prompt is clean
+            None => {
+                self.add_line(&format!("🧩 {}", entry.phrase), false, false);
+                self.resolving_macro = Some(name.to_string());
+                self.request_ai_command(&entry.phrase, self.ai.request_timeout);
                 self.input_buffer.clear();
                 self.cursor_pos = 0;
+            }
+        }
+    }
+
+    // An AI suggestion the guard flags is logged and waits for a y/n; true if it was held back.
+    // The caller shows the prompt.
+    fn hold_back_ai_command(&mut self, input: &str, cmd: &str) -> bool {
+        let Some(reason) = self.ai_guard.check(cmd).map(str::to_string) else {
+            return false;
+        };
+        self.add_line(&format!("🛑 Not running the suggestion automatically: it {}", reason), false, false);
+        self.add_line(&format!("   {}", cmd), false, false);
+        if let Err(e) = guard::log_blocked(input, cmd, &reason) {
+            self.add_line(&format!("ERROR: could not log the blocked suggestion: {}", e), false, false);
+        }
+        self.last_exit_code = 1;
+        self.pending_ai_confirm = Some(cmd.to_string());
+        true
+    }
+
+    // Answer to a held-back AI suggestion: run it as it is, or drop it
+    fn resolve_ai_confirm(&mut self, accept: bool) {
+        let Some(cmd) = self.pending_ai_confirm.take() else { return };
+        if accept {
+            self.run_ai_command(&cmd);
+        }
+    }
+
+    // Answer to the retry offer after a timeout: retry with the longer timeout, or give up
+    fn resolve_ai_retry(&mut self, accept: bool) {
+        let Some((input, timeout)) = self.pending_ai_retry.take() else { return };
+        if !accept {
+            self.resolving_macro = None;
+        }
+        if accept {
+            // The retry's output replaces the prompt until the AI answers
+            if self.lines.back().is_some_and(|line| line.is_prompt) {
+                self.lines.pop_back();
+            }
+            self.request_ai_command(&input, timeout);
+        }
+    }
+
+    // Send the `ai-run` script's next request to the AI, or finish the script once none are left
+    fn next_ai_step(&mut self) {
+        let Some(script) = &mut self.ai_script else { return };
+        let Some(step) = script.steps.pop_front() else {
+            self.finish_ai_script(None);
+            return;
+        };
+        let heading = format!("🧩 {}:{}: {}", script.file, step.line, step.request);
+        let request = step.request.clone();
+        script.current = Some(step);
+
+        // Each step's output replaces the prompt the previous step left
+        if self.lines.back().is_some_and(|line| line.is_prompt) {
+            self.lines.pop_back();
+        }
+        self.add_line(&heading, false, false);
+        self.request_ai_command(&request, self.ai.request_timeout);
+    }
+
+    // The AI's answer for the script's current step: run it, ask first, or record the failure
+    fn resolve_ai_step(&mut self, result: Result<String, AiError>) {
+        let Some(script) = &self.ai_script else { return };
+        let (location, yes) = (script.location(), script.options.yes);
+        let cmd = match result {
+            Ok(cmd) => cmd,
+            Err(error) => {
+                self.add_line(&format!("❌ {}: {}", location, error), false, false);
+                self.end_ai_step(false);
                 return;
             }
-            "history" => {
-                // Update the last prompt line to include the history command
-                if let Some(last_line) = self.lines.back_mut() {
-                    if last_line.is_prompt {
-                        last_line.text = format!("{} > {}", last_line.text, command);
-                        last_line.is_prompt = false; // Mark as completed command
-                    }
-                }
-                
-                let history = self.command_history.clone();
-                for (i, cmd) in history.iter().enumerate() {
-                    let history_line = format!(" {}: {}", i + 1, cmd);
-                    self.add_line(&history_line, false, false);
+        };
+        self.add_line(&format!("✅ {}", cmd), false, false);
+        if self.ai_dry_run {
+            // Resolve the whole script without running anything
+            if let Some(script) = &mut self.ai_script {
+                script.resolved += 1;
+                script.current = None;
+            }
+            self.next_ai_step();
+            return;
+        }
+        let flagged = match self.ai_guard.check(&cmd).map(str::to_string) {
+            Some(reason) => {
+                self.add_line(&format!("🛑 It {}", reason), false, false);
+                if let Err(e) = guard::log_blocked(&location, &cmd, &reason) {
+                    self.add_line(&format!("ERROR: could not log the blocked suggestion: {}", e), false, false);
                 }
-                self.show_prompt();
-                
-                // Clear the input buffer after command execution so new prompt is clean
-                self.input_buffer.clear();
-                self.cursor_pos = 0;
-                return;
+                true
             }
-            _ => {}
+            None => false,
+        };
+        if flagged || !yes {
+            self.pending_ai_step = Some(cmd);
+            self.show_prompt();
+            self.input_buffer.clear();
+            self.cursor_pos = 0;
+            return;
         }
+        self.run_ai_step(&cmd);
+    }
 
-        // Execute external command synchronously for now
-    let result = Command::new(&cmd_name)
-            .args(&args)
-            .current_dir(&self.current_dir)
-            .output();
+    fn run_ai_step(&mut self, cmd: &str) {
+        self.run_ai_command(cmd);
+        let ok = self.last_exit_code == 0;
+        if !ok {
+            let location = self.ai_script.as_ref().map(AiScript::location).unwrap_or_default();
+            self.add_line_above_prompt(&format!("❌ {}: exited with code {}", location, self.last_exit_code));
+        }
+        self.end_ai_step(ok);
+    }
 
-        match result {
-            Ok(output) => {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+    // Answer to running the script's current step: run it, or stop the script
+    fn resolve_ai_step_confirm(&mut self, accept: bool) {
+        let Some(cmd) = self.pending_ai_step.take() else { return };
+        if accept {
+            self.run_ai_step(&cmd);
+        } else {
+            let location = self.ai_script.as_ref().map(AiScript::location);
+            self.finish_ai_script(location);
+        }
+    }
 
-                // Check if output is short enough to display inline
-                let stdout_lines: Vec<&str> = stdout.lines().collect();
-                let stderr_lines: Vec<&str> = stderr.lines().collect();
+    // Count the current step and go on to the next, unless it failed and the script stops on failure
+    fn end_ai_step(&mut self, ok: bool) {
+        let Some(script) = &mut self.ai_script else { return };
+        if ok {
+            script.ran += 1;
+        } else {
+            script.failed += 1;
+        }
+        let stop = !ok && !script.options.keep_going;
+        let location = script.location();
+        script.current = None;
+        if stop {
+            self.finish_ai_script(Some(location));
+        } else {
+            self.next_ai_step();
+        }
+    }
 
-                let is_short_output = stdout_lines.len() <= 1 &&
-                                    stderr_lines.is_empty() &&
-                                    stdout.trim().len() < 80 && // Less than 80 characters
-                                    !stdout.contains('\n'); // No newlines
+    // Report how the script went, and where it stopped if it didn't finish, then bring the prompt back
+    fn finish_ai_script(&mut self, stopped_at: Option<String>) {
+        let Some(script) = self.ai_script.take() else { return };
+        if self.lines.back().is_some_and(|line| line.is_prompt) {
+            self.lines.pop_back();
+        }
+        if let Some(location) = stopped_at {
+            self.add_line(&format!("ai-run: stopped at {}", location), false, false);
+        }
+        self.add_line(&script.summary(), false, false);
+        self.last_exit_code = if script.failed > 0 || script.current.is_some() { 1 } else { 0 };
+        self.show_prompt();
+        self.input_buffer.clear();
+        self.cursor_pos = 0;
+    }
 
-                if is_short_output && !stdout.trim().is_empty() {
-                    // Update the last prompt line to include the command and output inline
-                    if let Some(last_line) = self.lines.back_mut() {
-                        if last_line.is_prompt {
-                            last_line.text = format!("{} > {} {}", last_line.text, command, stdout.trim());
-                            last_line.is_prompt = false; // Mark as completed command
-                        }
-                    }
-                } else {
-                    // Update the last prompt line to include the command
-                    if let Some(last_line) = self.lines.back_mut() {
-                        if last_line.is_prompt {
-                            last_line.text = format!("{} > {}", last_line.text, command);
-                            last_line.is_prompt = false; // Mark as completed command
-                        }
-                    }
+    // Answer to the install offer for a missing command: run the install command, or do nothing
+    fn resolve_install(&mut self, accept: bool) {
+        let Some(install) = self.pending_install.take() else { return };
+        if accept {
+            self.execute_command(&install);
+        }
+    }
 
-                    // Add stdout on separate lines
-                    for line in stdout_lines {
-                        if !line.is_empty() {
-                            self.add_line(line, false, false);
-                        }
-                    }
-                }
+    // Dry run: leave the suggested command in the input line for review instead of running it
+    fn suggest_ai_command(&mut self, cmd: &str) {
+        self.add_line("📝 Dry run: edit the command below and press Enter to run it", false, false);
+        self.show_prompt();
+        self.input_buffer = cmd.to_string();
+        self.cursor_pos = self.input_buffer.len();
+        self.selection_start = None;
+        self.selection_end = None;
+    }
 
-                // Add stderr (always on separate lines for visibility)
-                for line in stderr_lines {
-                    if !line.is_empty() {
-                        self.add_line(&format!("ERROR: {}", line), false, false);
-                    }
-                }
+    // Handle an AI answer once it arrives, then show the prompt again
+    fn poll_ai(&mut self) {
+        let Ok(AIResponse { input, result, usage, cache_error }) = self.ai.receiver.try_recv() else {
+            return;
+        };
+        if let Some(usage) = usage {
+            self.last_ai_usage = Some(usage);
+            self.ai_usage_total.add(usage);
+        }
+        if let Some(msg) = cache_error {
+            self.add_line(&format!("ai cache: {}: {} (answers are no longer saved)", AiCache::path().display(), msg), false, false);
+        }
+        let Some(pending) = self.ai_pending.take() else {
+            return;
+        };
+        if self.ai_script.is_some() {
+            self.resolve_ai_step(result);
+            return;
+        }
 
-                // Add exit status if non-zero
-                if !output.status.success() {
-                    if let Some(code) = output.status.code() {
-                        self.add_line(&format!("Command '{}' exited with code {}", cmd_name, code), false, false);
+        // The first answer for a macro is kept, so it runs instantly from now on
+        let macro_name = self.resolving_macro.take();
+        if let (Some(name), Ok(cmd)) = (&macro_name, &result) {
+            let mut macros = Macros::load(&self.configured_macros);
+            macros.resolve(name, cmd);
+            if let Err(e) = macros.save() {
+                self.add_line(&format!("ERROR: could not save macro '{}': {}", name, e), false, false);
+            }
+            *self.macros.borrow_mut() = macros;
+        }
+
+        // Keep anything typed while the AI was thinking
+        let typed = std::mem::take(&mut self.input_buffer);
+        let held_back = result.as_ref().is_ok_and(|cmd| self.hold_back_ai_command(&input, cmd));
+        match result {
+            Ok(_) if held_back => {}
+            Ok(cmd) if self.ai_dry_run => {
+                self.add_line(&format!("✅ {}", &cmd), false, false);
+                self.suggest_ai_command(&cmd);
+                return;
+            }
+            Ok(cmd) => {
+                self.add_line(&format!("✅ {}", &cmd), false, false);
+                self.run_ai_command(&cmd);
+                // The command has shown a fresh prompt
+                self.cursor_pos = typed.len();
+                self.input_buffer = typed;
+                return;
+            }
+            Err(error) => {
+                self.last_exit_code = 127;
+                match error {
+                    AiError::NotUnderstood | AiError::InvalidCommand => {
+                        self.add_line("🤔 I don't understand that request. Please try:", false, false);
+                        self.add_line("   • Use clear commands like 'list files', 'create folder test'", false, false);
+                        self.add_line("   • Avoid gibberish or random characters", false, false);
+                        self.add_line("   • Try rephrasing your request", false, false);
+                    }
+                    AiError::Timeout => {
+                        let retry_timeout = pending.timeout * 2;
+                        self.add_line(&format!("⏰ AI timed out after {}s.", pending.timeout.as_secs()), false, false);
+                        self.pending_ai_retry = Some((input, retry_timeout));
+                        self.resolving_macro = macro_name;
+                    }
+                    error => {
+                        self.add_line(&format!("❌ Could not interpret: {}", input), false, false);
+                        self.add_line(&format!("   (AI error: {})", error), false, false);
                     }
                 }
             }
-            Err(e) => {
-                // Try AI interpretation only when command/binary not found
-                let err_msg = format!("{}", e);
-                let is_cmd_missing = err_msg.contains("No such file or directory") || err_msg.contains("command not found");
+        }
+        self.show_prompt();
+        self.cursor_pos = typed.len();
+        self.input_buffer = typed;
+    }
 
-                if is_cmd_missing {
-                    // Check for instant commands first (ultra-fast, no AI call)
-                    if let Some(instant_cmd) = AIAssistant::get_instant_command(command) {
-                        // Update the last prompt line to include the command
-                        if let Some(last_line) = self.lines.back_mut() {
-                            if last_line.is_prompt {
-                                last_line.text = format!("{} > {}", last_line.text, command);
-                                last_line.is_prompt = false; // Mark as completed command
-                            }
-                        }
-                        self.add_line(&format!("⚡ {}", &instant_cmd), false, false);
-                        self.run_command_and_render(&instant_cmd);
-                        self.input_buffer.clear();
-                        self.cursor_pos = 0;
-                        return;
-                    }
+    // Store flags looked up in the background, completing with them if that command is still being typed
+    fn poll_flags(&mut self) {
+        while let Ok((command, flags)) = self.flag_receiver.try_recv() {
+            self.flag_lookups -= 1;
+            let typing = self.input_buffer.split_whitespace().next() == Some(command.as_str());
+            self.command_flags.insert(command, flags);
+            if typing {
+                self.update_autocomplete();
+            }
+        }
+    }
 
-                    // Close the current prompt line with the raw input
-                    if let Some(last_line) = self.lines.back_mut() {
-                        if last_line.is_prompt {
-                            last_line.text = format!("{} > {}", last_line.text, command);
-                            last_line.is_prompt = false;
+    // Drain events from job worker threads, reporting finished jobs above the prompt
+    fn poll_jobs(&mut self) {
+        while let Ok(event) = self.job_receiver.try_recv() {
+            match event {
+                JobEvent::Output { id, line, stream } => {
+                    let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) else { continue };
+                    if job.foreground {
+                        if jobs::is_password_prompt(&line) && job.stdin.is_some() {
+                            self.secure_input = Some(id);
                         }
+                        self.output_dir = Some(job.dir.clone());
+                        self.add_output_line_above_prompt(stream, &line);
+                        self.output_dir = None;
+                    } else {
+                        job.output.push((stream, line));
                     }
-                    self.add_line("⚡ Processing...", false, false);
-                    // Run AI generation without borrowing &mut self across await
-                    let input_clone = command.to_string();
-                    let ai_result = self.rt.block_on(self.ai.generate_command(&input_clone));
-                    match ai_result {
-                        Ok(cmd) => {
-                            self.add_line(&format!("✅ {}", &cmd), false, false);
-                            self.run_command_and_render(&cmd);
-                            // self.show_prompt(); // Removed to avoid duplicate
-                            self.input_buffer.clear();
-                            self.cursor_pos = 0;
-                        }
-                        Err(err) => {
-                            let msg = err.to_string();
-                            if msg.contains("I_DONT_UNDERSTAND") || msg.contains("don't understand") {
-                                self.add_line("🤔 I don't understand that request. Please try:", false, false);
-                                self.add_line("   • Use clear commands like 'list files', 'create folder test'", false, false);
-                                self.add_line("   • Avoid gibberish or random characters", false, false);
-                                self.add_line("   • Try rephrasing your request", false, false);
-                            } else if msg.contains("deadline has elapsed") {
-                                self.add_line("⏰ AI timed out. Try again.", false, false);
-                            } else {
-                                self.add_line(&format!("❌ Could not interpret: {}", command), false, false);
-                                self.add_line(&format!("   (AI error: {})", msg), false, false);
-                            }
-                            // self.show_prompt(); // Removed to avoid duplicate
-                            self.input_buffer.clear();
-                            self.cursor_pos = 0;
-                        }
+                }
+                JobEvent::Exited { id, code } => {
+                    let Some(index) = self.jobs.iter().position(|job| job.id == id) else { continue };
+                    let mut job = self.jobs.remove(index);
+                    job.status = JobStatus::Done(code);
+                    if self.secure_input == Some(id) {
+                        self.secure_input = None;
+                        sudo::wipe(&mut self.secure_text);
                     }
-                } else {
-                    // Update the last prompt line to include the failed command
-                    if let Some(last_line) = self.lines.back_mut() {
-                        if last_line.is_prompt {
-                            last_line.text = format!("{} > {} (Failed: {})", last_line.text, command, e);
-                            last_line.is_prompt = false; // Mark as completed command
+                    self.note_finished(&job.command, code, job.started.elapsed());
+                    if job.foreground {
+                        if code != 0 {
+                            self.add_line_above_prompt(&format!("Command '{}' exited with code {}", job.command, code));
+                        }
+                    } else {
+                        self.output_dir = Some(std::mem::take(&mut job.dir));
+                        for (stream, line) in std::mem::take(&mut job.output) {
+                            self.add_output_line_above_prompt(stream, &line);
                         }
+                        self.output_dir = None;
+                        self.add_line_above_prompt(&format!("[{}]+  {:<8} {}", job.id, job.status.label(), job.command));
                     }
                 }
             }
         }
+    }
 
-    self.show_prompt();
-        
-        // Clear the input buffer after command execution so new prompt is clean
-        self.input_buffer.clear();
-        self.cursor_pos = 0;
+    // Open a link clicked in the output: URLs and files go to xdg-open, directories are cd'd into.
+    // Relative paths are taken from `dir`, where the line was printed, not where we are now
+    fn open_link(&mut self, link: Link, dir: &str) {
+        let target = match link {
+            Link::Url(url) => url,
+            Link::Path(path) => {
+                let resolved = self.resolve_path_in(dir, &path);
+                if resolved.is_dir() {
+                    // Run it like a typed `cd`, keeping whatever is in the input line
+                    let input = std::mem::take(&mut self.input_buffer);
+                    let cursor_pos = self.cursor_pos;
+                    let target = match dir == self.current_dir {
+                        true => path,
+                        false => resolved.to_string_lossy().to_string(),
+                    };
+                    self.execute_command(&format!("cd {}", target));
+                    self.input_buffer = input;
+                    self.cursor_pos = cursor_pos;
+                    return;
+                }
+                if !resolved.exists() {
+                    self.add_line_above_prompt(&format!("ERROR: {}: No such file or directory", path));
+                    return;
+                }
+                resolved.to_string_lossy().to_string()
+            }
+        };
+
+        if let Err(e) = Command::new("xdg-open").arg(&target).spawn() {
+            self.add_line_above_prompt(&format!("ERROR: xdg-open {}: {}", target, e));
+        }
     }
 
-    fn run_command_and_render(&mut self, cmd: &str) {
-        let parts: Vec<&str> = cmd.trim().split_whitespace().collect();
-        if parts.is_empty() {
-            self.add_line("❌ Empty command", false, false);
-            return;
+    /// Work out which directory a `cd` argument refers to (no argument means home, `-` the previous directory)
+    fn resolve_cd_target(&self, arg: Option<&str>) -> Result<String, String> {
+        match arg {
+            None => Ok(env::var("HOME").unwrap_or_else(|_| "/".to_string())),
+            Some("-") => self.prev_dir.clone().ok_or_else(|| "cd: OLDPWD not set".to_string()),
+            Some(dir) => Ok(dir.to_string()),
+        }
+    }
+
+    /// Switch to `target_dir`, remembering the old directory for `cd -`.
+    /// Returns the new directory, or the message to show if it can't be entered.
+    fn change_directory(&mut self, target_dir: &str) -> Result<String, String> {
+        // Like bash's `cd -L`: `..` steps back out of a symlink rather than to its target's
+        // parent, unless that names nothing and only the physical path exists
+        let logical = logical_path::join(&self.current_dir, target_dir);
+        let physical = std::path::PathBuf::from(&self.current_dir).join(target_dir);
+        let (new_path, canonical_path) = match logical.canonicalize() {
+            Ok(canonical) => (logical, canonical),
+            Err(_) => {
+                let canonical = physical.canonicalize().map_err(|e| cd_error(target_dir, &physical, &e))?;
+                (canonical.clone(), canonical)
+            }
+        };
+        if !canonical_path.is_dir() {
+            // A file: say where it lives and how to open it instead
+            let mut msg = format!("cd: {}: Not a directory", target_dir);
+            match std::path::Path::new(target_dir).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                Some(parent) => msg.push_str(&format!("\n  It's a file; try `cd {}`, or open it with `xdg-open {}`", parent.display(), target_dir)),
+                None => msg.push_str(&format!("\n  It's a file; open it with `xdg-open {}`", target_dir)),
+            }
+            return Err(msg);
+        }
+        // Searching a directory needs execute permission, which canonicalize doesn't check. Children
+        // start here through child_command; the process's own directory is shared by every tab
+        nix::unistd::access(&canonical_path, nix::unistd::AccessFlags::X_OK)
+            .map_err(|errno| cd_error(target_dir, &canonical_path, &errno.into()))?;
+
+        let new_dir = new_path.to_string_lossy().to_string();
+        self.physical_dir = canonical_path.to_string_lossy().to_string();
+        self.prev_dir = Some(std::mem::replace(&mut self.current_dir, new_dir.clone()));
+
+        // Remember the visit for `j`
+        let mut jumps = JumpDatabase::load();
+        jumps.record(&new_dir, jump::now());
+        let _ = jumps.save();
+        Ok(new_dir)
+    }
+
+    /// `j`: change to the best-ranked visited directory matching `query`, printing it first
+    fn jump(&mut self, query: &[String]) -> Result<(), String> {
+        let dir = JumpDatabase::load()
+            .best_match(query, &self.current_dir, jump::now())
+            .ok_or_else(|| format!("j: no visited directory matches '{}'", query.join(" ")))?;
+        self.add_line(&dir, false, false);
+        self.change_directory(&dir).map(|_| ())
+    }
+
+    /// Current directory followed by the pushd stack (top first), home shown as ~
+    fn format_dir_stack(&self) -> String {
+        let home = env::var("HOME").unwrap_or_else(|_| "/home/user".to_string());
+        std::iter::once(&self.current_dir)
+            .chain(self.dir_stack.iter().rev())
+            .map(|dir| match dir.strip_prefix(&home) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+                _ => dir.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // Resolve a user-supplied path against the current directory, expanding a leading ~
+    fn resolve_path(&self, path: &str) -> std::path::PathBuf {
+        self.resolve_path_in(&self.current_dir, path)
+    }
+
+    // The same, against another directory
+    fn resolve_path_in(&self, dir: &str, path: &str) -> std::path::PathBuf {
+        if path == "~" || path.starts_with("~/") {
+            let home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+            std::path::PathBuf::from(home).join(path.trim_start_matches('~').trim_start_matches('/'))
+        } else {
+            std::path::PathBuf::from(dir).join(path)
+        }
+    }
+
+    // `help` with no arguments: every builtin, then pointers to the other kinds of help
+    fn list_builtins(&mut self) {
+        self.add_line("🚀 Terminal Help", false, false);
+        let width = builtins::BUILTINS.iter().map(|builtin| builtin.usage.len()).max().unwrap_or(0);
+        for builtin in builtins::BUILTINS {
+            self.add_line(&format!("  {:width$}  {}", builtin.usage, builtin.summary, width = width), false, false);
         }
-        let (name, args) = (parts[0], &parts[1..]);
+        self.add_line("Type 'help <builtin>' for more about one of these", false, false);
+        self.add_line("Type 'command --help' for details on other commands", false, false);
+        self.add_line("Type '=2+2*10' to do quick math", false, false);
+    }
 
-        // Special handling for cd
-        if name == "cd" {
-            let target_dir = if args.is_empty() {
-                env::var("HOME").unwrap_or_else(|_| "/".to_string())
-            } else {
-                args[0].to_string()
-            };
-            
-            let new_path = if target_dir.starts_with('/') {
-                std::path::PathBuf::from(&target_dir)
-            } else {
-                std::path::PathBuf::from(&self.current_dir).join(&target_dir)
-            };
-            
-            match new_path.canonicalize() {
-                Ok(canonical_path) => {
-                    if canonical_path.is_dir() {
-                        self.current_dir = canonical_path.to_string_lossy().to_string();
-                        let _ = env::set_current_dir(&canonical_path);
-                        self.add_line("✅ Directory changed", false, false);
-                    } else {
-                        self.add_line(&format!("cd: {}: Not a directory", target_dir), false, false);
-                    }
-                }
-                Err(_) => {
-                    self.add_line(&format!("cd: {}: No such file or directory", target_dir), false, false);
+    /// Run every non-empty, non-comment line of a script as if it had been typed.
+    /// In quiet mode only failing commands are left in the scrollback.
+    /// Returns the `file:line: command` location of each command that failed.
+    fn source_file(&mut self, path: &std::path::Path, quiet: bool) -> Result<Vec<String>, String> {
+        let canonical = path.canonicalize()
+            .map_err(|e| format!("source: {}: {}", path.display(), e))?;
+        if self.sourcing.contains(&canonical) {
+            return Err(format!("source: {}: already being sourced, skipping recursive source", path.display()));
+        }
+        let content = std::fs::read_to_string(&canonical)
+            .map_err(|e| format!("source: {}: {}", path.display(), e))?;
+
+        // Script lines don't belong in the interactive history
+        let history_len = self.command_history.len();
+        let mut failures = Vec::new();
+        self.sourcing.push(canonical);
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let prompt_index = self.lines.len().saturating_sub(1);
+            let prompt = self.lines.back().cloned();
+            self.execute_command(line);
+
+            if self.last_exit_code != 0 {
+                failures.push(format!("{}:{}: {}", path.display(), index + 1, line));
+            } else if quiet {
+                // Drop the echoed command and its output, leaving the original prompt
+                self.lines.truncate(prompt_index);
+                if let Some(prompt) = prompt {
+                    self.lines.push_back(prompt);
                 }
             }
-            return;
         }
+        self.sourcing.pop();
+        self.command_history.truncate(history_len);
+        Ok(failures)
+    }
 
-        let output = Command::new(name).args(args).current_dir(&self.current_dir).output();
-        match output {
-            Ok(out) => {
-                let stdout = String::from_utf8_lossy(&out.stdout);
-                let stderr = String::from_utf8_lossy(&out.stderr);
-                for line in stdout.lines() { if !line.is_empty() { self.add_line(line, false, false); } }
-                for line in stderr.lines() { if !line.is_empty() { self.add_line(&format!("ERROR: {}", line), false, false); } }
-                if out.status.success() && stdout.trim().is_empty() && stderr.trim().is_empty() {
-                    self.add_line("✅ Command executed successfully", false, false);
-                }
-            }
-            Err(e) => {
-                self.add_line(&format!("❌ Failed to execute '{}': {}", name, e), false, false);
-            }
+    // Startup files, in the order they are searched
+    fn startup_files() -> Vec<std::path::PathBuf> {
+        let home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+        let config_home = env::var("XDG_CONFIG_HOME")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|_| std::path::PathBuf::from(&home).join(".config"));
+        vec![
+            std::path::PathBuf::from(&home).join(".linararc"),
+            config_home.join("linara").join("rc"),
+        ]
+    }
+
+    // Quietly run the first startup file that exists, reporting only failures
+    fn source_startup_file(&mut self) {
+        let candidates = Self::startup_files();
+        let Some(path) = candidates.iter().find(|path| path.is_file()) else {
+            return;
+        };
+        let search_order = candidates.iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", then ");
+
+        let messages = match self.source_file(path, true) {
+            Ok(failures) => failures.into_iter()
+                .map(|failure| format!("⚠️  Startup command failed at {} (startup files: {})", failure, search_order))
+                .collect(),
+            Err(msg) => vec![format!("⚠️  {} (startup files: {})", msg, search_order)],
+        };
+
+        // Rebuild the prompt, since the startup file may have changed directory
+        if self.lines.back().is_some_and(|line| line.is_prompt) {
+            self.lines.pop_back();
+        }
+        for message in messages {
+            self.add_line(&message, false, false);
         }
+        self.show_prompt();
+    }
+
+    // Run an AI-suggested command exactly as if it had been typed, pipes and all.
+    // The user's request is already in history, so the suggestion isn't added.
+    fn run_ai_command(&mut self, cmd: &str) {
+        let history_len = self.command_history.len();
+        self.running_ai_command = true;
+        self.execute_command(cmd);
+        self.running_ai_command = false;
+        self.command_history.truncate(history_len);
+        self.history_index = -1;
     }
 
     async fn execute_natural_language(&mut self, natural_input: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
@@ -822,16 +3149,15 @@ impl TerminalApp {
         let cmd = match self.ai.generate_command(natural_input).await {
             Ok(c) => c,
             Err(e) => {
-                let msg = e.to_string();
-                if msg.contains("I_DONT_UNDERSTAND") || msg.contains("don't understand") {
-                    self.add_line("🤔 I don't understand that request. Please try:", false, false);
-                    self.add_line("   • Use clear commands like 'list files', 'create folder test'", false, false);
-                    self.add_line("   • Avoid gibberish or random characters", false, false);
-                    self.add_line("   • Try rephrasing your request", false, false);
-                } else if msg.contains("deadline has elapsed") {
-                    self.add_line("⏰ AI timed out. Try again.", false, false);
-                } else {
-                    self.add_line(&format!("❌ AI Error: {}", msg), false, false);
+                match e {
+                    AiError::NotUnderstood | AiError::InvalidCommand => {
+                        self.add_line("🤔 I don't understand that request. Please try:", false, false);
+                        self.add_line("   • Use clear commands like 'list files', 'create folder test'", false, false);
+                        self.add_line("   • Avoid gibberish or random characters", false, false);
+                        self.add_line("   • Try rephrasing your request", false, false);
+                    }
+                    AiError::Timeout => self.add_line("⏰ AI timed out. Try again.", false, false),
+                    e => self.add_line(&format!("❌ AI Error: {}", e), false, false),
                 }
                 self.show_prompt();
                 self.input_buffer.clear();
@@ -893,653 +3219,76 @@ impl TerminalApp {
     }
 
     fn explain_command(&mut self, cmd: &str) {
-        let explanation = match cmd {
-            "ls" => {
-                "📁 ls - List files and directories\n  -l  : Long format (permissions, size, date)\n  -a  : Show hidden files (start with .)\n  -h  : Human readable sizes\n  -la : Show all files in long format"
-            }
-            "cd" => {
-                "📂 cd - Change directory\n  Usage: cd <directory>\n  cd ..    : Go up one level\n  cd ~     : Go to home directory\n  cd /     : Go to root directory"
-            }
-            "pwd" => {
-                "📍 pwd - Print working directory\n  Shows your current location in the file system\n  No flags needed - just type 'pwd'"
-            }
-            "mkdir" => {
-                "📁 mkdir - Make directory\n  -p  : Create parent directories if needed\n  Usage: mkdir <dirname> or mkdir -p path/to/dir"
-            }
-            "rm" => {
-                "🗑️ rm - Remove files/directories\n  -r  : Remove directories recursively\n  -f  : Force (no confirmation)\n  -rf : Force remove directory and contents"
-            }
-            "cp" => {
-                "📋 cp - Copy files/directories\n  -r  : Copy directories recursively\n  -v  : Verbose (show what it's doing)\n  Usage: cp <source> <destination>"
-            }
-            "mv" => {
-                "📦 mv - Move/rename files\n  Usage: mv <old_name> <new_name>\n  Can move files between directories\n  Same command for renaming and moving"
-            }
-            "cat" => {
-                "📄 cat - Show file contents\n  -n  : Show line numbers\n  Usage: cat <filename>\n  Concatenates and displays files"
-            }
-            "grep" => {
-                "🔍 grep - Search for text patterns\n  -i  : Case insensitive\n  -n  : Show line numbers\n  -r  : Search recursively\n  Usage: grep 'pattern' <file>"
-            }
-            "find" => {
-                "🔎 find - Search for files\n  -name : Search by filename\n  -type : Search by type (f=file, d=dir)\n  Usage: find . -name '*.txt'"
-            }
-            "ps" => {
-                "📊 ps - Show running processes\n  -a  : All processes\n  -u  : Show user info\n  -x  : Include processes without terminal\n  aux : Show all processes with details"
-            }
-            "kill" => {
-                "💀 kill - Stop processes\n  -9  : Force kill (SIGKILL)\n  Usage: kill <PID> or kill -9 <PID>\n  Use 'ps' to find process IDs"
-            }
-            "top" | "htop" => {
-                "📈 top - Monitor system processes\n  Shows CPU, memory usage\n  Press 'q' to quit\n  htop is a nicer version if installed"
-            }
-            "df" => {
-                "💾 df - Show disk space usage\n  -h  : Human readable sizes\n  Shows space used/free on all disks"
-            }
-            "du" => {
-                "📏 du - Show directory/file sizes\n  -h  : Human readable\n  -s  : Summary only\n  Usage: du -sh <directory>"
-            }
-            "chmod" => {
-                "🔐 chmod - Change file permissions\n  +x  : Make executable\n  755 : Owner full, others read/execute\n  Usage: chmod +x <file> or chmod 755 <file>"
-            }
-            "chown" => {
-                "👤 chown - Change file owner\n  Usage: chown <user> <file>\n  chown <user>:<group> <file>\n  Usually needs sudo"
-            }
-            "tar" => {
-                "📦 tar - Archive files\n  -c  : Create archive\n  -x  : Extract archive\n  -f  : Specify filename\n  -z  : Use gzip compression\n  -v  : Verbose\n  Examples:\n    tar -czf archive.tar.gz files/\n    tar -xzf archive.tar.gz"
-            }
-            "wget" | "curl" => {
-                "🌐 wget/curl - Download from internet\n  wget <URL>  : Download file\n  curl -O <URL> : Download file\n  curl <URL>   : Show webpage content"
-            }
-            "ssh" => {
-                "🔗 ssh - Connect to remote server\n  Usage: ssh user@hostname\n  -i <key> : Use specific SSH key\n  -p <port> : Use different port"
-            }
-            "git" => {
-                "📚 git - Version control\n  status    : Show current state\n  add .     : Stage all changes\n  commit -m 'msg' : Save changes\n  push      : Upload to remote\n  pull      : Download from remote\n  clone <URL> : Copy repository"
-            }
-            "apt" | "yum" | "dnf" | "pacman" => {
-                "📦 Package managers\n  apt install <pkg>   : Install package\n  apt remove <pkg>    : Remove package\n  apt search <pkg>    : Search packages\n  apt update          : Update package list\n  apt upgrade         : Upgrade all packages"
-            }
-            "systemctl" => {
-                "⚙️ systemctl - Control system services\n  start <service>   : Start service\n  stop <service>    : Stop service\n  status <service>  : Show service status\n  enable <service>  : Start on boot\n  restart <service> : Restart service"
-            }
-            "ping" => {
-                "📡 ping - Test network connection\n  -c 4  : Send 4 packets only\n  Usage: ping <hostname or IP>\n  Tests if a host is reachable"
-            }
-            "ifconfig" | "ip" => {
-                "🌐 Network configuration\n  ifconfig          : Show network interfaces\n  ip addr show      : Show IP addresses\n  ip route show     : Show routing table"
-            }
-            "man" => {
-                "📖 man - Manual pages\n  Usage: man <command>\n  Shows detailed help for commands\n  Press 'q' to quit, '/' to search"
-            }
-            "history" => {
-                "📜 history - Command history\n  Shows previously typed commands\n  !123 : Run command number 123\n  !!   : Run last command"
-            }
-            "alias" => {
-                "🏷️ alias - Create command shortcuts\n  alias ll='ls -la'  : Create shortcut\n  alias              : Show all aliases\n  unalias <name>     : Remove alias"
-            }
-            "echo" => {
-                "🔊 echo - Print text\n  -n  : No newline at end\n  Usage: echo 'Hello World'\n  echo $HOME : Show environment variable"
-            }
-            "which" => {
-                "🔍 which - Find where a command is located\n  Usage: which <command>\n  Shows the full path to executable"
-            }
-            "whoami" => {
-                "👤 whoami - Show current user\n  Shows your username\n  Same as 'id -un'"
-            }
-            "date" => {
-                "📅 date - Show current date/time\n  +'%Y-%m-%d' : Custom format\n  Shows system date and time"
-            }
-            "cal" => {
-                "📅 cal - Show calendar\n  cal           : Current month\n  cal 2024      : Specific year\n  cal 12 2024   : Specific month/year"
-            }
-            "head" | "tail" => {
-                "📄 head/tail - Show file beginning/end\n  -n 10  : Show 10 lines\n  -f     : Follow (tail only, for logs)\n  Usage: head -n 5 <file> or tail -f <logfile>"
-            }
-            "sort" => {
-                "🔤 sort - Sort lines in file\n  -n  : Numeric sort\n  -r  : Reverse order\n  -u  : Unique lines only\n  Usage: sort <file> or command | sort"
-            }
-            "wc" => {
-                "📊 wc - Count lines/words/characters\n  -l  : Count lines only\n  -w  : Count words only\n  -c  : Count characters only\n  Usage: wc <file> or command | wc -l"
-            }
-            "diff" => {
-                "🔄 diff - Compare files\n  -u  : Unified format\n  Usage: diff file1 file2\n  Shows differences between files"
-            }
-            "mount" | "umount" => {
-                "💿 mount - Mount/unmount filesystems\n  mount /dev/sdb1 /mnt  : Mount device\n  umount /mnt           : Unmount\n  Usually needs sudo"
-            }
-            "free" => {
-                "🧠 free - Show memory usage\n  -h  : Human readable\n  Shows RAM and swap usage"
-            }
-            "uname" => {
-                "💻 uname - Show system information\n  -a  : All information\n  Shows OS, kernel version, etc."
-            }
-            "uptime" => {
-                "⏰ uptime - Show system uptime\n  Shows how long system has been running\n  Also shows load average"
-            }
-            "id" => {
-                "🆔 id - Show user/group IDs\n  Shows your user ID, group ID, and groups\n  id <username> : Show info for other user"
-            }
-            "passwd" => {
-                "🔑 passwd - Change password\n  Usage: passwd\n  Changes your login password\n  Usually needs current password"
-            }
-            "su" | "sudo" => {
-                "👑 su/sudo - Run as different user/superuser\n  sudo <command>  : Run command as root\n  su <user>       : Switch to different user\n  su -            : Switch to root"
-            }
-            "useradd" | "userdel" | "usermod" => {
-                "👥 User management\n  useradd <name>  : Create new user\n  userdel <name>  : Delete user\n  usermod -aG <group> <user> : Add to group\n  Usually needs sudo"
-            }
-            "groupadd" | "groupdel" => {
-                "👥 Group management\n  groupadd <name> : Create group\n  groupdel <name> : Delete group\n  Usually needs sudo"
-            }
-            "crontab" => {
-                "⏰ crontab - Schedule tasks\n  -l  : List scheduled tasks\n  -e  : Edit schedule\n  Format: minute hour day month day-of-week command"
-            }
-            "at" => {
-                "⏰ at - Run command at specific time\n  Usage: at 3:00 PM tomorrow\n  at> echo 'hello'\n  at> <Ctrl+D>\n  Schedules one-time tasks"
-            }
-            "screen" | "tmux" => {
-                "💻 Terminal multiplexers\n  screen -S <name> : Create session\n  screen -r <name> : Reconnect\n  Keep processes running after disconnect"
-            }
-            "rsync" => {
-                "🔄 rsync - Sync files/directories\n  -a  : Archive mode (preserves permissions)\n  -v  : Verbose\n  -z  : Compress during transfer\n  Usage: rsync -av source/ destination/"
-            }
-            "scp" => {
-                "📤 scp - Secure copy over SSH\n  Usage: scp file user@host:/path/\n  scp user@host:/path/file .\n  Copy files between computers securely"
-            }
-            "zip" | "unzip" => {
-                "📦 zip/unzip - Compress/decompress files\n  zip archive.zip file1 file2\n  unzip archive.zip\n  unzip -l archive.zip : List contents"
-            }
-            "gzip" | "gunzip" => {
-                "📦 gzip/gunzip - Compress/decompress\n  gzip file.txt     : Creates file.txt.gz\n  gunzip file.txt.gz : Restores file.txt\n  -k : Keep original file (gzip)"
-            }
-            "xz" | "unxz" => {
-                "📦 xz - High compression\n  xz file.txt       : Creates file.txt.xz\n  unxz file.txt.xz   : Restores file.txt\n  Better compression than gzip"
-            }
-            "less" | "more" => {
-                "📄 less/more - View file contents\n  less <file> : View file (better than more)\n  /pattern : Search forward\n  n : Next match\n  q : Quit"
-            }
-            "nano" | "vim" | "emacs" => {
-                "📝 Text editors\n  nano <file>  : Simple editor\n  vim <file>   : Powerful editor\n  emacs <file> : Advanced editor\n  All can create and edit text files"
-            }
-            "touch" => {
-                "📄 touch - Create empty file or update timestamp\n  Usage: touch <filename>\n  Creates file if it doesn't exist\n  Updates modification time if it does"
-            }
-            "ln" => {
-                "🔗 ln - Create links\n  -s  : Symbolic link (shortcut)\n  Usage: ln -s target linkname\n  ln source linkname : Hard link"
-            }
-            "file" => {
-                "🔍 file - Determine file type\n  Usage: file <filename>\n  Shows what type of file it is\n  Useful for unknown files"
-            }
-            "stat" => {
-                "📊 stat - Show file/directory details\n  Usage: stat <file>\n  Shows size, permissions, timestamps\n  More detailed than ls -l"
-            }
-            "basename" | "dirname" => {
-                "📁 basename/dirname - Extract parts of path\n  basename /path/to/file.txt → file.txt\n  dirname /path/to/file.txt → /path/to\n  Useful in scripts"
-            }
-            "realpath" => {
-                "📍 realpath - Show absolute path\n  Usage: realpath <file>\n  Converts relative paths to absolute\n  Resolves symbolic links"
-            }
-            "mktemp" => {
-                "📄 mktemp - Create temporary file/directory\n  -d  : Create directory instead of file\n  Usage: mktemp or mktemp -d\n  Creates unique temporary names"
-            }
-            "split" => {
-                "✂️ split - Split files into pieces\n  -b 1M : Split into 1MB chunks\n  Usage: split -b 100m largefile part_\n  Creates part_aa, part_ab, etc."
-            }
-            "csplit" => {
-                "✂️ csplit - Split by content\n  Usage: csplit file.txt '/pattern/' '{*}' \n  Splits file at pattern matches"
-            }
-            "comm" => {
-                "🔄 comm - Compare sorted files\n  -1  : Suppress column 1 (unique to file1)\n  -2  : Suppress column 2 (unique to file2)\n  -3  : Suppress column 3 (common lines)\n  Usage: comm file1 file2"
-            }
-            "join" => {
-                "🔗 join - Join files on common field\n  -t ',' : Use comma as field separator\n  Usage: join file1 file2\n  Like database join operation"
-            }
-            "paste" => {
-                "📋 paste - Merge lines from files\n  -d ',' : Use comma as delimiter\n  Usage: paste file1 file2\n  Combines corresponding lines"
-            }
-            "expand" | "unexpand" => {
-                "↹ expand/unexpand - Convert tabs/spaces\n  expand -t 4 file : Convert tabs to 4 spaces\n  unexpand -t 4 file : Convert spaces to tabs"
-            }
-            "tr" => {
-                "🔄 tr - Translate characters\n  'a-z' 'A-Z' : Convert to uppercase\n  -d 'abc' : Delete characters a,b,c\n  Usage: command | tr 'a-z' 'A-Z'"
-            }
-            "cut" => {
-                "✂️ cut - Extract columns from text\n  -d ',' -f 1 : Get first comma-separated field\n  -c 1-10 : Get characters 1 through 10\n  Usage: command | cut -d ' ' -f 1"
-            }
-            "awk" => {
-                "🔧 awk - Text processing\n  '{print $1}' : Print first column\n  '/pattern/ {print}' : Print lines matching pattern\n  Powerful text manipulation tool"
-            }
-            "sed" => {
-                "🔧 sed - Stream editor\n  's/old/new/g' : Replace text\n  '/pattern/d' : Delete lines\n  Usage: sed 's/hello/hi/g' file.txt"
-            }
-            "xargs" => {
-                "🔧 xargs - Build command from input\n  -n 1 : One argument per command\n  Usage: echo 'file1 file2' | xargs rm\n  Converts input into command arguments"
-            }
-            "tee" => {
-                "📋 tee - Copy output to files and screen\n  Usage: command | tee output.txt\n  Shows output on screen AND saves to file"
-            }
-            "yes" => {
-                "🔁 yes - Output string repeatedly\n  Usage: yes 'y' | command\n  Automatically answers 'y' to prompts\n  yes | head -10 : Print 'y' 10 times"
-            }
-            "seq" => {
-                "🔢 seq - Generate sequences\n  Usage: seq 1 10\n  seq 1 2 20 : Count by 2s\n  Generates number sequences"
-            }
-            "factor" => {
-                "🔢 factor - Factorize numbers\n  Usage: factor 12345\n  Shows prime factors of numbers"
-            }
-            "bc" => {
-                "🔢 bc - Calculator\n  Usage: echo '2+2' | bc\n  bc : Interactive calculator\n  Supports advanced math"
-            }
-            "time" => {
-                "⏱️ time - Measure command execution time\n  Usage: time command\n  Shows real, user, and system time"
-            }
-            "timeout" => {
-                "⏱️ timeout - Run command with time limit\n  Usage: timeout 10s command\n  Kills command after 10 seconds"
-            }
-            "watch" => {
-                "👀 watch - Run command repeatedly\n  -n 2 : Run every 2 seconds\n  Usage: watch -n 1 'ls -la'\n  Monitor changes over time"
-            }
-            "sleep" => {
-                "😴 sleep - Pause for specified time\n  Usage: sleep 5s, sleep 1m, sleep 1h\n  Pauses script execution"
-            }
-            "wait" => {
-                "⏳ wait - Wait for background processes\n  Usage: wait\n  wait <PID> : Wait for specific process\n  Used in shell scripts"
-            }
-            "jobs" => {
-                "💼 jobs - Show background jobs\n  Shows running/stopped background processes\n  %1 : Refer to job number 1"
-            }
-            "fg" | "bg" => {
-                "💼 fg/bg - Foreground/background jobs\n  fg %1 : Bring job 1 to foreground\n  bg %1 : Send job 1 to background\n  Control background processes"
-            }
-            "disown" => {
-                "💼 disown - Remove job from shell control\n  Usage: disown %1\n  Job continues after shell exits"
-            }
-            "nice" | "renice" => {
-                "⚡ nice/renice - Set process priority\n  nice -n 10 command : Lower priority\n  renice -n -5 <PID> : Higher priority\n  -20 to 19 range (lower = higher priority)"
-            }
-            "ionice" => {
-                "💿 ionice - Set I/O priority\n  -c 3 : Idle I/O class\n  -c 2 -n 7 : Best-effort class\n  Controls disk I/O priority"
-            }
-            "taskset" => {
-                "🖥️ taskset - Set CPU affinity\n  -c 0-3 : Use CPUs 0,1,2,3\n  Usage: taskset -c 0 command\n  Bind process to specific CPUs"
-            }
-            "chrt" => {
-                "⚡ chrt - Set scheduling policy\n  --rr : Round-robin scheduling\n  --fifo : First-in-first-out\n  Advanced process scheduling"
-            }
-            "strace" => {
-                "🔍 strace - Trace system calls\n  -p <PID> : Trace running process\n  -e trace=open : Trace only open calls\n  Shows what system calls a program makes"
-            }
-            "ltrace" => {
-                "🔍 ltrace - Trace library calls\n  -p <PID> : Trace running process\n  Shows library function calls"
-            }
-            "gdb" => {
-                "🐛 gdb - GNU debugger\n  gdb program : Debug program\n  run : Start execution\n  break main : Set breakpoint\n  Powerful debugging tool"
-            }
-            "valgrind" => {
-                "🐛 valgrind - Memory debugger\n  --leak-check=full : Check memory leaks\n  Usage: valgrind program\n  Finds memory errors and leaks"
-            }
-            "perf" => {
-                "📊 perf - Performance profiler\n  stat : Basic statistics\n  record : Record performance data\n  report : Show performance report\n  Linux performance analysis tool"
-            }
-            "dmesg" => {
-                "📋 dmesg - Kernel message buffer\n  -T : Human readable timestamps\n  Shows kernel log messages\n  Useful for hardware/driver issues"
-            }
-            "syslog" | "journalctl" => {
-                "📋 System logging\n  journalctl -u service : Service logs\n  journalctl -f : Follow new messages\n  journalctl --since '1 hour ago'\n  View system and service logs"
-            }
-            "logger" => {
-                "📝 logger - Add messages to system log\n  Usage: logger 'message'\n  logger -p local0.info 'message'\n  Write to system log from scripts"
-            }
-            "lsof" => {
-                "🔍 lsof - List open files\n  -p <PID> : Files open by process\n  -i : Network connections\n  -u <user> : Files open by user\n  Shows all open files and network connections"
-            }
-            "fuser" => {
-                "🔍 fuser - Find processes using file\n  -k : Kill processes\n  Usage: fuser -k /path/to/file\n  Shows/kills processes using a file"
-            }
-            "vmstat" => {
-                "📊 vmstat - Virtual memory statistics\n  1 : Update every second\n  Shows memory, CPU, I/O statistics"
-            }
-            "iostat" => {
-                "💿 iostat - I/O statistics\n  -x : Extended statistics\n  1 : Update every second\n  Shows disk I/O performance"
-            }
-            "sar" => {
-                "📊 sar - System activity report\n  -u : CPU usage\n  -r : Memory usage\n  -d : Disk I/O\n  Collects and reports system activity"
-            }
-            "mpstat" => {
-                "📊 mpstat - Multi-processor statistics\n  -P ALL : All CPUs\n  1 : Update every second\n  Shows per-CPU statistics"
-            }
-            "pstree" => {
-                "🌳 pstree - Process tree\n  -p : Show PIDs\n  Shows process hierarchy\n  Visual representation of process relationships"
-            }
-            "pgrep" => {
-                "🔍 pgrep - Find processes by name\n  Usage: pgrep firefox\n  Shows PIDs of matching processes"
-            }
-            "pkill" => {
-                "💀 pkill - Kill processes by name\n  Usage: pkill firefox\n  Kills all processes matching name"
-            }
-            "pidof" => {
-                "🔍 pidof - Find PID of program\n  Usage: pidof firefox\n  Shows process ID of running program"
-            }
-            "nohup" => {
-                "💼 nohup - Run command immune to hangups\n  Usage: nohup command &\n  Process continues after logout"
-            }
-            "setsid" => {
-                "💼 setsid - Run program in new session\n  Usage: setsid command\n  Creates new process group and session"
-            }
-            "daemonize" => {
-                "👻 daemonize - Run as daemon\n  Usage: daemonize command\n  Detach from terminal, run in background"
-            }
-            "trap" => {
-                "🪤 trap - Catch signals in scripts\n  trap 'echo cleanup' EXIT\n  trap 'handler' INT TERM\n  Handle signals and cleanup"
-            }
-            "ulimit" => {
-                "⚙️ ulimit - Set resource limits\n  -u 100 : Max user processes\n  -v 1000000 : Max virtual memory\n  Control resource usage limits"
-            }
-            "getconf" => {
-                "⚙️ getconf - Get configuration values\n  Usage: getconf PAGE_SIZE\n  Shows system configuration values"
-            }
-            "locale" => {
-                "🌍 locale - Show locale settings\n  Shows language and regional settings\n  locale -a : List all available locales"
-            }
-            "tzselect" | "timedatectl" => {
-                "🕐 Time zone management\n  timedatectl set-timezone America/New_York\n  tzselect : Interactive timezone selection\n  Set system timezone"
-            }
-            "hostname" => {
-                "💻 hostname - Show/set system hostname\n  hostname : Show current hostname\n  hostname newname : Set new hostname"
-            }
-            "dnsdomainname" => {
-                "🌐 dnsdomainname - Show DNS domain name\n  Shows system's DNS domain\n  Part of hostname after first dot"
-            }
-            "domainname" => {
-                "🌐 domainname - Show/set NIS domain\n  Shows NIS/YP domain name\n  Used in network information services"
-            }
-            "nisdomainname" => {
-                "🌐 nisdomainname - Show/set NIS domain\n  Same as domainname\n  Network Information Service domain"
-            }
-            "ypdomainname" => {
-                "🌐 ypdomainname - Yellow Pages domain\n  Same as domainname\n  Legacy name for NIS"
-            }
-            "arch" => {
-                "💻 arch - Show machine architecture\n  Shows CPU architecture (x86_64, arm64, etc.)\n  Same as uname -m"
-            }
-            "nproc" => {
-                "🖥️ nproc - Show number of CPUs\n  Shows available CPU cores\n  --all : Include offline CPUs"
-            }
-            "lscpu" => {
-                "🖥️ lscpu - CPU information\n  Shows detailed CPU architecture information\n  Cores, sockets, threads, cache, etc."
-            }
-            "lsmem" => {
-                "🧠 lsmem - Memory information\n  Shows memory block information\n  --summary : Brief summary"
-            }
-            "lsblk" => {
-                "💿 lsblk - List block devices\n  Shows disk and partition information\n  -f : Show filesystem types"
-            }
-            "blkid" => {
-                "💿 blkid - Show block device attributes\n  Shows UUID, filesystem type, etc.\n  Useful for /etc/fstab configuration"
-            }
-            "findmnt" => {
-                "💿 findmnt - Find mounted filesystems\n  Shows all mounted filesystems\n  -t ext4 : Filter by type"
-            }
-            "mountpoint" => {
-                "💿 mountpoint - Check if directory is mount point\n  Usage: mountpoint /mnt\n  Returns success if directory is a mount point"
-            }
-            "losetup" => {
-                "💿 losetup - Set up loop devices\n  -f : Find free loop device\n  losetup /dev/loop0 file.iso\n  Mount ISO files or disk images"
-            }
-            "swapon" | "swapoff" => {
-                "💾 Swap management\n  swapon /dev/sda2 : Enable swap\n  swapoff /dev/sda2 : Disable swap\n  swapon -s : Show swap status"
-            }
-            "mkswap" => {
-                "💾 mkswap - Set up swap area\n  Usage: mkswap /dev/sda2\n  Format partition for use as swap"
-            }
-            "fdisk" => {
-                "💿 fdisk - Disk partition table manipulator\n  -l : List partitions\n  Interactive partitioning tool\n  Create, delete, modify partitions"
-            }
-            "parted" => {
-                "💿 parted - Advanced partitioning tool\n  print : Show partition table\n  mkpart : Create partition\n  rm : Remove partition\n  More advanced than fdisk"
-            }
-            "mkfs" => {
-                "💿 mkfs - Make filesystem\n  mkfs.ext4 /dev/sda1 : Create ext4 filesystem\n  mkfs.vfat /dev/sda1 : Create FAT filesystem\n  Format partitions"
-            }
-            "fsck" => {
-                "💿 fsck - Filesystem check and repair\n  fsck /dev/sda1 : Check filesystem\n  -y : Answer yes to all questions\n  Repair filesystem errors"
-            }
-            "tune2fs" => {
-                "💿 tune2fs - Adjust ext2/ext3/ext4 filesystem\n  -l : Show filesystem information\n  -c 30 : Check every 30 mounts\n  Adjust filesystem parameters"
-            }
-            "dumpe2fs" => {
-                "💿 dumpe2fs - Dump ext2/ext3/ext4 filesystem info\n  Usage: dumpe2fs /dev/sda1\n  Shows detailed filesystem information"
-            }
-            "resize2fs" => {
-                "💿 resize2fs - Resize ext2/ext3/ext4 filesystem\n  Usage: resize2fs /dev/sda1\n  Grow or shrink filesystem size"
-            }
-            "e2fsck" => {
-                "💿 e2fsck - Check ext2/ext3/ext4 filesystem\n  Same as fsck for ext filesystems\n  More detailed checking and repair"
-            }
-            "debugfs" => {
-                "💿 debugfs - Ext filesystem debugger\n  debugfs /dev/sda1\n  Interactive filesystem debugging tool\n  Advanced filesystem manipulation"
-            }
-            "xfs_info" => {
-                "💿 xfs_info - Show XFS filesystem info\n  Usage: xfs_info /dev/sda1\n  Shows XFS filesystem parameters"
-            }
-            "xfs_repair" => {
-                "💿 xfs_repair - Repair XFS filesystem\n  Usage: xfs_repair /dev/sda1\n  Repair corrupted XFS filesystem"
-            }
-            "btrfs" => {
-                "💿 btrfs - Btrfs filesystem utilities\n  filesystem show : Show btrfs filesystems\n  subvolume list / : List subvolumes\n  Advanced filesystem with snapshots"
-            }
-            "zfs" => {
-                "💿 zfs - ZFS filesystem management\n  list : Show ZFS datasets\n  create tank/data : Create dataset\n  snapshot tank/data@backup\n  Enterprise-grade filesystem"
-            }
-            "mdadm" => {
-                "💿 mdadm - Software RAID management\n  --detail /dev/md0 : Show RAID array info\n  --create /dev/md0 : Create RAID array\n  Manage software RAID arrays"
-            }
-            "cryptsetup" => {
-                "🔐 cryptsetup - Disk encryption\n  luksFormat /dev/sda1 : Encrypt partition\n  luksOpen /dev/sda1 secret : Open encrypted device\n  Linux Unified Key Setup"
-            }
-            "luks" => {
-                "🔐 LUKS - Linux Unified Key Setup\n  Part of cryptsetup\n  Standard for disk encryption on Linux"
-            }
-            "gpg" => {
-                "🔐 gpg - GNU Privacy Guard\n  --gen-key : Generate key pair\n  --encrypt file : Encrypt file\n  --decrypt file.gpg : Decrypt file\n  GNU implementation of OpenPGP"
-            }
-            "openssl" => {
-                "🔐 openssl - SSL/TLS toolkit\n  rand -base64 32 : Generate random data\n  req -new -x509 : Create self-signed certificate\n  Comprehensive cryptography toolkit"
-            }
-            "ssh-keygen" => {
-                "🔐 ssh-keygen - Generate SSH keys\n  -t rsa : Generate RSA key\n  -t ed25519 : Generate Ed25519 key\n  Create SSH key pairs for authentication"
-            }
-            "ssh-copy-id" => {
-                "🔐 ssh-copy-id - Copy SSH keys to server\n  Usage: ssh-copy-id user@host\n  Installs your public key on remote server\n  Enables passwordless SSH login"
-            }
-            "ssh-agent" => {
-                "🔐 ssh-agent - SSH key manager\n  ssh-agent bash : Start agent\n  ssh-add : Add keys to agent\n  Manages SSH keys in memory"
-            }
-            "ssh-add" => {
-                "🔐 ssh-add - Add SSH keys to agent\n  ssh-add ~/.ssh/id_rsa : Add specific key\n  ssh-add -l : List loaded keys\n  Add private keys to ssh-agent"
-            }
-            "sshd" => {
-                "🔐 sshd - SSH daemon\n  /usr/sbin/sshd : SSH server daemon\n  Listens for SSH connections\n  Usually started by systemd"
-            }
-            "iptables" => {
-                "🔥 iptables - Firewall rules\n  -L : List rules\n  -A INPUT -p tcp --dport 22 -j ACCEPT\n  Configure netfilter firewall rules"
-            }
-            "ufw" => {
-                "🔥 ufw - Uncomplicated Firewall\n  status : Show status\n  allow 22 : Allow SSH\n  enable : Enable firewall\n  Simpler interface to iptables"
-            }
-            "firewalld" => {
-                "🔥 firewalld - Dynamic firewall\n  --state : Show status\n  --add-service=ssh : Allow SSH\n  --reload : Reload rules\n  Modern firewall management"
-            }
-            "nftables" => {
-                "🔥 nftables - Netfilter tables\n  list ruleset : Show all rules\n  Successor to iptables\n  More efficient and flexible"
-            }
-            "tcpdump" => {
-                "📡 tcpdump - Network packet analyzer\n  -i eth0 : Listen on interface\n  port 80 : Filter by port\n  -w capture.pcap : Save to file\n  Capture and analyze network traffic"
-            }
-            "wireshark" => {
-                "📡 wireshark - Network protocol analyzer\n  GUI version of tcpdump\n  Analyze network traffic with GUI\n  Powerful protocol dissection"
-            }
-            "nmap" => {
-                "📡 nmap - Network mapper\n  -sP 192.168.1.0/24 : Ping scan network\n  -p 80,443 : Scan specific ports\n  -A : Aggressive scan with OS detection\n  Network discovery and security auditing"
-            }
-            "netstat" => {
-                "📡 netstat - Network statistics\n  -tlnp : Show listening TCP ports\n  -rn : Show routing table\n  -i : Show network interfaces\n  Network connection information"
-            }
-            "ss" => {
-                "📡 ss - Socket statistics\n  -tlnp : Show listening TCP sockets\n  -rn : Show routing table\n  Modern replacement for netstat"
-            }
-            "route" => {
-                "📡 route - Show/manipulate routing table\n  -n : Numeric output\n  add default gw 192.168.1.1 : Add default route\n  Legacy routing table management"
-            }
-            "traceroute" => {
-                "📡 traceroute - Trace packet route\n  Usage: traceroute host\n  Shows path packets take to destination\n  Useful for network troubleshooting"
-            }
-            "mtr" => {
-                "📡 mtr - Network diagnostic tool\n  Usage: mtr host\n  Combines traceroute and ping\n  Real-time network diagnostics"
-            }
-            "dig" => {
-                "🌐 dig - DNS lookup\n  Usage: dig google.com\n  @8.8.8.8 : Use specific DNS server\n  Shows DNS records and resolution"
-            }
-            "nslookup" => {
-                "🌐 nslookup - DNS query tool\n  Usage: nslookup google.com\n  Interactive DNS queries\n  Legacy DNS lookup tool"
-            }
-            "host" => {
-                "🌐 host - DNS lookup utility\n  Usage: host google.com\n  Shows IP addresses for hostnames\n  Simple DNS lookups"
-            }
-            "whois" => {
-                "🌐 whois - Domain registration info\n  Usage: whois google.com\n  Shows domain registration details\n  Owner, registrar, dates, etc."
-            }
-            "lynx" | "links" | "elinks" => {
-                "🌐 Text-based web browsers\n  lynx google.com : Browse web in terminal\n  Useful for headless servers\n  No graphics, pure text"
-            }
-            "ftp" => {
-                "📁 ftp - File Transfer Protocol\n  ftp ftp.example.com\n  get file.txt : Download file\n  put file.txt : Upload file\n  Legacy file transfer protocol"
-            }
-            "sftp" => {
-                "📁 sftp - Secure File Transfer\n  sftp user@host\n  get file.txt : Download file\n  put file.txt : Upload file\n  Secure version of FTP over SSH"
-            }
-            "nc" | "netcat" => {
-                "📡 netcat - Networking utility\n  nc -l 1234 : Listen on port 1234\n  nc host 1234 : Connect to port 1234\n  Swiss army knife of networking"
-            }
-            "socat" => {
-                "📡 socat - Multipurpose relay\n  socat TCP-LISTEN:1234 TCP:host:80\n  Advanced netcat replacement\n  Create network connections and tunnels"
-            }
-            "telnet" => {
-                "📡 telnet - Connect to remote host\n  telnet host 23 : Connect to telnet server\n  telnet host 80 : Manual HTTP requests\n  Legacy remote login protocol"
-            }
-            "rsh" | "rlogin" => {
-                "📡 Remote shell commands\n  rsh host command : Run command remotely\n  rlogin host : Login remotely\n  Legacy remote execution tools"
-            }
-            "byobu" => {
-                "💻 byobu - Enhanced terminal multiplexer\n  Wrapper around tmux/screen\n  Pre-configured with useful features\n  Easy to use terminal management"
-            }
-            "script" => {
-                "📝 script - Record terminal session\n  script logfile.txt : Start recording\n  exit : Stop recording\n  Records everything typed and output"
-            }
-            "scriptreplay" => {
-                "📝 scriptreplay - Replay recorded session\n  scriptreplay timingfile logfile\n  Replay terminal session with timing\n  Play back recorded sessions"
-            }
-            "tput" => {
-                "🎨 tput - Terminal capabilities\n  tput clear : Clear screen\n  tput cup 10 20 : Move cursor\n  tput setaf 1 : Set foreground color\n  Control terminal appearance"
-            }
-            "stty" => {
-                "⚙️ stty - Terminal settings\n  stty -a : Show all settings\n  stty sane : Reset to sane defaults\n  Configure terminal behavior"
-            }
-            "reset" => {
-                "🔄 reset - Reset terminal\n  reset : Reset terminal settings\n  clear : Just clear screen\n  Fix corrupted terminal display"
-            }
-            "clear" => {
-                "🧹 clear - Clear terminal screen\n  clear : Clear screen and scrollback\n  Ctrl+L : Clear screen (in most shells)\n  Clean terminal display"
-            }
-            "resize" => {
-                "📐 resize - Set terminal size\n  resize : Update LINES and COLUMNS\n  Useful after terminal resize\n  Update shell's idea of terminal size"
-            }
-            "tty" => {
-                "💻 tty - Show terminal device\n  tty : Show current terminal device\n  Shows /dev/pts/X or /dev/ttyX\n  Which terminal you're using"
-            }
-            "mesg" => {
-                "💬 mesg - Control write access to terminal\n  mesg y : Allow write access\n  mesg n : Deny write access\n  Control who can write to your terminal"
-            }
-            "wall" => {
-                "📢 wall - Write to all users\n  wall 'message' : Send message to all\n  Usually requires root\n  Broadcast messages to all logged-in users"
-            }
-            "write" => {
-                "💬 write - Write to specific user\n  write user tty : Send message to user\n  Ctrl+D : End message\n  Send messages to specific users"
-            }
-            "talk" => {
-                "💬 talk - Interactive chat\n  talk user@host : Start chat\n  Legacy interactive chat program\n  Real-time text chat between users"
-            }
-            "finger" => {
-                "👤 finger - User information\n  finger user : Show user info\n  finger @host : Show logged-in users\n  Show user information and status"
-            }
-            "w" => {
-                "👥 w - Show who is logged in\n  w : Show logged-in users and activity\n  Shows user, terminal, login time, activity\n  More detailed than who"
-            }
-            "who" => {
-                "👥 who - Show logged-in users\n  who : Show logged-in users\n  who am i : Show your own info\n  Basic logged-in user information"
-            }
-            "last" => {
-                "📜 last - Show login history\n  last : Show recent logins/logouts\n  last -10 : Show last 10 entries\n  Login history from /var/log/wtmp"
-            }
-            "lastlog" => {
-                "📜 lastlog - Show last login times\n  lastlog : Show last login for all users\n  Shows when each user last logged in\n  From /var/log/lastlog"
-            }
-            "ac" => {
-                "⏰ ac - Show connect time\n  ac : Show total connect time\n  ac -p : Per-user connect time\n  Show user connection statistics"
-            }
-            "tload" => {
-                "📊 tload - Show system load\n  tload : Graphical load average\n  Shows system load over time\n  Text-based load graph"
-            }
-            "isag" => {
-                "📊 isag - Interactive system activity graph\n  isag : Interactive performance graphs\n  Visual system performance monitoring\n  Part of sysstat package"
-            }
-            _ => {
-                "❓ Command not found in database\n  Try: man <command> (if available)\n  Or: <command> --help\n  Or: whatis <command>"
-            }
-        };
+        let explanations = Explanations::load();
+        let explanation = explanations.get(cmd)
+            .map(str::to_string)
+            .or_else(|| explain::from_system(cmd))
+            .unwrap_or_else(|| "❓ Command not found in database\n  Try: man <command> (if available)\n  Or: <command> --help\n  Or: whatis <command>".to_string());
 
         self.add_line(&explanation, false, false);
+        if let Some(error) = explanations.error {
+            self.add_line(&format!("ERROR: {}", error), false, false);
+        }
     }
 
     fn update_autocomplete(&mut self) {
         self.refresh_command_cache();
 
-        // Get the current word being typed (last word in input)
+        // Complete the word at the cursor, matching on the part before it
+        let (range, word_index) = line_edit::word_at(&self.input_buffer, self.cursor_pos);
+        let cursor = line_edit::floor_char_boundary(&self.input_buffer, self.cursor_pos).max(range.start);
+        let current_word = &self.input_buffer[range.start..cursor];
         let words: Vec<&str> = self.input_buffer.split_whitespace().collect();
-        let current_word = if self.input_buffer.ends_with(' ') {
-            ""
-        } else {
-            words.last().map_or("", |&word| word)
-        };
+        self.completion_range = range.clone();
+        self.completion_word = word_index;
 
         // Find matching suggestions
         let mut suggestions = Vec::new();
 
-        // If it's the first word, match against commands
-        if words.len() <= 1 {
+        // `$HO` or `${HO` completes environment variable names
+        if let Some((open, prefix)) = current_word.strip_prefix("${").map(|prefix| ("${", prefix))
+            .or_else(|| current_word.strip_prefix('$').map(|prefix| ("$", prefix)))
+        {
+            let close = if open == "${" { "}" } else { "" };
+            let mut names: Vec<String> = env::vars_os()
+                .filter_map(|(name, _)| name.into_string().ok())
+                .filter(|name| name.starts_with(prefix))
+                .collect();
+            names.sort();
+            suggestions.extend(names.into_iter().take(20).map(|name| {
+                Suggestion::new(format!("{}{}{}", open, name, close), SuggestionKind::Variable)
+            }));
+        } else if word_index == 0 {
+            // It's the first word, match against commands
             if current_word.is_empty() {
                 // Show recent commands when input is empty
                 for cmd in self.command_history.iter().rev().take(10) {
                     if let Some(first_word) = cmd.split_whitespace().next() {
-                        suggestions.push(first_word.to_string());
+                        suggestions.push(Suggestion::new(first_word, SuggestionKind::History));
                     }
                 }
             } else {
                 // Get suggestions from different sources
                 let mut all_candidates = Vec::new();
 
+                // Builtins
+                for builtin in builtins::BUILTINS {
+                    for name in builtin.names.iter().filter(|name| name.starts_with(current_word)) {
+                        all_candidates.push((name.to_string(), 95, SuggestionKind::Builtin)); // Highest priority
+                    }
+                }
+
                 // Common commands
                 for cmd in &self.common_commands {
                     if cmd.starts_with(current_word) {
-                        all_candidates.push((cmd.clone(), 90)); // High priority
+                        all_candidates.push((cmd.clone(), 90, SuggestionKind::Command)); // High priority
                     }
                 }
 
                 // PATH commands
                 for cmd in &self.path_commands {
                     if cmd.starts_with(current_word) {
-                        all_candidates.push((cmd.clone(), 80)); // Medium-high priority
+                        all_candidates.push((cmd.clone(), 80, SuggestionKind::Command)); // Medium-high priority
                     }
                 }
 
@@ -1547,7 +3296,7 @@ impl TerminalApp {
                 if let Some(package_cmds) = self.command_cache.get("packages") {
                     for cmd in package_cmds {
                         if cmd.starts_with(current_word) {
-                            all_candidates.push((cmd.clone(), 70)); // Medium priority
+                            all_candidates.push((cmd.clone(), 70, SuggestionKind::Command)); // Medium priority
                         }
                     }
                 }
@@ -1555,7 +3304,7 @@ impl TerminalApp {
                 // Command history
                 let history_suggestions = self.get_command_history_suggestions(current_word);
                 for cmd in history_suggestions {
-                    all_candidates.push((cmd, 85)); // High-medium priority
+                    all_candidates.push((cmd, 85, SuggestionKind::History)); // High-medium priority
                 }
 
                 // Fuzzy matching if enabled
@@ -1564,14 +3313,14 @@ impl TerminalApp {
 
                     // Check all sources for fuzzy matches
                     for cmd in &self.common_commands {
-                        let score = self.fuzzy_match(current_word, cmd);
+                        let score = fuzzy::score(current_word, cmd);
                         if score > 0 {
                             fuzzy_candidates.push((cmd.clone(), score));
                         }
                     }
 
                     for cmd in &self.path_commands {
-                        let score = self.fuzzy_match(current_word, cmd);
+                        let score = fuzzy::score(current_word, cmd);
                         if score > 0 {
                             fuzzy_candidates.push((cmd.clone(), score));
                         }
@@ -1579,7 +3328,7 @@ impl TerminalApp {
 
                     if let Some(package_cmds) = self.command_cache.get("packages") {
                         for cmd in package_cmds {
-                            let score = self.fuzzy_match(current_word, cmd);
+                            let score = fuzzy::score(current_word, cmd);
                             if score > 0 {
                                 fuzzy_candidates.push((cmd.clone(), score));
                             }
@@ -1589,8 +3338,8 @@ impl TerminalApp {
                     // Sort fuzzy candidates by score and take top ones
                     fuzzy_candidates.sort_by(|a, b| b.1.cmp(&a.1));
                     for (cmd, _) in fuzzy_candidates.into_iter().take(5) {
-                        if !all_candidates.iter().any(|(c, _)| c == &cmd) {
-                            all_candidates.push((cmd, 60)); // Lower priority for fuzzy matches
+                        if !all_candidates.iter().any(|(c, _, _)| c == &cmd) {
+                            all_candidates.push((cmd, 60, SuggestionKind::Command)); // Lower priority for fuzzy matches
                         }
                     }
                 }
@@ -1599,9 +3348,11 @@ impl TerminalApp {
                 all_candidates.sort_by(|a, b| b.1.cmp(&a.1));
                 let mut seen = std::collections::HashSet::new();
 
-                for (cmd, _) in all_candidates {
+                for (cmd, _, kind) in all_candidates {
                     if !seen.contains(&cmd) {
-                        suggestions.push(cmd.clone());
+                        // A name that is also a builtin runs the builtin
+                        let kind = if builtins::find(&cmd).is_some() { SuggestionKind::Builtin } else { kind };
+                        suggestions.push(Suggestion::new(cmd.clone(), kind));
                         seen.insert(cmd);
                         if suggestions.len() >= 20 { // Limit suggestions
                             break;
@@ -1613,13 +3364,23 @@ impl TerminalApp {
             // For subsequent words, check if we should suggest flags first
             let command = words[0];
 
-            // Check if current word looks like a flag (starts with -)
-            if current_word.starts_with('-') {
+            // Check if current word looks like a flag (starts with -); `--color=au` completes the value
+            if current_word.starts_with('-') && current_word.contains('=') {
+                suggestions.extend(flags::complete_value(command, current_word).into_iter().map(|value| Suggestion::new(value, SuggestionKind::Flag)));
+            } else if current_word.starts_with('-') {
+                // Commands outside the built-in table get their flags from their man page, once;
+                // the suggestions are filled in when the lookup answers
+                if !self.command_flags.contains_key(command) && self.path_commands.iter().any(|cmd| cmd == command) {
+                    self.command_flags.insert(command.to_string(), Vec::new());
+                    flags::read_flags_in_background(command, self.flag_sender.clone());
+                    self.flag_lookups += 1;
+                }
+
                 // Suggest flags for this command
                 if let Some(flags) = self.command_flags.get(command) {
                     for flag in flags {
                         if flag.starts_with(current_word) {
-                            suggestions.push(flag.clone());
+                            suggestions.push(Suggestion::new(flag.clone(), SuggestionKind::Flag));
                         }
                     }
                 }
@@ -1635,9 +3396,9 @@ impl TerminalApp {
                             if file_name.starts_with(current_word) {
                                 // Add directory indicator if it's a directory
                                 let suggestion = if entry.file_type().map_or(false, |ft| ft.is_dir()) {
-                                    format!("{}/", file_name)
+                                    Suggestion::new(format!("{}/", file_name), SuggestionKind::Directory)
                                 } else {
-                                    file_name.to_string()
+                                    Suggestion::new(file_name, SuggestionKind::File)
                                 };
                                 suggestions.push(suggestion);
                             }
@@ -1694,44 +3455,6 @@ impl TerminalApp {
         }
     }
 
-    fn fuzzy_match(&self, query: &str, candidate: &str) -> i32 {
-        if query.is_empty() {
-            return 0;
-        }
-
-        let query_lower = query.to_lowercase();
-        let candidate_lower = candidate.to_lowercase();
-
-        // Exact prefix match gets highest score
-        if candidate_lower.starts_with(&query_lower) {
-            return 100 - candidate.len() as i32;
-        }
-
-        // Contains match gets medium score
-        if candidate_lower.contains(&query_lower) {
-            return 50 - candidate.len() as i32;
-        }
-
-        // Fuzzy matching: check if all characters of query appear in order
-        let mut query_chars = query_lower.chars();
-        let mut current_char = query_chars.next();
-
-        for c in candidate_lower.chars() {
-            if let Some(qc) = current_char {
-                if c == qc {
-                    current_char = query_chars.next();
-                }
-            }
-        }
-
-        if current_char.is_none() {
-            // All characters found in order, but not consecutive
-            return 25 - candidate.len() as i32;
-        }
-
-        0 // No match
-    }
-
     fn get_command_history_suggestions(&self, prefix: &str) -> Vec<String> {
         let mut suggestions = Vec::new();
         let mut seen = std::collections::HashSet::new();
@@ -1836,34 +3559,75 @@ impl TerminalApp {
             }
         }
 
-        let suggestion = &self.autocomplete_suggestions[self.autocomplete_index as usize];
-
-        // Replace the current word with the suggestion
-        let words: Vec<&str> = self.input_buffer.split_whitespace().collect();
-        if words.is_empty() {
-            self.input_buffer = suggestion.clone();
-        } else {
-            let mut new_buffer = words[..words.len() - 1].join(" ");
-            if !new_buffer.is_empty() {
-                new_buffer.push(' ');
-            }
-            new_buffer.push_str(suggestion);
-
-            // If it's a flag or command, add a space for easier continuation
-            if suggestion.starts_with('-') || words.len() == 1 {
-                new_buffer.push(' ');
-            }
+        self.apply_suggestion(self.autocomplete_index as usize);
+        true
+    }
 
-            self.input_buffer = new_buffer;
+    // The rest of the newest history entry that starts with the input, shown dimmed after the
+    // cursor. Only offered at the end of the line and while the suggestion popup is closed.
+    fn history_hint(&self) -> Option<&str> {
+        let popup_open = self.show_autocomplete && !self.autocomplete_suggestions.is_empty();
+        if popup_open || self.cursor_pos < self.input_buffer.len() || self.selection_start.is_some() {
+            return None;
         }
+        line_edit::history_hint(&self.command_history, &self.input_buffer)
+    }
 
+    // Complete the input line with the autosuggestion, if there is one
+    fn accept_history_hint(&mut self) -> bool {
+        let Some(hint) = self.history_hint().map(str::to_string) else {
+            return false;
+        };
+        self.input_buffer.push_str(&hint);
         self.cursor_pos = self.input_buffer.len();
+        self.update_autocomplete();
         true
     }
 
+    // Put suggestion `index` into the input line in place of the word being typed
+    fn apply_suggestion(&mut self, index: usize) {
+        let Some(suggestion) = self.autocomplete_suggestions.get(index).map(|suggestion| &suggestion.text) else {
+            return;
+        };
+
+        // Replace the word being completed (or the suggestion applied before it when cycling),
+        // leaving the text after it alone
+        let mut replacement = suggestion.clone();
+        let text = &self.input_buffer;
+        let range = self.completion_range.clone();
+        // The line may have changed some other way since the suggestions were made
+        let range = if range.start <= range.end && range.end <= text.len() && text.is_char_boundary(range.start) && text.is_char_boundary(range.end) {
+            range
+        } else {
+            line_edit::word_at(text, self.cursor_pos).0
+        };
+
+        // If it's a flag or command, add a space for easier continuation
+        let followed_by_space = self.input_buffer[range.end..].starts_with(char::is_whitespace);
+        if (replacement.starts_with('-') || self.completion_word == 0) && !followed_by_space {
+            replacement.push(' ');
+        }
+
+        self.completion_range = line_edit::replace_word(&mut self.input_buffer, range, &replacement);
+        self.cursor_pos = self.completion_range.end;
+    }
+
     fn handle_key(&mut self, key: egui::Key, modifiers: egui::Modifiers) {
+        if let Some(action) = self.keymap.action(key, modifiers) {
+            self.run_key_action(action);
+            return;
+        }
+
         match key {
             egui::Key::Enter => {
+                // Commands wait until the AI has answered the previous one
+                if self.ai_pending.is_some() {
+                    return;
+                }
+                if self.pending_heredoc.is_some() {
+                    self.continue_heredoc();
+                    return;
+                }
                 let command = self.input_buffer.clone();
                 // Input buffer will be cleared in execute_command after successful execution
                 // Clear autocomplete
@@ -1877,9 +3641,7 @@ impl TerminalApp {
                     // Delete selection if exists
                     self.delete_selection();
                     self.update_autocomplete();
-                } else if self.cursor_pos > 0 {
-                    self.input_buffer.remove(self.cursor_pos - 1);
-                    self.cursor_pos -= 1;
+                } else if line_edit::delete_before(&mut self.input_buffer, &mut self.cursor_pos) {
                     self.update_autocomplete();
                 }
             }
@@ -1888,8 +3650,7 @@ impl TerminalApp {
                     // Delete selection if exists
                     self.delete_selection();
                     self.update_autocomplete();
-                } else if self.cursor_pos < self.input_buffer.len() {
-                    self.input_buffer.remove(self.cursor_pos);
+                } else if line_edit::delete_after(&mut self.input_buffer, &mut self.cursor_pos) {
                     self.update_autocomplete();
                 }
             }
@@ -1900,14 +3661,12 @@ impl TerminalApp {
                         self.selection_start = Some(self.cursor_pos);
                     }
                     if self.cursor_pos > 0 {
-                        self.cursor_pos -= 1;
+                        self.cursor_pos = line_edit::prev_char_boundary(&self.input_buffer, self.cursor_pos);
                         self.selection_end = Some(self.cursor_pos);
                     }
                 } else {
                     // Left: Move cursor and clear selection
-                    if self.cursor_pos > 0 {
-                        self.cursor_pos -= 1;
-                    }
+                    self.cursor_pos = line_edit::prev_char_boundary(&self.input_buffer, self.cursor_pos);
                     self.selection_start = None;
                     self.selection_end = None;
                 }
@@ -1919,14 +3678,16 @@ impl TerminalApp {
                         self.selection_start = Some(self.cursor_pos);
                     }
                     if self.cursor_pos < self.input_buffer.len() {
-                        self.cursor_pos += 1;
+                        self.cursor_pos = line_edit::next_char_boundary(&self.input_buffer, self.cursor_pos);
                         self.selection_end = Some(self.cursor_pos);
                     }
+                } else if self.accept_history_hint() {
+                    // Right at the end of the line takes the autosuggestion
+                    self.selection_start = None;
+                    self.selection_end = None;
                 } else {
                     // Right: Move cursor and clear selection
-                    if self.cursor_pos < self.input_buffer.len() {
-                        self.cursor_pos += 1;
-                    }
+                    self.cursor_pos = line_edit::next_char_boundary(&self.input_buffer, self.cursor_pos);
                     self.selection_start = None;
                     self.selection_end = None;
                 }
@@ -1996,148 +3757,683 @@ impl TerminalApp {
                     // Tab was used for autocomplete
                 } else {
                     // Fallback: add space
-                    self.input_buffer.push(' ');
-                    self.cursor_pos += 1;
+                    line_edit::insert_char(&mut self.input_buffer, &mut self.cursor_pos, ' ');
+                    self.update_autocomplete();
+                }
+            }
+            egui::Key::Escape => {
+                // Hide autocomplete suggestions
+                self.show_autocomplete = false;
+                self.autocomplete_suggestions.clear();
+                self.autocomplete_index = -1;
+                self.cancel_heredoc();
+            }
+            _ => {}
+        }
+    }
+
+    // A shortcut from the keymap
+    fn run_key_action(&mut self, action: Action) {
+        match action {
+            Action::ToggleAutocomplete => {
+                if self.show_autocomplete {
+                    self.show_autocomplete = false;
+                } else {
+                    self.update_autocomplete();
+                }
+            }
+            Action::CommandPalette => {
+                self.palette_open = true;
+                self.palette_query.clear();
+                self.update_palette();
+                self.show_autocomplete = false;
+            }
+            Action::HistoryPicker => {
+                self.history_picker = Some(HistoryPicker::new(&self.input_buffer, &self.command_history));
+                self.show_autocomplete = false;
+            }
+            Action::Filter => {
+                self.filter_editing = true;
+                self.show_autocomplete = false;
+            }
+            Action::ToggleFuzzy => {
+                self.fuzzy_enabled = !self.fuzzy_enabled;
+                if self.show_autocomplete {
                     self.update_autocomplete();
                 }
-            }
-            egui::Key::Escape => {
-                // Hide autocomplete suggestions
-                self.show_autocomplete = false;
-                self.autocomplete_suggestions.clear();
-                self.autocomplete_index = -1;
-            }
-            egui::Key::Space if modifiers.ctrl => {
-                // Ctrl+Space: Toggle autocomplete suggestions
-                if self.show_autocomplete {
-                    self.show_autocomplete = false;
-                } else {
-                    self.update_autocomplete();
+                self.add_line(&format!("Fuzzy matching {}", if self.fuzzy_enabled { "enabled" } else { "disabled" }), false, false);
+            }
+            Action::Copy => self.copy_selection_or_line(),
+            Action::CopyLastOutput => {
+                if let Some(output) = self.command_output(0) {
+                    self.pending_copy = Some(output);
+                }
+            }
+            Action::CopyOrInterrupt => {
+                if self.selection_start.is_some() && self.selection_end.is_some() {
+                    // Copy selected text
+                    if let Some(selected_text) = self.selected_text() {
+                        if !selected_text.is_empty() {
+                            self.pending_copy = Some(selected_text);
+                        }
+                    }
+                } else if !self.input_buffer.is_empty() {
+                    // Copy entire line if no selection
+                    self.pending_copy = Some(self.input_buffer.clone());
+                } else {
+                    // No selection and empty buffer - interrupt command
+                    self.pending_heredoc = None;
+                    self.add_line("^C", false, false);
+                    self.input_buffer.clear();
+                    self.cursor_pos = 0;
+                    self.show_prompt();
+                }
+            }
+            Action::Cut => {
+                if let Some(selected_text) = self.selected_text() {
+                    if !selected_text.is_empty() {
+                        self.pending_copy = Some(selected_text);
+                        self.delete_selection();
+                        self.update_autocomplete();
+                    }
+                }
+            }
+            // Handled in handle_input, which has the context
+            Action::Paste => self.pending_paste = true,
+            Action::SelectAll => {
+                self.selection_start = Some(0);
+                self.selection_end = Some(self.input_buffer.len());
+            }
+            Action::LineStart => {
+                self.cursor_pos = 0;
+                self.selection_start = None;
+                self.selection_end = None;
+            }
+            Action::LineEnd => {
+                self.accept_history_hint();
+                self.cursor_pos = self.input_buffer.len();
+                self.selection_start = None;
+                self.selection_end = None;
+            }
+            Action::DeleteToStart => {
+                let cursor = line_edit::floor_char_boundary(&self.input_buffer, self.cursor_pos);
+                self.input_buffer.replace_range(..cursor, "");
+                self.cursor_pos = 0;
+                self.selection_start = None;
+                self.selection_end = None;
+                self.update_autocomplete();
+            }
+            Action::DeleteToEnd => {
+                self.cursor_pos = line_edit::floor_char_boundary(&self.input_buffer, self.cursor_pos);
+                self.input_buffer.truncate(self.cursor_pos);
+                self.selection_start = None;
+                self.selection_end = None;
+                self.update_autocomplete();
+            }
+            // EOF closes this tab
+            Action::Eof => self.closed = true,
+            Action::Clear => {
+                self.lines.clear();
+                self.show_prompt();
+            }
+            Action::ScrollPageUp => self.scroll_by(-self.scroll_page),
+            Action::ScrollPageDown => self.scroll_by(self.scroll_page),
+            Action::ScrollUp => self.scroll_by(-self.scroll_line * SCROLL_STEP_LINES),
+            Action::ScrollDown => self.scroll_by(self.scroll_line * SCROLL_STEP_LINES),
+            Action::ScrollTop => self.scroll_target = Some(0.0),
+            Action::ScrollBottom => {
+                self.scroll_target = None;
+                self.scroll_to_bottom = true;
+            }
+            // Tab and window actions are taken by TerminalApp before input reaches the session
+            Action::NewTab | Action::CloseTab | Action::NextTab | Action::PrevTab | Action::ToggleSplit | Action::Preferences => {}
+        }
+    }
+
+    // Move the scrollback by `delta` points from where it is, or from where an earlier key this frame put it
+    fn scroll_by(&mut self, delta: f32) {
+        let from = self.scroll_target.unwrap_or(self.scroll_offset);
+        self.scroll_target = Some((from + delta).clamp(0.0, self.scroll_max));
+    }
+
+    // Commands matching the palette query with where they came from, best first.
+    // History matches rank above common and PATH commands with the same score.
+    fn palette_results(&self) -> Vec<(String, &'static str)> {
+        let query = self.palette_query.trim();
+        let history = self.command_history.iter().rev().map(|cmd| (cmd, "history", 1000));
+        let common = self.common_commands.iter().map(|cmd| (cmd, "common", 0));
+        let path = self.path_commands.iter().map(|cmd| (cmd, "PATH", 0));
+
+        let mut seen = std::collections::HashSet::new();
+        let mut scored: Vec<(i32, usize, String, &'static str)> = Vec::new();
+        for (order, (cmd, source, bonus)) in history.chain(common).chain(path).enumerate() {
+            if !seen.insert(cmd.as_str()) {
+                continue;
+            }
+            if query.is_empty() {
+                scored.push((bonus, order, cmd.clone(), source));
+                continue;
+            }
+            let score = fuzzy::score(query, cmd);
+            if score > 0 {
+                scored.push((score + bonus, order, cmd.clone(), source));
+            }
+        }
+
+        // Highest score first, keeping recent history ahead on ties
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        scored.into_iter()
+            .take(PALETTE_MAX_RESULTS)
+            .map(|(_, _, cmd, source)| (cmd, source))
+            .collect()
+    }
+
+    // Rank the matches for a new query, selecting the best
+    fn update_palette(&mut self) {
+        self.palette_matches = self.palette_results();
+        self.palette_index = 0;
+        self.palette_scroll = true;
+    }
+
+    fn handle_palette_event(&mut self, event: &egui::Event) {
+        match event {
+            egui::Event::Text(text) => {
+                self.palette_query.push_str(text);
+                self.update_palette();
+            }
+            egui::Event::Key { key, pressed: true, .. } => match key {
+                egui::Key::Escape => self.palette_open = false,
+                egui::Key::Backspace => {
+                    self.palette_query.pop();
+                    self.update_palette();
+                }
+                egui::Key::ArrowUp => {
+                    self.palette_index = self.palette_index.saturating_sub(1);
+                    self.palette_scroll = true;
+                }
+                egui::Key::ArrowDown if self.palette_index + 1 < self.palette_matches.len() => {
+                    self.palette_index += 1;
+                    self.palette_scroll = true;
+                }
+                egui::Key::Enter => {
+                    if let Some((cmd, _)) = self.palette_matches.get(self.palette_index).cloned() {
+                        self.insert_palette_command(&cmd);
+                    } else {
+                        self.palette_open = false;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    // Keys while the history picker is open: Enter puts the selected command in the input line
+    fn handle_history_picker_event(&mut self, event: &egui::Event) {
+        let Some(picker) = &mut self.history_picker else { return };
+        match event {
+            egui::Event::Text(text) => {
+                picker.query.push_str(text);
+                picker.update(&self.command_history);
+            }
+            egui::Event::Key { key, pressed: true, .. } => match key {
+                egui::Key::Escape => self.history_picker = None,
+                egui::Key::Backspace => {
+                    picker.query.pop();
+                    picker.update(&self.command_history);
+                }
+                egui::Key::ArrowUp => picker.select(picker.index.saturating_sub(1)),
+                egui::Key::ArrowDown => picker.select(picker.index + 1),
+                egui::Key::PageUp => picker.select(picker.index.saturating_sub(self.page_lines)),
+                egui::Key::PageDown => picker.select(picker.index + self.page_lines),
+                egui::Key::Enter => {
+                    let chosen = picker.matches.get(picker.index).cloned();
+                    match chosen {
+                        Some(cmd) => self.use_history_command(&cmd),
+                        None => self.history_picker = None,
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    // Replace the input line with a command picked from history
+    fn use_history_command(&mut self, cmd: &str) {
+        self.history_picker = None;
+        self.input_buffer = cmd.to_string();
+        self.cursor_pos = self.input_buffer.len();
+        self.selection_start = None;
+        self.selection_end = None;
+        self.history_index = -1;
+    }
+
+    // Keys while typing the scrollback filter: Enter keeps it applied, Escape clears it
+    fn handle_filter_event(&mut self, event: &egui::Event) {
+        let before = (self.filter_query.clone(), self.filter_regex);
+        self.edit_filter(event);
+        if (&self.filter_query, self.filter_regex) != (&before.0, before.1) {
+            self.update_filter();
+        }
+    }
+
+    fn edit_filter(&mut self, event: &egui::Event) {
+        match event {
+            egui::Event::Text(text) => self.filter_query.push_str(text),
+            egui::Event::Key { key, pressed: true, modifiers, .. } => match key {
+                egui::Key::Escape => {
+                    self.filter_query.clear();
+                    self.filter_editing = false;
+                }
+                egui::Key::Enter => self.filter_editing = false,
+                egui::Key::Backspace => {
+                    self.filter_query.pop();
+                }
+                egui::Key::R if modifiers.ctrl => self.filter_regex = !self.filter_regex,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn update_filter(&mut self) {
+        self.line_filter = LineFilter::new(&self.filter_query, self.filter_regex);
+    }
+
+    fn insert_palette_command(&mut self, cmd: &str) {
+        if self.selection_start.is_some() && self.selection_end.is_some() {
+            self.delete_selection();
+        }
+        self.insert_text(cmd);
+        self.palette_open = false;
+        self.update_autocomplete();
+    }
+
+    fn delete_selection(&mut self) {
+        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
+            let (min_pos, max_pos) = line_edit::selection_range(&self.input_buffer, start, end);
+            
+            self.input_buffer.replace_range(min_pos..max_pos, "");
+            self.cursor_pos = min_pos;
+            self.selection_start = None;
+            self.selection_end = None;
+        }
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
+            let (min_pos, max_pos) = line_edit::selection_range(&self.input_buffer, start, end);
+            Some(self.input_buffer[min_pos..max_pos].to_string())
+        } else {
+            None
+        }
+    }
+
+    fn copy_selection_or_line(&mut self) {
+        if let Some(selected_text) = self.selected_text() {
+            if !selected_text.is_empty() {
+                self.pending_copy = Some(selected_text);
+            }
+        } else if !self.input_buffer.is_empty() {
+            // Copy entire input buffer if no selection
+            self.pending_copy = Some(self.input_buffer.clone());
+        }
+    }
+
+    fn paste_text(&mut self, text: &str) {
+        // Clear selection if any
+        if self.selection_start.is_some() && self.selection_end.is_some() {
+            self.delete_selection();
+        }
+
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let mut lines: Vec<&str> = text.split('\n').collect();
+        // The last segment has no newline after it, so it stays in the input buffer
+        let partial_line = lines.pop().unwrap_or("");
+
+        // Every complete line runs as if it had been typed and followed by Enter
+        for line in lines {
+            self.insert_text(line);
+            let command = self.input_buffer.clone();
+            if command.trim().is_empty() {
+                self.input_buffer.clear();
+                self.cursor_pos = 0;
+                continue;
+            }
+            self.show_autocomplete = false;
+            self.autocomplete_suggestions.clear();
+            self.autocomplete_index = -1;
+            self.execute_command(&command);
+        }
+
+        self.insert_text(partial_line);
+        self.update_autocomplete();
+        self.selection_start = None;
+        self.selection_end = None;
+    }
+
+    fn resolve_large_paste(&mut self, accept: bool) {
+        if let Some(text) = self.pending_large_paste.take() {
+            if accept {
+                self.paste_text(&text);
+            }
+        }
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        for ch in text.chars() {
+            if ch.is_control() {
+                continue;
+            }
+            line_edit::insert_char(&mut self.input_buffer, &mut self.cursor_pos, ch);
+        }
+    }
+}
+
+// Draw an expanded prompt, colouring each placeholder by what it shows
+fn render_prompt_segments(ui: &mut egui::Ui, segments: &[PromptSegment]) {
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for segment in segments {
+            let color = match segment.kind {
+                SegmentKind::Literal => egui::Color32::from_rgb(100, 150, 255), // Blue
+                SegmentKind::User => egui::Color32::from_rgb(255, 100, 150), // Pink
+                SegmentKind::Host => egui::Color32::from_rgb(200, 150, 255), // Purple
+                SegmentKind::Cwd => egui::Color32::from_rgb(255, 200, 100), // Yellow
+                SegmentKind::Git => egui::Color32::from_rgb(255, 255, 100), // Bright yellow for git
+                SegmentKind::Exit if segment.text == "0" => egui::Color32::from_rgb(100, 255, 150), // Green
+                SegmentKind::Exit => egui::Color32::from_rgb(255, 100, 100), // Red for failures
+                SegmentKind::Status if segment.text == "✔" => egui::Color32::from_rgb(100, 255, 150), // Green
+                SegmentKind::Status => egui::Color32::from_rgb(255, 100, 100), // Red
+                SegmentKind::Duration => egui::Color32::from_rgb(255, 180, 80), // Orange
+            };
+            ui.label(
+                egui::RichText::new(&segment.text)
+                    .font(egui::FontId::monospace(16.0))
+                    .color(color)
+            );
+        }
+    });
+}
+
+// The window background at the configured opacity. Without a compositing window manager
+// the alpha is ignored and the dark color shows through darker still, which is the opaque fallback.
+fn background_color(config: &Config) -> egui::Color32 {
+    with_alpha(theme_background(config.theme), config.background_alpha)
+}
+
+fn with_alpha(color: egui::Color32, alpha: f32) -> egui::Color32 {
+    egui::Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), (alpha * 255.0).round() as u8)
+}
+
+fn theme_background(theme: Theme) -> egui::Color32 {
+    match theme {
+        Theme::Midnight => BACKGROUND,
+        Theme::Black => egui::Color32::BLACK,
+        Theme::Solarized => egui::Color32::from_rgb(0, 43, 54),
+    }
+}
+
+// Dark egui visuals with the theme's background behind panels and windows
+fn terminal_visuals(theme: Theme) -> egui::Visuals {
+    let background = theme_background(theme);
+    let mut visuals = egui::Visuals::dark();
+    visuals.window_fill = background;
+    visuals.panel_fill = background;
+    visuals.extreme_bg_color = background;
+    visuals
+}
+
+// A `cd` failure named after its cause rather than always "No such file or directory"
+fn cd_error(target_dir: &str, path: &std::path::Path, error: &std::io::Error) -> String {
+    let reason = match error.kind() {
+        // canonicalize follows symlinks, so a dangling one looks like a missing path
+        std::io::ErrorKind::NotFound if path.symlink_metadata().is_ok() => "Broken symbolic link".to_string(),
+        std::io::ErrorKind::NotFound => "No such file or directory".to_string(),
+        std::io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        std::io::ErrorKind::NotADirectory => "Not a directory".to_string(),
+        _ => error.to_string(),
+    };
+    format!("cd: {}: {}", target_dir, reason)
+}
+
+// Format a duration the way bash's `time` does, e.g. "0m1.234s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    let minutes = (secs / 60.0).floor();
+    format!("{}m{:.3}s", minutes as u64, secs - minutes * 60.0)
+}
+
+// User and system CPU time used by finished child processes that have been waited for
+fn children_cpu_times() -> Option<(Duration, Duration)> {
+    use nix::sys::resource::{getrusage, UsageWho};
+    let usage = getrusage(UsageWho::RUSAGE_CHILDREN).ok()?;
+    let time = |t: nix::sys::time::TimeVal| Duration::new(t.tv_sec() as u64, t.tv_usec() as u32 * 1000);
+    Some((time(usage.user_time()), time(usage.system_time())))
+}
+
+impl Session {
+    // Tab title: the last component of the working directory
+    fn title(&self) -> String {
+        std::path::Path::new(&self.current_dir)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.current_dir.clone())
+    }
+
+    // Per-frame work that runs for every tab, focused or not. `blink` is set for the
+    // active tab of a focused window, the only place a blinking cursor can be seen.
+    fn tick(&mut self, ctx: &egui::Context, blink: bool) {
+        // Blink the cursor. The next frame is asked for when the cursor next changes, not
+        // sooner: frames drawn for input in between would otherwise leave the blink stalled.
+        // Elsewhere, or with blinking off, the cursor holds still, so an idle window doesn't repaint.
+        if let (true, Some(interval)) = (blink, self.cursor_blink) {
+            if self.last_cursor_blink.elapsed() >= interval {
+                self.show_cursor = !self.show_cursor;
+                self.last_cursor_blink = Instant::now();
+            }
+            ctx.request_repaint_after(interval.saturating_sub(self.last_cursor_blink.elapsed()));
+        } else {
+            self.show_cursor = true;
+            self.last_cursor_blink = Instant::now();
+        }
+
+        // Pick up output and exit codes from background jobs, looked-up flags, and AI answers
+        self.poll_jobs();
+        self.poll_flags();
+        self.poll_package_lookup();
+        self.poll_ai();
+        self.update_ai_progress();
+        if let Some(info) = self.banner_receiver.as_ref().and_then(|receiver| receiver.try_recv().ok()) {
+            self.banner_receiver = None;
+            self.insert_banner(&info);
+        }
+        self.poll_watch();
+        if let Some(watch) = &self.watch {
+            if watch.run.is_none() && Instant::now() >= watch.next_run {
+                self.run_watch();
+            }
+            if let Some(watch) = &self.watch {
+                // While a run is going, check for its output as jobs are checked
+                let wait = match watch.run {
+                    Some(_) => Duration::from_millis(100),
+                    None => watch.next_run.saturating_duration_since(Instant::now()),
+                };
+                ctx.request_repaint_after(wait);
+            }
+        }
+        // Output can arrive any time from these; a stopped job waits for input like fg or kill
+        let running = self.jobs.iter().any(|job| job.status == JobStatus::Running);
+        if running || self.ai_pending.is_some() || self.banner_receiver.is_some() || self.flag_lookups > 0
+            || self.package_lookup.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+    }
+
+    // Keyboard, mouse and clipboard events for the focused tab
+    fn handle_input(&mut self, ctx: &egui::Context) {
+        // Handle keyboard input
+        ctx.input(|i| {
+            for event in &i.events {
+                // The command palette takes all keyboard input while it is open
+                if self.palette_open {
+                    self.handle_palette_event(event);
+                    continue;
+                }
+
+                // As does the history picker
+                if self.history_picker.is_some() {
+                    self.handle_history_picker_event(event);
+                    continue;
                 }
-            }
-            egui::Key::F if modifiers.ctrl => {
-                // Ctrl+F: Toggle fuzzy matching
-                self.fuzzy_enabled = !self.fuzzy_enabled;
-                if self.show_autocomplete {
-                    self.update_autocomplete();
+
+                // So does the scrollback filter while its query is being typed
+                if self.filter_editing {
+                    self.handle_filter_event(event);
+                    continue;
                 }
-                self.add_line(&format!("Fuzzy matching {}", if self.fuzzy_enabled { "enabled" } else { "disabled" }), false, false);
-            }
-            egui::Key::C if modifiers.ctrl && modifiers.shift => {
-                // Ctrl+Shift+C: Copy selected text or current line (legacy shortcut)
-                if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-                    let selected_text = if start <= end {
-                        self.input_buffer[start..end].to_string()
-                    } else {
-                        self.input_buffer[end..start].to_string()
-                    };
-                    if !selected_text.is_empty() {
-                        self.pending_copy = Some(selected_text);
+
+                // After an AI timeout the next key answers the retry offer: 'y' retries, anything else cancels
+                if self.pending_ai_retry.is_some() {
+                    match event {
+                        egui::Event::Text(text) => {
+                            let accept = text.trim().eq_ignore_ascii_case("y");
+                            self.resolve_ai_retry(accept);
+                        }
+                        egui::Event::Key { key: egui::Key::Enter | egui::Key::Escape, pressed: true, .. } => {
+                            self.resolve_ai_retry(false);
+                        }
+                        _ => {}
                     }
-                } else {
-                    // Copy entire input buffer if no selection
-                    if !self.input_buffer.is_empty() {
-                        self.pending_copy = Some(self.input_buffer.clone());
+                    continue;
+                }
+
+                // A held-back AI suggestion only runs on 'y'; anything else drops it
+                if self.pending_ai_confirm.is_some() {
+                    match event {
+                        egui::Event::Text(text) => {
+                            let accept = text.trim().eq_ignore_ascii_case("y");
+                            self.resolve_ai_confirm(accept);
+                        }
+                        egui::Event::Key { key: egui::Key::Enter | egui::Key::Escape, pressed: true, .. } => {
+                            self.resolve_ai_confirm(false);
+                        }
+                        _ => {}
                     }
+                    continue;
                 }
-            }
-            egui::Key::C if modifiers.ctrl => {
-                // Ctrl+C - copy selected text or interrupt
-                if self.selection_start.is_some() && self.selection_end.is_some() {
-                    // Copy selected text
-                    if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-                        let selected_text = if start <= end {
-                            self.input_buffer[start..end].to_string()
-                        } else {
-                            self.input_buffer[end..start].to_string()
-                        };
-                        if !selected_text.is_empty() {
-                            self.pending_copy = Some(selected_text);
+
+                // A step of an `ai-run` script runs on 'y'; anything else stops the script
+                if self.pending_ai_step.is_some() {
+                    match event {
+                        egui::Event::Text(text) => {
+                            let accept = text.trim().eq_ignore_ascii_case("y");
+                            self.resolve_ai_step_confirm(accept);
+                        }
+                        egui::Event::Key { key: egui::Key::Enter | egui::Key::Escape, pressed: true, .. } => {
+                            self.resolve_ai_step_confirm(false);
                         }
+                        _ => {}
                     }
-                } else if !self.input_buffer.is_empty() {
-                    // Copy entire line if no selection
-                    self.pending_copy = Some(self.input_buffer.clone());
-                } else {
-                    // No selection and empty buffer - interrupt command
-                    self.add_line("^C", false, false);
-                    self.input_buffer.clear();
-                    self.cursor_pos = 0;
-                    self.show_prompt();
+                    continue;
                 }
-            }
-            egui::Key::X if modifiers.ctrl => {
-                // Ctrl+X - cut selected text
-                if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-                    let selected_text = if start <= end {
-                        self.input_buffer[start..end].to_string()
-                    } else {
-                        self.input_buffer[end..start].to_string()
-                    };
-                    if !selected_text.is_empty() {
-                        self.pending_copy = Some(selected_text);
-                        self.delete_selection();
-                        self.update_autocomplete();
+
+                // Same for the offer to install a missing command
+                if self.pending_install.is_some() {
+                    match event {
+                        egui::Event::Text(text) => {
+                            let accept = text.trim().eq_ignore_ascii_case("y");
+                            self.resolve_install(accept);
+                        }
+                        egui::Event::Key { key: egui::Key::Enter | egui::Key::Escape, pressed: true, .. } => {
+                            self.resolve_install(false);
+                        }
+                        _ => {}
                     }
+                    continue;
                 }
-            }
-            egui::Key::A if modifiers.ctrl => {
-                // Ctrl+A: Select all
-                self.selection_start = Some(0);
-                self.selection_end = Some(self.input_buffer.len());
-            }
-            _ => {
-                if modifiers.ctrl {
-                    match key {
-                        egui::Key::V => {
-                            // Ctrl+V - paste from clipboard
-                            // We'll handle this in the update method to access ctx
-                            self.pending_paste = true;
+
+                // Right after `examples`, a number puts that example in the input line;
+                // any other key drops the offer and is handled as usual
+                if !self.offered_examples.is_empty() {
+                    match event {
+                        egui::Event::Text(text) => {
+                            let offered = std::mem::take(&mut self.offered_examples);
+                            let picked = text.parse::<usize>().ok()
+                                .and_then(|number| number.checked_sub(1))
+                                .and_then(|index| offered.get(index));
+                            if let Some(command) = picked {
+                                self.input_buffer = command.clone();
+                                self.cursor_pos = self.input_buffer.len();
+                                self.selection_start = None;
+                                self.selection_end = None;
+                                continue;
+                            }
                         }
-                        egui::Key::D => {
-                            // Ctrl+D - EOF/exit
-                            std::process::exit(0);
+                        egui::Event::Key { key: egui::Key::Escape, pressed: true, .. } => {
+                            self.offered_examples.clear();
+                            continue;
                         }
-                        egui::Key::L => {
-                            // Ctrl+L - clear screen
-                            self.lines.clear();
-                            self.show_prompt();
+                        // The digit's key press arrives before its text
+                        egui::Event::Key { key, pressed: true, .. } if !key.name().chars().all(|c| c.is_ascii_digit()) => {
+                            self.offered_examples.clear();
                         }
                         _ => {}
                     }
                 }
-            }
-        }
-    }
 
-    fn delete_selection(&mut self) {
-        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-            let (min_pos, max_pos) = if start <= end {
-                (start, end)
-            } else {
-                (end, start)
-            };
-            
-            self.input_buffer.replace_range(min_pos..max_pos, "");
-            self.cursor_pos = min_pos;
-            self.selection_start = None;
-            self.selection_end = None;
-        }
-    }
-}
+                // The sudo password prompt takes every key until it is answered
+                if self.pending_sudo.is_some() {
+                    self.handle_sudo_event(event);
+                    continue;
+                }
 
-impl eframe::App for TerminalApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Handle cursor blinking (optimized)
-        if self.last_cursor_blink.elapsed() > Duration::from_millis(500) {
-            self.show_cursor = !self.show_cursor;
-            self.last_cursor_blink = Instant::now();
-            ctx.request_repaint_after(Duration::from_millis(500)); // Only repaint when needed
-        }
+                // So does a foreground job's password prompt
+                if self.secure_input.is_some() {
+                    self.handle_secure_input_event(event);
+                    continue;
+                }
+
+                // q, Escape or Ctrl+C stops a `watch`; other keys are ignored while it runs
+                if self.watch.is_some() {
+                    match event {
+                        egui::Event::Text(text) if text.eq_ignore_ascii_case("q") => self.stop_watch(),
+                        egui::Event::Key { key: egui::Key::Escape, pressed: true, .. } => self.stop_watch(),
+                        egui::Event::Key { key: egui::Key::C, pressed: true, modifiers, .. } if modifiers.ctrl => self.stop_watch(),
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // Held-back output takes the keyboard until it has all been shown or dropped
+                if !self.paged_output.is_empty() {
+                    self.handle_pager_event(event);
+                    continue;
+                }
+
+                // A pending large paste captures the next key: 'y' pastes, anything else cancels
+                if self.pending_large_paste.is_some() {
+                    match event {
+                        egui::Event::Text(text) => {
+                            let accept = text.trim().eq_ignore_ascii_case("y");
+                            self.resolve_large_paste(accept);
+                        }
+                        egui::Event::Key { key: egui::Key::Enter | egui::Key::Escape, pressed: true, .. } => {
+                            self.resolve_large_paste(false);
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // Typing returns a scrolled-up view to the bottom; scroll keys are the exception
+                let scroll_key = matches!(event, egui::Event::Key { key, modifiers, .. }
+                    if self.keymap.action(*key, *modifiers).is_some_and(Action::is_scroll));
+                if self.scroll_locked && !scroll_key && matches!(event, egui::Event::Key { pressed: true, .. } | egui::Event::Text(_) | egui::Event::Paste(_)) {
+                    self.scroll_to_bottom = true;
+                }
 
-        // Handle keyboard input
-        ctx.input(|i| {
-            for event in &i.events {
                 match event {
                     egui::Event::Key { key, pressed: true, modifiers, .. } => {
                         self.handle_key(*key, *modifiers);
@@ -2147,18 +4443,29 @@ impl eframe::App for TerminalApp {
                         if self.selection_start.is_some() && self.selection_end.is_some() {
                             self.delete_selection();
                         }
-                        for ch in text.chars() {
-                            if ch.is_control() || ch == '\n' || ch == '\r' {
-                                continue;
-                            }
-                            self.input_buffer.insert(self.cursor_pos, ch);
-                            self.cursor_pos += 1;
-                        }
+                        self.insert_text(text);
                         // Update autocomplete immediately when typing
                         self.update_autocomplete();
                         self.selection_start = None;
                         self.selection_end = None;
                     }
+                    egui::Event::Paste(text) => {
+                        // System clipboard paste (Ctrl+V is delivered as a Paste event)
+                        self.clipboard_content = text.clone();
+                        self.pending_paste = true;
+                    }
+                    egui::Event::PointerButton { button: egui::PointerButton::Middle, pressed: true, .. } => {
+                        // Middle-click pastes the selection, or the clipboard if nothing is selected (X11 style)
+                        let text = self.selected_text()
+                            .filter(|text| !text.is_empty())
+                            .unwrap_or_else(|| self.clipboard_content.clone());
+                        // Keep the selected text in place rather than replacing it
+                        self.selection_start = None;
+                        self.selection_end = None;
+                        if !text.is_empty() {
+                            self.paste_text(&text);
+                        }
+                    }
                     egui::Event::PointerButton { pos: _, button: egui::PointerButton::Primary, pressed: true, .. } => {
                         // Handle mouse click for cursor positioning
                         // For now, we'll just clear selection on click
@@ -2179,538 +4486,466 @@ impl eframe::App for TerminalApp {
             self.pending_paste = false;
             // Use our internal clipboard content
             if !self.clipboard_content.is_empty() {
-                // Clear selection if any
-                if self.selection_start.is_some() && self.selection_end.is_some() {
-                    self.delete_selection();
-                }
-                // Insert clipboard text at cursor position
-                for ch in self.clipboard_content.chars() {
-                    if ch != '\n' && ch != '\r' { // Avoid multiline paste
-                        self.input_buffer.insert(self.cursor_pos, ch);
-                        self.cursor_pos += 1;
-                    }
+                // Commands copied from a tutorial often start with a `$ ` or `# ` prompt
+                let text = line_edit::strip_prompt_markers(&self.clipboard_content);
+                let char_count = text.chars().count();
+                let line_count = text.lines().count();
+                if char_count > LARGE_PASTE_CHARS || line_count > LARGE_PASTE_LINES {
+                    // Hold large pastes until the user confirms them
+                    self.pending_large_paste = Some(text);
+                } else {
+                    self.paste_text(&text);
                 }
-                self.update_autocomplete();
-                self.selection_start = None;
-                self.selection_end = None;
             }
         }
+    }
 
-        // Main terminal panel - fullscreen
-        egui::CentralPanel::default()
-            .frame(egui::Frame::none().fill(egui::Color32::from_rgb(12, 12, 20)))
-            .show(ctx, |ui| {
-                // Terminal content with proper margins
-                egui::Frame::none()
-                    .fill(egui::Color32::from_rgb(12, 12, 20))
-                    .inner_margin(egui::Margin::same(12.0))
-                    .show(ui, |ui| {
-                        // Scrollable terminal area
-                        egui::ScrollArea::vertical()
-                            .stick_to_bottom(true)
-                            .auto_shrink([false, false])
-                            .show(ui, |ui| {
-                                ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
-                                    // Display all terminal lines except the last prompt
-                                    let lines_to_show: Vec<_> = if self.lines.back().map_or(false, |line| line.is_prompt) {
-                                        self.lines.iter().take(self.lines.len() - 1).collect()
-                                    } else {
-                                        self.lines.iter().collect()
-                                    };
-
-                                    for line in lines_to_show {
-                                        // Check if this is a system info line for special rendering
-                                        let is_system_info = line.text.contains("██") || 
-                                                            line.text.starts_with("OS:") ||
-                                                            line.text.starts_with("Kernel:") ||
-                                                            line.text.starts_with("Uptime:") ||
-                                                            line.text.starts_with("Memory:") ||
-                                                            line.text.starts_with("CPU:") ||
-                                                            line.text.starts_with("Terminal:") ||
-                                                            line.text.starts_with("$ ") ||
-                                                            line.text.starts_with("┌─") && line.text.contains("System Information") ||
-                                                            line.text.starts_with("└─");
-
-                                        let color = if line.text.starts_with("ERROR:") {
-                                            egui::Color32::from_rgb(255, 100, 100) // Red for errors
-                                        } else if line.is_prompt {
-                                            // Multicolor prompt styling for completed commands
-                                            if line.text.starts_with("🏠") {
-                                                // This will be handled by special rendering below
-                                                egui::Color32::from_rgb(220, 220, 220) // Default for special case
-                                            } else if line.text.starts_with("┌─") {
-                                                egui::Color32::from_rgb(100, 200, 255) // Cyan for top line
-                                            } else if line.text.starts_with("└─") {
-                                                egui::Color32::from_rgb(255, 150, 100) // Orange for arrow
-                                            } else {
-                                                egui::Color32::from_rgb(100, 255, 100) // Green fallback
-                                            }
-                                        } else if line.is_input {
-                                            egui::Color32::from_rgb(255, 255, 100) // Yellow for input
-                                        } else {
-                                            egui::Color32::from_rgb(220, 220, 220) // Normal text
-                                        };
-                                        
-                                        // Special rendering for PowerShell-like header bar (completed commands)
-                                        if line.text.starts_with("🏠") {
-                                            // Render the colorful header bar like PowerShell for completed output
-                                            ui.horizontal(|ui| {
-                                                // Parse the line to extract prompt parts and any command/output
-                                                let line_text = &line.text;
-
-                                                // Split by ">" to separate prompt from command/output
-                                                if let Some(prompt_end) = line_text.find(" > ") {
-                                                    let prompt_part = &line_text[..prompt_end];
-                                                    let command_output_part = &line_text[prompt_end + 3..]; // Skip " > "
-
-                                                    // Parse the prompt part
-                                                    let parts: Vec<&str> = prompt_part.split_whitespace().collect();
-
-                                                    // Find the path - it's after "📂" symbol
-                                                    let mut path_from_line = "~";
-                                                    for (i, part) in parts.iter().enumerate() {
-                                                        if *part == "📂" && i + 1 < parts.len() {
-                                                            // Check if the next part is the path (not git info)
-                                                            let potential_path = parts[i + 1];
-                                                            if !potential_path.starts_with("⚡") {
-                                                                path_from_line = potential_path;
-                                                            }
-                                                            break;
-                                                        }
-                                                    }
-
-                                                    // Create a background frame for the header
-                                                    ui.add_space(2.0);
-                                                    egui::Frame::none()
-                                                        .fill(egui::Color32::from_rgb(30, 30, 40))
-                                                        .inner_margin(egui::Margin::symmetric(8.0, 4.0))
-                                                        .rounding(egui::Rounding::same(6.0))
-                                                        .show(ui, |ui| {
-                                                            ui.horizontal(|ui| {
-                                                                // Render each segment with proper colors
-                                                                ui.label(
-                                                                    egui::RichText::new("🏠 ")
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(100, 150, 255)) // Blue
-                                                                );
-                                                                ui.label(
-                                                                    egui::RichText::new(&self.username)
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(255, 100, 150)) // Pink
-                                                                );
-                                                                ui.label(
-                                                                    egui::RichText::new(" 📂 ")
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(100, 255, 150)) // Green
-                                                                );
-                                                                ui.label(
-                                                                    egui::RichText::new(path_from_line)
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(255, 200, 100)) // Yellow
-                                                                );
-
-                                                                // Add git info if present in the prompt
-                                                                for part in parts.iter() {
-                                                                    if part.starts_with("⚡") {
-                                                                        ui.label(
-                                                                            egui::RichText::new(&format!(" {}", part))
-                                                                                .font(egui::FontId::monospace(16.0))
-                                                                                .color(egui::Color32::from_rgb(255, 255, 100)) // Bright yellow for git
-                                                                        );
-                                                                        break;
-                                                                    }
-                                                                }
-
-                                                                // Add the ">" symbol
-                                                                ui.label(
-                                                                    egui::RichText::new(" > ")
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(150, 150, 150)) // Gray
-                                                                );
-
-                                                                // Render command/output with original terminal colors (not white)
-                                                                if !command_output_part.is_empty() {
-                                                                    ui.label(
-                                                                        egui::RichText::new(command_output_part)
-                                                                            .font(egui::FontId::monospace(16.0))
-                                                                            .color(egui::Color32::from_rgb(220, 220, 220)) // Light gray like normal terminal text
-                                                                    );
-                                                                }
-                                                            });
-                                                        });
-                                                } else {
-                                                    // Fallback: just render as regular prompt (no command/output)
-                                                    let parts: Vec<&str> = line_text.split_whitespace().collect();
-
-                                                    // Find the path - it's after "📂" symbol
-                                                    let mut path_from_line = "~";
-                                                    for (i, part) in parts.iter().enumerate() {
-                                                        if *part == "📂" && i + 1 < parts.len() {
-                                                            let potential_path = parts[i + 1];
-                                                            if !potential_path.starts_with("⚡") {
-                                                                path_from_line = potential_path;
-                                                            }
-                                                            break;
-                                                        }
-                                                    }
-
-                                                    // Create a background frame for the header
-                                                    ui.add_space(2.0);
-                                                    egui::Frame::none()
-                                                        .fill(egui::Color32::from_rgb(30, 30, 40))
-                                                        .inner_margin(egui::Margin::symmetric(8.0, 4.0))
-                                                        .rounding(egui::Rounding::same(6.0))
-                                                        .show(ui, |ui| {
-                                                            ui.horizontal(|ui| {
-                                                                // Render each segment with proper colors
-                                                                ui.label(
-                                                                    egui::RichText::new("🏠 ")
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(100, 150, 255)) // Blue
-                                                                );
-                                                                ui.label(
-                                                                    egui::RichText::new(&self.username)
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(255, 100, 150)) // Pink
-                                                                );
-                                                                ui.label(
-                                                                    egui::RichText::new(" 📂 ")
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(100, 255, 150)) // Green
-                                                                );
-                                                                ui.label(
-                                                                    egui::RichText::new(path_from_line)
-                                                                        .font(egui::FontId::monospace(16.0))
-                                                                        .color(egui::Color32::from_rgb(255, 200, 100)) // Yellow
-                                                                );
-
-                                                                // Add git info if present
-                                                                for part in parts.iter() {
-                                                                    if part.starts_with("⚡") {
-                                                                        ui.label(
-                                                                            egui::RichText::new(&format!(" {}", part))
-                                                                                .font(egui::FontId::monospace(16.0))
-                                                                                .color(egui::Color32::from_rgb(255, 255, 100)) // Bright yellow for git
-                                                                        );
-                                                                        break;
-                                                                    }
-                                                                }
-                                                            });
-                                                        });
-                                                }
-                                            });
-                                        } else if line.is_prompt && line.text.starts_with("┌─") {
-                                            // Render the top prompt line with multiple colors (legacy support)
-                                            ui.horizontal(|ui| {
-                                                let parts: Vec<&str> = line.text.split(" ").collect();
-                                                for (i, part) in parts.iter().enumerate() {
-                                                    let part_color = match i {
-                                                        0 => egui::Color32::from_rgb(100, 200, 255), // ┌─
-                                                        1 => egui::Color32::from_rgb(255, 200, 100), // 💻
-                                                        2 => egui::Color32::from_rgb(150, 255, 150), // username
-                                                        3 => egui::Color32::from_rgb(200, 150, 255), // ◦
-                                                        4 => egui::Color32::from_rgb(255, 180, 120), // 📁
-                                                        _ => egui::Color32::from_rgb(120, 255, 200), // directory
-                                                    };
-                                                    
-                                                    ui.label(
-                                                        egui::RichText::new(*part)
-                                                            .font(egui::FontId::monospace(18.0))
-                                                            .color(part_color)
-                                                    );
-                                                    if i < parts.len() - 1 {
-                                                        ui.label(
-                                                            egui::RichText::new(" ")
-                                                                .font(egui::FontId::monospace(18.0))
-                                                        );
-                                                    }
-                                                }
-                                            });
-                                        } else if is_system_info {
-                                            // Special colorful rendering for system information
-                                            if line.text.contains("██") {
-                                                // ASCII art rendering with rainbow colors
-                                                ui.horizontal(|ui| {
-                                                    let chars: Vec<char> = line.text.chars().collect();
-                                                    for (i, ch) in chars.iter().enumerate() {
-                                                        if *ch == '█' {
-                                                            // Rainbow colors for ASCII art blocks
-                                                            let rainbow_colors = [
-                                                                egui::Color32::from_rgb(255, 100, 100), // Red
-                                                                egui::Color32::from_rgb(255, 165, 0),   // Orange
-                                                                egui::Color32::from_rgb(255, 255, 0),   // Yellow
-                                                                egui::Color32::from_rgb(100, 255, 100), // Green
-                                                                egui::Color32::from_rgb(100, 150, 255), // Blue
-                                                                egui::Color32::from_rgb(150, 100, 255), // Purple
-                                                                egui::Color32::from_rgb(255, 100, 200), // Pink
-                                                            ];
-                                                            let color_index = (i / 2) % rainbow_colors.len();
-                                                            
-                                                            ui.label(
-                                                                egui::RichText::new(ch.to_string())
-                                                                    .font(egui::FontId::monospace(16.0))
-                                                                    .color(rainbow_colors[color_index])
-                                                            );
-                                                        } else {
-                                                            ui.label(
-                                                                egui::RichText::new(ch.to_string())
-                                                                    .font(egui::FontId::monospace(16.0))
-                                                                    .color(egui::Color32::from_rgb(200, 200, 200))
-                                                            );
-                                                        }
-                                                    }
-                                                });
-                                            } else if line.text.starts_with("OS:") {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(
-                                                        egui::RichText::new("OS: ")
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(100, 150, 255))
-                                                    );
-                                                    ui.label(
-                                                        egui::RichText::new(&line.text[4..])
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 255))
-                                                    );
-                                                });
-                                            } else if line.text.starts_with("Kernel:") {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(
-                                                        egui::RichText::new("Kernel: ")
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(150, 100, 255))
-                                                    );
-                                                    ui.label(
-                                                        egui::RichText::new(&line.text[8..])
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 255))
-                                                    );
-                                                });
-                                            } else if line.text.starts_with("Uptime:") {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(
-                                                        egui::RichText::new("Uptime: ")
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 200, 100))
-                                                    );
-                                                    ui.label(
-                                                        egui::RichText::new(&line.text[8..])
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 255))
-                                                    );
-                                                });
-                                            } else if line.text.starts_with("Memory:") {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(
-                                                        egui::RichText::new("Memory: ")
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 150, 100))
-                                                    );
-                                                    ui.label(
-                                                        egui::RichText::new(&line.text[8..])
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 255))
-                                                    );
-                                                });
-                                            } else if line.text.starts_with("CPU:") {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(
-                                                        egui::RichText::new("CPU: ")
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 100, 255))
-                                                    );
-                                                    ui.label(
-                                                        egui::RichText::new(&line.text[5..])
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 255))
-                                                    );
-                                                });
-                                            } else if line.text.starts_with("Terminal:") {
-                                                ui.horizontal(|ui| {
-                                                    ui.label(
-                                                        egui::RichText::new("Terminal: ")
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(100, 255, 255))
-                                                    );
-                                                    ui.label(
-                                                        egui::RichText::new(&line.text[10..])
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 255))
-                                                    );
-                                                });
-                                            } else if line.text.starts_with("┌─") && line.text.contains("System Information") {
-                                                ui.label(
-                                                    egui::RichText::new(&line.text)
-                                                        .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(100, 200, 255))
-                                                );
-                                            } else if line.text.starts_with("└─") {
+    // Draw the terminal into `ui`. Returns true when the pane was clicked, so a split can move focus to it.
+    fn show(&mut self, ui: &mut egui::Ui, focused: bool) -> bool {
+        // Link clicked in the output this frame and the directory of its line, handled once rendering is done
+        let mut clicked_link: Option<(Link, Rc<str>)> = None;
+        // Only the focused pane shows a blinking cursor
+        let show_cursor = self.show_cursor && focused;
+        // Scrollback filter; an invalid regex leaves every line visible and reports the error
+        let (line_filter, filter_error) = match &self.line_filter {
+            Ok(filter) => (filter.clone(), None),
+            Err(e) => (None, Some(e.clone())),
+        };
+        let mut filter_counts = (0, 0); // Lines shown and lines in the scrollback
+
+        let clicked = ui
+            .push_id(self.id, |ui| {
+                let clicked = ui.rect_contains_pointer(ui.max_rect()) && ui.input(|i| i.pointer.any_pressed());
+
+                // Right-click context menu for clipboard operations
+                let panel_response = ui.interact(ui.max_rect(), ui.id().with("terminal_context_menu"), egui::Sense::click());
+                panel_response.context_menu(|ui| {
+                    // Clipboard requests are handled at the start of the next frame
+                    if ui.button("📋 Copy").clicked() {
+                        self.copy_selection_or_line();
+                        ui.ctx().request_repaint();
+                        ui.close_menu();
+                    }
+                    if ui.button("📥 Paste").clicked() {
+                        self.pending_paste = true;
+                        ui.ctx().request_repaint();
+                        ui.close_menu();
+                    }
+                    if ui.button("🔤 Select All").clicked() {
+                        self.selection_start = Some(0);
+                        self.selection_end = Some(self.input_buffer.len());
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("🧹 Clear").clicked() {
+                        self.lines.clear();
+                        self.show_prompt();
+                        ui.close_menu();
+                    }
+                });
+
+                // Terminal content with proper margins; the panel behind it paints the background
+                egui::Frame::none()
+                    .inner_margin(egui::Margin::same(12.0))
+                    .show(ui, |ui| {
+                        // Scrollable terminal area. It follows new output unless the user has scrolled up.
+                        let scroll_area = match self.line_wrap {
+                            LineWrap::Wrap => egui::ScrollArea::vertical(),
+                            LineWrap::Truncate => egui::ScrollArea::both(),
+                        };
+                        let scroll_area = match self.scroll_target.take() {
+                            // A scroll key was pressed: go where it said, letting go of the bottom
+                            Some(offset) => scroll_area.vertical_scroll_offset(offset).stick_to_bottom(false),
+                            None => scroll_area.stick_to_bottom(!self.scroll_locked),
+                        };
+                        let scroll_output = scroll_area
+                            .auto_shrink([false, false])
+                            .show(ui, |ui| {
+                                if self.line_wrap == LineWrap::Truncate {
+                                    // Every label keeps its line on one row; the area scrolls sideways instead
+                                    ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
+                                }
+                                // Text dragged over in the scrollback gets the input line's selection color;
+                                // egui paints it over each selected part, from mid-line to mid-line
+                                ui.visuals_mut().selection.bg_fill = SELECTION_COLOR;
+                                ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT), |ui| {
+                                    // Display all terminal lines except the last prompt
+                                    let mut lines_to_show: Vec<_> = if self.lines.back().map_or(false, |line| line.is_prompt) {
+                                        self.lines.iter().take(self.lines.len() - 1).collect()
+                                    } else {
+                                        self.lines.iter().collect()
+                                    };
+                                    if let Some(filter) = &line_filter {
+                                        let total = lines_to_show.len();
+                                        lines_to_show.retain(|line| filter.matches(&line.text));
+                                        filter_counts = (lines_to_show.len(), total);
+                                    }
+
+                                    // Estimated row heights, used to skip laying out lines that are scrolled out of view
+                                    let item_spacing = ui.spacing().item_spacing.y;
+                                    let text_row_height = ui.fonts(|f| f.row_height(&egui::FontId::monospace(18.0))) + item_spacing;
+                                    let prompt_row_height = ui.fonts(|f| f.row_height(&egui::FontId::monospace(16.0))) + 10.0 + item_spacing;
+
+                                    for line in lines_to_show {
+                                        // Off-screen lines only reserve their space so large scrollbacks stay fast
+                                        let row_height = if line.prompt.is_some() && !self.classic_prompt { prompt_row_height } else { text_row_height };
+                                        let row_rect = egui::Rect::from_min_size(ui.cursor().min, egui::vec2(ui.available_width(), row_height));
+                                        if !ui.is_rect_visible(row_rect) {
+                                            ui.add_space(row_height);
+                                            continue;
+                                        }
+
+                                        // A completed command in the classic layout: the prompt and command as one plain line
+                                        if let (true, Some(prompt_line)) = (self.classic_prompt, &line.prompt) {
+                                            ui.horizontal(|ui| {
+                                                render_prompt_segments(ui, &prompt_line.segments);
+                                                ui.spacing_mut().item_spacing.x = 0.0;
                                                 ui.label(
-                                                    egui::RichText::new(&line.text)
+                                                    egui::RichText::new(format!("{} ", self.prompt_symbol()))
                                                         .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(100, 200, 255))
+                                                        .color(self.prompt_symbol_color(egui::Color32::from_rgb(220, 220, 220)))
                                                 );
-                                            } else if line.text.starts_with("$ ") {
-                                                ui.horizontal(|ui| {
+                                                if let Some(command) = &prompt_line.command {
                                                     ui.label(
-                                                        egui::RichText::new("$ ")
+                                                        egui::RichText::new(command)
                                                             .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(100, 255, 150)) // Green for command prompt
+                                                            .color(egui::Color32::from_rgb(220, 220, 220))
                                                     );
+                                                }
+                                            });
+                                        } else if let Some(prompt_line) = &line.prompt {
+                                            // Special rendering for PowerShell-like header bar (completed commands)
+
+                                            // Create a background frame for the header
+                                            ui.add_space(2.0);
+                                            let border = if self.is_root { egui::Stroke::new(1.0, ROOT_ACCENT) } else { egui::Stroke::NONE };
+                                            egui::Frame::none()
+                                                .fill(egui::Color32::from_rgb(30, 30, 40))
+                                                .stroke(border)
+                                                .inner_margin(egui::Margin::symmetric(8.0, 4.0))
+                                                .rounding(egui::Rounding::same(6.0))
+                                                .show(ui, |ui| {
+                                                    ui.horizontal(|ui| {
+                                                        render_prompt_segments(ui, &prompt_line.segments);
+
+                                                        // Add the ">" symbol
+                                                        ui.label(
+                                                            egui::RichText::new(format!(" {} ", self.prompt_symbol()))
+                                                                .font(egui::FontId::monospace(16.0))
+                                                                .color(self.prompt_symbol_color(egui::Color32::from_rgb(150, 150, 150))) // Gray
+                                                        );
+
+                                                        // Render command/output with original terminal colors (not white)
+                                                        if let Some(command) = &prompt_line.command {
+                                                            ui.label(
+                                                                egui::RichText::new(command)
+                                                                    .font(egui::FontId::monospace(16.0))
+                                                                    .color(egui::Color32::from_rgb(220, 220, 220)) // Light gray like normal terminal text
+                                                            );
+                                                        }
+                                                        if let Some(output) = &prompt_line.output {
+                                                            ui.label(
+                                                                egui::RichText::new(format!(" {}", output))
+                                                                    .font(egui::FontId::monospace(16.0))
+                                                                    .color(egui::Color32::from_rgb(220, 220, 220))
+                                                            );
+                                                        }
+                                                    });
+                                                });
+                                        } else {
+                                            match line.style {
+                                                LineStyle::PromptParts => {
+                                                    // Render the top prompt line with multiple colors (legacy support)
+                                                    ui.horizontal(|ui| {
+                                                        let parts: Vec<&str> = line.text.split(" ").collect();
+                                                        for (i, part) in parts.iter().enumerate() {
+                                                            let part_color = match i {
+                                                                0 => egui::Color32::from_rgb(100, 200, 255), // ┌─
+                                                                1 => egui::Color32::from_rgb(255, 200, 100), // 💻
+                                                                2 => egui::Color32::from_rgb(150, 255, 150), // username
+                                                                3 => egui::Color32::from_rgb(200, 150, 255), // ◦
+                                                                4 => egui::Color32::from_rgb(255, 180, 120), // 📁
+                                                                _ => egui::Color32::from_rgb(120, 255, 200), // directory
+                                                            };
 
-                                                    // Render command and output with original terminal color
-                                                    let text_after_dollar = &line.text[2..];
+                                                            ui.label(
+                                                                egui::RichText::new(*part)
+                                                                    .font(egui::FontId::monospace(18.0))
+                                                                    .color(part_color)
+                                                            );
+                                                            if i < parts.len() - 1 {
+                                                                ui.label(
+                                                                    egui::RichText::new(" ")
+                                                                        .font(egui::FontId::monospace(18.0))
+                                                                );
+                                                            }
+                                                        }
+                                                    });
+                                                }
+                                                LineStyle::Art => {
+                                                    // ASCII art rendering with rainbow colors
+                                                    ui.horizontal(|ui| {
+                                                        let rainbow_colors = [
+                                                            egui::Color32::from_rgb(255, 100, 100), // Red
+                                                            egui::Color32::from_rgb(255, 165, 0),   // Orange
+                                                            egui::Color32::from_rgb(255, 255, 0),   // Yellow
+                                                            egui::Color32::from_rgb(100, 255, 100), // Green
+                                                            egui::Color32::from_rgb(100, 150, 255), // Blue
+                                                            egui::Color32::from_rgb(150, 100, 255), // Purple
+                                                            egui::Color32::from_rgb(255, 100, 200), // Pink
+                                                        ];
+                                                        for (i, ch) in line.text.chars().enumerate() {
+                                                            let color = if ch == '█' {
+                                                                rainbow_colors[(i / 2) % rainbow_colors.len()]
+                                                            } else {
+                                                                egui::Color32::from_rgb(200, 200, 200)
+                                                            };
+                                                            ui.label(
+                                                                egui::RichText::new(ch.to_string())
+                                                                    .font(egui::FontId::monospace(16.0))
+                                                                    .color(color)
+                                                            );
+                                                        }
+                                                    });
+                                                }
+                                                LineStyle::Field { label, color } => {
+                                                    ui.horizontal(|ui| {
+                                                        ui.label(
+                                                            egui::RichText::new(format!("{} ", &line.text[..label]))
+                                                                .font(egui::FontId::monospace(16.0))
+                                                                .color(color)
+                                                        );
+                                                        ui.label(
+                                                            egui::RichText::new(line.text[label..].trim_start())
+                                                                .font(egui::FontId::monospace(16.0))
+                                                                .color(egui::Color32::from_rgb(255, 255, 255))
+                                                        );
+                                                    });
+                                                }
+                                                LineStyle::Border => {
                                                     ui.label(
-                                                        egui::RichText::new(text_after_dollar)
+                                                        egui::RichText::new(&line.text)
                                                             .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(220, 220, 220)) // Light gray like normal terminal text
+                                                            .color(egui::Color32::from_rgb(100, 200, 255))
                                                     );
-                                                });
-                                            } else {
-                                                // Fallback for other system info lines
-                                                ui.label(
-                                                    egui::RichText::new(&line.text)
-                                                        .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(150, 150, 255))
-                                                );
+                                                }
+                                                LineStyle::Command => {
+                                                    ui.horizontal(|ui| {
+                                                        ui.label(
+                                                            egui::RichText::new("$ ")
+                                                                .font(egui::FontId::monospace(16.0))
+                                                                .color(egui::Color32::from_rgb(100, 255, 150)) // Green for command prompt
+                                                        );
+                                                        ui.label(
+                                                            egui::RichText::new(&line.text[2..])
+                                                                .font(egui::FontId::monospace(16.0))
+                                                                .color(line_style::TEXT)
+                                                        );
+                                                    });
+                                                }
+                                                LineStyle::Text { color, links: true } => {
+                                                    // Paths and URLs become clickable, the rest stays normal text
+                                                    ui.horizontal(|ui| {
+                                                        ui.spacing_mut().item_spacing.x = 0.0;
+                                                        for segment in linkify::linkify(&line.text) {
+                                                            match segment {
+                                                                Segment::Text(text) => {
+                                                                    ui.label(
+                                                                        egui::RichText::new(text)
+                                                                            .font(egui::FontId::monospace(18.0))
+                                                                            .color(color)
+                                                                    );
+                                                                }
+                                                                Segment::Link(text, link) => {
+                                                                    let response = ui.link(
+                                                                        egui::RichText::new(text)
+                                                                            .font(egui::FontId::monospace(18.0))
+                                                                            .color(egui::Color32::from_rgb(100, 180, 255)) // Blue for links
+                                                                    );
+                                                                    if response.clicked() {
+                                                                        clicked_link = Some((link, line.dir.clone()));
+                                                                    }
+                                                                }
+                                                            }
+                                                        }
+                                                    });
+                                                }
+                                                LineStyle::Text { color, links: false } => {
+                                                    ui.label(
+                                                        egui::RichText::new(&line.text)
+                                                            .font(egui::FontId::monospace(18.0))
+                                                            .color(color)
+                                                    );
+                                                }
                                             }
-                                        } else {
-                                            ui.label(
-                                                egui::RichText::new(&line.text)
-                                                    .font(egui::FontId::monospace(18.0))
-                                                    .color(color)
-                                            );
                                         }
                                     }
 
+                                    // The password prompt for sudo, masked
+                                    if self.pending_sudo.is_some() {
+                                        ui.label(
+                                            egui::RichText::new(format!("[sudo] password for {}: {}", self.username, "•".repeat(self.sudo_password.chars().count())))
+                                                .font(egui::FontId::monospace(16.0))
+                                                .color(egui::Color32::from_rgb(255, 200, 100))
+                                        );
+                                    // A foreground job's password, masked; the job's own prompt is the line above
+                                    } else if self.secure_input.is_some() {
+                                        ui.label(
+                                            egui::RichText::new(format!("🔒 {} (Enter to send, Esc to cancel)", "•".repeat(self.secure_text.chars().count())))
+                                                .font(egui::FontId::monospace(16.0))
+                                                .color(egui::Color32::from_rgb(255, 200, 100))
+                                        );
+                                    // While watching, a hint on how to stop stands in for the input line
+                                    } else if self.watch.is_some() {
+                                        ui.label(
+                                            egui::RichText::new("-- Watching (q, Esc or Ctrl+C to stop) --")
+                                                .font(egui::FontId::monospace(16.0))
+                                                .color(BACKGROUND)
+                                                .background_color(egui::Color32::from_rgb(220, 220, 220))
+                                        );
+                                    // The pager footer stands in for the input line while output is held back
+                                    } else if !self.paged_output.is_empty() {
+                                        ui.label(
+                                            egui::RichText::new(format!("-- More ({} lines left; space for next, q to quit) --", self.paged_output.len()))
+                                                .font(egui::FontId::monospace(16.0))
+                                                .color(BACKGROUND)
+                                                .background_color(egui::Color32::from_rgb(220, 220, 220))
+                                        );
                                     // Current input line with prompt and cursor - inline style
-                                    if let Some(last_line) = self.lines.back() {
-                                        if last_line.is_prompt && last_line.text.starts_with("🏠") {
+                                    } else if let Some(last_line) = self.lines.back() {
+                                        if let (true, Some(prompt_line)) = (last_line.is_prompt, &last_line.prompt) {
                                             ui.horizontal(|ui| {
-                                                // Get shortened display directory
-                                                let home = env::var("HOME").unwrap_or_else(|_| "/home/user".to_string());
-                                                let display_dir = if self.current_dir.starts_with(&home) {
-                                                    self.current_dir.replace(&home, "~")
-                                                } else {
-                                                    self.current_dir.clone()
-                                                };
+                                                // Render header segments with colors
+                                                render_prompt_segments(ui, &prompt_line.segments);
                                                 
-                                                let short_path = if display_dir == "~" {
-                                                    "~".to_string()
+                                                // Show the prompt arrow, or a `$` right after the classic prompt
+                                                let symbol = if self.classic_prompt {
+                                                    ui.spacing_mut().item_spacing.x = 0.0;
+                                                    format!("{} ", self.prompt_symbol())
                                                 } else {
-                                                    let path_parts: Vec<&str> = display_dir.split('/').collect();
-                                                    if path_parts.len() <= 2 {
-                                                        display_dir.clone()
-                                                    } else {
-                                                        format!(".../{}/{}", path_parts[path_parts.len() - 2], path_parts[path_parts.len() - 1])
-                                                    }
+                                                    format!(" {} ", self.prompt_symbol())
                                                 };
-                                                
-                                                // Render header segments with colors
                                                 ui.label(
-                                                    egui::RichText::new("🏠 ")
+                                                    egui::RichText::new(symbol)
                                                         .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(100, 150, 255)) // Blue
+                                                        .color(self.prompt_symbol_color(egui::Color32::from_rgb(100, 255, 150))) // Green prompt
                                                 );
+
+                                                // The input as one galley, with the caret put at the measured position of the
+                                                // cursor, so it sits right however wide the characters before it are
+                                                let text_format = |color, background| egui::TextFormat {
+                                                    font_id: egui::FontId::monospace(16.0),
+                                                    color,
+                                                    background,
+                                                    ..Default::default()
+                                                };
+                                                let (start, end) = match (self.selection_start, self.selection_end) {
+                                                    (Some(sel_start), Some(sel_end)) => line_edit::selection_range(&self.input_buffer, sel_start, sel_end),
+                                                    _ => (0, 0),
+                                                };
+                                                let white = egui::Color32::from_rgb(255, 255, 255);
+                                                let mut job = egui::text::LayoutJob::default();
+                                                job.append(&self.input_buffer[..start], 0.0, text_format(white, egui::Color32::TRANSPARENT));
+                                                job.append(&self.input_buffer[start..end], 0.0, text_format(white, SELECTION_COLOR));
+                                                job.append(&self.input_buffer[end..], 0.0, text_format(white, egui::Color32::TRANSPARENT));
+                                                // Autosuggestion from history, right after the cursor
+                                                if let Some(hint) = self.history_hint() {
+                                                    job.append(hint, 0.0, text_format(egui::Color32::from_rgb(100, 100, 100), egui::Color32::TRANSPARENT));
+                                                }
+                                                let galley = ui.fonts(|f| f.layout_job(job));
+                                                // Room for the cursor after the last character
+                                                let space = ui.fonts(|f| f.glyph_width(&egui::FontId::monospace(16.0), ' '));
+                                                let (rect, _) = ui.allocate_exact_size(galley.size() + egui::vec2(space, 0.0), egui::Sense::hover());
+                                                ui.painter().galley(rect.min, galley.clone(), white);
+                                                if show_cursor {
+                                                    // The cursor covers the character under it, or a space's width at the end of the line
+                                                    let font = egui::FontId::monospace(16.0);
+                                                    let cursor = line_edit::floor_char_boundary(&self.input_buffer, self.cursor_pos);
+                                                    let index = self.input_buffer[..cursor].chars().count();
+                                                    let under = self.input_buffer[cursor..].chars().next();
+                                                    let at = galley.pos_from_ccursor(egui::text::CCursor::new(index)).translate(rect.min.to_vec2());
+                                                    let width = match under {
+                                                        Some(_) => galley.pos_from_ccursor(egui::text::CCursor::new(index + 1)).min.x - (at.min.x - rect.min.x),
+                                                        None => space,
+                                                    };
+                                                    match self.cursor_style {
+                                                        CursorStyle::Block => {
+                                                            ui.painter().rect_filled(egui::Rect::from_min_size(at.min, egui::vec2(width, at.height())), 0.0, white);
+                                                            // Redrawn in the background color so it still shows through
+                                                            if let Some(ch) = under {
+                                                                ui.painter().text(at.min, egui::Align2::LEFT_TOP, ch, font, BACKGROUND);
+                                                            }
+                                                        }
+                                                        CursorStyle::Bar => {
+                                                            ui.painter().rect_filled(egui::Rect::from_min_size(at.min, egui::vec2(2.0, at.height())), 0.0, white);
+                                                        }
+                                                        CursorStyle::Underline => {
+                                                            let underline = egui::Rect::from_min_max(egui::pos2(at.min.x, at.max.y - 2.0), egui::pos2(at.min.x + width, at.max.y));
+                                                            ui.painter().rect_filled(underline, 0.0, white);
+                                                        }
+                                                    }
+                                                }
+                                            });
+
+                                            // Ask before inserting a large paste
+                                            if let Some(text) = &self.pending_large_paste {
+                                                ui.add_space(5.0);
                                                 ui.label(
-                                                    egui::RichText::new(&self.username)
+                                                    egui::RichText::new(format!("⚠️  Paste {} characters / {} lines? (y/n)", text.chars().count(), text.lines().count()))
                                                         .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(255, 100, 150)) // Pink
+                                                        .color(egui::Color32::from_rgb(255, 180, 80)) // Orange warning
                                                 );
+                                            }
+
+                                            // Offer a retry after an AI timeout
+                                            if let Some((_, timeout)) = &self.pending_ai_retry {
+                                                ui.add_space(5.0);
                                                 ui.label(
-                                                    egui::RichText::new(" 📂 ")
+                                                    egui::RichText::new(format!("⏰ Retry with a {}s timeout? (y/n)", timeout.as_secs()))
                                                         .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(100, 255, 150)) // Green
+                                                        .color(egui::Color32::from_rgb(255, 180, 80)) // Orange warning
                                                 );
+                                            }
+
+                                            // Say how to use the examples just listed
+                                            if !self.offered_examples.is_empty() {
+                                                ui.add_space(5.0);
                                                 ui.label(
-                                                    egui::RichText::new(&short_path)
+                                                    egui::RichText::new(format!("💡 Press 1-{} to put an example in the input line", self.offered_examples.len()))
                                                         .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(255, 200, 100)) // Yellow
+                                                        .color(egui::Color32::from_rgb(100, 100, 100))
                                                 );
-                                                
-                                                // Add git info if present
-                                                let git_info = self.get_git_branch();
-                                                if !git_info.is_empty() {
-                                                    ui.label(
-                                                        egui::RichText::new(&format!(" {}", git_info))
-                                                            .font(egui::FontId::monospace(16.0))
-                                                            .color(egui::Color32::from_rgb(255, 255, 100)) // Bright yellow
-                                                    );
-                                                }
-                                                
-                                                // Show the prompt arrow
+                                            }
+
+                                            // Ask before running a held-back AI suggestion
+                                            if self.pending_ai_confirm.is_some() {
+                                                ui.add_space(5.0);
                                                 ui.label(
-                                                    egui::RichText::new(" > ")
+                                                    egui::RichText::new("🛑 Run it anyway? (y/n)")
                                                         .font(egui::FontId::monospace(16.0))
-                                                        .color(egui::Color32::from_rgb(100, 255, 150)) // Green prompt
+                                                        .color(egui::Color32::from_rgb(255, 180, 80)) // Orange warning
                                                 );
+                                            }
 
-                                                // Show the input with cursor and selection
-                                                ui.horizontal(|ui| {
-                                                    if let (Some(sel_start), Some(sel_end)) = (self.selection_start, self.selection_end) {
-                                                        let (start, end) = if sel_start <= sel_end {
-                                                            (sel_start, sel_end)
-                                                        } else {
-                                                            (sel_end, sel_start)
-                                                        };
-                                                        
-                                                        // Render unselected part before selection
-                                                        if start > 0 {
-                                                            ui.label(
-                                                                egui::RichText::new(&self.input_buffer[0..start])
-                                                                    .font(egui::FontId::monospace(16.0))
-                                                                    .color(egui::Color32::from_rgb(255, 255, 255))
-                                                            );
-                                                        }
-                                                        
-                                                        // Render selected part with bright background
-                                                        if start < end {
-                                                            ui.label(
-                                                                egui::RichText::new(&self.input_buffer[start..end])
-                                                                    .font(egui::FontId::monospace(16.0))
-                                                                    .color(egui::Color32::from_rgb(255, 255, 255))
-                                                                    .background_color(egui::Color32::from_rgb(0, 120, 255)) // Bright blue
-                                                            );
-                                                        }
-                                                        
-                                                        // Render unselected part after selection
-                                                        if end < self.input_buffer.len() {
-                                                            ui.label(
-                                                                egui::RichText::new(&self.input_buffer[end..])
-                                                                    .font(egui::FontId::monospace(16.0))
-                                                                    .color(egui::Color32::from_rgb(255, 255, 255))
-                                                            );
-                                                        }
-                                                        
-                                                        // Add cursor if it's at the end
-                                                        if self.show_cursor && self.cursor_pos >= self.input_buffer.len() {
-                                                            ui.label(
-                                                                egui::RichText::new("█")
-                                                                    .font(egui::FontId::monospace(16.0))
-                                                                    .color(egui::Color32::from_rgb(255, 255, 255))
-                                                            );
-                                                        }
-                                                    } else {
-                                                        // No selection - render normally with cursor
-                                                        let mut display_input = self.input_buffer.clone();
-                                                        
-                                                        // Add blinking cursor
-                                                        if self.show_cursor {
-                                                            if self.cursor_pos >= display_input.len() {
-                                                                display_input.push('█');
-                                                            } else {
-                                                                display_input.insert(self.cursor_pos, '█');
-                                                            }
-                                                        }
+                                            // Ask before running a step of an `ai-run` script
+                                            if let Some(cmd) = &self.pending_ai_step {
+                                                ui.add_space(5.0);
+                                                ui.label(
+                                                    egui::RichText::new(format!("▶ Run `{}`? (y/n, n stops the script)", cmd))
+                                                        .font(egui::FontId::monospace(16.0))
+                                                        .color(egui::Color32::from_rgb(255, 180, 80)) // Orange warning
+                                                );
+                                            }
 
-                                                        ui.label(
-                                                            egui::RichText::new(&display_input)
-                                                                .font(egui::FontId::monospace(16.0))
-                                                                .color(egui::Color32::from_rgb(255, 255, 255))
-                                                        );
-                                                    }
-                                                });
-                                            });
+                                            // Offer to install a command that wasn't found
+                                            if let Some(install) = &self.pending_install {
+                                                ui.add_space(5.0);
+                                                ui.label(
+                                                    egui::RichText::new(format!("📦 Install it with `{}`? [y/N]", install))
+                                                        .font(egui::FontId::monospace(16.0))
+                                                        .color(egui::Color32::from_rgb(255, 180, 80)) // Orange warning
+                                                );
+                                            }
 
                                             // Show autocomplete suggestions
                                             if self.show_autocomplete && !self.autocomplete_suggestions.is_empty() {
@@ -2718,28 +4953,31 @@ impl eframe::App for TerminalApp {
                                                 ui.separator();
                                                 ui.add_space(5.0);
 
-                                                // Show suggestions in a grid-like layout
+                                                // Show suggestions in a grid-like layout; clicking one applies it
                                                 let suggestions_per_row = 4;
                                                 let mut current_row = Vec::new();
+                                                let mut clicked_suggestion = None;
 
                                                 for (i, suggestion) in self.autocomplete_suggestions.iter().enumerate() {
                                                     let color = if i == self.autocomplete_index as usize {
                                                         egui::Color32::from_rgb(255, 255, 100) // Yellow highlight for selected
                                                     } else {
-                                                        egui::Color32::from_rgb(150, 150, 150) // Gray for others
+                                                        suggestion.kind.color() // Otherwise colored by where it came from
                                                     };
 
-                                                    current_row.push((suggestion.clone(), color));
+                                                    current_row.push((i, suggestion.label(), color));
 
                                                     // Start new row or show current row
                                                     if current_row.len() == suggestions_per_row || i == self.autocomplete_suggestions.len() - 1 {
                                                         ui.horizontal(|ui| {
-                                                            for (sugg, col) in &current_row {
-                                                                ui.label(
-                                                                    egui::RichText::new(sugg)
-                                                                        .font(egui::FontId::monospace(14.0))
-                                                                        .color(*col)
-                                                                );
+                                                            for (index, sugg, col) in &current_row {
+                                                                let text = egui::RichText::new(sugg)
+                                                                    .font(egui::FontId::monospace(14.0))
+                                                                    .color(*col);
+                                                                let selected = *index as isize == self.autocomplete_index;
+                                                                if ui.selectable_label(selected, text).on_hover_cursor(egui::CursorIcon::PointingHand).clicked() {
+                                                                    clicked_suggestion = Some(*index);
+                                                                }
                                                                 ui.add_space(15.0); // Space between suggestions
                                                             }
                                                         });
@@ -2747,37 +4985,376 @@ impl eframe::App for TerminalApp {
                                                     }
                                                 }
 
+                                                if let Some(index) = clicked_suggestion {
+                                                    self.autocomplete_index = index as isize;
+                                                    self.apply_suggestion(index);
+                                                    self.show_autocomplete = false;
+                                                }
+
                                                 ui.add_space(5.0);
                                                 ui.label(
-                                                    egui::RichText::new(format!("{} suggestions (Tab to cycle, Enter to select)", self.autocomplete_suggestions.len()))
+                                                    egui::RichText::new(format!("{} suggestions (Tab to cycle, Enter to select, or click)", self.autocomplete_suggestions.len()))
                                                         .font(egui::FontId::monospace(12.0))
                                                         .color(egui::Color32::from_rgb(100, 100, 100))
                                                 );
                                             }
                                         }
                                     }
+
+                                    // A key was pressed while scrolled up: bring the input line back into view
+                                    if std::mem::take(&mut self.scroll_to_bottom) {
+                                        ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
+                                    }
                                 });
                             });
 
+                        // Lock scrolling while the view is away from the bottom, and release it once it is back
+                        let max_offset = (scroll_output.content_size.y - scroll_output.inner_rect.height()).max(0.0);
+                        self.scroll_locked = scroll_output.state.offset.y < max_offset - SCROLL_LOCK_SLACK;
+
+                        // A page is what fits above the pager footer
+                        let row_height = ui.fonts(|f| f.row_height(&egui::FontId::monospace(18.0))) + ui.spacing().item_spacing.y;
+                        self.scroll_offset = scroll_output.state.offset.y;
+                        self.scroll_max = max_offset;
+                        self.scroll_line = row_height;
+                        self.scroll_page = (scroll_output.inner_rect.height() - row_height).max(row_height);
+                        let rows = (scroll_output.inner_rect.height() / row_height) as usize;
+                        self.page_lines = rows.saturating_sub(1).max(1);
+
+                        // The window's size in characters, for width-aware programs; follows resizes
+                        let glyph_width = ui.fonts(|f| f.glyph_width(&egui::FontId::monospace(18.0), 'M'));
+                        if glyph_width > 0.0 {
+                            self.term_size = (((scroll_output.inner_rect.width() / glyph_width) as usize).max(20), rows.max(5));
+                        }
+
+                        // Filter bar, shown while a filter is typed or applied
+                        if self.filter_editing || !self.filter_query.is_empty() {
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                let cursor = if self.filter_editing && show_cursor { "▏" } else { "" };
+                                ui.label(
+                                    egui::RichText::new(format!("🔎 {}{}", self.filter_query, cursor))
+                                        .font(egui::FontId::monospace(14.0))
+                                        .color(egui::Color32::from_rgb(255, 255, 255))
+                                );
+                                let (status, color) = match &filter_error {
+                                    Some(e) => (e.clone(), egui::Color32::from_rgb(255, 100, 100)),
+                                    None if line_filter.is_some() => (
+                                        format!("filtered: {} of {} lines", filter_counts.0, filter_counts.1),
+                                        egui::Color32::from_rgb(255, 180, 80),
+                                    ),
+                                    None => ("type to filter".to_string(), egui::Color32::from_rgb(150, 150, 150)),
+                                };
+                                ui.label(
+                                    egui::RichText::new(status)
+                                        .font(egui::FontId::monospace(14.0))
+                                        .color(color)
+                                );
+                                let mode = if self.filter_regex { "regex" } else { "text" };
+                                ui.small(format!("{} | Ctrl+R: regex | Enter: keep | Esc: clear | Ctrl+Shift+F: edit", mode));
+                            });
+                        }
+
                         // Status bar (simplified)
                         ui.separator();
                         ui.horizontal(|ui| {
+                            if ui.small_button("⚙").on_hover_text("Preferences (Ctrl+,)").clicked() {
+                                self.preferences_requested = true;
+                            }
                             let fuzzy_status = if self.fuzzy_enabled { "ON" } else { "OFF" };
                             let status_text = if self.show_autocomplete && !self.autocomplete_suggestions.is_empty() {
-                                format!("{} | Fuzzy: {} | Ctrl+C/X/V: clipboard | Ctrl+A: select all | Tab: cycle ({}/{}) | Ctrl+Space: toggle | Ctrl+F: fuzzy",
+                                format!("{} | Fuzzy: {} | Ctrl+C/X/V: clipboard | Ctrl+A/E: line start/end | Tab: cycle ({}/{}) | Ctrl+Space: toggle | Ctrl+F: fuzzy | Ctrl+P: palette | Ctrl+R: history | Ctrl+T/W: tabs",
                                     self.current_dir,
                                     fuzzy_status,
                                     self.autocomplete_index + 1,
                                     self.autocomplete_suggestions.len())
                             } else {
-                                format!("{} | Fuzzy: {} | Ctrl+C/X/V: clipboard | Ctrl+A: select all | Ctrl+Space: show suggestions | Ctrl+F: fuzzy",
+                                format!("{} | Fuzzy: {} | Ctrl+C/X/V: clipboard | Ctrl+A/E: line start/end | Ctrl+Space: show suggestions | Ctrl+F: fuzzy | Ctrl+P: palette | Ctrl+R: history | Ctrl+T/W: tabs",
                                     self.current_dir,
                                     fuzzy_status)
                             };
+                            if !self.ai_enabled {
+                                ui.label(
+                                    egui::RichText::new("🔒 AI off")
+                                        .font(egui::FontId::monospace(12.0))
+                                        .color(egui::Color32::from_rgb(255, 180, 80))
+                                );
+                            }
                             ui.small(status_text);
+
+                            // Which model answers, and what the requests so far have cost
+                            if self.ai_enabled {
+                                let mut ai_status = if self.ai.has_api_key {
+                                    format!("🤖 {} {}", ai_assistant::PROVIDER, self.ai.model)
+                                } else {
+                                    format!("🤖 {}: no API key", ai_assistant::PROVIDER)
+                                };
+                                if let Some(last) = self.last_ai_usage {
+                                    ai_status.push_str(&format!(" | last: {} | tab: {}", last, self.ai_usage_total));
+                                }
+                                ui.small(ai_status);
+                            }
+
+                            // Clock and resource use on the right
+                            if let Some(resources) = &mut self.resources {
+                                resources.refresh();
+                                ui.ctx().request_repaint_after(system_info::RESOURCE_REFRESH);
+                                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                    ui.small(format!("{}  {}", system_info::clock(), resources.summary()));
+                                });
+                            }
+                        });
+                    });
+
+                // A visual bell lights the whole pane for a moment
+                if let (Bell::Visual, Some(rung)) = (self.bell, self.bell_rung) {
+                    let left = BELL_FLASH.saturating_sub(rung.elapsed());
+                    if !left.is_zero() {
+                        ui.painter().rect_filled(ui.max_rect(), 0.0, egui::Color32::from_white_alpha(40));
+                        ui.ctx().request_repaint_after(left);
+                    }
+                }
+
+                clicked
+            })
+            .inner;
+
+        if let Some((link, dir)) = clicked_link {
+            self.open_link(link, &dir);
+        }
+        clicked
+    }
+
+    // Ctrl+P command palette overlay
+    fn show_palette(&mut self, ctx: &egui::Context) {
+        if self.palette_open {
+            let results = std::mem::take(&mut self.palette_matches);
+            let scroll = std::mem::take(&mut self.palette_scroll);
+            let screen = ctx.screen_rect();
+            let mut chosen: Option<String> = None;
+            egui::Window::new("command_palette")
+                .title_bar(false)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+                .fixed_size([screen.width() * 0.7, screen.height() * 0.7])
+                .frame(egui::Frame::window(&ctx.style()).fill(egui::Color32::from_rgb(24, 24, 36)))
+                .show(ctx, |ui| {
+                    ui.label(
+                        egui::RichText::new(format!("🔎 {}▏", self.palette_query))
+                            .font(egui::FontId::monospace(18.0))
+                            .color(egui::Color32::from_rgb(255, 255, 255))
+                    );
+                    ui.small("↑/↓: select | Enter: insert | Esc: close");
+                    ui.separator();
+
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false, false])
+                        .show(ui, |ui| {
+                            if results.is_empty() {
+                                ui.label(
+                                    egui::RichText::new("No matching commands")
+                                        .font(egui::FontId::monospace(16.0))
+                                        .color(egui::Color32::from_rgb(150, 150, 150))
+                                );
+                            }
+                            for (i, (cmd, source)) in results.iter().enumerate() {
+                                let selected = i == self.palette_index;
+                                let response = ui.horizontal(|ui| {
+                                    let response = ui.selectable_label(
+                                        selected,
+                                        egui::RichText::new(cmd)
+                                            .font(egui::FontId::monospace(16.0))
+                                            .color(egui::Color32::from_rgb(220, 220, 220))
+                                    );
+                                    ui.label(
+                                        egui::RichText::new(*source)
+                                            .font(egui::FontId::monospace(12.0))
+                                            .color(egui::Color32::from_rgb(120, 160, 255))
+                                    );
+                                    response
+                                }).inner;
+                                if selected && scroll {
+                                    response.scroll_to_me(None);
+                                }
+                                if response.clicked() {
+                                    chosen = Some(cmd.clone());
+                                }
+                            }
                         });
+                });
+            self.palette_matches = results;
+            if let Some(cmd) = chosen {
+                self.insert_palette_command(&cmd);
+            }
+        }
+    }
+
+    // Ctrl+R history picker, covering most of the window
+    fn show_history_picker(&mut self, ctx: &egui::Context) {
+        let Some(picker) = &mut self.history_picker else { return };
+        let scroll = std::mem::take(&mut picker.scroll);
+        let results = &picker.matches;
+        let screen = ctx.screen_rect();
+        let mut chosen: Option<String> = None;
+        egui::Window::new("history_picker")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .fixed_size([screen.width() - 80.0, screen.height() - 120.0])
+            .frame(egui::Frame::window(&ctx.style()).fill(egui::Color32::from_rgb(24, 24, 36)))
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(format!("⌕ {}▏", picker.query))
+                        .font(egui::FontId::monospace(18.0))
+                        .color(egui::Color32::from_rgb(255, 255, 255))
+                );
+                ui.small(format!("{} of {} commands | ↑/↓ PgUp/PgDn: select | Enter: use | Esc: close",
+                    results.len(), picker.distinct));
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        if results.is_empty() {
+                            ui.label(
+                                egui::RichText::new("No matching commands")
+                                    .font(egui::FontId::monospace(16.0))
+                                    .color(egui::Color32::from_rgb(150, 150, 150))
+                            );
+                        }
+                        for (i, cmd) in results.iter().enumerate() {
+                            let selected = i == picker.index;
+                            let response = ui.selectable_label(
+                                selected,
+                                egui::RichText::new(cmd)
+                                    .font(egui::FontId::monospace(16.0))
+                                    .color(egui::Color32::from_rgb(220, 220, 220))
+                            );
+                            if selected && scroll {
+                                response.scroll_to_me(None);
+                            }
+                            if response.clicked() {
+                                chosen = Some(cmd.clone());
+                            }
+                        }
+                    });
+            });
+        if let Some(cmd) = chosen {
+            self.use_history_command(&cmd);
+        }
+    }
+}
+
+impl eframe::App for TerminalApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_tab_shortcuts(ctx);
+        self.track_window(ctx);
+
+        // Removed history entries (`history -c`/`-d`) shouldn't survive a crash in the session file
+        if self.sessions.iter().any(|session| session.history_edited) {
+            self.save_session();
+            for session in &mut self.sessions {
+                session.history_edited = false;
+            }
+        }
+
+        // A long command finished while the window was in the background (the last frame
+        // may have been held up by the command itself, so focus is only known now)
+        for session in &mut self.sessions {
+            if let Some(finished) = session.finished.take() {
+                if !ctx.input(|i| i.focused) {
+                    notify::send(&finished);
+                    ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(egui::UserAttentionType::Informational));
+                }
+            }
+        }
+
+        // Background tabs keep collecting job output and AI answers
+        let window_focused = ctx.input(|i| i.focused);
+        for (index, session) in self.sessions.iter_mut().enumerate() {
+            session.tick(ctx, window_focused && index == self.layout.active);
+        }
+        self.sessions[self.layout.active].handle_input(ctx);
+        if self.sessions.iter().any(|session| session.finished.is_some()) {
+            ctx.request_repaint();
+        }
+
+        // A BEL in any tab gets the window noticed when it's in the background
+        for session in &mut self.sessions {
+            if std::mem::take(&mut session.bell_attention) && !window_focused {
+                ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(egui::UserAttentionType::Informational));
+            }
+        }
+
+        // The `-e` command runs after the window has shown its first prompt
+        if self.startup_command.is_some() {
+            if ctx.frame_nr() > 0 {
+                if let Some(command) = self.startup_command.take() {
+                    self.sessions[self.layout.active].execute_command(&command);
+                }
+            } else {
+                ctx.request_repaint();
+            }
+        }
+
+        self.show_tab_bar(ctx);
+        self.close_finished_sessions(ctx);
+
+        // Main terminal panel - fullscreen, or two panes side by side when split
+        let mut focus = None;
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none().fill(self.background))
+            .show(ctx, |ui| match self.layout.split {
+                Some(panes) => {
+                    ui.columns(2, |columns| {
+                        for (column, index) in columns.iter_mut().zip(panes) {
+                            if self.sessions[index].show(column, index == self.layout.active) {
+                                focus = Some(index);
+                            }
+                        }
                     });
+                }
+                None => {
+                    self.sessions[self.layout.active].show(ui, true);
+                }
             });
+        if let Some(index) = focus {
+            self.layout.focus(index);
+        }
+
+        self.sessions[self.layout.active].show_palette(ctx);
+        self.sessions[self.layout.active].show_history_picker(ctx);
+
+        if std::mem::take(&mut self.sessions[self.layout.active].preferences_requested) {
+            self.toggle_preferences();
+        }
+        if std::mem::take(&mut self.sessions[self.layout.active].reset_requested) {
+            ctx.set_zoom_factor(self.config.font_size / config::DEFAULT_FONT_SIZE);
+        }
+        self.show_preferences(ctx);
+    }
+
+    // Let the panel's translucent fill show the desktop instead of clearing to an opaque color
+    fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
+        if self.background.is_opaque() {
+            visuals.panel_fill.to_normalized_gamma_f32()
+        } else {
+            egui::Rgba::TRANSPARENT.to_array()
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // Quitting with the preferences window open keeps what was changed in it
+        if let Some(preferences) = self.preferences.take() {
+            self.save_preferences(&preferences);
+        }
+        self.save_session();
+        // Like the session, there is nowhere left to report a failure
+        if let Some(window) = self.window {
+            let _ = window.save();
+        }
     }
 }
 // Development milestone: Basic UI framework added
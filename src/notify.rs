@@ -0,0 +1,60 @@
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use crate::prompt::format_elapsed;
+
+// A desktop notification when a long command finishes while the window is in
+// the background, sent with `notify-send` when it is installed. The window also
+// asks for attention, so there is a hint even without a notification daemon.
+
+/// A command that ran for longer than `notify_after`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finished {
+    /// None for commands kept out of history, which shouldn't appear on screen either
+    pub command: Option<String>,
+    pub exit_code: i32,
+    pub duration: Duration,
+}
+
+impl Finished {
+    pub fn title(&self) -> String {
+        let command = self.command.as_deref().unwrap_or("Command");
+        if self.exit_code == 0 {
+            format!("✔ {} finished", command)
+        } else {
+            format!("✘ {} failed (exit {})", command, self.exit_code)
+        }
+    }
+
+    pub fn body(&self) -> String {
+        format!("after {}", format_elapsed(self.duration))
+    }
+}
+
+/// Show `finished` as a desktop notification; does nothing without `notify-send`
+pub fn send(finished: &Finished) {
+    let child = Command::new("notify-send")
+        .args(["--app-name=Linara", &finished.title(), &finished.body()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Ok(mut child) = child {
+        // Reap it off the UI thread
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_show_command_status_and_time() {
+        let built = Finished { command: Some("cargo build".to_string()), exit_code: 0, duration: Duration::from_secs(75) };
+        assert_eq!(built.title(), "✔ cargo build finished");
+        assert_eq!(built.body(), "after 1m15s");
+
+        let failed = Finished { command: None, exit_code: 101, duration: Duration::from_secs(12) };
+        assert_eq!(failed.title(), "✘ Command failed (exit 101)");
+    }
+}
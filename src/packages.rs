@@ -0,0 +1,149 @@
+use std::env;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use crate::flags::run_for_text;
+
+// Offering to install a command that isn't found. The package manager is the
+// first one on PATH; the package comes from its local database (Debian's
+// command-not-found, apt-file, pacman's file list) and is only offered when one
+// of them names it, so a typo isn't mistaken for a program. It is only asked once
+// the AI has been ruled out, and on a worker thread, since apt-file can take a
+// while. dnf and brew need the network to answer, so they are not asked.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+    Brew,
+}
+
+impl PackageManager {
+    /// The first package manager found on PATH
+    pub fn detect() -> Option<Self> {
+        [
+            ("apt-get", PackageManager::Apt),
+            ("dnf", PackageManager::Dnf),
+            ("pacman", PackageManager::Pacman),
+            ("brew", PackageManager::Brew),
+        ]
+        .into_iter()
+        .find(|(program, _)| on_path(program))
+        .map(|(_, manager)| manager)
+    }
+
+    /// The command line that installs `package`. Commands run without a terminal to
+    /// answer the manager's own "continue?" question, so the offer stands in for it
+    pub fn install_command(self, package: &str) -> String {
+        match self {
+            PackageManager::Apt => format!("sudo apt install -y {}", package),
+            PackageManager::Dnf => format!("sudo dnf install -y {}", package),
+            PackageManager::Pacman => format!("sudo pacman -S --noconfirm {}", package),
+            PackageManager::Brew => format!("brew install {}", package),
+        }
+    }
+
+    /// Look up `command`'s package on a worker thread; the install command, if there is one, arrives on the channel
+    pub fn find_install(self, command: &str) -> Receiver<Option<String>> {
+        let (sender, receiver) = mpsc::channel();
+        let command = command.to_string();
+        std::thread::spawn(move || {
+            let _ = sender.send(self.package_for(&command).map(|package| self.install_command(&package)));
+        });
+        receiver
+    }
+
+    /// The package that provides `command`, or None if the local database doesn't know it
+    pub fn package_for(self, command: &str) -> Option<String> {
+        if !is_program_name(command) {
+            return None;
+        }
+        match self {
+            PackageManager::Apt => {
+                run_for_text(Command::new("/usr/lib/command-not-found").args(["--no-failure-msg", command]))
+                    .and_then(|text| parse_command_not_found(&text))
+                    .or_else(|| {
+                        let pattern = format!("/bin/{}$", regex::escape(command));
+                        run_for_text(Command::new("apt-file").args(["search", "--regexp", &pattern]))
+                            .and_then(|text| parse_apt_file(&text, command))
+                    })
+            }
+            PackageManager::Pacman => {
+                run_for_text(Command::new("pacman").args(["-F", &format!("/usr/bin/{}", command)]))
+                    .and_then(|text| parse_pacman_owner(&text))
+            }
+            PackageManager::Dnf | PackageManager::Brew => None,
+        }
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+// Only plain names are looked up, never paths or anything with shell syntax
+fn is_program_name(command: &str) -> bool {
+    !command.is_empty()
+        && !command.starts_with('-')
+        && command.chars().all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c))
+}
+
+// "sudo apt install htop" from Debian's command-not-found handler
+fn parse_command_not_found(text: &str) -> Option<String> {
+    text.lines()
+        .find_map(|line| line.trim().strip_prefix("sudo apt install "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+}
+
+// "htop: /usr/bin/htop" from apt-file
+fn parse_apt_file(text: &str, command: &str) -> Option<String> {
+    text.lines().find_map(|line| {
+        let (package, path) = line.split_once(": ")?;
+        path.trim().ends_with(&format!("/bin/{}", command)).then(|| package.trim().to_string())
+    })
+}
+
+// "usr/bin/htop is owned by extra/htop 3.3.0-1" from pacman -F
+fn parse_pacman_owner(text: &str) -> Option<String> {
+    text.lines()
+        .find_map(|line| line.split_once(" is owned by "))
+        .and_then(|(_, owner)| owner.split_whitespace().next())
+        .map(|package| package.rsplit('/').next().unwrap_or(package).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_commands_match_the_manager() {
+        assert_eq!(PackageManager::Apt.install_command("htop"), "sudo apt install -y htop");
+        assert_eq!(PackageManager::Pacman.install_command("htop"), "sudo pacman -S --noconfirm htop");
+        assert_eq!(PackageManager::Brew.install_command("htop"), "brew install htop");
+    }
+
+    #[test]
+    fn providing_packages_are_parsed() {
+        let debian = "\nCommand 'rg' not found, but can be installed with:\n\nsudo apt install ripgrep\n";
+        assert_eq!(parse_command_not_found(debian), Some("ripgrep".to_string()));
+        assert_eq!(parse_command_not_found("Command 'qqq' not found"), None);
+
+        let apt_file = "htop: /usr/bin/htop\nhtop-doc: /usr/share/doc/htop/bin/htop\n";
+        assert_eq!(parse_apt_file(apt_file, "htop"), Some("htop".to_string()));
+        assert_eq!(parse_apt_file("", "htop"), None);
+
+        let pacman = "usr/bin/rg is owned by extra/ripgrep 14.1.0-1\n";
+        assert_eq!(parse_pacman_owner(pacman), Some("ripgrep".to_string()));
+    }
+
+    #[test]
+    fn only_plain_names_are_looked_up() {
+        assert!(is_program_name("htop"));
+        assert!(is_program_name("g++"));
+        assert!(!is_program_name("./build.sh"));
+        assert!(!is_program_name("$(rm -rf ~)"));
+        assert!(!is_program_name("--help"));
+    }
+}
@@ -0,0 +1,177 @@
+use eframe::egui;
+use crate::config::{self, Config, CursorStyle, Theme};
+
+// The preferences window (Ctrl+, or the gear in the status bar): the most common
+// config settings as widgets. Changes apply to every tab as they are made and are
+// written back to the config file when the window closes, replacing the lines
+// they came from so the rest of the file, comments included, is left alone.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preferences {
+    pub font_size: f32,
+    pub theme: Theme,
+    pub cursor_style: CursorStyle,
+    pub cursor_blink: bool,
+    pub cursor_blink_ms: u64,
+    pub fuzzy: bool,
+    pub ai_enabled: bool,
+    pub model: String,
+    pub scrollback_limit: usize,
+    pub show_banner: bool,
+    pub edited: bool, // Something changed since the window opened, so the file needs writing
+}
+
+impl Preferences {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            font_size: config.font_size,
+            theme: config.theme,
+            cursor_style: config.cursor_style,
+            cursor_blink: config.cursor_blink.is_some(),
+            cursor_blink_ms: config.cursor_blink.unwrap_or(config::DEFAULT_CURSOR_BLINK).as_millis() as u64,
+            fuzzy: config.fuzzy,
+            ai_enabled: config.ai_enabled,
+            model: config.model.clone(),
+            scrollback_limit: config.scrollback_limit,
+            show_banner: config.show_banner,
+            edited: false,
+        }
+    }
+
+    /// The settings as config file keys and values
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let on_off = |value: bool| if value { "on" } else { "off" }.to_string();
+        vec![
+            ("font_size", self.font_size.to_string()),
+            ("theme", self.theme.name().to_string()),
+            ("cursor_style", self.cursor_style.name().to_string()),
+            ("cursor_blink", if self.cursor_blink { self.cursor_blink_ms.to_string() } else { on_off(false) }),
+            ("fuzzy", on_off(self.fuzzy)),
+            ("ai", on_off(self.ai_enabled)),
+            ("model", self.model.trim().to_string()),
+            ("scrollback_limit", self.scrollback_limit.to_string()),
+            ("show_banner", on_off(self.show_banner)),
+        ]
+    }
+
+    /// Write the settings into the config file, creating it if needed
+    pub fn save(&self) -> std::io::Result<()> {
+        Config::save_settings(&self.settings())
+    }
+
+    /// Draw the widgets; true if a setting changed this frame
+    pub fn show(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        egui::Grid::new("preferences").num_columns(2).spacing([16.0, 8.0]).show(ui, |ui| {
+            ui.label("Font size");
+            changed |= ui.add(egui::Slider::new(&mut self.font_size, config::FONT_SIZES).step_by(1.0)).changed();
+            ui.end_row();
+
+            ui.label("Theme");
+            egui::ComboBox::from_id_source("theme")
+                .selected_text(self.theme.name())
+                .show_ui(ui, |ui| {
+                    for theme in Theme::ALL {
+                        changed |= ui.selectable_value(&mut self.theme, theme, theme.name()).changed();
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Cursor");
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("cursor_style")
+                    .selected_text(self.cursor_style.name())
+                    .show_ui(ui, |ui| {
+                        for style in CursorStyle::ALL {
+                            changed |= ui.selectable_value(&mut self.cursor_style, style, style.name()).changed();
+                        }
+                    });
+                changed |= ui.checkbox(&mut self.cursor_blink, "Blink every").changed();
+                changed |= ui.add_enabled(
+                    self.cursor_blink,
+                    egui::DragValue::new(&mut self.cursor_blink_ms).range(config::CURSOR_BLINK_MS).speed(10).suffix(" ms"),
+                ).changed();
+            });
+            ui.end_row();
+
+            ui.label("Suggestions");
+            changed |= ui.checkbox(&mut self.fuzzy, "Fuzzy matching").changed();
+            ui.end_row();
+
+            ui.label("AI");
+            changed |= ui.checkbox(&mut self.ai_enabled, "Send unknown commands to the AI").changed();
+            ui.end_row();
+
+            ui.label("Model");
+            changed |= ui.text_edit_singleline(&mut self.model).changed();
+            ui.end_row();
+
+            ui.label("Scrollback lines");
+            changed |= ui.add(egui::DragValue::new(&mut self.scrollback_limit).range(100..=1_000_000).speed(100)).changed();
+            ui.end_row();
+
+            ui.label("Startup banner");
+            changed |= ui.checkbox(&mut self.show_banner, "Show system info in new tabs").changed();
+            ui.end_row();
+        });
+        ui.add_space(4.0);
+        ui.small(format!("Changes apply right away and are saved to {} when this window closes.", Config::path().display()));
+        self.edited |= changed;
+        changed
+    }
+}
+
+/// `content` with each setting's line replaced, or added at the end if the file doesn't have one
+pub fn with_settings(content: &str, settings: &[(&str, String)]) -> String {
+    let mut written = vec![false; settings.len()];
+    let mut lines: Vec<String> = content.lines().map(|line| {
+        let key = crate::comment::strip_comment(line).split_once('=').map(|(key, _)| key.trim());
+        match key.and_then(|key| settings.iter().position(|(name, _)| *name == key)) {
+            Some(index) => {
+                written[index] = true;
+                format!("{} = {}", settings[index].0, settings[index].1)
+            }
+            None => line.to_string(),
+        }
+    }).collect();
+    for ((key, value), _) in settings.iter().zip(&written).filter(|(_, written)| !**written) {
+        lines.push(format!("{} = {}", key, value));
+    }
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_replace_their_lines_and_keep_the_rest() {
+        let content = "# my config\nprompt = \"{cwd} $ \"\ntheme = black # dark enough\nfuzzy=on\n";
+        let settings = [("theme", "solarized".to_string()), ("fuzzy", "off".to_string()), ("font_size", "18".to_string())];
+        assert_eq!(
+            with_settings(content, &settings),
+            "# my config\nprompt = \"{cwd} $ \"\ntheme = solarized\nfuzzy = off\nfont_size = 18\n"
+        );
+        assert_eq!(with_settings("", &settings[..1]), "theme = solarized\n");
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let mut preferences = Preferences::from_config(&Config::default());
+        preferences.font_size = 20.0;
+        preferences.theme = Theme::Black;
+        preferences.cursor_style = CursorStyle::Underline;
+        preferences.cursor_blink_ms = 800;
+        preferences.fuzzy = false;
+        preferences.model = "openai/gpt-4o-mini".to_string();
+        preferences.scrollback_limit = 5000;
+
+        let mut config = Config::default();
+        for (key, value) in preferences.settings() {
+            config.set(key, &value);
+        }
+        assert_eq!(Preferences::from_config(&config), Preferences { edited: false, ..preferences });
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+// Building blocks for the prompt line.
+
+/// Prompt layout used when the config doesn't set `prompt`
+pub const DEFAULT_TEMPLATE: &str = "{status} 🏠 {user} 📂 {cwd} {git} {duration}";
+
+/// Prompt layout for `classic_prompt` when the config doesn't set `prompt`; the `$` is added when drawn
+pub const CLASSIC_TEMPLATE: &str = "{user}@{host}:{cwd}";
+
+/// Commands that run at least this long show their duration in the next prompt
+pub const LONG_COMMAND: Duration = Duration::from_secs(2);
+
+/// What a piece of the prompt shows, so the renderer can colour it
+#[derive(Clone, Copy, PartialEq)]
+pub enum SegmentKind {
+    Literal,
+    User,
+    Host,
+    Cwd,
+    Git,
+    Exit,
+    Status, // ✔ or ✘ for the last command
+    Duration,
+}
+
+#[derive(Clone)]
+pub struct PromptSegment {
+    pub kind: SegmentKind,
+    pub text: String,
+}
+
+/// A prompt line as built by show_prompt; `command` and `output` are filled in once it runs
+#[derive(Clone)]
+pub struct PromptLine {
+    pub segments: Vec<PromptSegment>,
+    pub command: Option<String>,
+    pub output: Option<String>, // Short output shown inline after the command
+}
+
+/// Values substituted into the prompt template
+pub struct PromptInfo<'a> {
+    pub user: &'a str,
+    pub host: &'a str,
+    pub cwd: &'a str,
+    pub git: &'a str,
+    pub exit_code: i32,
+    pub duration: Option<Duration>, // How long the last command took; None until one has run
+}
+
+/// Expand `{user}`, `{host}`, `{cwd}`, `{git}`, `{exit}`, `{status}` and `{duration}` in a prompt template.
+/// Unknown placeholders are kept as written and empty values (no git branch, no command run yet,
+/// a quick command's duration) are dropped.
+pub fn expand(template: &str, info: &PromptInfo) -> Vec<PromptSegment> {
+    let mut segments: Vec<PromptSegment> = Vec::new();
+    let mut push = |kind: SegmentKind, text: &str| {
+        if text.is_empty() {
+            return;
+        }
+        match segments.last_mut() {
+            Some(last) if kind == SegmentKind::Literal && last.kind == SegmentKind::Literal => {
+                // Only happens when a value between them was dropped; keep a single space
+                let text = if last.text.ends_with(' ') { text.trim_start() } else { text };
+                last.text.push_str(text);
+            }
+            _ => segments.push(PromptSegment { kind, text: text.to_string() }),
+        }
+    };
+
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        push(SegmentKind::Literal, &rest[..open]);
+        let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+            rest = &rest[open..];
+            break;
+        };
+        let exit_code = info.exit_code.to_string();
+        let status = match info.duration {
+            Some(_) if info.exit_code == 0 => "✔",
+            Some(_) => "✘",
+            None => "",
+        };
+        let duration = info.duration
+            .filter(|duration| *duration >= LONG_COMMAND)
+            .map(|duration| format!("⏱ {}", format_elapsed(duration)))
+            .unwrap_or_default();
+        let (kind, value) = match &rest[open + 1..close] {
+            "user" => (SegmentKind::User, info.user),
+            "host" => (SegmentKind::Host, info.host),
+            "cwd" => (SegmentKind::Cwd, info.cwd),
+            "git" => (SegmentKind::Git, info.git),
+            "exit" => (SegmentKind::Exit, exit_code.as_str()),
+            "status" => (SegmentKind::Status, status),
+            "duration" => (SegmentKind::Duration, duration.as_str()),
+            _ => (SegmentKind::Literal, &rest[open..=close]),
+        };
+        push(kind, value);
+        rest = &rest[close + 1..];
+    }
+    push(SegmentKind::Literal, rest);
+
+    // A dropped value at either end would otherwise leave dangling spaces
+    if let Some(first) = segments.first_mut().filter(|first| first.kind == SegmentKind::Literal) {
+        first.text = first.text.trim_start().to_string();
+        if first.text.is_empty() {
+            segments.remove(0);
+        }
+    }
+    if let Some(last) = segments.last_mut().filter(|last| last.kind == SegmentKind::Literal) {
+        let trimmed = last.text.trim_end().len();
+        last.text.truncate(trimmed);
+        if last.text.is_empty() {
+            segments.pop();
+        }
+    }
+    segments
+}
+
+/// Elapsed time in a compact form: `2.5s`, `1m05s`, `1h02m`
+pub fn format_elapsed(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// The prompt as plain text
+pub fn segments_text(segments: &[PromptSegment]) -> String {
+    segments.iter().map(|segment| segment.text.as_str()).collect()
+}
+
+/// Directory shown in the prompt: `$HOME` becomes `~` and anything deeper than
+/// two components is shortened to `.../parent/dir`
+pub fn short_display_path(current_dir: &str, home: &str) -> String {
+    let current_dir = trim_trailing_slashes(current_dir);
+    let home = trim_trailing_slashes(home);
+
+    let display_dir = if current_dir == home {
+        "~".to_string()
+    } else {
+        match current_dir.strip_prefix(home).filter(|rest| rest.starts_with('/') && home != "/") {
+            Some(rest) => format!("~{}", rest),
+            None => current_dir.to_string(),
+        }
+    };
+
+    // Shorten path to only show last 2 parent directories
+    let path_parts: Vec<&str> = display_dir.split('/').collect();
+    if path_parts.len() <= 2 {
+        display_dir
+    } else {
+        format!(".../{}/{}", path_parts[path_parts.len() - 2], path_parts[path_parts.len() - 1])
+    }
+}
+
+// "/usr/" -> "/usr", but "/" stays "/"
+fn trim_trailing_slashes(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() && path.starts_with('/') { "/" } else { trimmed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOME: &str = "/home/user";
+
+    #[test]
+    fn home_itself_is_a_tilde() {
+        assert_eq!(short_display_path("/home/user", HOME), "~");
+        assert_eq!(short_display_path("/home/user/", HOME), "~");
+        assert_eq!(short_display_path("/home/user", "/home/user/"), "~");
+    }
+
+    #[test]
+    fn short_paths_are_kept_whole() {
+        assert_eq!(short_display_path("/home/user/src", HOME), "~/src");
+        assert_eq!(short_display_path("/usr", HOME), "/usr");
+        assert_eq!(short_display_path("/tmp/", HOME), "/tmp");
+    }
+
+    #[test]
+    fn deep_paths_keep_the_last_two_components() {
+        assert_eq!(short_display_path("/home/user/src/linara", HOME), ".../src/linara");
+        assert_eq!(short_display_path("/usr/local/bin", HOME), ".../local/bin");
+        assert_eq!(short_display_path("/usr/local/bin/", HOME), ".../local/bin");
+    }
+
+    #[test]
+    fn root_is_shown_as_is() {
+        assert_eq!(short_display_path("/", HOME), "/");
+        assert_eq!(short_display_path("/", "/"), "~");
+        assert_eq!(short_display_path("/etc", "/"), "/etc");
+    }
+
+    fn info(git: &str, exit_code: i32) -> PromptInfo<'_> {
+        PromptInfo { user: "ada", host: "box", cwd: "~/src", git, exit_code, duration: None }
+    }
+
+    #[test]
+    fn default_template_matches_the_classic_prompt() {
+        let segments = expand(DEFAULT_TEMPLATE, &info("⚡ main", 0));
+        assert_eq!(segments_text(&segments), "🏠 ada 📂 ~/src ⚡ main");
+        // Outside a repository the trailing git placeholder leaves no space behind
+        let segments = expand(DEFAULT_TEMPLATE, &info("", 0));
+        assert_eq!(segments_text(&segments), "🏠 ada 📂 ~/src");
+    }
+
+    #[test]
+    fn template_placeholders_are_expanded() {
+        let segments = expand("[{exit}] {user}@{host}:{cwd}$", &info("", 2));
+        assert_eq!(segments_text(&segments), "[2] ada@box:~/src$");
+        let kinds: Vec<SegmentKind> = segments.iter().map(|segment| segment.kind).collect();
+        assert!(kinds == [
+            SegmentKind::Literal, SegmentKind::Exit, SegmentKind::Literal, SegmentKind::User,
+            SegmentKind::Literal, SegmentKind::Host, SegmentKind::Literal, SegmentKind::Cwd,
+            SegmentKind::Literal,
+        ]);
+    }
+
+    #[test]
+    fn finished_commands_show_status_and_long_durations() {
+        let quick = PromptInfo { duration: Some(Duration::from_millis(300)), ..info("", 0) };
+        assert_eq!(segments_text(&expand(DEFAULT_TEMPLATE, &quick)), "✔ 🏠 ada 📂 ~/src");
+
+        // The empty git value in between doesn't leave a double space
+        let slow = PromptInfo { duration: Some(Duration::from_secs(75)), ..info("", 1) };
+        let segments = expand(DEFAULT_TEMPLATE, &slow);
+        assert_eq!(segments_text(&segments), "✘ 🏠 ada 📂 ~/src ⏱ 1m15s");
+        assert!(segments.first().is_some_and(|segment| segment.kind == SegmentKind::Status));
+        assert!(segments.last().is_some_and(|segment| segment.kind == SegmentKind::Duration));
+    }
+
+    #[test]
+    fn elapsed_times_are_compact() {
+        assert_eq!(format_elapsed(Duration::from_millis(2500)), "2.5s");
+        assert_eq!(format_elapsed(Duration::from_secs(65)), "1m05s");
+        assert_eq!(format_elapsed(Duration::from_secs(3720)), "1h02m");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_alone() {
+        assert_eq!(segments_text(&expand("{nope} {user} {", &info("", 0))), "{nope} ada {");
+    }
+
+    #[test]
+    fn similar_named_directories_are_not_home() {
+        assert_eq!(short_display_path("/home/user2", HOME), ".../home/user2");
+    }
+}
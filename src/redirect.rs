@@ -0,0 +1,221 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::interleave::{self, Output};
+
+// Input redirection for external commands: `< file`, here-strings (`<<< word`)
+// and here-docs (`<< DELIM`, with the body typed on the following lines).
+
+/// Where a command's stdin should come from
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    File(String),
+    HereString(String),
+    HereDoc(String), // Delimiter that ends the body
+}
+
+/// Stdin handed to the child process
+pub enum StdinSource {
+    Null,
+    File(File),
+    Text(String),
+}
+
+/// Pull an input redirection out of a command's arguments.
+/// Returns the remaining arguments and the redirection, if any.
+pub fn split_input_redirect(args: &[String]) -> Result<(Vec<String>, Option<Input>), String> {
+    let mut remaining = Vec::new();
+    let mut input = None;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let (operator, attached) = if let Some(rest) = arg.strip_prefix("<<<") {
+            ("<<<", rest)
+        } else if let Some(rest) = arg.strip_prefix("<<") {
+            ("<<", rest)
+        } else if let Some(rest) = arg.strip_prefix('<') {
+            ("<", rest)
+        } else {
+            remaining.push(arg.clone());
+            continue;
+        };
+
+        let word = if attached.is_empty() {
+            match iter.next() {
+                Some(word) => word.clone(),
+                None => return Err("syntax error near unexpected token `newline'".to_string()),
+            }
+        } else {
+            attached.to_string()
+        };
+
+        // Like other shells, the last redirection wins
+        input = Some(match operator {
+            "<<<" => Input::HereString(quoted_word(word, &mut iter)),
+            "<<" => Input::HereDoc(unquote(&word).to_string()),
+            _ => Input::File(word),
+        });
+    }
+
+    Ok((remaining, input))
+}
+
+// A here-string may be quoted across several whitespace-split arguments: "hello world"
+fn quoted_word<'a>(first: String, rest: &mut impl Iterator<Item = &'a String>) -> String {
+    let Some(quote) = first.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+        return first;
+    };
+    let mut word = first;
+    while word.len() < 2 || !word.ends_with(quote) {
+        match rest.next() {
+            Some(next) => {
+                word.push(' ');
+                word.push_str(next);
+            }
+            None => break,
+        }
+    }
+    unquote(&word).to_string()
+}
+
+fn unquote(word: &str) -> &str {
+    for quote in ['"', '\''] {
+        if word.len() >= 2 && word.starts_with(quote) && word.ends_with(quote) {
+            return &word[1..word.len() - 1];
+        }
+    }
+    word
+}
+
+/// Run `command` with the given stdin, capturing its output like `Command::output`,
+/// with the lines of stdout and stderr also kept in the order they came
+pub fn output_with_stdin(command: &mut Command, stdin: StdinSource) -> std::io::Result<Output> {
+    let (stdin, text) = match stdin {
+        StdinSource::Null => (Stdio::null(), None),
+        StdinSource::File(file) => (Stdio::from(file), None),
+        StdinSource::Text(text) => (Stdio::piped(), Some(text)),
+    };
+
+    // The text may be a password for `sudo -S`, so it is wiped however this goes
+    let spawned = command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            if let Some(mut text) = text {
+                crate::sudo::wipe(&mut text);
+            }
+            return Err(e);
+        }
+    };
+    if let Some(mut text) = text {
+        match child.stdin.take() {
+            // Write from another thread so a child that fills its stdout pipe can't deadlock us
+            Some(mut child_stdin) => {
+                std::thread::spawn(move || {
+                    let _ = child_stdin.write_all(text.as_bytes());
+                    crate::sudo::wipe(&mut text);
+                });
+            }
+            None => crate::sudo::wipe(&mut text),
+        }
+    }
+    interleave::wait(child)
+}
+
+/// Open a redirected input file, with a shell-style error if it can't be read
+pub fn open_input(path: &Path, shown_as: &str) -> Result<File, String> {
+    if path.is_dir() {
+        return Err(format!("{}: Is a directory", shown_as));
+    }
+    File::open(path).map_err(|e| {
+        let reason = match e.kind() {
+            std::io::ErrorKind::NotFound => "No such file or directory".to_string(),
+            std::io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+            _ => e.to_string(),
+        };
+        format!("{}: {}", shown_as, reason)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_separate_and_attached_redirects() {
+        let (args, input) = split_input_redirect(&strings(&["-l", "<", "data.txt"])).unwrap();
+        assert_eq!(args, strings(&["-l"]));
+        assert_eq!(input, Some(Input::File("data.txt".to_string())));
+
+        let (args, input) = split_input_redirect(&strings(&["<data.txt", "-r"])).unwrap();
+        assert_eq!(args, strings(&["-r"]));
+        assert_eq!(input, Some(Input::File("data.txt".to_string())));
+
+        let (args, input) = split_input_redirect(&strings(&["a", "b"])).unwrap();
+        assert_eq!(args, strings(&["a", "b"]));
+        assert!(input.is_none());
+    }
+
+    #[test]
+    fn missing_file_name_is_a_syntax_error() {
+        assert!(split_input_redirect(&strings(&["-l", "<"])).is_err());
+        assert!(split_input_redirect(&strings(&["<<<"])).is_err());
+    }
+
+    #[test]
+    fn here_strings_keep_quoted_words_together() {
+        let (args, input) = split_input_redirect(&strings(&["<<<", "\"hello", "world\"", "-n"])).unwrap();
+        assert_eq!(args, strings(&["-n"]));
+        assert_eq!(input, Some(Input::HereString("hello world".to_string())));
+
+        let (_, input) = split_input_redirect(&strings(&["<<<hi"])).unwrap();
+        assert_eq!(input, Some(Input::HereString("hi".to_string())));
+    }
+
+    #[test]
+    fn here_docs_take_a_delimiter() {
+        let (args, input) = split_input_redirect(&strings(&["-l", "<<", "'EOF'"])).unwrap();
+        assert_eq!(args, strings(&["-l"]));
+        assert_eq!(input, Some(Input::HereDoc("EOF".to_string())));
+
+        let (_, input) = split_input_redirect(&strings(&["<<END"])).unwrap();
+        assert_eq!(input, Some(Input::HereDoc("END".to_string())));
+    }
+
+    #[test]
+    fn missing_input_file_is_reported() {
+        let err = open_input(Path::new("/nonexistent/linara-input"), "missing.txt").unwrap_err();
+        assert_eq!(err, "missing.txt: No such file or directory");
+    }
+
+    #[test]
+    fn wc_counts_lines_from_redirected_file() {
+        let path = std::env::temp_dir().join(format!("linara-redirect-{}.txt", std::process::id()));
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+        let command = format!("-l < {}", path.display());
+        let parts: Vec<String> = command.split_whitespace().map(|s| s.to_string()).collect();
+        let (args, input) = split_input_redirect(&parts).unwrap();
+        let Some(Input::File(name)) = input else { panic!("expected a file redirect") };
+        let file = open_input(&path, &name).unwrap();
+        let output = output_with_stdin(Command::new("wc").args(&args), StdinSource::File(file)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "3");
+    }
+
+    #[test]
+    fn here_string_is_fed_to_stdin() {
+        let output = output_with_stdin(&mut Command::new("cat"), StdinSource::Text("hello\n".to_string())).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+    }
+}
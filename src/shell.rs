@@ -0,0 +1,182 @@
+// Shell syntax Linara doesn't interpret itself: pipes, `&&`/`||`, `;`, output
+// redirection and command substitution. Commands that use any of it outside
+// quotes are run with `sh -c` so they behave as they would in a shell. A builtin at the
+// front of a `&&`/`||`/`;` chain is split off and run first, since `cd` in sh would only
+// move sh.
+
+/// Whether `command` needs a real shell to run
+pub fn needs_shell(command: &str) -> bool {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            // Substitution still happens inside double quotes
+            (_, '`') => return true,
+            (_, '$') if chars.peek() == Some(&'(') => return true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '|' | ';' | '>') => return true,
+            (None, '&') if chars.peek() == Some(&'&') => return true,
+            (None, _) => {}
+        }
+    }
+    false
+}
+
+/// Split `command` at its first pipe into the first stage and the rest, when the first
+/// stage is a plain command: `history | grep git` gives `history` and `grep git`
+pub fn split_pipe(command: &str) -> Option<(&str, &str)> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut chars = command.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            // `||` is not a pipe
+            (None, '|') if chars.peek().is_some_and(|(_, next)| *next == '|') => return None,
+            (None, '|') => {
+                let (head, rest) = (command[..i].trim(), command[i + 1..].trim());
+                return (!head.is_empty() && !rest.is_empty() && !needs_shell(head)).then_some((head, rest));
+            }
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+/// How a command in a chain decides whether the next one runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    And,  // `&&`: only after a success
+    Or,   // `||`: only after a failure
+    Then, // `;`: always
+}
+
+impl Chain {
+    /// Whether the command after this operator runs, given the exit code before it
+    pub fn runs_next(self, exit_code: i32) -> bool {
+        match self {
+            Chain::And => exit_code == 0,
+            Chain::Or => exit_code != 0,
+            Chain::Then => true,
+        }
+    }
+}
+
+/// Split `command` at its first `&&`, `||` or `;` into the first command, the operator and
+/// the rest, when the first command is a plain one: `cd build && make` gives `cd build`,
+/// `Chain::And` and `make`
+pub fn split_chain(command: &str) -> Option<(&str, Chain, &str)> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut chars = command.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        let (chain, len) = match (quote, c) {
+            (Some('\''), '\'') => {
+                quote = None;
+                continue;
+            }
+            (Some('\''), _) => continue,
+            (_, '\\') => {
+                escaped = true;
+                continue;
+            }
+            (Some('"'), '"') => {
+                quote = None;
+                continue;
+            }
+            (Some(_), _) => continue,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                continue;
+            }
+            (None, ';') => (Chain::Then, 1),
+            (None, '&') if chars.peek().is_some_and(|(_, next)| *next == '&') => (Chain::And, 2),
+            (None, '|') if chars.peek().is_some_and(|(_, next)| *next == '|') => (Chain::Or, 2),
+            // A background job in the middle of the line is left to sh
+            (None, '&') => return None,
+            _ => continue,
+        };
+        let (head, rest) = (command[..i].trim(), command[i + len..].trim());
+        return (!head.is_empty() && !needs_shell(head)).then_some((head, chain, rest));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_need_a_shell() {
+        assert!(needs_shell("find . -name '*.log' | xargs rm"));
+        assert!(needs_shell("make && ./run"));
+        assert!(needs_shell("test -f x || touch x"));
+        assert!(needs_shell("cd /tmp; ls"));
+        assert!(needs_shell("ls > files.txt"));
+        assert!(needs_shell("echo \"today is $(date)\""));
+    }
+
+    #[test]
+    fn plain_and_quoted_commands_do_not() {
+        assert!(!needs_shell("ls -la"));
+        assert!(!needs_shell("grep 'a|b' file"));
+        assert!(!needs_shell("echo \"x > y; z\""));
+        assert!(!needs_shell("echo '$(date)'"));
+        assert!(!needs_shell("echo a\\|b"));
+        assert!(!needs_shell("wc -l < notes.txt"));
+    }
+
+    #[test]
+    fn pipes_split_after_a_plain_first_stage() {
+        assert_eq!(split_pipe("history | grep git"), Some(("history", "grep git")));
+        assert_eq!(split_pipe("explain ls|head -n 2 | tail -1"), Some(("explain ls", "head -n 2 | tail -1")));
+        assert_eq!(split_pipe("grep 'a|b' notes | wc -l"), Some(("grep 'a|b' notes", "wc -l")));
+        assert_eq!(split_pipe("history; ls | wc -l"), None);
+        assert_eq!(split_pipe("make || true"), None);
+        assert_eq!(split_pipe("ls |"), None);
+        assert_eq!(split_pipe("ls -la"), None);
+    }
+
+    #[test]
+    fn chains_split_after_a_plain_first_command() {
+        assert_eq!(split_chain("cd build && make"), Some(("cd build", Chain::And, "make")));
+        assert_eq!(split_chain("cd /tmp; ls; pwd"), Some(("cd /tmp", Chain::Then, "ls; pwd")));
+        assert_eq!(split_chain("cd src || mkdir src"), Some(("cd src", Chain::Or, "mkdir src")));
+        assert_eq!(split_chain("cd 'a;b' && ls"), Some(("cd 'a;b'", Chain::And, "ls")));
+        assert_eq!(split_chain("cd /tmp;"), Some(("cd /tmp", Chain::Then, "")));
+        assert_eq!(split_chain("pwd | wc -c && ls"), None);
+        assert_eq!(split_chain("sleep 1 & cd /tmp; ls"), None);
+        assert_eq!(split_chain("pwd | wc -c"), None);
+        assert_eq!(split_chain("ls -la"), None);
+    }
+
+    #[test]
+    fn chain_operators_follow_the_exit_code() {
+        assert!(Chain::And.runs_next(0) && !Chain::And.runs_next(1));
+        assert!(Chain::Or.runs_next(2) && !Chain::Or.runs_next(0));
+        assert!(Chain::Then.runs_next(0) && Chain::Then.runs_next(1));
+    }
+}
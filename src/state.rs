@@ -0,0 +1,119 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+// Per-tab state kept between launches: the working directory and recent history.
+// Scrollback is not saved; each tab starts with a fresh screen. The window's size
+// and position go in the config file (see `config::WindowGeometry`).
+
+/// History entries kept per tab in the session file
+pub const HISTORY_LIMIT: usize = 500;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub tabs: Vec<SavedTab>,
+    pub active: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedTab {
+    pub current_dir: String,
+    pub history: Vec<String>,
+}
+
+impl SavedTab {
+    /// Keep only the most recent history entries
+    pub fn new(current_dir: &str, history: &[String]) -> Self {
+        let start = history.len().saturating_sub(HISTORY_LIMIT);
+        Self {
+            current_dir: current_dir.to_string(),
+            history: history[start..].to_vec(),
+        }
+    }
+}
+
+impl SavedSession {
+    /// Location of the session file
+    pub fn path() -> PathBuf {
+        state_dir().join("session.json")
+    }
+
+    /// Load the last session; a missing or unreadable file just means a fresh start
+    pub fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path()).ok()?;
+        serde_json::from_str::<Self>(&content).ok().filter(|saved| !saved.tabs.is_empty())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)
+    }
+}
+
+/// Directory for files Linara keeps between launches, honouring `XDG_STATE_HOME`
+pub fn state_dir() -> PathBuf {
+    let base = env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            let home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+            PathBuf::from(home).join(".local").join("state")
+        });
+    base.join("linara")
+}
+
+/// Directory for files that can be rebuilt if lost, honouring `XDG_CACHE_HOME`
+pub fn cache_dir() -> PathBuf {
+    let base = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            let home = env::var("HOME").unwrap_or_else(|_| "/".to_string());
+            PathBuf::from(home).join(".cache")
+        });
+    base.join("linara")
+}
+
+/// A saved directory to reopen in, or `home` if it has since been removed
+pub fn restore_dir(saved: &str, home: &str) -> String {
+    if Path::new(saved).is_dir() {
+        saved.to_string()
+    } else {
+        home.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_directory_falls_back_to_home() {
+        let tmp = env::temp_dir().to_string_lossy().to_string();
+        assert_eq!(restore_dir(&tmp, "/home/user"), tmp);
+        assert_eq!(restore_dir("/nonexistent/linara-dir", "/home/user"), "/home/user");
+    }
+
+    #[test]
+    fn only_recent_history_is_kept() {
+        let history: Vec<String> = (0..HISTORY_LIMIT + 5).map(|i| format!("echo {}", i)).collect();
+        let tab = SavedTab::new("/tmp", &history);
+        assert_eq!(tab.history.len(), HISTORY_LIMIT);
+        assert_eq!(tab.history.first().map(String::as_str), Some("echo 5"));
+    }
+
+    #[test]
+    fn session_round_trips_through_json() {
+        let saved = SavedSession {
+            tabs: vec![SavedTab::new("/tmp", &["ls".to_string(), "cd src".to_string()])],
+            active: 0,
+        };
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(serde_json::from_str::<SavedSession>(&json).unwrap(), saved);
+        // Session files from before the window geometry moved to the config file still load
+        let with_window = r#"{"tabs":[{"current_dir":"/tmp","history":[]}],"active":0,"window":{"size":[900.0,600.0]}}"#;
+        assert_eq!(serde_json::from_str::<SavedSession>(with_window).unwrap().tabs.len(), 1);
+    }
+}
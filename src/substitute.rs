@@ -0,0 +1,184 @@
+// Command substitution: `$(command)` and `command` in backticks are replaced by
+// the command's output, as in a shell, and `$?` by the last exit status. Nothing
+// is substituted inside single quotes or after a backslash. Nested substitutions
+// are left to the shell that runs the outer one.
+
+/// Replace each substitution in `command` with what `run` returns for the inner command,
+/// minus trailing newlines
+pub fn substitute(command: &str, mut run: impl FnMut(&str) -> Result<String, String>) -> Result<String, String> {
+    let mut result = String::with_capacity(command.len());
+    let mut quote: Option<char> = None;
+    let mut chars = command.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let inner = match (quote, c) {
+            (Some('\''), '\'') => {
+                quote = None;
+                None
+            }
+            (Some('\''), _) => None,
+            (_, '\\') => {
+                result.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    result.push(escaped);
+                }
+                continue;
+            }
+            // `$((...))` is arithmetic, not a command
+            (_, '$') if command[i + 1..].starts_with('(') && !command[i + 1..].starts_with("((") => {
+                let end = closing_paren(command, i + 2).ok_or("unterminated $(")?;
+                Some((command[i + 2..end].to_string(), end))
+            }
+            (_, '`') => {
+                let end = closing_backtick(command, i + 1).ok_or("unterminated `")?;
+                Some((command[i + 1..end].replace("\\`", "`"), end))
+            }
+            (Some('"'), '"') => {
+                quote = None;
+                None
+            }
+            (Some(_), _) => None,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                None
+            }
+            (None, _) => None,
+        };
+        match inner {
+            Some((inner, end)) => {
+                let output = run(&inner)?;
+                result.push_str(output.trim_end_matches('\n'));
+                while chars.next_if(|(j, _)| *j <= end).is_some() {}
+            }
+            None => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// Replace `$?` with `status`, except where it is quoted or escaped as substitutions are
+pub fn expand_exit_status(command: &str, status: i32) -> String {
+    let mut result = String::with_capacity(command.len());
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                result.push(c);
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+                continue;
+            }
+            (_, '$') if chars.next_if_eq(&'?').is_some() => {
+                result.push_str(&status.to_string());
+                continue;
+            }
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, _) => {}
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Whether `command` contains a complete substitution
+pub fn has_substitution(command: &str) -> bool {
+    let mut found = false;
+    let _ = substitute(command, |_| {
+        found = true;
+        Ok(String::new())
+    });
+    found
+}
+
+// Index of the `)` closing a `$(` whose contents start at `start`
+fn closing_paren(command: &str, start: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in command[start..].char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start + i);
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+// Index of the next unescaped backtick from `start`
+fn closing_backtick(command: &str, start: usize) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in command[start..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '`' => return Some(start + i),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_run(inner: &str) -> Result<String, String> {
+        match inner {
+            "date +%F" => Ok("2024-05-01\n".to_string()),
+            "dirname \"$PWD\"" => Ok("/home\n".to_string()),
+            "echo $(pwd)" => Ok("/tmp\n".to_string()),
+            _ => Err(format!("unexpected command: {}", inner)),
+        }
+    }
+
+    #[test]
+    fn substitutions_are_replaced_by_output() {
+        assert_eq!(substitute("echo today is $(date +%F)", fake_run).unwrap(), "echo today is 2024-05-01");
+        assert_eq!(substitute("cd $(dirname \"$PWD\")", fake_run).unwrap(), "cd /home");
+        assert_eq!(substitute("echo `date +%F`!", fake_run).unwrap(), "echo 2024-05-01!");
+        assert_eq!(substitute("echo \"in $(echo $(pwd))\"", fake_run).unwrap(), "echo \"in /tmp\"");
+    }
+
+    #[test]
+    fn quoted_and_escaped_text_is_kept() {
+        assert_eq!(substitute("echo '$(date +%F)'", fake_run).unwrap(), "echo '$(date +%F)'");
+        assert_eq!(substitute("echo \\$(date)", fake_run).unwrap(), "echo \\$(date)");
+        assert_eq!(substitute("echo $((1 + 2))", fake_run).unwrap(), "echo $((1 + 2))");
+        assert!(!has_substitution("echo '$(date)' $((1 + 2))"));
+        assert!(has_substitution("ls $(pwd)"));
+    }
+
+    #[test]
+    fn exit_status_is_expanded_outside_single_quotes() {
+        assert_eq!(expand_exit_status("false; echo $?", 1), "false; echo 1");
+        assert_eq!(expand_exit_status("echo \"status $?\"", 2), "echo \"status 2\"");
+        assert_eq!(expand_exit_status("echo '$?' \\$? $?", 127), "echo '$?' \\$? 127");
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        assert!(substitute("echo $(date", fake_run).is_err());
+        assert_eq!(substitute("echo $(false)", fake_run), Err("unexpected command: false".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+use std::process::{Command, Stdio};
+
+// sudo asks for a password on the controlling terminal, which commands run by
+// Linara don't have. Linara asks for it instead and hands it to `sudo -S` on stdin.
+
+// Options after which sudo never prompts, or doesn't run a command at all
+const NO_PROMPT_OPTIONS: &[&str] = &["-n", "--non-interactive", "-S", "--stdin", "-A", "--askpass", "-k", "-K", "--reset-timestamp", "--remove-timestamp", "-h", "--help", "-V", "--version"];
+
+/// Whether `sudo args` could stop to ask for a password
+pub fn may_prompt(args: &[String]) -> bool {
+    args.iter()
+        .take_while(|arg| arg.starts_with('-'))
+        .all(|arg| !NO_PROMPT_OPTIONS.contains(&arg.as_str()))
+}
+
+/// Whether sudo would run without a password right now (cached credentials or NOPASSWD)
+pub fn credentials_cached() -> bool {
+    Command::new("sudo")
+        .args(["-n", "true"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The arguments for reading the password from stdin, without sudo's own prompt
+pub fn with_stdin_password(args: &[String]) -> Vec<String> {
+    ["-S", "-p", ""].iter()
+        .map(|arg| arg.to_string())
+        .chain(args.iter().cloned())
+        .collect()
+}
+
+/// Longest password that can be typed, in bytes
+pub const MAX_PASSWORD: usize = 256;
+
+/// An empty buffer with room for the longest password and the newline sent after it,
+/// so typing never reallocates and leaves copies of the password behind
+pub fn password_buffer() -> String {
+    String::with_capacity(MAX_PASSWORD + 1)
+}
+
+/// Add typed `text` to a password, unless it would grow past `MAX_PASSWORD`
+pub fn push_password(password: &mut String, text: &str) {
+    if password.len() + text.len() <= MAX_PASSWORD {
+        password.push_str(text);
+    }
+}
+
+/// Overwrite a secret so it doesn't linger in freed memory, then empty it
+pub fn wipe(secret: &mut String) {
+    // SAFETY: zero bytes are valid UTF-8, so the string stays well-formed
+    unsafe { secret.as_mut_vec() }.fill(0);
+    secret.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn non_interactive_invocations_do_not_prompt() {
+        assert!(may_prompt(&args("apt update")));
+        assert!(may_prompt(&args("-u www-data ls")));
+        assert!(!may_prompt(&args("-n apt update")));
+        assert!(!may_prompt(&args("-k")));
+        // Options of the command being run don't count
+        assert!(may_prompt(&args("ls -n")));
+    }
+
+    #[test]
+    fn password_is_read_from_stdin_quietly() {
+        assert_eq!(with_stdin_password(&args("apt update")), ["-S", "-p", "", "apt", "update"]);
+    }
+
+    #[test]
+    fn passwords_never_outgrow_their_buffer() {
+        let mut password = password_buffer();
+        let capacity = password.capacity();
+        for _ in 0..MAX_PASSWORD + 10 {
+            push_password(&mut password, "x");
+        }
+        push_password(&mut password, "pasted");
+        password.push('\n');
+        assert_eq!(password.len(), MAX_PASSWORD + 1);
+        assert_eq!(password.capacity(), capacity);
+    }
+
+    #[test]
+    fn wiped_secrets_are_empty() {
+        let mut secret = "hunter2".to_string();
+        wipe(&mut secret);
+        assert!(secret.is_empty());
+    }
+}
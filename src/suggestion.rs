@@ -0,0 +1,87 @@
+use eframe::egui::Color32;
+
+// Autocomplete suggestions remember where they came from, so the popup can
+// color and mark them: builtins, programs, history, files and so on.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    Builtin,
+    /// A program on PATH or from the common-commands list
+    Command,
+    History,
+    File,
+    Directory,
+    Flag,
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// What goes into the input line
+    pub text: String,
+    pub kind: SuggestionKind,
+}
+
+impl Suggestion {
+    pub fn new(text: impl Into<String>, kind: SuggestionKind) -> Self {
+        Self { text: text.into(), kind }
+    }
+
+    /// The text as listed in the popup, after the kind's icon
+    pub fn label(&self) -> String {
+        match self.kind.icon() {
+            "" => self.text.clone(),
+            icon => format!("{} {}", icon, self.text),
+        }
+    }
+}
+
+impl SuggestionKind {
+    pub fn color(self) -> Color32 {
+        match self {
+            SuggestionKind::Builtin => Color32::from_rgb(190, 140, 255),
+            SuggestionKind::Command => Color32::from_rgb(120, 210, 120),
+            SuggestionKind::History => Color32::from_rgb(150, 150, 150),
+            SuggestionKind::File => Color32::from_rgb(210, 210, 210),
+            SuggestionKind::Directory => Color32::from_rgb(90, 160, 255),
+            SuggestionKind::Flag => Color32::from_rgb(230, 170, 90),
+            SuggestionKind::Variable => Color32::from_rgb(90, 210, 210),
+        }
+    }
+
+    // Flags and variables are recognisable by their `-` and `$`
+    fn icon(self) -> &'static str {
+        match self {
+            SuggestionKind::Builtin => "⚙",
+            SuggestionKind::Command => "▶",
+            SuggestionKind::History => "🕘",
+            SuggestionKind::File => "📄",
+            SuggestionKind::Directory => "📁",
+            SuggestionKind::Flag | SuggestionKind::Variable => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_carry_the_kind_icon() {
+        assert_eq!(Suggestion::new("src/", SuggestionKind::Directory).label(), "📁 src/");
+        assert_eq!(Suggestion::new("--all", SuggestionKind::Flag).label(), "--all");
+    }
+
+    #[test]
+    fn kinds_have_distinct_colors() {
+        let kinds = [
+            SuggestionKind::Builtin, SuggestionKind::Command, SuggestionKind::History, SuggestionKind::File,
+            SuggestionKind::Directory, SuggestionKind::Flag, SuggestionKind::Variable,
+        ];
+        for (i, a) in kinds.iter().enumerate() {
+            for b in &kinds[i + 1..] {
+                assert_ne!(a.color(), b.color());
+            }
+        }
+    }
+}
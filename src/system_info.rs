@@ -0,0 +1,178 @@
+use std::time::{Duration, Instant};
+use sysinfo::System;
+
+// System details for the neofetch-style banner shown at startup and by `neofetch`/`sysinfo`.
+// Everything is read in-process (uname(2), /proc/uptime, sysinfo) rather than by spawning commands.
+
+pub struct SystemInfo {
+    pub os: String,
+    pub kernel: String,
+    pub uptime: String,
+    pub cpu: String,
+    pub memory: String,
+}
+
+impl SystemInfo {
+    /// Collect the details; keep this off the UI thread at startup
+    pub fn gather() -> Self {
+        // OS and kernel version, as `uname -sr` and `uname -r` would print them
+        let (os, kernel) = match nix::sys::utsname::uname() {
+            Ok(uts) => {
+                let release = uts.release().to_string_lossy().to_string();
+                (format!("{} {}", uts.sysname().to_string_lossy(), release), release)
+            }
+            Err(_) => ("Linux".to_string(), "Unknown".to_string()),
+        };
+
+        // Uptime, falling back to sysinfo where there is no /proc
+        let uptime_secs = std::fs::read_to_string("/proc/uptime")
+            .ok()
+            .and_then(|content| parse_proc_uptime(&content))
+            .unwrap_or_else(System::uptime);
+        let uptime = format_uptime(uptime_secs);
+
+        let mut system = System::new();
+        system.refresh_memory();
+        system.refresh_cpu();
+
+        // Memory info
+        let memory = if system.total_memory() > 0 {
+            let used = system.total_memory().saturating_sub(system.available_memory());
+            format!("{:.1}GB / {:.1}GB", gigabytes(used), gigabytes(system.total_memory()))
+        } else {
+            "Unknown".to_string()
+        };
+
+        // CPU info
+        let cpu = system.cpus().first()
+            .map(|cpu| cpu.brand().trim().to_string())
+            .filter(|brand| !brand.is_empty())
+            .unwrap_or_else(|| "Unknown CPU".to_string());
+
+        Self { os, kernel, uptime, cpu, memory }
+    }
+
+    /// The banner: LINARA in ASCII art followed by the details in a box
+    pub fn banner_lines(&self, username: &str, hostname: &str) -> Vec<String> {
+        vec![
+            String::new(),
+            // Colorful ASCII Art for LINARA - Clean and readable design (left-aligned)
+            "██╗     ██╗███╗   ██╗ █████╗ ██████╗  █████╗ ".to_string(),
+            "██║     ██║████╗  ██║██╔══██╗██╔══██╗██╔══██╗".to_string(),
+            "██║     ██║██╔██╗ ██║███████║██████╔╝███████║".to_string(),
+            "██║     ██║██║╚██╗██║██╔══██║██╔══██╗██╔══██║".to_string(),
+            "███████╗██║██║ ╚████║██║  ██║██║  ██║██║  ██║".to_string(),
+            "╚══════╝╚═╝╚═╝  ╚═══╝╚═╝  ╚═╝╚═╝  ╚═╝╚═╝  ╚═╝".to_string(),
+            String::new(),
+            // Display system information in a beautiful box (left-aligned)
+            "╭─────────────────────────────────────────────────────────────╮".to_string(),
+            format!("{}@{}", username, hostname),
+            "├─────────────────────────────────────────────────────────────┤".to_string(),
+            format!("OS: {}", self.os),
+            format!("Host: {}", hostname),
+            format!("Kernel: {}", self.kernel),
+            format!("Uptime: {}", self.uptime),
+            "Terminal: Linara Terminal".to_string(),
+            format!("CPU: {}", self.cpu),
+            format!("Memory: {}", self.memory),
+            "╰─────────────────────────────────────────────────────────────╯".to_string(),
+        ]
+    }
+}
+
+/// The status bar changes once a second at most
+pub const RESOURCE_REFRESH: Duration = Duration::from_secs(1);
+
+/// CPU and memory use for the status bar. Only CPU usage and memory are refreshed,
+/// and no more than once a second, so calling `refresh` every frame is cheap.
+#[derive(Default)]
+pub struct Resources {
+    system: System,
+    refreshed: Option<Instant>,
+}
+
+impl Resources {
+    pub fn refresh(&mut self) {
+        if self.refreshed.is_some_and(|refreshed| refreshed.elapsed() < RESOURCE_REFRESH) {
+            return;
+        }
+        self.system.refresh_cpu_usage();
+        self.system.refresh_memory();
+        self.refreshed = Some(Instant::now());
+    }
+
+    /// `CPU 12% MEM 48%`; CPU reads 0% until the second refresh
+    pub fn summary(&self) -> String {
+        let total = self.system.total_memory();
+        let used = total.saturating_sub(self.system.available_memory());
+        format_usage(self.system.global_cpu_info().cpu_usage(), used, total)
+    }
+}
+
+fn format_usage(cpu: f32, used_memory: u64, total_memory: u64) -> String {
+    let memory = if total_memory > 0 { used_memory as f64 * 100.0 / total_memory as f64 } else { 0.0 };
+    format!("CPU {:.0}% MEM {:.0}%", cpu, memory)
+}
+
+/// The local time as `HH:MM`
+pub fn clock() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs()) as libc::time_t;
+    // SAFETY: localtime_r only writes to the `tm` we pass it
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return String::new();
+    }
+    format!("{:02}:{:02}", tm.tm_hour, tm.tm_min)
+}
+
+fn gigabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0 / 1024.0
+}
+
+// First field of /proc/uptime: seconds since boot, with a fractional part
+fn parse_proc_uptime(content: &str) -> Option<u64> {
+    let secs: f64 = content.split_whitespace().next()?.parse().ok()?;
+    Some(secs as u64)
+}
+
+/// Uptime the way `uptime -p` words it, without the leading "up": `2 days, 3 hours, 1 minute`
+pub fn format_uptime(secs: u64) -> String {
+    let units = [("week", secs / 604_800), ("day", secs / 86_400 % 7), ("hour", secs / 3600 % 24), ("minute", secs / 60 % 60)];
+    let parts: Vec<String> = units.iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(unit, count)| format!("{} {}{}", count, unit, if *count == 1 { "" } else { "s" }))
+        .collect();
+    if parts.is_empty() {
+        "0 minutes".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proc_uptime_is_parsed() {
+        assert_eq!(parse_proc_uptime("35712.41 140263.55\n"), Some(35712));
+        assert_eq!(parse_proc_uptime(""), None);
+    }
+
+    #[test]
+    fn usage_is_shown_as_percentages() {
+        assert_eq!(format_usage(12.4, 4, 8), "CPU 12% MEM 50%");
+        assert_eq!(format_usage(0.0, 0, 0), "CPU 0% MEM 0%");
+        assert_eq!(clock().len(), 5);
+    }
+
+    #[test]
+    fn uptime_reads_like_uptime_p() {
+        assert_eq!(format_uptime(30), "0 minutes");
+        assert_eq!(format_uptime(60), "1 minute");
+        assert_eq!(format_uptime(35712), "9 hours, 55 minutes");
+        assert_eq!(format_uptime(8 * 86_400 + 3600), "1 week, 1 day, 1 hour");
+    }
+}
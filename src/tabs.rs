@@ -0,0 +1,108 @@
+// Tab focus and split-pane bookkeeping.
+//
+// Sessions live in a Vec owned by the app; this only tracks indexes into it,
+// so the rules for switching and closing tabs can be tested without a UI.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Layout {
+    pub active: usize,
+    pub split: Option<[usize; 2]>, // Sessions shown left and right; the active one is always among them
+}
+
+impl Layout {
+    /// Focus tab `index`. In a split, the pane showing the old tab switches to the new one.
+    pub fn focus(&mut self, index: usize) {
+        if let Some(panes) = &mut self.split {
+            if !panes.contains(&index) {
+                if let Some(pane) = panes.iter_mut().find(|pane| **pane == self.active) {
+                    *pane = index;
+                }
+            }
+        }
+        self.active = index;
+    }
+
+    /// Focus the next (or previous) of `count` tabs, wrapping around
+    pub fn cycle(&mut self, count: usize, forward: bool) {
+        if count == 0 {
+            return;
+        }
+        let next = if forward {
+            (self.active + 1) % count
+        } else {
+            (self.active + count - 1) % count
+        };
+        self.focus(next);
+    }
+
+    /// Show tab `index` beside the active one and focus it
+    pub fn split_with(&mut self, index: usize) {
+        self.split = Some([self.active, index]);
+        self.active = index;
+    }
+
+    /// Fix up indexes after tab `index` was removed, leaving `count` tabs
+    pub fn remove(&mut self, index: usize, count: usize) {
+        // A split can't survive losing one of its panes
+        if self.split.is_some_and(|panes| panes.contains(&index)) {
+            self.split = None;
+        }
+        if let Some(panes) = &mut self.split {
+            for pane in panes.iter_mut().filter(|pane| **pane > index) {
+                *pane -= 1;
+            }
+        }
+        if self.active > index || self.active >= count {
+            self.active = self.active.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycling_wraps_around() {
+        let mut layout = Layout::default();
+        layout.cycle(3, false);
+        assert_eq!(layout.active, 2);
+        layout.cycle(3, true);
+        assert_eq!(layout.active, 0);
+    }
+
+    #[test]
+    fn closing_a_tab_keeps_focus_on_a_neighbour() {
+        let mut layout = Layout { active: 2, split: None };
+        layout.remove(0, 2);
+        assert_eq!(layout.active, 1);
+
+        // Closing the last, focused tab moves focus to the one before it
+        layout.remove(1, 1);
+        assert_eq!(layout.active, 0);
+    }
+
+    #[test]
+    fn focusing_another_tab_replaces_the_focused_pane() {
+        let mut layout = Layout { active: 0, split: None };
+        layout.split_with(1);
+        assert_eq!(layout, Layout { active: 1, split: Some([0, 1]) });
+
+        layout.focus(2);
+        assert_eq!(layout, Layout { active: 2, split: Some([0, 2]) });
+
+        // Tabs already on screen just take focus
+        layout.focus(0);
+        assert_eq!(layout, Layout { active: 0, split: Some([0, 2]) });
+    }
+
+    #[test]
+    fn closing_a_pane_ends_the_split() {
+        let mut layout = Layout { active: 3, split: Some([1, 3]) };
+        layout.remove(0, 3);
+        assert_eq!(layout, Layout { active: 2, split: Some([0, 2]) });
+
+        layout.remove(2, 2);
+        assert_eq!(layout, Layout { active: 1, split: None });
+    }
+}